@@ -2,7 +2,7 @@
 
 extern crate itertools;
 
-use itertools::put_back;
+use itertools::put_back_n;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
@@ -34,8 +34,8 @@ pub enum LexerEvent {
     DoubleQuote, // ""
     Equals,  // =
     ExclamationPoint,
-    ExpressionStarter(char), // ( [ 
-    ExpressionEnder(char), // ) , ? : ; ]
+    ExpressionStarter(char), // ( [ {
+    ExpressionEnder(char), // ) , ? : ; ] }
     Caret, // ^
     Period, // .
     Sign(char), // + -
@@ -45,6 +45,8 @@ pub enum LexerEvent {
     AmpersandBar(char), // & |
     Tilde,
     SquareRoot, // √
+    At, // @
+    Hash, // #
     Other(char)
 }
 
@@ -55,7 +57,7 @@ impl LexerEvent {
             '»' => LexerEvent::EOS,
             ' ' | '\t' => LexerEvent::Space,
             '\n' => LexerEvent::Newline,
-            'a'..='z' | 'A'..='Z' | 'α'..='ω' | 'Α'..='Ω' => LexerEvent::Letter(c),
+            'a'..='z' | 'A'..='Z' | 'α'..='ω' | 'Α'..='Ω' | '°' => LexerEvent::Letter(c),
             '0'..='9' => LexerEvent::Digit(c),
             '¹' | '²' | '³' | '⁴' | '⁵' | '⁶' | '⁷' | '⁸' | '⁹' | '⁰' => LexerEvent::Superscript(c),
             '$' | '_' => LexerEvent::DollarUnderscore(c),
@@ -64,8 +66,8 @@ impl LexerEvent {
             '"'  => LexerEvent::DoubleQuote,
             '='  => LexerEvent::Equals,
             '!'  => LexerEvent::ExclamationPoint,
-            '(' | '[' => LexerEvent::ExpressionStarter(c),
-            ')' | ',' | '?' | ':' | ';' | ']' => LexerEvent::ExpressionEnder(c),
+            '(' | '[' | '{' => LexerEvent::ExpressionStarter(c),
+            ')' | ',' | '?' | ':' | ';' | ']' | '}' => LexerEvent::ExpressionEnder(c),
             '^'  => LexerEvent::Caret,
             '.'  => LexerEvent::Period,
             '+' | '-'  => LexerEvent::Sign(c),
@@ -75,6 +77,8 @@ impl LexerEvent {
             '&' | '|'  => LexerEvent::AmpersandBar(c),
             '~'  => LexerEvent::Tilde,
             '√'  => LexerEvent::SquareRoot,
+            '@'  => LexerEvent::At,
+            '#'  => LexerEvent::Hash,
             _ => LexerEvent::Other(c)
         }
     }
@@ -108,6 +112,8 @@ impl Display for LexerEvent {
             LexerEvent::AmpersandBar(ab) => _ctos(*ab),
             LexerEvent::Tilde => _ctos('~'),
             LexerEvent::SquareRoot => _ctos('√'),
+            LexerEvent::At => _ctos('@'),
+            LexerEvent::Hash => _ctos('#'),
             LexerEvent::Other(o) => _ctos(*o)
         }
     }
@@ -140,8 +146,8 @@ impl From<LexerEvent> for char {
 ///  LexerEventIterator iterates over a string and generates LexerEvents for each character.
 /// You can also put_back characters/events, and peek ahead.
 pub struct LexerEventIterator<'e> {
-    /// Iterates over the characters in a string, with ability to put_back a character.
-    char_iter: itertools::PutBack<std::str::Chars<'e>>,
+    /// Iterates over the characters in a string, with ability to put_back characters.
+    char_iter: itertools::PutBackN<std::str::Chars<'e>>,
 
     /// Has the beginning of string token (BOS) been issued yet?
     issued_bos: bool,
@@ -160,7 +166,7 @@ pub struct LexerEventIterator<'e> {
 impl<'e> LexerEventIterator<'e> {
     pub fn new(s: &'e std::string::String) -> LexerEventIterator<'e> {
         LexerEventIterator { 
-            char_iter: put_back(s.chars()),
+            char_iter: put_back_n(s.chars()),
             issued_bos: false,
             issued_eos: false,
             position: 0,
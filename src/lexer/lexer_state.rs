@@ -28,7 +28,16 @@ custom_derive! {
         ExponentSign,
         ExponentDigits,
         Power,
-        Exclamation
+        Exclamation,
+        QuestionMark,
+        NumberSuffix,
+        DateTime,
+        QuotedRegex,
+        QuotedRegexEscape,
+        LineComment,
+        BlockComment,
+        BlockCommentStar,
+        Range
     }
 }
 
@@ -53,11 +62,28 @@ impl LexerState {
             LexerState::ExponentSign => 15,
             LexerState::ExponentDigits => 16,
             LexerState::Power => 17,
-            LexerState::Exclamation => 18
+            LexerState::Exclamation => 18,
+            LexerState::QuestionMark => 19,
+            LexerState::NumberSuffix => 20,
+            LexerState::DateTime => 21,
+            LexerState::QuotedRegex => 22,
+            LexerState::QuotedRegexEscape => 23,
+            LexerState::LineComment => 24,
+            LexerState::BlockComment => 25,
+            LexerState::BlockCommentStar => 26,
+            LexerState::Range => 27
         }
     }
     pub fn size() -> i32 {
-        19
+        28
+    }
+
+    /// True for the states that skip the text of a comment.
+    pub fn is_comment(&self) -> bool {
+        match *self {
+            LexerState::LineComment | LexerState::BlockComment | LexerState::BlockCommentStar => true,
+            _ => false
+        }
     }
 }
 
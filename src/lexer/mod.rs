@@ -11,12 +11,20 @@ use lexer_state::LexerState;
 pub mod parser_token;
 use parser_token::{ParserToken, LexerError};
 
+pub mod span;
+use span::Span;
+
+use crate::parser::temporal;
+use crate::parser::units;
+use crate::parser::decimal::Decimal;
+
 
 // Tokenizer classes:
 //    - LexerError: Stored inside ParserToken::Error.
 //    - ParserToken: Categorizes part of the expression string as a token useable by a parser 
 //    - LexerState: Describes which state the Lexer state machine is in (see lexer_state.rs)
 //    - Lexer: Uses a state machine to break a string into ParserTokens
+//    - Span: The part of the string from which a ParserToken was read
 
 
 //..................................................................
@@ -24,6 +32,7 @@ use parser_token::{ParserToken, LexerError};
 /// Lexer is a lexical analyzer for breaking an input string of characters 
 /// into a series of lexical tokens of type ParserToken, which can be consumed by the Parser.
 /// The Lexer reports errors by returning a ParserToken::Error, which describes the error and its position in the expression.
+/// Unless resynchronize is set, it yields no more tokens after an error.
 pub struct Lexer<'e> {
     /// Current state of the lexical analyzer.
     current_state: LexerState,
@@ -37,9 +46,37 @@ pub struct Lexer<'e> {
     /// Buffer holding the next token in cases when we need to yield two tokens at once
     token_buffer: Option<ParserToken>,
 
-    /// Character position within the string being tokenized where the first error occurred, or -1 if no error yet.
+    /// Span of the token in token_buffer.
+    buffered_span: Span,
+
+    /// Character offset where the token being built began, or None if no character of it has been read yet.
+    token_start: Option<usize>,
+
+    /// Number of whitespace characters read since the last character that belongs to the token being built.
+    trailing_whitespace: usize,
+
+    /// Span of the token most recently yielded by the iterator.
+    last_span: Span,
+
+    /// Character offset where the comment being skipped began.
+    comment_start: usize,
+
+    /// State to return to once the comment being skipped ends.
+    state_after_comment: LexerState,
+
+    /// Span of each comment skipped so far.
+    comments: Vec<Span>,
+
+    /// Character position within the string being tokenized where the current error occurred, or -1 if there is none.
     position_with_error: i32,
 
+    /// Number of newlines skipped since the last character other than whitespace, while in the Error state.
+    newlines_skipped: usize,
+
+    /// If true, after an error skip ahead to the next statement boundary (a semicolon or a blank line) 
+    /// and resume yielding tokens from there, so that every error in the string may be reported.
+    pub resynchronize: bool,
+
     /// Turn on/off logging
     pub enable_logging: bool,
 
@@ -56,7 +93,16 @@ impl<'e> Lexer<'e> {
             events: LexerEventIterator::new(&s),
             next_token: String::new(),
             token_buffer: None,
+            buffered_span: Span::default(),
+            token_start: None,
+            trailing_whitespace: 0,
+            last_span: Span::default(),
+            comment_start: 0,
+            state_after_comment: LexerState::Empty,
+            comments: Vec::new(),
             position_with_error: -1,
+            newlines_skipped: 0,
+            resynchronize: false,
             enable_logging: false,
             transition_log: String::new()
         } 
@@ -71,6 +117,21 @@ impl<'e> Lexer<'e> {
 
     pub fn has_error(&mut self) -> bool { self.current_state == LexerState::Error }
 
+    /// Span of the source text from which the token most recently yielded was read.
+    pub fn span(&self) -> Span { self.last_span }
+
+    /// Span of each comment read so far, including the characters that open and close it.
+    pub fn comments(&self) -> &[Span] { &self.comments }
+
+    /// Consume the Lexer, pairing each ParserToken with the span of the source text from which it was read.
+    pub fn with_spans(mut self) -> Vec<(ParserToken, Span)> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next() {
+            tokens.push((token, self.span()));
+        }
+        tokens
+    }
+
     //..................................................................
 
     // Stack manipulation
@@ -203,6 +264,21 @@ impl<'e> Lexer<'e> {
 
     /// Transition to the Error state; do not yield a token.
     fn transition_to_error(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        self.fail(e, None)
+    }
+
+    /// Transition to the Error state because the literal just read is not valid, as with a date that has no such month. 
+    /// The event that ended the literal is put back, so that the error spans the literal and nothing more.
+    fn reject_literal(&mut self, message: String, ending: LexerEvent) -> Option<ParserToken> {
+        if ending != LexerEvent::EOS {
+            self.events.put_back(ending);
+        }
+        let start = self.token_start.unwrap_or(0);
+        self.fail(ending, Some((message, start)))
+    }
+
+    /// Transition to the Error state, yielding a ParserToken::Error for the given event or invalid literal.
+    fn fail(&mut self, e: LexerEvent, invalid_literal: Option<(String, usize)>) -> Option<ParserToken> {
         // Solution to Chicken-and-egg problem:
         //
         // Q: Logging is done in two places, here and in Lexer.next.
@@ -225,15 +301,25 @@ impl<'e> Lexer<'e> {
         if self.position_with_error < 0 {
             self.position_with_error = self.events.current_position();
         }
+        let unexpected = match e {
+            LexerEvent::EOS => None,
+            _ => Some(e.into())
+        };
         let temp_error_token = Some(ParserToken::Error(LexerError { 
             error_position: self.position_with_error, 
             error_line: self.events.current_line(),
+            unexpected,
+            invalid_literal: invalid_literal.clone(),
             log: format!("Error receiving '{}'", e.to_string())
          }));
         self.log_append(self.position_with_error, e.to_string(), previous_state, &temp_error_token);
+        if self.resynchronize && e == LexerEvent::ExpressionEnder(';') && invalid_literal.is_none() {
+            // The semicolon that caused the error is also where the next statement begins.
+            self.events.put_back(e);
+        }
         Some(
             ParserToken::Error(
-                LexerError { error_position: self.position_with_error, error_line: self.events.current_line(), log: self.get_log() }
+                LexerError { error_position: self.position_with_error, error_line: self.events.current_line(), unexpected, invalid_literal, log: self.get_log() }
             )
         )
     }
@@ -278,19 +364,31 @@ impl<'e> Lexer<'e> {
             // (and handle escaped characters)
             LexerEvent::DoubleQuote => self.transition_without_yield(LexerState::String),
 
+            // Comments are skipped like whitespace: # or // begins one that runs to the end of the line, 
+            // and /* begins one that runs to the next */.
+            LexerEvent::Hash => self.begin_comment(LexerState::LineComment, 1),
+            LexerEvent::Slash if self.does_next_token_match_string("/".to_owned()) => self.begin_comment(LexerState::LineComment, 2),
+            LexerEvent::Slash if self.does_next_token_match_string("*".to_owned()) => self.begin_comment(LexerState::BlockComment, 2),
+
             // Unambiguously single character operators or grouping symbols
             LexerEvent::ExpressionStarter('(') => self.reenter_with_yield(ParserToken::OpenParenthesis),
             LexerEvent::ExpressionStarter('[') => self.reenter_with_yield(ParserToken::OpenBracket),
             LexerEvent::ExpressionEnder(')') => self.reenter_with_yield(ParserToken::CloseParenthesis),
             LexerEvent::ExpressionEnder(']') => self.reenter_with_yield(ParserToken::CloseBracket),
+            LexerEvent::ExpressionStarter('{') => self.reenter_with_yield(ParserToken::OpenBrace),
+            LexerEvent::ExpressionEnder('}') => self.reenter_with_yield(ParserToken::CloseBrace),
             LexerEvent::ExpressionEnder(',') => self.reenter_with_yield(ParserToken::Comma),
             LexerEvent::ExpressionEnder(';') => self.reenter_with_yield(ParserToken::Semicolon),
-            LexerEvent::ExpressionEnder('?') => self.reenter_with_yield(ParserToken::QuestionMark),
+            LexerEvent::ExpressionEnder('?') if self.does_next_token_match_string("?".to_owned()) => self.transition_with_push(LexerState::LogicalOperator, e),
+            LexerEvent::ExpressionEnder('?') => self.transition_without_yield(LexerState::QuestionMark),
             LexerEvent::ExpressionEnder(':') => self.reenter_with_yield(ParserToken::Colon),
             LexerEvent::Caret => self.reenter_with_yield(ParserToken::ExponentiationOp),
             LexerEvent::Comparison(relop) => self.reenter_with_yield(ParserToken::RelationalOp(relop.to_string())),
             LexerEvent::SquareRoot => self.reenter_with_yield(ParserToken::SquareRootOp),
 
+            // Two periods separate the bounds of a range, as in 100..500
+            LexerEvent::Period if self.does_next_token_match_string(".".to_owned()) => self.transition_with_push(LexerState::Range, e),
+
             // A period can dereference a property (e.g. person.name) or begin a degenerate number (e.g. .5)
             LexerEvent::Period => if self.does_next_token_match_filter(
                 |evt| {
@@ -322,7 +420,7 @@ impl<'e> Lexer<'e> {
                  { self.transition_with_push(LexerState::IntegerDigits, e) }
             else { self.transition_with_push(LexerState::ContinuableOperator, e) },
 
-            // First character in a logical operator that may have two or three characters, like &&, ||, &&=, ||=
+            // First character in a logical operator that may have two or three characters, like &&, ||, ??, &&=, ||=
             LexerEvent::AmpersandBar(_) => self.transition_with_push(LexerState::LogicalOperator, e),
 
             // Digits begin a number with no leading sign
@@ -334,8 +432,17 @@ impl<'e> Lexer<'e> {
             // Exclamation point may be prefix (logical not or not match) or suffix (factorial)
             LexerEvent::ExclamationPoint => self.transition_with_push(LexerState::Exclamation, e),
 
-            // Tilde signifies that we are about to match a regex pattern
-            LexerEvent::Tilde => self.transition_with_yield(LexerState::ExpectRegex, ParserToken::MatchOp("~".to_owned())),
+            // At-sign begins a date/time literal, like @2024-01-05T10:00Z
+            LexerEvent::At => self.transition_without_yield(LexerState::DateTime),
+
+            // Tilde signifies that we are about to match a regex pattern. If an angle bracket follows, as in "~>", we are to capture its groups.
+            LexerEvent::Tilde => if self.does_next_token_match_string(">".to_owned()) {
+                self.events.next();
+                self.transition_with_yield(LexerState::ExpectRegex, ParserToken::MatchOp("~>".to_owned()))
+            }
+            else {
+                self.transition_with_yield(LexerState::ExpectRegex, ParserToken::MatchOp("~".to_owned()))
+            },
 
             LexerEvent::EOS => self.transition_without_yield(LexerState::Goal),
 
@@ -382,23 +489,28 @@ impl<'e> Lexer<'e> {
     /// Identifier state transitions, part of building an identifier or a function name.
     fn identifier(&mut self, e: LexerEvent) -> Option<ParserToken> {
         match e {
+            // Two periods end the identifier and begin a range, as in low..high.
+            LexerEvent::Period if self.does_next_token_match_string(".".to_owned()) 
+              => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::word(s)), e),
             LexerEvent::Letter(_) | LexerEvent::Digit(_)
             | LexerEvent::DollarUnderscore(_) | LexerEvent::Period => self.reenter_with_push(e),
+            // A question mark followed by a period is null-safe navigation, as in well?.casing, and continues the property chain.
+            LexerEvent::ExpressionEnder('?') if self.does_next_token_match_string(".".to_owned()) => self.reenter_with_push(e),
             LexerEvent::ExclamationPoint => if self.does_next_token_match_string("=".to_owned()) {
                 // The exclamation point is part of a not equals operator (!=). Put it back for reuse.
-                // We call to_property_chain in case there are periods in the name, indicating a series of property references.
-                // If so, we make it into a PropertyChain.
-                self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Identifier(s).to_property_chain()), e)
+                // The word may be a keyword like in. Otherwise, if there are periods in the name, indicating a series 
+                // of property references, we make it into a PropertyChain.
+                self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::word(s)), e)
             }
             else {
                 // The exclamation point is the factorial operator. Yield two tokens, an identifier followed by a factorial.
-                self.transition_with_double_yield(LexerState::Empty, |s| Some(ParserToken::Identifier(s).to_property_chain()), ParserToken::FactorialOp)
+                self.transition_with_double_yield(LexerState::Empty, |s| Some(ParserToken::word(s)), ParserToken::FactorialOp)
             },
-            // If an identifier is followed by an open parenthesis, it is a function name.
+            // If an identifier is followed by an open parenthesis, it is a function name, unless it is a keyword, as in x in (1, 2).
             // Do not attempt to make it into a PropertyChain.
-            LexerEvent::ExpressionStarter('(') => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Function(s)), e),
+            LexerEvent::ExpressionStarter('(') => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::keyword(&s).unwrap_or(ParserToken::Function(s))), e),
             LexerEvent::Space => self.transition_without_yield(LexerState::FunctionName),
-            _ => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Identifier(s).to_property_chain()), e)
+            _ => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::word(s)), e)
         }
     }
 
@@ -408,8 +520,8 @@ impl<'e> Lexer<'e> {
     fn function_name(&mut self, e: LexerEvent) -> Option<ParserToken> {
         match e {
             LexerEvent::Space => self.reenter_without_yield(),
-            LexerEvent::ExpressionStarter('(') => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Function(s)), e),
-            _ => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Identifier(s).to_property_chain()), e)
+            LexerEvent::ExpressionStarter('(') => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::keyword(&s).unwrap_or(ParserToken::Function(s))), e),
+            _ => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::word(s)), e)
         }
     }
 
@@ -425,6 +537,12 @@ impl<'e> Lexer<'e> {
                     _ => None // will enter Error state
                 }, 
                 e),
+            // The arrow of a lambda, as in x => x > 100
+            LexerEvent::AngleBracket('>') if self.next_token == "=" 
+              => self.transition_with_pop_plus_event(LexerState::Empty, |_s| Some(ParserToken::Arrow), e),
+            // The spreadsheet form of not equals, as in x <> 0
+            LexerEvent::AngleBracket('>') if self.next_token == "<" 
+              => self.transition_with_pop_plus_event(LexerState::Empty, |s| Some(ParserToken::EqualityOp(s)), e),
             LexerEvent::Sign(_) => self.transition_with_pop_plus_event(
                 LexerState::Empty, 
                 |s| match s.as_str() {
@@ -444,11 +562,11 @@ impl<'e> Lexer<'e> {
                 e)
         }
     }
-    /// LogicalOperator state transitions, which may yield a logical operator like && or ||, 
+    /// LogicalOperator state transitions, which may yield a logical operator like &&, || or ??, 
     /// or an assignment operator like &&= or ||=.
     fn logical_operator(&mut self, e: LexerEvent) -> Option<ParserToken> {
         match e {
-            LexerEvent::AmpersandBar(_) => self.reenter_with_push(e),
+            LexerEvent::AmpersandBar(_) | LexerEvent::ExpressionEnder('?') => self.reenter_with_push(e),
             LexerEvent::Equals => self.transition_with_pop_plus_event(
                 LexerState::Empty, 
                 |s| match s.as_str() {
@@ -462,12 +580,20 @@ impl<'e> Lexer<'e> {
                 e)
         }
     }
+    /// Range state transitions. The first of the two periods of a range has been read.
+    fn range(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Period => self.transition_with_pop_plus_event(LexerState::Empty, |_s| Some(ParserToken::RangeOp), e),
+            _ => self.transition_to_error(e)
+        }
+    }
     /// ExpectRegex state transitions.
-    /// After seeing a match operator, we expect to see a Regex beginning delimiter (slash), but might have to toss some whitespace first.
+    /// After seeing a match operator, we expect to see a Regex beginning delimiter (slash or double quote), but might have to toss some whitespace first.
     fn expect_regex(&mut self, e: LexerEvent) -> Option<ParserToken> {
         match e {
             LexerEvent::Space | LexerEvent::Newline => self.reenter_without_yield(),
             LexerEvent::Slash => self.transition_without_yield(LexerState::Regex),
+            LexerEvent::DoubleQuote => self.transition_without_yield(LexerState::QuotedRegex),
             _ => self.transition_to_error(e)
         }
     }
@@ -489,6 +615,33 @@ impl<'e> Lexer<'e> {
     fn regex_escape(&mut self, e: LexerEvent) -> Option<ParserToken> {
         self.transition_with_push(LexerState::Regex, e)
     }
+
+    /// QuotedRegex state transitions. Build a Regex delimited by double quotes, one character at a time.
+    /// The quote delimiters are discarded.
+    fn quoted_regex(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            // Found double quote delimiter that ends the Regex 
+            LexerEvent::DoubleQuote => self.transition_with_pop(LexerState::Empty, |s| Some(ParserToken::Regex(s))),
+
+            // Start an escape sequence. The backslash is not pushed until we see what it escapes.
+            LexerEvent::Backslash => self.transition_without_yield(LexerState::QuotedRegexEscape),
+
+            // All other characters are added to the Regex without modification
+            _ => self.reenter_with_push(e)
+        }
+    }
+
+    /// QuotedRegexEscape state transitions. 
+    /// An escaped double quote becomes a plain double quote; all other escape sequences are passed to the Regex as written.
+    fn quoted_regex_escape(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::DoubleQuote => self.transition_with_push(LexerState::QuotedRegex, e),
+            _ => {
+                self.push_char('\\');
+                self.transition_with_push(LexerState::QuotedRegex, e)
+            }
+        }
+    }
     /// IntegerDigits state transitions. 
     /// Add digits to the integer part until the number terminates or we find a decimal point.
     fn integer_digits(&mut self, e: LexerEvent) -> Option<ParserToken> {
@@ -496,12 +649,19 @@ impl<'e> Lexer<'e> {
             // Continue building the whole number part before the decimal
             LexerEvent::Digit(_) => self.reenter_with_push(e),
 
+            // Found two periods, which end the integer and begin a range, as in 100..500.
+            LexerEvent::Period if self.does_next_token_match_string(".".to_owned()) 
+              => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Integer(s)), e),
+
             // Found decimal point, so advance to thee fractional part.
             LexerEvent::Period => self.transition_with_push(LexerState::FractionalDigits, e),
 
             // Found an exclamation point. Interpret it as a postfix Factorial operator, not a prefix negation operator.
             LexerEvent::ExclamationPoint => self.transition_with_double_yield(LexerState::Empty, |s| Some(ParserToken::Integer(s)), ParserToken::FactorialOp),
 
+            // Found a letter immediately after the number, which begins a unit, as in 5min.
+            LexerEvent::Letter(_) => self.transition_with_push(LexerState::NumberSuffix, e),
+
             // Went too far - make an Integer with no fractional part and put the new character back. It is either whitespace or part of the next token.
             _ => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Integer(s)), e)
         }
@@ -514,6 +674,20 @@ impl<'e> Lexer<'e> {
             // Found the 'e' or 'E' that begins the optional exponent.
             LexerEvent::Letter('e') | LexerEvent::Letter('E') => self.transition_with_push(LexerState::ExponentSign, e),
 
+            // Found any other letter immediately after the number, which begins a unit, as in 1.5h.
+            LexerEvent::Letter(_) => self.transition_with_push(LexerState::NumberSuffix, e),
+
+            // Found a second decimal point, as in 1.2.3d, unless it begins a range. Take in the rest of the number, so that the error shows all of it.
+            LexerEvent::Period if !self.does_next_token_match_string(".".to_owned()) => {
+                self.push(e);
+                while self.does_next_token_match_filter(|evt| matches!(evt, LexerEvent::Digit(_) | LexerEvent::Letter(_) | LexerEvent::Period)) {
+                    let event = self.events.next().unwrap();
+                    self.push(event);
+                }
+                let ending = self.events.next().unwrap_or(LexerEvent::EOS);
+                self.reject_literal(format!("Invalid number '{}'", self.next_token), ending)
+            },
+
             // Went too far - make a rational number with a fractional part and no exponent and put the new character back. 
             // It is either whitespace or part of the next token.
             _ => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Rational(s)), e)
@@ -573,9 +747,166 @@ impl<'e> Lexer<'e> {
             _ => self.transition_with_pop_and_put_back(LexerState::Empty, |_s| Some(ParserToken::LogicalNotOp), e),
         }
     }
-    /// In the Error state, remain in this state regardless of the incoming LexerEvent.
-    fn error(&mut self, _e: LexerEvent) -> Option<ParserToken> {
-        None
+
+    /// QuestionMark state transitions.
+    /// Disambiguate the question mark's use as the postfix quit-if-false operator from its use in a ternary conditional.
+    /// Whitespace is skipped until the next significant character is found.
+    fn question_mark(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Space | LexerEvent::Newline => self.reenter_without_yield(),
+
+            // Comments are skipped too, after which the search for the next significant character resumes.
+            LexerEvent::Hash => self.begin_comment(LexerState::LineComment, 1),
+            LexerEvent::Slash if self.does_next_token_match_string("/".to_owned()) => self.begin_comment(LexerState::LineComment, 2),
+            LexerEvent::Slash if self.does_next_token_match_string("*".to_owned()) => self.begin_comment(LexerState::BlockComment, 2),
+
+            // Only a postfix operator may be followed by the end of an expression, as in "x > 5?;"
+            LexerEvent::ExpressionEnder(_) | LexerEvent::EOS => self.transition_with_pop_and_put_back(LexerState::Empty, |_s| Some(ParserToken::QuestionMark), e),
+
+            // Anything else begins the first branch of a ternary conditional, as in "x > 5 ? a : b".
+            _ => self.transition_with_pop_and_put_back(LexerState::Empty, |_s| Some(ParserToken::TernaryOp), e),
+        }
+    }
+
+    /// NumberSuffix state transitions.
+    /// Letters immediately following a number name its unit: either a duration unit, as in 5min or 1.5h, 
+    /// or a unit of measure, as in 3.2m or 70°F. The suffix d instead marks an exact decimal, as in 12.50d.
+    fn number_suffix(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Letter(_) => self.reenter_with_push(e),
+
+            // Went too far - make a Decimal if the only letter is d and put the new character back.
+            _ if Decimal::parse_literal(&self.next_token).is_some() 
+              => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Decimal(s)), e),
+            // Went too far - make a Duration if the unit is known and put the new character back.
+            _ if temporal::parse_duration(&self.next_token).is_some() 
+              => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Duration(s)), e),
+            _ if units::parse_quantity(&self.next_token).is_some() 
+              => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::Quantity(s)), e),
+            _ => self.reject_literal(format!("Invalid number '{}': the suffix is neither d, a duration nor a unit", self.next_token), e)
+        }
+    }
+
+    /// DateTime state transitions.
+    /// Build an ISO-8601 date/time literal that follows an at-sign, such as @2024-01-05T10:00Z or @2024-01-05T12:00:00.5+02:00.
+    /// The literal ends at the first character that cannot be part of a date/time, such as whitespace.
+    fn date_time(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Digit(_) | LexerEvent::Letter(_) | LexerEvent::Sign(_) 
+            | LexerEvent::Period | LexerEvent::ExpressionEnder(':') => self.reenter_with_push(e),
+
+            // Went too far - make a DateTime if the literal is valid and put the new character back.
+            _ if temporal::parse_date_time(&self.next_token).is_some() 
+              => self.transition_with_pop_and_put_back(LexerState::Empty, |s| Some(ParserToken::DateTime(s)), e),
+            _ => self.reject_literal(format!("Invalid date/time '@{}'", self.next_token), e)
+        }
+    }
+
+    /// LineComment state transitions.
+    /// Skip everything up to the end of the line, then return to the state in which the comment began.
+    fn line_comment(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Newline => {
+                let end = self.events.current_position() as usize - 1;
+                self.end_comment(end);
+                None
+            },
+            // The state in which the comment began must also see the end of the expression.
+            LexerEvent::EOS => {
+                let end = self.events.current_position() as usize;
+                self.end_comment(end);
+                self.events.put_back(e);
+                None
+            },
+            _ => self.reenter_without_yield()
+        }
+    }
+
+    /// BlockComment state transitions.
+    /// Skip everything, including newlines, up to an asterisk that may begin the closing */.
+    fn block_comment(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Multiplicative('*') => self.transition_without_yield(LexerState::BlockCommentStar),
+            LexerEvent::EOS => self.transition_to_error(e),
+            _ => self.reenter_without_yield()
+        }
+    }
+
+    /// BlockCommentStar state transitions.
+    /// An asterisk has been read inside a block comment. If a slash follows, the comment is over.
+    fn block_comment_star(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        match e {
+            LexerEvent::Slash => {
+                let end = self.events.current_position() as usize;
+                self.end_comment(end);
+                None
+            },
+            LexerEvent::Multiplicative('*') => self.reenter_without_yield(),
+            LexerEvent::EOS => self.transition_to_error(e),
+            _ => self.transition_without_yield(LexerState::BlockComment)
+        }
+    }
+
+    /// In the Error state, remain in this state regardless of the incoming LexerEvent, 
+    /// unless resynchronizing, in which case return to the Empty state at the next semicolon (which is put back, to be yielded)
+    /// or after the next blank line.
+    fn error(&mut self, e: LexerEvent) -> Option<ParserToken> {
+        if !self.resynchronize {
+            return None;
+        }
+        match e {
+            LexerEvent::ExpressionEnder(';') => {
+                self.clear_error();
+                self.transition_with_put_back(LexerState::Empty, e)
+            },
+            LexerEvent::Newline if self.newlines_skipped > 0 => {
+                self.clear_error();
+                self.transition_without_yield(LexerState::Empty)
+            },
+            LexerEvent::Newline => {
+                self.newlines_skipped += 1;
+                None
+            },
+            LexerEvent::Space => None,
+            _ => {
+                self.newlines_skipped = 0;
+                None
+            }
+        }
+    }
+
+    /// Begin skipping a comment, returning to the current state when it ends. 
+    /// If the comment opens with two characters (// or /*), the second is consumed here.
+    fn begin_comment(&mut self, comment_state: LexerState, opener_length: usize) -> Option<ParserToken> {
+        for _ in 1..opener_length {
+            self.events.next();
+            if self.token_start.is_some() {
+                self.trailing_whitespace += 1;
+            }
+        }
+        self.comment_start = self.events.current_position() as usize - opener_length;
+        self.state_after_comment = self.current_state;
+        self.transition_without_yield(comment_state)
+    }
+
+    /// Finish skipping a comment that ends just before the given character offset, and return to the state it began in.
+    /// Unless a token was being built when the comment began, the comment is not the start of the next token.
+    fn end_comment(&mut self, end: usize) {
+        self.comments.push(Span::new(self.comment_start, end));
+        if self.token_start == Some(self.comment_start) {
+            self.token_start = None;
+            self.trailing_whitespace = 0;
+        }
+        self.current_state = self.state_after_comment;
+    }
+
+    /// Discard the partial token and the position of the error, in preparation for leaving the Error state.
+    fn clear_error(&mut self) {
+        self.yield_string();
+        self.token_start = None;
+        self.trailing_whitespace = 0;
+        self.newlines_skipped = 0;
+        self.position_with_error = -1;
     }
 
     //..................................................................
@@ -623,9 +954,10 @@ impl<'e> Iterator for Lexer<'e> {
     type Item = ParserToken;
     fn next(&mut self) -> Option<ParserToken> {
         if let Some(_) = self.token_buffer {
+            self.last_span = self.buffered_span;
             return mem::replace(&mut self.token_buffer, None)
         }
-        if self.has_error() {
+        if self.has_error() && !self.resynchronize {
             return None;
         }
         #[allow(irrefutable_let_patterns)]
@@ -633,10 +965,18 @@ impl<'e> Iterator for Lexer<'e> {
             match event_option {
                 Some(event) =>
                 {  
+                    let state_before_event = self.current_state;
                     let previous_state = self.current_state.to_string();
                     let event_string = event.to_string();
                     // Record the character position here for logging, before any push_back occurs which will decrement the position.
                     let char_position = self.events.current_position();
+                    let is_whitespace = match event {
+                        LexerEvent::Space | LexerEvent::Newline => true,
+                        _ => false
+                    };
+                    if self.token_start.is_none() && !is_whitespace && event != LexerEvent::BOS && event != LexerEvent::EOS {
+                        self.token_start = Some(char_position as usize - 1);
+                    }
                     let possible_token = match self.current_state {
                         LexerState::Start               => self.start(event),
                         LexerState::Goal         => return self.goal(event), // Reached the goal! Must return.
@@ -650,12 +990,21 @@ impl<'e> Iterator for Lexer<'e> {
                         LexerState::ExpectRegex         => self.expect_regex(event),
                         LexerState::Regex               => self.regex(event),
                         LexerState::RegexEscape         => self.regex_escape(event),
+                        LexerState::QuotedRegex         => self.quoted_regex(event),
+                        LexerState::QuotedRegexEscape   => self.quoted_regex_escape(event),
                         LexerState::IntegerDigits       => self.integer_digits(event),
                         LexerState::FractionalDigits    => self.fractional_digits(event),
                         LexerState::ExponentSign        => self.exponent_sign(event),
                         LexerState::ExponentDigits      => self.exponent_digits(event),
                         LexerState::Power               => self.power(event),
                         LexerState::Exclamation         => self.exclamation(event),
+                        LexerState::QuestionMark        => self.question_mark(event),
+                        LexerState::NumberSuffix        => self.number_suffix(event),
+                        LexerState::DateTime            => self.date_time(event),
+                        LexerState::LineComment         => self.line_comment(event),
+                        LexerState::BlockComment        => self.block_comment(event),
+                        LexerState::BlockCommentStar    => self.block_comment_star(event),
+                        LexerState::Range               => self.range(event),
                         LexerState::Error               => self.error(event)
                     };
 
@@ -670,9 +1019,36 @@ impl<'e> Iterator for Lexer<'e> {
                     if must_log {
                         self.log_append(char_position, event_string, previous_state, &possible_token);
                     }
+                    // An event that was put back will be read again, so it is not yet part of any token.
+                    let was_put_back = self.events.current_position() < char_position;
+                    // The characters of a comment count as whitespace.
+                    let is_in_comment = state_before_event.is_comment() || self.current_state.is_comment();
+                    if self.token_start.is_some() && !was_put_back && event != LexerEvent::EOS {
+                        self.trailing_whitespace = if is_whitespace || is_in_comment { self.trailing_whitespace + 1 } else { 0 };
+                    }
                     match possible_token {
-                        Some(ParserToken::Error(err)) => return Some(ParserToken::Error(err)),
-                        Some(token) => return Some(token),
+                        Some(ParserToken::Error(err)) => {
+                            self.last_span = err.span();
+                            return Some(ParserToken::Error(err))
+                        },
+                        Some(token) => {
+                            let end = match (was_put_back, event) {
+                                (false, _) => self.events.current_position() as usize,
+                                (true, LexerEvent::EOS) => char_position as usize,
+                                (true, _) => char_position as usize - 1
+                            } - self.trailing_whitespace;
+                            let start = self.token_start.take().unwrap_or(end);
+                            self.trailing_whitespace = 0;
+                            if self.token_buffer.is_some() {
+                                // The last character read is the buffered token.
+                                self.last_span = Span::new(start, end - 1);
+                                self.buffered_span = Span::new(end - 1, end);
+                            }
+                            else {
+                                self.last_span = Span::new(start, end);
+                            }
+                            return Some(token)
+                        },
                         None => continue
                     }
                 },
@@ -690,6 +1066,7 @@ impl<'e> Iterator for Lexer<'e> {
 mod tests {
     #[allow(unused_imports)]
     use super::*;
+    use crate::parser::chain_link::ChainLink;
 
     #[allow(unused_imports)]
     use spectral::prelude::*;
@@ -794,6 +1171,187 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Verify the Lexer can distinguish the postfix quit-if-false question mark from the ternary question mark.
+    fn question_mark() {
+        lexer_test_helper(
+            "x > 5 ? a : b; y? ;z ?", 
+            vec![
+                ParserToken::Identifier("x".to_string()),
+                ParserToken::RelationalOp(">".to_string()),
+                ParserToken::Integer("5".to_string()),
+                ParserToken::TernaryOp,
+                ParserToken::Identifier("a".to_string()),
+                ParserToken::Colon,
+                ParserToken::Identifier("b".to_string()),
+                ParserToken::Semicolon,
+                ParserToken::Identifier("y".to_string()),
+                ParserToken::QuestionMark,
+                ParserToken::Semicolon,
+                ParserToken::Identifier("z".to_string()),
+                ParserToken::QuestionMark
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer can parse null-safe property chains and the null-coalescing operator, without disturbing the ternary.
+    fn null_safe_navigation() {
+        lexer_test_helper(
+            "well?.casing?.depth ?? 0; x?y:z", 
+            vec![
+                ParserToken::PropertyChain(vec!["well".into(), ChainLink::NullSafe("casing".into()), ChainLink::NullSafe("depth".into())]),
+                ParserToken::LogicalOp("??".to_string()),
+                ParserToken::Integer("0".to_string()),
+                ParserToken::Semicolon,
+                ParserToken::Identifier("x".to_string()),
+                ParserToken::TernaryOp,
+                ParserToken::Identifier("y".to_string()),
+                ParserToken::Colon,
+                ParserToken::Identifier("z".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer treats in, between and and as keywords, even before a parenthesis, but not as parts of longer names.
+    fn membership_keywords() {
+        lexer_test_helper(
+            "x in (1, 2); inch between 1 and 3", 
+            vec![
+                ParserToken::Identifier("x".to_string()),
+                ParserToken::InOp,
+                ParserToken::OpenParenthesis,
+                ParserToken::Integer("1".to_string()),
+                ParserToken::Comma,
+                ParserToken::Integer("2".to_string()),
+                ParserToken::CloseParenthesis,
+                ParserToken::Semicolon,
+                ParserToken::Identifier("inch".to_string()),
+                ParserToken::BetweenOp,
+                ParserToken::Integer("1".to_string()),
+                ParserToken::LogicalOp("and".to_string()),
+                ParserToken::Integer("3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer ends a number or a name at the two periods of a range, rather than reading a decimal point or a property.
+    fn range_patterns() {
+        lexer_test_helper(
+            "100..500, low..high, 1.5..2", 
+            vec![
+                ParserToken::Integer("100".to_string()),
+                ParserToken::RangeOp,
+                ParserToken::Integer("500".to_string()),
+                ParserToken::Comma,
+                ParserToken::Identifier("low".to_string()),
+                ParserToken::RangeOp,
+                ParserToken::Identifier("high".to_string()),
+                ParserToken::Comma,
+                ParserToken::Rational("1.5".to_string()),
+                ParserToken::RangeOp,
+                ParserToken::Integer("2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer can parse the braces and key: value pairs of an object literal.
+    fn object_literal() {
+        lexer_test_helper(
+            r#"{level: 2, "msg": s.id}"#, 
+            vec![
+                ParserToken::OpenBrace,
+                ParserToken::Identifier("level".to_string()),
+                ParserToken::Colon,
+                ParserToken::Integer("2".to_string()),
+                ParserToken::Comma,
+                ParserToken::StringLiteral("msg".to_string()),
+                ParserToken::Colon,
+                ParserToken::new_property_chain(&"s.id".to_string()),
+                ParserToken::CloseBrace
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer can parse date/time and duration literals.
+    fn temporal_literals() {
+        lexer_test_helper(
+            "@2024-01-05T10:00Z - 1.5h < t + 30s", 
+            vec![
+                ParserToken::DateTime("2024-01-05T10:00Z".to_string()),
+                ParserToken::AdditiveOp("-".to_string()),
+                ParserToken::Duration("1.5h".to_string()),
+                ParserToken::RelationalOp("<".to_string()),
+                ParserToken::Identifier("t".to_string()),
+                ParserToken::AdditiveOp("+".to_string()),
+                ParserToken::Duration("30s".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer can parse a number with an attached unit of measure, but leaves a separate unit name as an identifier.
+    fn quantity_literals() {
+        lexer_test_helper(
+            "3.2m + 70°F - 15 psi", 
+            vec![
+                ParserToken::Quantity("3.2m".to_string()),
+                ParserToken::AdditiveOp("+".to_string()),
+                ParserToken::Quantity("70°F".to_string()),
+                ParserToken::AdditiveOp("-".to_string()),
+                ParserToken::Integer("15".to_string()),
+                ParserToken::Identifier("psi".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer makes a decimal of a number followed by d, but not of a number followed by a unit such as day.
+    fn decimal_literals() {
+        lexer_test_helper(
+            "12.50d * 3d + 1day", 
+            vec![
+                ParserToken::Decimal("12.50d".to_string()),
+                ParserToken::MultiplicativeOp("*".to_string()),
+                ParserToken::Decimal("3d".to_string()),
+                ParserToken::AdditiveOp("+".to_string()),
+                ParserToken::Duration("1day".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify the Lexer distinguishes the arrow of a lambda from assignment and comparison.
+    fn lambda_arrow() {
+        lexer_test_helper(
+            "f = x => x >= 1", 
+            vec![
+                ParserToken::Identifier("f".to_string()),
+                ParserToken::AssignmentOp("=".to_string()),
+                ParserToken::Identifier("x".to_string()),
+                ParserToken::Arrow,
+                ParserToken::Identifier("x".to_string()),
+                ParserToken::RelationalOp(">=".to_string()),
+                ParserToken::Integer("1".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify that malformed date/time literals and unknown duration units are lexical errors.
+    fn bad_temporal_literals() {
+        for expression in vec!["@2024-13-05", "@", "5parsecs"].iter() {
+            let expression_string = expression.to_string();
+            let lexer = Lexer::new(&expression_string);
+            let has_error = lexer.into_iter().any(|token| match token { ParserToken::Error(_) => true, _ => false });
+            asserting(expression).that(&has_error).is_equal_to(true);
+        }
+    }
+
     #[test]
     /// Verify the Lexer can parse regular expressions and match operators.
     fn regex_and_match_op() {
@@ -811,6 +1369,25 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Verify the capture operator and a regex delimited by double quotes, whose backslashes are kept except before a quote.
+    fn capture_op_and_quoted_regex() {
+        lexer_test_helper(
+            r#"m = serial ~> "^(\w+)-(\d+)$" || s ~ "say \"hi\"""#, 
+            vec![
+                ParserToken::Identifier("m".to_string()),
+                ParserToken::AssignmentOp("=".to_string()),
+                ParserToken::Identifier("serial".to_string()),
+                ParserToken::MatchOp("~>".to_string()),
+                ParserToken::Regex(r"^(\w+)-(\d+)$".to_string()),
+                ParserToken::LogicalOp("||".to_string()),
+                ParserToken::Identifier("s".to_string()),
+                ParserToken::MatchOp("~".to_string()),
+                ParserToken::Regex(r#"say "hi""#.to_string())
+            ]
+        );
+    }
+
     #[test]
     /// Verify the Lexer can parse integers, floating point and numbers using exponential notation.
     fn numbers() {
@@ -878,7 +1455,7 @@ mod tests {
     #[test]
     /// Verify that an illegal character does not panic, but returns an Error
     fn illegal_character() {
-        let expression = "5 + #3".to_string();
+        let expression = "5 + `3".to_string();
         let tokenizer = Lexer::new(&expression);
         let mut actual_tokens = Vec::new();
         actual_tokens.extend(tokenizer);
@@ -905,6 +1482,101 @@ mod tests {
         assert_that!(actual_tokens.len()).is_equal_to(0);
     }
 
+    #[test]
+    /// Verify that the span of each token covers its text, without the whitespace around it.
+    fn token_spans() {
+        let expression = "total += n! * 2.5  ? \"a b\" : max (x, y²)".to_string();
+        let characters: Vec<char> = expression.chars().collect();
+        let actual_text: Vec<String> = Lexer::new(&expression).with_spans().iter()
+            .map(|(_, span)| characters[span.start..span.end].iter().collect())
+            .collect();
+        let expected_text = vec!["total", "+=", "n", "!", "*", "2.5", "?", "\"a b\"", ":", "max", "(", "x", ",", "y", "²", ")"];
+        asserting("token text").that(&actual_text).is_equal_to(expected_text.iter().map(|text| text.to_string()).collect::<Vec<String>>());
+    }
+
+    #[test]
+    /// Verify that a lexical error is given the span of the character that could not be read.
+    fn error_span() {
+        let expression = "5 + `3".to_string();
+        let mut lexer = Lexer::new(&expression);
+        while let Some(token) = lexer.next() {
+            if let ParserToken::Error(err) = token {
+                asserting("message").that(&err.message()).is_equal_to("Unexpected character '`'".to_string());
+            }
+        }
+        asserting("span").that(&lexer.span()).is_equal_to(Span::new(4, 5));
+    }
+
+    #[test]
+    /// Verify that a date/time or number literal that was read but is not valid is reported as such, spanning the whole literal.
+    fn invalid_literal_errors() {
+        let cases = vec![
+            ("x = @2020-13-45 + 1", "Invalid date/time '@2020-13-45'", Span::new(4, 15)),
+            ("@2020-13-45", "Invalid date/time '@2020-13-45'", Span::new(0, 11)),
+            ("y = 1.2.3d * 2", "Invalid number '1.2.3d'", Span::new(4, 10)),
+            ("1.2.3", "Invalid number '1.2.3'", Span::new(0, 5)),
+            ("5xyz", "Invalid number '5xyz': the suffix is neither d, a duration nor a unit", Span::new(0, 4))
+        ];
+        for (expression, expected_message, expected_span) in cases {
+            let expression = expression.to_string();
+            let mut lexer = Lexer::new(&expression);
+            let error = lexer.by_ref().find_map(|token| match token { ParserToken::Error(err) => Some(err), _ => None }).unwrap();
+            asserting(&format!("message for {}", expression)).that(&error.message()).is_equal_to(expected_message.to_string());
+            asserting(&format!("span for {}", expression)).that(&lexer.span()).is_equal_to(expected_span);
+        }
+    }
+
+    #[test]
+    /// Verify that a resynchronizing Lexer resumes after an error at the next semicolon or blank line, reporting each error.
+    fn resynchronize_after_errors() {
+        let expression = "a = `1 + 2; b = 3\nc = 4 `\n   \nd = @2024-13 ; e".to_string();
+        let characters: Vec<char> = expression.chars().collect();
+        let mut lexer = Lexer::new(&expression);
+        lexer.resynchronize = true;
+        let actual_text: Vec<String> = lexer.with_spans().iter()
+            .map(|(token, span)| match token {
+                ParserToken::Error(_) => format!("error at {}", span.start),
+                _ => characters[span.start..span.end].iter().collect()
+            })
+            .collect();
+        let expected_text = vec!["a", "=", "error at 4", ";", "b", "=", "3", "c", "=", "4", "error at 24", "d", "=", "error at 34", ";", "e"];
+        asserting("tokens").that(&actual_text).is_equal_to(expected_text.iter().map(|text| text.to_string()).collect::<Vec<String>>());
+    }
+
+    #[test]
+    /// Verify that line and block comments are skipped like whitespace, but not inside strings and regexes.
+    fn comments() {
+        let expression = "x = 1 # set x\ny = 2 // and y\nz = /* inline */ 3 /* spans\nlines **/ / 4 ~ /a#b/ + \"// text\"".to_string();
+        let characters: Vec<char> = expression.chars().collect();
+        let mut lexer = Lexer::new(&expression);
+        let actual_text: Vec<String> = lexer.by_ref().collect::<Vec<ParserToken>>().iter().map(|token| token.to_string()).collect();
+        let expected_text = vec!["x", "=", "1", "y", "=", "2", "z", "=", "3", "/", "4", "~", "a#b", "+", "// text"];
+        asserting("tokens").that(&actual_text).is_equal_to(expected_text.iter().map(|text| text.to_string()).collect::<Vec<String>>());
+        let comment_text: Vec<String> = lexer.comments().iter().map(|span| characters[span.start..span.end].iter().collect()).collect();
+        asserting("comments").that(&comment_text).is_equal_to(vec![
+            "# set x".to_string(), "// and y".to_string(), "/* inline */".to_string(), "/* spans\nlines **/".to_string()
+        ]);
+        
+        let spanned: Vec<String> = Lexer::new(&expression).with_spans().iter().skip(7).take(3)
+            .map(|(_, span)| characters[span.start..span.end].iter().collect())
+            .collect();
+        asserting("spans skip comments").that(&spanned).is_equal_to(vec!["=".to_string(), "3".to_string(), "/".to_string()]);
+
+        // A hash is never an operator, so what follows it is a comment even where an operand is expected.
+        let hash_after_operator = "5 + #3".to_string();
+        let mut lexer = Lexer::new(&hash_after_operator);
+        let actual_text: Vec<String> = lexer.by_ref().map(|token| token.to_string()).collect();
+        asserting("tokens before hash").that(&actual_text).is_equal_to(vec!["5".to_string(), "+".to_string()]);
+        asserting("hash comment").that(&lexer.comments().to_vec()).is_equal_to(vec![Span::new(4, 6)]);
+
+        lexer_test_helper("ok = x > 5? // positive", vec![ParserToken::QuestionMark]);
+        lexer_test_helper("x ? /* yes */ 1 : 2", vec![ParserToken::TernaryOp]);
+
+        let unterminated = "x = 1 /* never closed".to_string();
+        let has_error = Lexer::new(&unterminated).any(|token| match token { ParserToken::Error(_) => true, _ => false });
+        asserting("unterminated block comment").that(&has_error).is_equal_to(true);
+    }
+
     //..................................................................
 
     // Test helper methods
@@ -2,6 +2,9 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
 
+use super::span::Span;
+use crate::parser::chain_link::ChainLink;
+
 //..................................................................
 
 /// Error details for the Lexer
@@ -9,9 +12,38 @@ use std::fmt::Result;
 pub struct LexerError {
     pub error_position: i32,
     pub error_line: i32,
+
+    /// The character that the Lexer could not accept, or None if the expression ended too soon.
+    pub unexpected: Option<char>,
+
+    /// If a whole literal was read but is not valid, as with a date that has no such month, 
+    /// a message describing it and the position where the literal starts.
+    pub invalid_literal: Option<(String, usize)>,
     pub log: String
 }
 
+impl LexerError {
+    /// Span of the unexpected character, or an empty span at the end of the expression if it ended too soon.
+    /// An invalid literal is spanned in full.
+    pub fn span(&self) -> Span {
+        let position = self.error_position.max(0) as usize;
+        match (&self.invalid_literal, self.unexpected) {
+            (Some((_, start)), _) => Span::new(*start, position),
+            (None, Some(_)) => Span::new(position.saturating_sub(1), position),
+            (None, None) => Span::new(position, position)
+        }
+    }
+
+    /// A brief description of the error, without the log.
+    pub fn message(&self) -> String {
+        match (&self.invalid_literal, self.unexpected) {
+            (Some((message, _)), _) => message.clone(),
+            (None, Some(c)) => format!("Unexpected character '{}'", c),
+            (None, None) => "Unexpected end of expression".to_string()
+        }
+    }
+}
+
 //..................................................................
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -25,34 +57,45 @@ pub struct LexerError {
 pub enum ParserToken {
     StringLiteral(String),
     Identifier(String),
-    PropertyChain(Vec<String>),
+    PropertyChain(Vec<ChainLink>),
     Function(String),
     LogicalNotOp,
     FactorialOp,
     Integer(String),
     Rational(String),
+    Decimal(String), // 12.50d (an exact decimal)
     Regex(String),
+    DateTime(String), // @2024-01-05T10:00Z (the at-sign is not stored)
+    Duration(String), // 5min
+    Quantity(String), // 3.2m (a number with a unit of measure)
     OpenParenthesis,
     CloseParenthesis,
     Comma,
-    QuestionMark,
+    QuestionMark, // Postfix quit-if-false operator
+    TernaryOp, // The ? in a ternary conditional: test ? a : b
     Colon,
     Semicolon,
     OpenBracket,
     CloseBracket,
+    OpenBrace,
+    CloseBrace,
     ExponentiationOp,
     PowerOp(String),
     MemberOp,
-    MatchOp(String), // ~ !~
+    MatchOp(String), // ~ !~ ~>
     AssignmentOp(String), // = += -= *= %= /= &&= ||=
     MultiplicativeOp(String), // * / %
     SignOp(String), // + -
     AdditiveOp(String), // + -  (note the conflict with SignOp! Parser may have to change one into the other based on context.)
     IncrementDecrementOp(String), // ++ --
     RelationalOp(String), // < <= ≤ > >= ≥ != ≠
-    EqualityOp(String), // ==
-    LogicalOp(String), // && ||
+    EqualityOp(String), // == (and <>, which only the spreadsheet dialect accepts)
+    LogicalOp(String), // && || and or
     SquareRootOp, // √
+    Arrow, // => that separates the parameters of a lambda from its body
+    InOp, // The keyword in, as in code in [3, 7, 9]
+    BetweenOp, // The keyword between, as in x between 10 and 20
+    RangeOp, // .. between the bounds of a pattern in a match expression, as in 100..500
     Error(LexerError)
 }
 
@@ -68,15 +111,22 @@ impl ParserToken {
             ParserToken::FactorialOp => "FactorialOp",
             ParserToken::Integer(_) => "Integer",
             ParserToken::Rational(_) => "Rational",
+            ParserToken::Decimal(_) => "Decimal",
             ParserToken::Regex(_) => "Regex",
+            ParserToken::DateTime(_) => "DateTime",
+            ParserToken::Duration(_) => "Duration",
+            ParserToken::Quantity(_) => "Quantity",
             ParserToken::OpenParenthesis => "OpenParenthesis",
             ParserToken::CloseParenthesis => "CloseParenthesis",
             ParserToken::Comma => "Comma",
             ParserToken::QuestionMark => "QuestionMark",
+            ParserToken::TernaryOp => "TernaryOp",
             ParserToken::Colon => "Colon",
             ParserToken::Semicolon => "Semicolon",
             ParserToken::OpenBracket => "OpenBracket",
             ParserToken::CloseBracket => "CloseBracket",
+            ParserToken::OpenBrace => "OpenBrace",
+            ParserToken::CloseBrace => "CloseBrace",
             ParserToken::ExponentiationOp => "ExponentiationOp",
             ParserToken::PowerOp(_) => "PowerOp",
             ParserToken::MemberOp => "MemberOp",
@@ -90,6 +140,10 @@ impl ParserToken {
             ParserToken::EqualityOp(_) => "EqualityOp",
             ParserToken::LogicalOp(_) => "LogicalOp", 
             ParserToken::SquareRootOp => "SquareRootOp", 
+            ParserToken::Arrow => "Arrow", 
+            ParserToken::InOp => "InOp",
+            ParserToken::BetweenOp => "BetweenOp",
+            ParserToken::RangeOp => "RangeOp",
             ParserToken::Error(_) => "Error", 
         }
     }
@@ -102,7 +156,7 @@ impl ParserToken {
             ParserToken::StringLiteral(s) => s,
             ParserToken::Identifier(s) => s,
             ParserToken::PropertyChain(vec) => {
-                 temp_string.push_str(&vec.join("."));
+                 temp_string.push_str(&ChainLink::join(vec));
                  &temp_string
             },
             ParserToken::Function(s) => s,
@@ -110,15 +164,26 @@ impl ParserToken {
             ParserToken::FactorialOp => "!",
             ParserToken::Integer(s) => s,
             ParserToken::Rational(s) => s,
+            ParserToken::Decimal(s) => s,
             ParserToken::Regex(s) => s,
+            ParserToken::DateTime(s) => {
+                 temp_string.push('@');
+                 temp_string.push_str(s);
+                 &temp_string
+            },
+            ParserToken::Duration(s) => s,
+            ParserToken::Quantity(s) => s,
             ParserToken::OpenParenthesis => "(",
             ParserToken::CloseParenthesis => ")",
             ParserToken::Comma => ",",
             ParserToken::QuestionMark => "?",
+            ParserToken::TernaryOp => "?",
             ParserToken::Colon => ":",
             ParserToken::Semicolon => ";",
             ParserToken::OpenBracket => "[",
             ParserToken::CloseBracket => "]",
+            ParserToken::OpenBrace => "{",
+            ParserToken::CloseBrace => "}",
             ParserToken::ExponentiationOp => "^",
             ParserToken::PowerOp(s) => s,
             ParserToken::MemberOp => ".",
@@ -132,6 +197,10 @@ impl ParserToken {
             ParserToken::EqualityOp(s) => s,
             ParserToken::LogicalOp(s) => s,
             ParserToken::SquareRootOp => "√",
+            ParserToken::Arrow => "=>",
+            ParserToken::InOp => "in",
+            ParserToken::BetweenOp => "between",
+            ParserToken::RangeOp => "..",
             ParserToken::Error(err) => {
                 error_message = format!("Error!\nLine {}, position {}, Log:\n{}", err.error_line, err.error_position, err.log);
                 &error_message
@@ -141,8 +210,26 @@ impl ParserToken {
         return_val.to_string()
     }
 
+    /// Split a string like person.address.zip into a PropertyChain.
+    /// A null-safe step like well?.casing makes the property that follows it a ChainLink::NullSafe.
     pub fn new_property_chain(chain_as_string : &String) -> Self {
-        ParserToken::PropertyChain(chain_as_string.split(".").map(|s| s.to_string()).collect())
+        ParserToken::PropertyChain(ChainLink::parse_chain(chain_as_string))
+    }
+
+    /// The token for a word that is an operator rather than a name, such as in, between or the logical and, or and not, or None.
+    pub fn keyword(word: &str) -> Option<Self> {
+        match word {
+            "in" => Some(ParserToken::InOp),
+            "between" => Some(ParserToken::BetweenOp),
+            "and" | "or" => Some(ParserToken::LogicalOp(word.to_string())),
+            "not" => Some(ParserToken::LogicalNotOp),
+            _ => None
+        }
+    }
+
+    /// The token for a word read by the Lexer: a keyword, an Identifier, or a PropertyChain if the word has periods.
+    pub fn word(word: String) -> Self {
+        ParserToken::keyword(&word).unwrap_or_else(|| ParserToken::Identifier(word).to_property_chain())
     }
 
     pub fn to_property_chain(&self) -> Self {
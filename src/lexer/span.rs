@@ -0,0 +1,73 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+use serde::{Serialize, Deserialize};
+
+/// The part of the source text from which a token was read, as character (not byte) offsets.
+/// The end is exclusive, so a span of a single character at offset 4 runs from 4 to 5.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end: end.max(start) }
+    }
+
+    /// The smallest span that covers both this span and the other.
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// Number of characters in the span.
+    pub fn len(&self) -> usize { self.end - self.start }
+
+    pub fn is_empty(&self) -> bool { self.start == self.end }
+
+    /// Shift the span later in the text by the given number of characters,
+    /// as when the text it was read from is embedded in a larger text.
+    pub fn offset(&self, characters: usize) -> Span {
+        Span::new(self.start + characters, self.end + characters)
+    }
+
+    /// The one-based line and column (counted in characters) in the source text where the span starts.
+    pub fn line_and_column(&self, source: &str) -> (usize, usize) {
+        let mut line = 1;
+        let mut column = 1;
+        for c in source.chars().take(self.start) {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            }
+            else {
+                column += 1;
+            }
+        }
+        (line, column)
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    #[test]
+    fn span_line_and_column() {
+        let source = "a = 1;\nb = a +\n  ²";
+        asserting("first line").that(&Span::new(4, 5).line_and_column(source)).is_equal_to((1, 5));
+        asserting("start of second line").that(&Span::new(7, 8).line_and_column(source)).is_equal_to((2, 1));
+        asserting("characters, not bytes").that(&Span::new(17, 18).line_and_column(source)).is_equal_to((3, 3));
+    }
+}
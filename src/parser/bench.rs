@@ -0,0 +1,35 @@
+use std::time::{Duration, Instant};
+
+#[allow(unused_imports)]
+use spectral::prelude::*;
+
+//..................................................................
+
+// Timing shared by the ignored tests that compare the speed of two ways of executing the same rules.
+// Run them with `cargo test --release -- --ignored`; the times appear in the message of a failed assertion.
+
+/// Time the given number of calls of a closure.
+pub fn time_runs<F>(runs: usize, mut run: F) -> Duration
+where F: FnMut() {
+    let start = Instant::now();
+    for _ in 0..runs {
+        run();
+    }
+    start.elapsed()
+}
+
+/// Assert that the given number of calls of the faster closure take less time than the same number of calls of the slower one.
+/// The two are timed in alternating rounds and the best round of each is compared, so that a pause in one round
+/// or a cache warmed by the other closure does not decide the outcome.
+pub fn assert_faster<S, F>(description: &str, runs: usize, mut slower: S, mut faster: F)
+where S: FnMut(), F: FnMut() {
+    let rounds = 5;
+    let mut slower_time = Duration::MAX;
+    let mut faster_time = Duration::MAX;
+    for _ in 0..rounds {
+        slower_time = slower_time.min(time_runs(runs / rounds, &mut slower));
+        faster_time = faster_time.min(time_runs(runs / rounds, &mut faster));
+    }
+    let message = format!("{} (best of {} rounds of {} runs: {:?} versus {:?})", description, rounds, runs / rounds, faster_time, slower_time);
+    asserting(&message).that(&(faster_time < slower_time)).is_equal_to(true);
+}
@@ -0,0 +1,410 @@
+use std::borrow::Cow;
+
+use super::shy_token::{ShyToken, ShyValue, SpannedToken};
+use super::chain_link::ChainLink;
+use super::shy_operator::ShyOperator;
+use super::expression::Expression;
+use super::execution_context::ExecutionContext;
+use super::user_function::UserFunction;
+use super::frame;
+use crate::lexer::span::Span;
+
+//..................................................................
+
+// Bytecode is the executable form of an Expression, assembled from its postfix order (or its bound postfix order)
+// the first time it is executed.
+//
+//   - Instructions are small and Copy. Values, variable names, property chains and function definitions
+//     are held in pools that the instructions index, so executing an instruction never clones it.
+//   - The stack borrows constants from the pool, so a constant is only cloned if an operator that works upon
+//     owned values needs it. Values loaded from the context are copied, since a later instruction may change the variable.
+//   - A variable or property chain followed by Load becomes a single instruction, which looks up the variable
+//     without first pushing its name onto the stack.
+//   - Unary and binary operators replace their operands on the stack with their result, in place.
+//   - The stack is allocated once per execution, with room for the most values the instructions could push.
+//   - Each instruction keeps the span of the source text of its token, so that an error can be traced to the 
+//     instruction that first produced it. Only when asked to locate errors does the virtual machine watch for them.
+//
+// Expression::interpret, which executes the postfix tokens one by one, is kept as the reference implementation.
+// The two must agree on results, on changes to the context and on applicability.
+
+/// An instruction for the virtual machine that executes Bytecode.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Instruction {
+    /// Push the value in the constant pool.
+    Constant(usize),
+    /// Push the value of the variable whose name is in the name pool.
+    LoadName(usize),
+    /// Push the value of the property chain in the chain pool.
+    LoadChain(usize),
+    /// Push the value of the variable in the slot of the context's frame.
+    LoadSlot(usize),
+    /// Push the value of the property chain in the chain pool, whose first variable is in the slot.
+    LoadChainSlot(usize, usize),
+    /// Store the value on top of the stack in the slot, leaving it there.
+    StoreSlot(usize),
+    /// Replace the operand on top of the stack with the result of the operator.
+    Unary(ShyOperator),
+    /// Replace the two operands on top of the stack with the result of the operator.
+    Binary(ShyOperator),
+    /// Apply an operator that takes some other number of operands.
+    Operate(ShyOperator),
+    /// Apply a branching operator, which may continue at another instruction.
+    Branch(ShyOperator, usize),
+    /// Stop if the value on top of the stack is falsey, marking the context as inapplicable.
+    QuitIfFalse,
+    /// Register the function in the function pool with the context.
+    DefineFunction(usize),
+    /// Stands for a token that cannot be executed.
+    Invalid
+}
+
+/// Instructions and the pools they refer to.
+#[derive(Clone, Debug)]
+pub struct Bytecode {
+    pub instructions: Vec<Instruction>,
+    constants: Vec<ShyValue>,
+    names: Vec<String>,
+    chains: Vec<Vec<ChainLink>>,
+    functions: Vec<UserFunction>,
+
+    /// Span of the source text for each instruction, read only to locate an error, 
+    /// and so kept apart from the instructions to keep them small.
+    spans: Vec<Span>
+}
+
+impl Bytecode {
+    /// Assemble the tokens of a postfix order into bytecode, adjusting branch targets for the tokens combined.
+    /// The span of each token is kept for the instruction made from it.
+    pub fn assemble(postfix_order: &[SpannedToken]) -> Self {
+        let landings = frame::landings(postfix_order);
+        let mut code = Bytecode { 
+            instructions: Vec::with_capacity(postfix_order.len()), 
+            constants: Vec::new(), names: Vec::new(), chains: Vec::new(), functions: Vec::new(), 
+            spans: Vec::with_capacity(postfix_order.len())
+        };
+        // The position of the instruction for each token. A Load combined with the preceding variable gets the position of the instruction that follows.
+        let mut new_positions = Vec::with_capacity(postfix_order.len() + 1);
+        let mut position = 0;
+        while position < postfix_order.len() {
+            new_positions.push(code.instructions.len());
+            let loaded = position + 1 < postfix_order.len()
+                && postfix_order[position + 1].token == ShyToken::Operator(ShyOperator::Load)
+                && !landings.contains(&(position + 1));
+            let instruction = match &postfix_order[position].token {
+                ShyToken::Value(ShyValue::Variable(name)) if loaded => Instruction::LoadName(code.add_name(name)),
+                ShyToken::Value(ShyValue::PropertyChain(chain)) if loaded => Instruction::LoadChain(code.add_chain(chain)),
+                ShyToken::Value(value) => Instruction::Constant(code.add_constant(value)),
+                ShyToken::Operator(ShyOperator::QuitIfFalse) => Instruction::QuitIfFalse,
+                ShyToken::Operator(op) => match op.arguments() {
+                    1 => Instruction::Unary(*op),
+                    2 => Instruction::Binary(*op),
+                    _ => Instruction::Operate(*op)
+                },
+                ShyToken::Branch(op, target) => Instruction::Branch(*op, *target),
+                ShyToken::FunctionDefinition(function) => {
+                    code.functions.push(function.clone());
+                    Instruction::DefineFunction(code.functions.len() - 1)
+                },
+                ShyToken::LoadSlot(slot) => Instruction::LoadSlot(*slot),
+                ShyToken::LoadChain(slot, chain) => Instruction::LoadChainSlot(*slot, code.add_chain(chain)),
+                ShyToken::StoreSlot(slot) => Instruction::StoreSlot(*slot),
+                _ => Instruction::Invalid
+            };
+            code.instructions.push(instruction);
+            code.spans.push(postfix_order[position].span);
+            if matches!(instruction, Instruction::LoadName(_) | Instruction::LoadChain(_)) {
+                new_positions.push(code.instructions.len());
+                position += 1;
+            }
+            position += 1;
+        }
+        new_positions.push(code.instructions.len());
+        for instruction in code.instructions.iter_mut() {
+            if let Instruction::Branch(op, target) = *instruction {
+                *instruction = Instruction::Branch(op, new_positions[target]);
+            }
+        }
+        code
+    }
+
+    fn add_constant(&mut self, value: &ShyValue) -> usize {
+        self.constants.push(value.clone());
+        self.constants.len() - 1
+    }
+
+    fn add_name(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|pooled| pooled == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    fn add_chain(&mut self, chain: &[ChainLink]) -> usize {
+        match self.chains.iter().position(|pooled| pooled.as_slice() == chain) {
+            Some(index) => index,
+            None => {
+                self.chains.push(chain.to_vec());
+                self.chains.len() - 1
+            }
+        }
+    }
+
+    /// Execute the bytecode against the context, as Expression::exec does.
+    /// If locating_errors, also return the span of the instruction that first left an error on top of the stack, 
+    /// if there was one and its span is known.
+    /// (User functions recurse through this method, so it does the work itself rather than adding a frame to the stack for each call.)
+    pub fn run<'a>(&self, context: &mut ExecutionContext<'a>, locating_errors: bool) -> (Result<ShyValue, String>, Option<Span>) {
+        let mut error_position = None;
+        // No instruction pushes more than one value, so the stack never needs to grow.
+        let mut stack: Vec<Cow<ShyValue>> = Vec::with_capacity(self.instructions.len());
+        // Holds the values moved off the stack for Expression::branch and Expression::operate, which work upon owned values.
+        let mut owned: Vec<ShyValue> = Vec::new();
+        let unused: ShyValue = 0.into();
+        context.is_applicable = true;
+        let mut position = 0;
+        while position < self.instructions.len() {
+            let instruction_position = position;
+            let instruction = self.instructions[position];
+            position += 1;
+            match instruction {
+                Instruction::Constant(index) => stack.push(Cow::Borrowed(&self.constants[index])),
+                Instruction::LoadName(index) => {
+                    let name = &self.names[index];
+                    let value = match context.load(name) {
+                        Some(value) => value,
+                        None => ShyValue::no_such_variable(name)
+                    };
+                    stack.push(Cow::Owned(value));
+                },
+                Instruction::LoadChain(index) => {
+                    let chain = &self.chains[index];
+                    let value = match context.load_chain(chain) {
+                        Some(value) => value,
+                        None => ShyValue::bad_property_chain(chain)
+                    };
+                    stack.push(Cow::Owned(value));
+                },
+                Instruction::LoadSlot(slot) => {
+                    let value = match context.load_slot(slot) {
+                        Some(value) => value,
+                        None => ShyValue::no_such_variable(context.variables.name_of(slot))
+                    };
+                    stack.push(Cow::Owned(value));
+                },
+                Instruction::LoadChainSlot(slot, index) => {
+                    let chain = &self.chains[index];
+                    let value = match context.load_chain_slot(slot, chain) {
+                        Some(value) => value,
+                        None => ShyValue::bad_property_chain(chain)
+                    };
+                    stack.push(Cow::Owned(value));
+                },
+                Instruction::StoreSlot(slot) => {
+                    match stack.last() {
+                        Some(value) if !value.is_error() => context.store_slot(slot, value.as_ref().clone()),
+                        Some(_) => (),
+                        None => stack.push(Cow::Owned(ShyValue::error("Too few values on stack for operation Assign. Size = 0".to_string())))
+                    }
+                },
+                Instruction::Unary(op) => {
+                    if Self::has_operands(&mut stack, op) {
+                        let operand = stack.last_mut().unwrap();
+                        match Expression::evaluate(op, operand, &unused, &unused, context) {
+                            Some(result) => *operand = Cow::Owned(result),
+                            None => Self::unsupported(&mut stack, op)
+                        }
+                    }
+                },
+                Instruction::Binary(op) => {
+                    if Self::has_operands(&mut stack, op) {
+                        let right = stack.pop().unwrap();
+                        let left = stack.last_mut().unwrap();
+                        match Expression::evaluate(op, left, &right, &unused, context) {
+                            Some(result) => *left = Cow::Owned(result),
+                            None => Self::unsupported(&mut stack, op)
+                        }
+                    }
+                },
+                Instruction::Operate(op) => {
+                    // Semicolon clears the whole stack. Other operators act upon their operands.
+                    let depth = if op == ShyOperator::Semicolon { usize::MAX } else { op.arguments().max(1) };
+                    Self::with_owned(&mut stack, depth, &mut owned, |owned| Expression::operate(owned, op, context));
+                },
+                Instruction::Branch(op, target) => {
+                    // A comparison in a chain and the test of a match arm look at two values, other branches at most one.
+                    let depth = match op {
+                        ShyOperator::Jump => 0,
+                        ShyOperator::Case => 2,
+                        op if op.is_ordering() => 2,
+                        _ => 1
+                    };
+                    if let Some(next_position) = Self::with_owned(&mut stack, depth, &mut owned, |owned| Expression::branch(owned, op, target)) {
+                        position = next_position;
+                    }
+                },
+                Instruction::QuitIfFalse => {
+                    let test_result = Self::with_owned(&mut stack, 1, &mut owned, |owned| Expression::operate(owned, ShyOperator::QuitIfFalse, context));
+                    if test_result.is_falsey() {
+                        context.is_applicable = false;
+                        break;
+                    }
+                },
+                Instruction::DefineFunction(index) => {
+                    let function = &self.functions[index];
+                    stack.push(Cow::Owned(ShyValue::FunctionName(function.name.clone())));
+                    context.define_function(function.clone());
+                },
+                Instruction::Invalid => stack.push(Cow::Owned(ShyValue::error("Invalid token in expression".to_string())))
+            }
+            if locating_errors && error_position.is_none() && stack.last().map_or(false, |value| value.is_error()) {
+                error_position = Some(instruction_position);
+            }
+        }
+        // The final result of the expression is on top of the stack.
+        let result = match stack.pop() {
+            Some(value) => Ok(value.into_owned()),
+            None => Err("Expression stack is empty".to_string())
+        };
+        // A token that was deserialized has an empty span, which locates nothing.
+        (result, error_position.and_then(|position: usize| self.spans.get(position).cloned()).filter(|span| !span.is_empty()))
+    }
+
+    /// Check the stack as Expression::operate does before applying an operator:
+    /// if an error is on top, leave it as the result; if there are too few operands, replace them by an error.
+    fn has_operands(stack: &mut Vec<Cow<ShyValue>>, op: ShyOperator) -> bool {
+        if matches!(stack.last(), Some(value) if value.is_error()) {
+            return false;
+        }
+        if op.arguments() > stack.len() {
+            let stack_empty = ShyValue::error(format!("Too few values on stack for operation {:?}. Size = {}", op, stack.len()));
+            stack.clear();
+            stack.push(Cow::Owned(stack_empty));
+            return false;
+        }
+        true
+    }
+
+    fn unsupported(stack: &mut Vec<Cow<ShyValue>>, op: ShyOperator) {
+        stack.clear();
+        stack.push(Cow::Owned(ShyValue::error(format!("Invalid operator {:?}", op))));
+    }
+
+    /// Move the top `depth` values of the stack (or all of them, if there are fewer) into `owned`,
+    /// cloning any borrowed from the constant pool, apply `f` to them, then move them back.
+    fn with_owned<'b, T, F>(stack: &mut Vec<Cow<'b, ShyValue>>, depth: usize, owned: &mut Vec<ShyValue>, f: F) -> T
+    where F: FnOnce(&mut Vec<ShyValue>) -> T {
+        let start = stack.len().saturating_sub(depth);
+        owned.extend(stack.drain(start..).map(Cow::into_owned));
+        let result = f(owned);
+        stack.extend(owned.drain(..).map(Cow::Owned));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    use super::super::bench;
+
+    #[test]
+    /// Verify that loads are combined with their variables and that branch targets are adjusted to match.
+    fn assemble_combines_loads() {
+        let expr = Expression::new("x > 0 && y");
+        let code = Bytecode::assemble(&expr.postfix_order);
+        asserting("instructions").that(&code.instructions).is_equal_to(&vec![
+            Instruction::LoadName(0),
+            Instruction::Constant(0),
+            Instruction::Binary(ShyOperator::GreaterThan),
+            Instruction::Branch(ShyOperator::And, 6),
+            Instruction::LoadName(1),
+            Instruction::Binary(ShyOperator::And)
+        ]);
+    }
+
+    #[test]
+    /// Verify that the bytecode gives the same results and leaves the context in the same state as the reference interpreter,
+    /// both for unbound expressions and for expressions bound to the context.
+    fn bytecode_matches_interpreter() {
+        let setup = "x = 1; y = 0; name = \"PUMP-42\"; well.depth = 100; readings = [3, 5, 8]; fn sq(n) = n * n";
+        let expressions = vec![
+            "z = x * 2 + y - 3 / 4 % 2",
+            "√16 * 2^3 / (x + 1) - x",
+            "z = x > 0 ? (y = 5) : (w = 6); z + y",
+            "x > 0 && (z = 2); y > 0 || (w = 3); z + w",
+            "!(x == 1) || x != 2 && x <= 1 && x >= 1 && x < 2",
+            "z = readings[x] + readings[2]; readings[0] = z; readings",
+            "well.depth += 10; well.depth",
+            "z = unknown + 1",
+            "z = well.height",
+            "z = \"a\" + 2; z",
+            "z = 1 +",
+            "fn twice(n) = 2 * n; twice(sq(y + 4))",
+            "fn fact(n) = n <= 1 ? 1 : n * fact(n - 1); fact(10)",
+            "map(readings, r => r + x)",
+            "reduce(filter(readings, r => r > 3), (a, r) => a + r, 0)",
+            "x += 2; y++; x -= 1; y *= 3; y /= 2; z = x + y",
+            "x > 5? ; z = 1",
+            "x < 5? ; z = 2",
+            "name ~ /^PUMP/ && !(name !~ \"42$\")",
+            "m = name ~> \"^(\\w+)-(\\d+)$\"; m[2]",
+            "upper(substr(name, 0, 4)) + to_string(len(name))",
+            "max(readings) + min(3, 9) + sum(1, 2, 3)",
+            "5 m + 20 cm to cm",
+            "z = [1, 2 + x, \"three\"]; z[1]",
+            "if(x == 1, \"one\", \"other\")",
+            "z = well?.casing?.depth ?? missing?.depth ?? (y = 7); z + y",
+            "well?.depth ?? (y = 3); y",
+            "x in readings || \"UMP\" in name && x not in [2] && x between y and readings[1]",
+            "0 < x <= readings[0] < 9 && !(5 < x < 9) && readings[1] >= x > y; 3 > x < \"a\" < 1",
+            "z = match x + y++ { < 0 => 0, x..readings[0] => (w = 2), in readings => 3, _ => 4 } + match name { ~ /^P/ => 5, _ => 6 }; z + w",
+            "w = case when x > 1 then (z = 1) when y == 0 then 2 else (z = 3) end; w + match 1d / 0 { 0 => 1, _ => 2 }"
+        ];
+        for expression in expressions {
+            for is_bound in vec![false, true] {
+                let mut reference_ctx = ExecutionContext::default();
+                let _ = Expression::new(setup).exec(&mut reference_ctx);
+                let mut vm_ctx = reference_ctx.clone();
+
+                let mut expr = Expression::new(expression);
+                if is_bound {
+                    expr.bind(&mut vm_ctx);
+                }
+                let description = format!("{} ({})", expression, if is_bound { "bound" } else { "by name" });
+                let expected = expr.interpret(&mut reference_ctx);
+                let actual = expr.exec(&mut vm_ctx);
+                asserting(&format!("result of {}", description)).that(&actual).is_equal_to(&expected);
+                asserting(&format!("variables after {}", description)).that(&vm_ctx.variables).is_equal_to(&reference_ctx.variables);
+                asserting(&format!("applicability of {}", description)).that(&vm_ctx.is_applicable).is_equal_to(&reference_ctx.is_applicable);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    /// Compare the time taken by the bytecode and by the reference interpreter for expressions heavy in constants.
+    /// Run with: cargo test --release -- --ignored bytecode_is_faster
+    fn bytecode_is_faster() {
+        let executions = 20000;
+        let setup = "x = 1; name = \"PUMP-42\"; readings = [3, 5, 8]";
+        let expression = Expression::new(
+            "z = name == \"PUMP-42\" && \"UMP\" in name ? \"Pump number forty two\" : \"Some other pump\"; \
+             w = x in [1, 2, 3, 5, 8, 13] ? len(\"Fibonacci\") : 0; \
+             z + \" has \" + to_string(w + readings[x] * 2 + 3 / 4)");
+        let mut context = ExecutionContext::default();
+        let _ = Expression::new(setup).exec(&mut context);
+
+        let mut interpreted_context = context.clone();
+        bench::assert_faster("Bytecode is faster", executions,
+            || { let _ = expression.interpret(&mut interpreted_context); },
+            || { let _ = expression.exec(&mut context); });
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Serialize, Deserialize};
+
+//..................................................................
+
+/// One link in a property chain: the first variable, a property name or a vector index.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum ChainLink {
+    /// A link reached directly, as casing in well.casing.
+    Field(String),
+
+    /// A link reached by a null-safe step, as casing in well?.casing.
+    /// If the value before it is null or lacks this property, the whole chain yields null instead of an error.
+    /// The first link of a chain is null-safe only for a variable loaded on the left of `??`, 
+    /// so that a missing variable counts as null.
+    NullSafe(String),
+
+    /// A link reached by an integer in brackets, as 2 in readings[2]. 
+    /// Assigning through it into a missing variable or property creates a vector rather than an object.
+    Index(String)
+}
+
+impl ChainLink {
+    /// The variable name, property name or index of the link.
+    pub fn name(&self) -> &str {
+        match self {
+            ChainLink::Field(name) | ChainLink::NullSafe(name) | ChainLink::Index(name) => name
+        }
+    }
+
+    pub fn is_null_safe(&self) -> bool {
+        match self {
+            ChainLink::NullSafe(_) => true,
+            _ => false
+        }
+    }
+
+    /// Split a string like person.address.zip or well?.casing into a chain of links.
+    pub fn parse_chain(chain_as_string: &str) -> Vec<ChainLink> {
+        let mut chain = Vec::new();
+        let mut null_safe = false;
+        for property in chain_as_string.split(".") {
+            let name = property.trim_end_matches('?').to_string();
+            chain.push(if null_safe { ChainLink::NullSafe(name) } else { ChainLink::Field(name) });
+            null_safe = property.ends_with('?');
+        }
+        chain
+    }
+
+    /// Join a chain of links back into the form in which it is written, such as well?.casing.depth or readings[2].
+    pub fn join(chain: &[ChainLink]) -> String {
+        let mut joined = String::new();
+        for (position, link) in chain.iter().enumerate() {
+            match link {
+                ChainLink::Index(index) => { joined.push_str(&format!("[{}]", index)); continue; },
+                _ if position == 0 => (),
+                ChainLink::NullSafe(_) => joined.push_str("?."),
+                ChainLink::Field(_) => joined.push('.')
+            }
+            joined.push_str(link.name());
+        }
+        joined
+    }
+
+    /// Join the names of a chain of links with periods, ignoring whether each step is null-safe, as in well.casing.depth.
+    pub fn join_names(chain: &[ChainLink]) -> String {
+        chain.iter().map(ChainLink::name).collect::<Vec<&str>>().join(".")
+    }
+}
+
+impl From<&str> for ChainLink {
+    fn from(name: &str) -> Self {
+        ChainLink::Field(name.to_string())
+    }
+}
+
+impl From<String> for ChainLink {
+    fn from(name: String) -> Self {
+        ChainLink::Field(name)
+    }
+}
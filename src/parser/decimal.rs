@@ -0,0 +1,345 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error;
+
+//..................................................................
+
+// Exact decimal numbers, used by ShyScalar::Decimal for monetary and regulatory values.
+//
+//   - A decimal literal is a number followed by the letter d: 12.50d, 100d, 0.075d.
+//   - A decimal is held as a whole number of units and a scale (the number of digits after the decimal point),
+//     so 12.50d is 1250 units at scale 2. The scale is kept when the decimal is displayed, so 12.50d prints as 12.50.
+//   - Equality and comparison are by value: 12.50d == 12.5d.
+//   - Addition, subtraction and remainder use the larger scale of the two operands. Multiplication adds the scales.
+//   - Division is carried to MAX_SCALE digits, rounded half away from zero, then trailing zeros are dropped,
+//     but never below the larger scale of the operands, so 10.00d / 4 is 2.50.
+//   - Any result with more than MAX_SCALE digits after the decimal point is rounded half away from zero.
+//   - Operations return None if the result does not fit, and the caller reports an overflow.
+
+/// Maximum number of digits after the decimal point.
+pub const MAX_SCALE: u32 = 28;
+
+/// A fixed point decimal number.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimal {
+    /// The value multiplied by ten to the power of scale.
+    pub units: i128,
+
+    /// Number of digits after the decimal point.
+    pub scale: u32
+}
+
+/// Ten to the power of the exponent, or None if it does not fit.
+fn power_of_ten(exponent: u32) -> Option<i128> {
+    10_i128.checked_pow(exponent)
+}
+
+/// Divide, rounding half away from zero.
+fn divide_rounded(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = (numerator % denominator).abs();
+    if remainder >= denominator.abs() - remainder {
+        if (numerator < 0) == (denominator < 0) { quotient + 1 } else { quotient - 1 }
+    }
+    else { quotient }
+}
+
+impl Decimal {
+    pub fn new(units: i128, scale: u32) -> Self {
+        Decimal { units, scale }
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        Decimal::new(value as i128, 0)
+    }
+
+    /// Parse text such as "12.50" or "-3", with an optional sign and fraction but no exponent.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (negative, digits) = match text.chars().next()? {
+            '-' => (true, &text[1..]),
+            '+' => (false, &text[1..]),
+            _ => (false, text)
+        };
+        let (whole, fraction) = match digits.find('.') {
+            Some(position) => (&digits[..position], &digits[position + 1..]),
+            None => (digits, "")
+        };
+        if whole.is_empty() && fraction.is_empty() { return None; }
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) { return None; }
+        let scale = fraction.len() as u32;
+        if scale > MAX_SCALE { return None; }
+        let mut units: i128 = 0;
+        for c in whole.chars().chain(fraction.chars()) {
+            units = units.checked_mul(10)?.checked_add(c.to_digit(10)? as i128)?;
+        }
+        Some(Decimal::new(if negative { -units } else { units }, scale))
+    }
+
+    /// Parse a decimal literal, which is a number followed by the letter d, as in 12.50d.
+    pub fn parse_literal(text: &str) -> Option<Self> {
+        Decimal::parse(text.trim().strip_suffix('d')?)
+    }
+
+    /// Convert a floating point number using its shortest exact representation, so 0.1 becomes 0.1, not 0.1000000000000000055511.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() { return None; }
+        let text = value.to_string();
+        match Decimal::parse(&text) {
+            Some(d) => Some(d),
+            // Very small numbers have more digits than MAX_SCALE.
+            None => Decimal::parse(&format!("{:.*}", MAX_SCALE as usize, value))
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse::<f64>().unwrap_or(f64::NAN)
+    }
+
+    /// The value as an integer, if it has no fractional part and fits.
+    pub fn to_i64(self) -> Option<i64> {
+        let divisor = power_of_ten(self.scale)?;
+        if self.units % divisor != 0 { return None; }
+        let whole = self.units / divisor;
+        if whole < i64::MIN as i128 || whole > i64::MAX as i128 { None } else { Some(whole as i64) }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.units == 0
+    }
+
+    /// Change the scale, rounding half away from zero if digits are dropped.
+    pub fn round(&self, scale: u32) -> Option<Self> {
+        if scale >= self.scale {
+            let units = self.units.checked_mul(power_of_ten(scale - self.scale)?)?;
+            Some(Decimal::new(units, scale))
+        }
+        else {
+            let units = divide_rounded(self.units, power_of_ten(self.scale - scale)?);
+            Some(Decimal::new(units, scale))
+        }
+    }
+
+    /// Both values expressed at the larger of their two scales.
+    fn align(&self, other: &Decimal) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        Some((self.round(scale)?.units, other.round(scale)?.units, scale))
+    }
+
+    /// Round a result down to MAX_SCALE if it has too many digits after the decimal point.
+    fn limit_scale(self) -> Option<Self> {
+        if self.scale > MAX_SCALE { self.round(MAX_SCALE) } else { Some(self) }
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Decimal::new(a.checked_add(b)?, scale))
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Decimal::new(a.checked_sub(b)?, scale))
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Self> {
+        Decimal::new(self.units.checked_mul(other.units)?, self.scale + other.scale).limit_scale()
+    }
+
+    /// Divide by long division, keeping as many digits after the decimal point as fit, up to MAX_SCALE.
+    pub fn checked_div(&self, other: &Decimal) -> Option<Self> {
+        if other.is_zero() { return None; }
+        let divisor = other.units.unsigned_abs();
+        let mut remainder = self.units.unsigned_abs();
+        let mut quotient = remainder / divisor;
+        remainder %= divisor;
+        // The quotient of the units is scaled by 10^(other.scale - self.scale); each digit of long division adds one to the scale.
+        let mut digits: u32 = 0;
+        let target = MAX_SCALE + other.scale - self.scale.min(MAX_SCALE + other.scale);
+        while digits < target && remainder != 0 {
+            let shifted = match remainder.checked_mul(10) { Some(r) => r, None => break };
+            let next = match quotient.checked_mul(10).and_then(|q| q.checked_add(shifted / divisor)) {
+                Some(q) if q <= i128::MAX as u128 => q,
+                _ => break
+            };
+            quotient = next;
+            remainder = shifted % divisor;
+            digits += 1;
+        }
+        if remainder != 0 && remainder >= divisor - remainder {
+            quotient = quotient.checked_add(1)?;
+        }
+        if quotient > i128::MAX as u128 { return None; }
+        let magnitude = quotient as i128;
+        let units = if (self.units < 0) != (other.units < 0) { -magnitude } else { magnitude };
+        let scale = digits as i64 + self.scale as i64 - other.scale as i64;
+        let result = if scale < 0 {
+            Decimal::new(units.checked_mul(power_of_ten((-scale) as u32)?)?, 0)
+        }
+        else {
+            Decimal::new(units, scale as u32)
+        };
+        Some(result.limit_scale()?.trim(self.scale.max(other.scale)))
+    }
+
+    pub fn checked_rem(&self, other: &Decimal) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        Some(Decimal::new(a.checked_rem(b)?, scale))
+    }
+
+    pub fn checked_neg(&self) -> Option<Self> {
+        Some(Decimal::new(self.units.checked_neg()?, self.scale))
+    }
+
+    /// Raise to a whole number power by repeated squaring.
+    pub fn checked_pow(&self, exponent: u32) -> Option<Self> {
+        let mut result = Decimal::from_i64(1);
+        let mut base = *self;
+        let mut remaining = exponent;
+        while remaining > 0 {
+            if remaining & 1 == 1 { result = result.checked_mul(&base)?; }
+            remaining >>= 1;
+            if remaining > 0 { base = base.checked_mul(&base)?; }
+        }
+        Some(result)
+    }
+
+    /// Drop trailing zeros after the decimal point, but keep at least the given scale.
+    pub fn trim(&self, minimum_scale: u32) -> Self {
+        let mut trimmed = *self;
+        while trimmed.scale > minimum_scale && trimmed.units % 10 == 0 {
+            trimmed = Decimal::new(trimmed.units / 10, trimmed.scale - 1);
+        }
+        trimmed
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare by value, even if aligning the scales would overflow.
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if let Some((a, b, _)) = self.align(other) {
+            return a.cmp(&b);
+        }
+        let self_divisor = power_of_ten(self.scale).unwrap_or(1);
+        let other_divisor = power_of_ten(other.scale).unwrap_or(1);
+        let whole = (self.units / self_divisor).cmp(&(other.units / other_divisor));
+        if whole != Ordering::Equal { return whole; }
+        // The whole parts are equal, so the fractions have at most MAX_SCALE digits and can be aligned.
+        let self_fraction = Decimal::new(self.units % self_divisor, self.scale);
+        let other_fraction = Decimal::new(other.units % other_divisor, other.scale);
+        match self_fraction.align(&other_fraction) {
+            Some((a, b, _)) => a.cmp(&b),
+            None => Ordering::Equal
+        }
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let digits = self.units.unsigned_abs().to_string();
+        let sign = if self.units < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// A decimal is serialized as a string, so that no digits are lost.
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Decimal::parse(&text).ok_or_else(|| D::Error::custom(format!("malformed decimal {}", text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    fn dec(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    #[test]
+    /// Verify that parsing keeps the scale, and that display shows it.
+    fn parse_and_display() {
+        asserting("scale kept").that(&dec("12.50").to_string()).is_equal_to(&"12.50".to_string());
+        asserting("negative fraction").that(&dec("-0.05").to_string()).is_equal_to(&"-0.05".to_string());
+        asserting("whole").that(&dec("100").to_string()).is_equal_to(&"100".to_string());
+        asserting("literal").that(&Decimal::parse_literal("0.075d").unwrap().to_string()).is_equal_to(&"0.075".to_string());
+        asserting("from f64").that(&Decimal::from_f64(0.1).unwrap().to_string()).is_equal_to(&"0.1".to_string());
+        assert!(Decimal::parse("1.2.3").is_none());
+        assert!(Decimal::parse("1e5").is_none());
+        assert!(Decimal::parse(".").is_none());
+    }
+
+    #[test]
+    /// Verify that equality ignores trailing zeros and that comparison works across scales.
+    fn compare() {
+        asserting("equal across scales").that(&dec("12.50")).is_equal_to(&dec("12.5"));
+        assert!(dec("0.3") > dec("0.29999999999999999999"));
+        assert!(dec("-1.5") < dec("-1.49"));
+        assert!(dec("170141183460469231731687303715884105727") > dec("0.5"));
+    }
+
+    #[test]
+    /// Verify exact arithmetic and the scale of each result.
+    fn arithmetic() {
+        let sum = dec("0.10").checked_add(&dec("0.2")).unwrap();
+        asserting("add").that(&sum.to_string()).is_equal_to(&"0.30".to_string());
+        asserting("subtract").that(&dec("1.00").checked_sub(&dec("0.01")).unwrap().to_string()).is_equal_to(&"0.99".to_string());
+        asserting("multiply").that(&dec("12.50").checked_mul(&dec("0.075")).unwrap().to_string()).is_equal_to(&"0.93750".to_string());
+        asserting("remainder").that(&dec("10.25").checked_rem(&dec("3")).unwrap().to_string()).is_equal_to(&"1.25".to_string());
+        asserting("power").that(&dec("1.1").checked_pow(3).unwrap().to_string()).is_equal_to(&"1.331".to_string());
+        asserting("round half away").that(&dec("-2.345").round(2).unwrap().to_string()).is_equal_to(&"-2.35".to_string());
+    }
+
+    #[test]
+    /// Verify that division keeps the operands' scale, rounds at MAX_SCALE and rejects zero.
+    fn division() {
+        asserting("keeps scale").that(&dec("10.00").checked_div(&dec("4")).unwrap().to_string()).is_equal_to(&"2.50".to_string());
+        asserting("third").that(&dec("1").checked_div(&dec("3")).unwrap().to_string())
+            .is_equal_to(&"0.3333333333333333333333333333".to_string());
+        asserting("two thirds").that(&dec("-2").checked_div(&dec("3")).unwrap().to_string())
+            .is_equal_to(&"-0.6666666666666666666666666667".to_string());
+        asserting("by fraction").that(&dec("1").checked_div(&dec("0.001")).unwrap().to_string()).is_equal_to(&"1000".to_string());
+        assert!(dec("1").checked_div(&dec("0.00")).is_none());
+    }
+
+    #[test]
+    /// Verify that results that do not fit are reported as None.
+    fn overflow() {
+        let big = dec("170141183460469231731687303715884105727");
+        assert!(big.checked_add(&dec("1")).is_none());
+        assert!(big.checked_mul(&dec("2")).is_none());
+        assert!(dec("10").checked_pow(39).is_none());
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use crate::lexer::parser_token::ParserToken;
+use super::execution_context::ExecutionContext;
+use super::higher_order::HIGHER_ORDER_FUNCTIONS;
+
+//..................................................................
+
+// Dialects of the expression language that the ShuntingYard accepts.
+//
+//   - Standard is the language described in the README.
+//   - Spreadsheet also accepts the habits of people who write Excel formulas:
+//       * <> for not equals, and = for equals, except where it assigns to the name that begins a statement, as in ok = depth = 0.
+//       * AND, OR and NOT as words, the same as and, or and not.
+//         As functions, AND(...) becomes all(...), OR(...) becomes any(...) and NOT(...) becomes !(...).
+//       * Function names in capitals, like IF, ROUND and SUM, for the standard functions of the same names in lower case.
+//
+// Each ParserToken is translated as it is read, so the rest of the ShuntingYard sees only tokens of the standard language.
+
+lazy_static! {
+    /// Names of the functions that every ExecutionContext has, which the Spreadsheet dialect also accepts in capitals.
+    static ref STANDARD_FUNCTION_NAMES: HashSet<String> = {
+        let mut names: HashSet<String> = ExecutionContext::standard_functions().keys().cloned().collect();
+        names.extend(HIGHER_ORDER_FUNCTIONS.iter().map(|name| name.to_string()));
+        names
+    };
+}
+
+/// A dialect of the expression language, chosen when the expression is compiled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Dialect {
+    /// The expression language as described in the README.
+    #[default]
+    Standard,
+
+    /// Also accepts the comparison operators, logical words and capitalized function names of spreadsheet formulas.
+    Spreadsheet
+}
+
+impl Dialect {
+    /// Translate a token of this dialect into a token of the standard language.
+    ///   follows_assignment_target ... True if the tokens read so far in the statement are a name that an equals sign
+    ///                                 assigns to, as in `x =` or `let x =`, or a function declaration, as in `fn f(x) =`.
+    pub fn translate(&self, token: ParserToken, follows_assignment_target: bool) -> ParserToken {
+        if *self == Dialect::Standard {
+            return token;
+        }
+        match token {
+            ParserToken::EqualityOp(ref op) if op == "<>" => ParserToken::EqualityOp("!=".to_string()),
+            ParserToken::AssignmentOp(ref op) if op == "=" && !follows_assignment_target => ParserToken::EqualityOp("==".to_string()),
+            ParserToken::Identifier(ref word) if word == "AND" || word == "OR" || word == "NOT" => ParserToken::word(word.to_lowercase()),
+            ParserToken::Function(ref name) if name == "AND" => ParserToken::Function("all".to_string()),
+            ParserToken::Function(ref name) if name == "OR" => ParserToken::Function("any".to_string()),
+            ParserToken::Function(ref name) if name == "NOT" => ParserToken::LogicalNotOp,
+            ParserToken::Function(ref name) if *name == name.to_uppercase() && STANDARD_FUNCTION_NAMES.contains(&name.to_lowercase())
+              => ParserToken::Function(name.to_lowercase()),
+            _ => token
+        }
+    }
+}
@@ -2,49 +2,119 @@
 use std::collections::HashMap;
 use std::f64;
 use std::fmt;
+use std::convert::TryFrom;
 use std::cmp::{Ordering, PartialOrd};
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Serializer};
+use serde::ser::SerializeStruct;
+use serde_json::{Value, Map};
 
 use super::shy_scalar::ShyScalar;
 use super::shy_token::ShyValue;
 use super::shy_object::ShyObject;
+use super::decimal::{Decimal, MAX_SCALE};
 use super::voting_rule::VotingRule;
+use super::user_function::UserFunction;
+use super::higher_order::{self, HIGHER_ORDER_FUNCTIONS};
+use super::frame::Frame;
+use super::chain_link::ChainLink;
+
+//..................................................................
+
+/// What integer arithmetic does when its result does not fit in an i64.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegerOverflow {
+    /// Repeat the operation upon the operands converted to Rational, giving an approximate result.
+    #[default]
+    Promote,
+    /// Return an error describing the overflow.
+    Error
+}
+
+/// How Decimals are written when values are converted to JSON. Either way no digits are lost.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecimalJson {
+    /// A string holding the prefix "Decimal: " followed by the digits, which converts back to a Decimal.
+    #[default]
+    String,
+    /// A number, if it holds exactly the same value as the Decimal, otherwise a string as above. 
+    /// A number converts back to an Integer or Rational, not a Decimal.
+    Number
+}
 
 //..................................................................
 
 /// ExecutionContext holds variables and functions needed when executing expressions.
 ///   - Some variables are loaded for use in the formulas.
 ///   - Some variables are used to store the results of formulas after execution. 
-///   - The functions may be called in the expressions.
+///   - The functions may be called in the expressions. Some are predefined in Rust, others are declared by expressions with `fn`.
 ///   - The is_applicable flag is reset at the start of each execution and unset if an applicability check fails.  
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 pub struct ExecutionContext<'a> {
     /// Holds variables representing inputs to or outputs from the execution. 
-    pub variables: HashMap<String, ShyValue>,
+    /// They may be accessed by name, or by slot once an Expression is bound to the context.
+    pub variables: Frame,
 
     /// Holds predefined functions available for use in the formulas. 
     #[serde(skip)]
     functions: HashMap<String, ShyFunction<'a>>,
 
+    /// Holds functions declared by expressions, which take precedence over predefined functions of the same name.
+    #[serde(skip)]
+    user_functions: HashMap<String, UserFunction>,
+
+    /// One entry per user function call in progress, holding the values of the variables hidden by its parameters
+    /// (or None if there was no such variable), to be restored when the call returns.
+    #[serde(skip)]
+    scopes: Vec<Vec<(String, Option<ShyValue>)>>,
+
     /// Was the rule applicable in this context? 
     /// Inapplicable rules are ignored when deciding if a `RuleSet` passed or failed. 
     /// This must be reset to true at the beginning of each execution of each new expression. 
     /// It is set to false if a question mark applicability operator is encountered and it 
     /// evaluates the previous clause in the formula to false. 
-    pub is_applicable : bool
+    pub is_applicable : bool,
+
+    /// What integer arithmetic does when its result overflows. 
+    #[serde(default)]
+    pub integer_overflow: IntegerOverflow,
+
+    /// How Decimals are written when the context or values computed in it are converted to JSON.
+    #[serde(default)]
+    pub decimal_json: DecimalJson
 }
 
 impl<'a> Clone for ExecutionContext<'a> {
-    /// Only a partial clone. Any non-default functions will be lost.
+    /// Only a partial clone. Any non-default functions defined in Rust will be lost, but functions declared by expressions are kept.
     fn clone(&self) -> Self {
         let mut the_clone = ExecutionContext::default();
         the_clone.is_applicable = self.is_applicable;
+        the_clone.integer_overflow = self.integer_overflow;
+        the_clone.decimal_json = self.decimal_json;
         the_clone.variables = self.variables.clone();
+        the_clone.user_functions = self.user_functions.clone();
         the_clone
     }
 }
 
-type ShyFunction<'a> = Box<(dyn Fn(ShyValue) -> ShyValue + 'a)>;
+/// Serialized as a derived Serialize would, except that the variables are written as JSON values,
+/// so that their Decimals are written as decimal_json directs.
+impl<'a> Serialize for ExecutionContext<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let variables : Map<String, Value> = self.variables.iter()
+            .map(|(name, value)| (name.clone(), value.to_json(self.decimal_json)))
+            .collect();
+        let mut state = serializer.serialize_struct("ExecutionContext", 4)?;
+        state.serialize_field("variables", &variables)?;
+        state.serialize_field("is_applicable", &self.is_applicable)?;
+        state.serialize_field("integer_overflow", &self.integer_overflow)?;
+        state.serialize_field("decimal_json", &self.decimal_json)?;
+        state.end()
+    }
+}
+
+/// A function defined in Rust. It receives the arguments of the call as a slice, one item per argument, 
+/// so f(a, b) receives two items and f([a, b]) receives one, a vector.
+type ShyFunction<'a> = Box<(dyn Fn(&[ShyValue]) -> ShyValue + 'a)>;
 
 type Ctx<'a> = ExecutionContext<'a>;
 
@@ -52,62 +122,55 @@ impl<'a> ExecutionContext<'a> {
 
     /// Get an alphabetized list of the names of all the implemented functions. 
     pub fn function_names(&self) -> Vec<String> {
-        let mut names : Vec<String> = self.functions.keys().map(|k| k.clone()).collect();
+        let mut names : Vec<String> = self.functions.keys().chain(self.user_functions.keys()).map(|k| k.clone())
+            .chain(HIGHER_ORDER_FUNCTIONS.iter().map(|name| name.to_string())).collect();
         names.sort();
+        names.dedup();
         names
     }
 
     pub fn shy_func<F>(f: F) -> ShyFunction<'a>
-        where F: Fn(ShyValue) -> ShyValue + 'a {
+        where F: Fn(&[ShyValue]) -> ShyValue + 'a {
             Box::new(f) as ShyFunction
     }
 
-    /// Define a context function that assumes the argument is a float or integer or a vector
-    /// that holds a single float or integer and returns a double.
+    /// Define a context function that assumes its sole argument is a float or integer and returns a double.
     pub fn shy_double_func<G>(g: G) -> ShyFunction<'a>
         where G: Fn(f64) -> f64 + 'a {
-            Ctx::shy_func(move |v| {
-                match v {
-                    ShyValue::Scalar(ShyScalar::Rational(x)) => g(x).into(),
-                    ShyValue::Scalar(ShyScalar::Integer(i)) => g(i as f64).into(),
-                    ShyValue::Vector(ref vect) if vect.len() == 1 => match vect[0] {
-                        ShyScalar::Rational(x) => g(x).into(),
-                        ShyScalar::Integer(i) => g(i as f64).into(),
-                        _ => f64::NAN.into()
-                    },
+            Ctx::shy_func(move |args| {
+                match args {
+                    [ShyValue::Scalar(ShyScalar::Rational(x))] => g(*x).into(),
+                    [ShyValue::Scalar(ShyScalar::Integer(i))] => g(*i as f64).into(),
+                    [ShyValue::Scalar(ShyScalar::Decimal(d))] => g(d.to_f64()).into(),
                     _ => f64::NAN.into()
                 }
             })
     }
 
-    /// Define a context function that assumes the argument is a float or integer or a vector
-    /// that holds a single float or integer and returns a bool.
+    /// Define a context function that assumes its sole argument is a float or integer and returns a bool.
     pub fn shy_double_to_bool_func<G>(g: G) -> ShyFunction<'a>
         where G: Fn(f64) -> bool + 'a {
-            Ctx::shy_func(move |v| {
-                match v {
-                    ShyValue::Scalar(ShyScalar::Rational(x)) => g(x).into(),
-                    ShyValue::Scalar(ShyScalar::Integer(i)) => g(i as f64).into(),
-                    ShyValue::Vector(ref vect) if vect.len() == 1 => match vect[0] {
-                        ShyScalar::Rational(x) => g(x).into(),
-                        ShyScalar::Integer(i) => g(i as f64).into(),
-                        _ => ShyValue::error("Vector holding non-numeric value passed to function expecting numbers".into())
-                    },
-                    _ => ShyValue::error("Non-numeric value passed to function expecting a number".into())
+            Ctx::shy_func(move |args| {
+                match args {
+                    [ShyValue::Scalar(ShyScalar::Rational(x))] => g(*x).into(),
+                    [ShyValue::Scalar(ShyScalar::Integer(i))] => g(*i as f64).into(),
+                    [ShyValue::Scalar(ShyScalar::Decimal(d))] => g(d.to_f64()).into(),
+                    [_] => ShyValue::error("Non-numeric value passed to function expecting a number".into()),
+                    _ => ShyValue::error("Function expecting a number must be passed exactly one argument".into())
                 }
             })
     }
 
-    /// Define a context function that acts like an if-then-else statement. It must be passed an array of three elements: 
+    /// Define a context function that acts like an if-then-else statement. It must be passed three arguments: 
     ///    - the boolean test
     ///    - the value to return if the test is true
     ///    - the value to return if the test is false.
     pub fn shy_if_func() -> ShyFunction<'a>
     {
-        Ctx::shy_func(move |v| {
-            match v {
-                ShyValue::Vector(ref vect) if vect.len() == 3 => match vect[0] {
-                    ShyScalar::Boolean(test) => ShyValue::Scalar(if test { vect[1].clone() } else { vect[2].clone() }),
+        Ctx::shy_func(move |args| {
+            match args {
+                [test, if_true, if_false] => match test {
+                    ShyValue::Scalar(ShyScalar::Boolean(test)) => if *test { if_true.clone() } else { if_false.clone() },
                     _ => ShyValue::error("'if' function first argument must be a boolean value".into())
                 },
                 _ => ShyValue::error("'if' function requires exactly three arguments".into())
@@ -126,58 +189,60 @@ impl<'a> ExecutionContext<'a> {
     /// The behavior for two arguments is similar to the SQL Server SQL function ISNULL. 
     pub fn shy_isnull_func() -> ShyFunction<'a>
     {
-        Ctx::shy_func(move |v| {
-            match v {
-                ShyValue::Vector(ref vect) if vect.len() == 1 => match vect[0] {
-                    ShyScalar::Null => true.into(),
-                    _ => false.into()
-                },
-                ShyValue::Vector(ref vect) if vect.len() == 2 => match vect[0] {
-                    ShyScalar::Null => ShyValue::Scalar(vect[1].clone()),
-                    _ => ShyValue::Scalar(vect[0].clone())
-                },
-                ShyValue::Scalar(ShyScalar::Null) => true.into(),
-                ShyValue::Scalar(_) => false.into(),
+        Ctx::shy_func(move |args| {
+            match args {
+                [ShyValue::Scalar(ShyScalar::Null)] => true.into(),
+                [_] => false.into(),
+                [ShyValue::Scalar(ShyScalar::Null), replacement] => replacement.clone(),
+                [value, _] => value.clone(),
                 _ => ShyValue::error("'isnull' function requires one or two arguments".into())
             }
         })
     }    
 
-    pub fn shy_voting_func(function_name : String, rule : VotingRule) -> ShyFunction<'a>
+    /// The items that a voting or aggregate function works upon: its arguments, 
+    /// or the items of its sole argument if that is a vector, as in max(readings).
+    fn items_of(args : &[ShyValue]) -> &[ShyValue] {
+        match args {
+            [ShyValue::Vector(items)] => items.as_slice(),
+            _ => args
+        }
+    }
+
+    /// Create a ShyFunction that counts how many of the values voting are true. 
+    /// The votes are the arguments, or the items of the sole argument if it is a vector, as in any(alarms).
+    pub fn shy_voting_func(_function_name : String, rule : VotingRule) -> ShyFunction<'a>
     {
-        Ctx::shy_func(move |v| {
-            match v {
-                ShyValue::Vector(ref vect) if vect.len() == 0 => {
-                    let vote = match rule {
-                        VotingRule::None => true,
-                        VotingRule::Unanimous =>  true,
-                        _ => false
-                    };
-                    vote.into()
-                },
-                ShyValue::Vector(ref vect) if vect.len() > 0 => {
-                    let full_count = vect.len();
-                    let true_count = vect.iter().filter(|&v| v.is_truthy()).count();
-                    let vote = match rule {
-                        VotingRule::None => true_count == 0,
-                        VotingRule::One => true_count == 1,
-                        VotingRule::Any => true_count > 0,
-                        VotingRule::Minority => true_count > 0 && true_count < (full_count + 1) / 2,
-                        VotingRule::Half => true_count * 2 == full_count,
-                        VotingRule::Majority => true_count > full_count / 2,
-                        VotingRule::TwoThirds => true_count >= full_count * 2 / 3,
-                        VotingRule::AllButOne => true_count > 0 && true_count == full_count - 1,
-                        VotingRule::All => true_count == full_count,
-                        VotingRule::Unanimous =>  true_count == 0 || true_count == full_count
-                    };
-                    vote.into()
-                },
-                _ => ShyValue::error(format!("'{}' function requires a vector as argument", function_name).into())
+        Ctx::shy_func(move |args| {
+            let vect = Ctx::items_of(args);
+            if vect.is_empty() {
+                let vote = match rule {
+                    VotingRule::None => true,
+                    VotingRule::Unanimous =>  true,
+                    _ => false
+                };
+                return vote.into();
             }
+            let full_count = vect.len();
+            let true_count = vect.iter().filter(|&v| v.is_truthy()).count();
+            let vote = match rule {
+                VotingRule::None => true_count == 0,
+                VotingRule::One => true_count == 1,
+                VotingRule::Any => true_count > 0,
+                VotingRule::Minority => true_count > 0 && true_count < (full_count + 1) / 2,
+                VotingRule::Half => true_count * 2 == full_count,
+                VotingRule::Majority => true_count > full_count / 2,
+                VotingRule::TwoThirds => true_count >= full_count * 2 / 3,
+                VotingRule::AllButOne => true_count > 0 && true_count == full_count - 1,
+                VotingRule::All => true_count == full_count,
+                VotingRule::Unanimous =>  true_count == 0 || true_count == full_count
+            };
+            vote.into()
         })
     } 
 
-    /// Create a ShyFunction that performs aggregation over a ShyValue::Vector. 
+    /// Create a ShyFunction that performs aggregation over its arguments, 
+    /// or over the items of its sole argument if it is a vector, as in max(readings). 
     /// 
     /// This can be used to build functions like max, min and sum.
     ///   - `function_name` is the name to use for the function, 
@@ -187,23 +252,152 @@ impl<'a> ExecutionContext<'a> {
     ///      for sum, a zero, and for product, a one.
     ///   - `aggr` is a function that can take an accumulated value and apply 
     ///      an additional value to extend the aggregation, like the `Iterator` Trait's `fold` method. 
+    /// 
+    /// A vector of Decimals (perhaps mixed with Integers) is folded starting from its first item instead of `init`, 
+    /// so that the sum or product of Decimals is an exact Decimal.
     pub fn shy_aggregate_func<F>(function_name : String, init : &ShyScalar, aggr : F) -> ShyFunction<'a>
     where F : Fn(ShyScalar,ShyScalar) -> ShyScalar + 'a + Clone
     {
         let init2 = init.clone();
         let aggr2 = aggr.clone();
-        Ctx::shy_func(move |v| {
-            match v {
-                ShyValue::Vector(ref vect) if vect.len() == 0 => ShyValue::Scalar(ShyScalar::Null),
-                ShyValue::Vector(ref vect) if vect.len() == 1 => ShyValue::Scalar(vect[0].clone()),
-                ShyValue::Vector(ref vect) if vect.len() > 1 => {
-                    let aggregate = vect.iter().fold(init2.clone(), |a,item| aggr2(a,item.clone()));
-                    ShyValue::Scalar(aggregate.clone())
-                },
-                _ => ShyValue::error(format!("'{}' function requires a vector as argument", function_name).into())
+        Ctx::shy_func(move |args| {
+            match Ctx::items_of(args) {
+                [] => ShyValue::Scalar(ShyScalar::Null),
+                vect if vect.iter().any(|item| !item.is_scalar()) => 
+                    ShyValue::error(format!("'{}' function requires scalars or a vector of scalars as arguments", function_name)),
+                [only] => only.clone(),
+                vect => {
+                    let is_exact = vect.iter().any(|item| matches!(item, ShyValue::Scalar(ShyScalar::Decimal(_))))
+                        && vect.iter().all(|item| matches!(item, ShyValue::Scalar(ShyScalar::Decimal(_)) | ShyValue::Scalar(ShyScalar::Integer(_))));
+                    let (init3, items) = match &vect[0] {
+                        ShyValue::Scalar(first) if is_exact => (first.clone(), &vect[1..]),
+                        _ => (init2.clone(), vect)
+                    };
+                    let aggregate = items.iter().fold(init3, |a,item| 
+                        match item {
+                            ShyValue::Scalar(scalar) => aggr2(a, scalar.clone()),
+                            _ => a
+                        }
+                    );
+                    ShyValue::Scalar(aggregate)
+                }
             }
         })
-    }  
+    }
+
+    /// Create a ShyFunction that takes from `min_args` to `max_args` arguments.
+    ///   - `function_name` is used in error messages.
+    ///   - `f` returns an error ShyValue as its Err, so that it may use `?` with the argument helpers, like `string_arg`.
+    /// If any argument is an error, that error is the result.
+    pub fn shy_multi_func<F>(function_name : String, min_args : usize, max_args : usize, f : F) -> ShyFunction<'a>
+    where F : Fn(&[ShyValue]) -> Result<ShyValue, ShyValue> + 'a
+    {
+        Ctx::shy_func(move |args| {
+            let result = match args.iter().find(|arg| arg.is_error()) {
+                Some(error) => Err(error.clone()),
+                None if args.len() >= min_args && args.len() <= max_args => f(args),
+                None if max_args == usize::MAX => Err(ShyValue::error(format!("'{}' function requires at least {} arguments", function_name, min_args))),
+                None if min_args == max_args => Err(ShyValue::error(format!("'{}' function requires {} argument{}", function_name, min_args, if min_args == 1 { "" } else { "s" }))),
+                None => Err(ShyValue::error(format!("'{}' function requires from {} to {} arguments", function_name, min_args, max_args)))
+            };
+            result.unwrap_or_else(|error| error)
+        })
+    }
+
+    /// Create a ShyFunction that takes a single string and returns a string, like upper.
+    pub fn shy_string_func<G>(function_name : String, g : G) -> ShyFunction<'a>
+    where G : Fn(&str) -> String + 'a
+    {
+        let name = function_name.clone();
+        Ctx::shy_multi_func(function_name, 1, 1, move |args| Ok(g(&Ctx::string_arg(&name, args, 0)?).into()))
+    }
+
+    /// Get the argument at the given position as a string, or an error naming the function if it is missing or not a string.
+    pub fn string_arg(function_name : &str, args : &[ShyValue], position : usize) -> Result<String, ShyValue> {
+        match args.get(position) {
+            Some(ShyValue::Scalar(ShyScalar::String(s))) => Ok(s.clone()),
+            Some(other) => Err(ShyValue::error(format!("'{}' function requires a string for argument {}, not {}", function_name, position + 1, other.type_name()))),
+            None => Err(ShyValue::error(format!("'{}' function is missing argument {}", function_name, position + 1)))
+        }
+    }
+
+    /// Get the argument at the given position as an integer, or an error naming the function if it is missing or not an integer.
+    pub fn integer_arg(function_name : &str, args : &[ShyValue], position : usize) -> Result<i64, ShyValue> {
+        match args.get(position) {
+            Some(ShyValue::Scalar(ShyScalar::Integer(i))) => Ok(*i),
+            Some(other) => Err(ShyValue::error(format!("'{}' function requires an integer for argument {}, not {}", function_name, position + 1, other.type_name()))),
+            None => Err(ShyValue::error(format!("'{}' function is missing argument {}", function_name, position + 1)))
+        }
+    }
+
+    /// Convert any value to the string that to_string and concat produce:
+    /// scalars as they are written in literals (strings without quotes) and vectors and objects as JSON.
+    fn display_string(value : &ShyValue) -> Result<String, ShyValue> {
+        match value {
+            ShyValue::Scalar(scalar) => String::try_from(scalar.clone()).map_err(|message| ShyValue::error(message.to_string())),
+            _ => Ok(Value::from(value).to_string())
+        }
+    }
+
+    /// Parse a string as an integer if possible, otherwise as a float.
+    fn parse_number(text : &str) -> Result<ShyValue, ShyValue> {
+        let trimmed = text.trim();
+        if let Ok(i) = trimmed.parse::<i64>() { return Ok(i.into()); }
+        match trimmed.parse::<f64>() {
+            Ok(x) => Ok(x.into()),
+            Err(_) => Err(ShyValue::error(format!("Cannot parse '{}' as a number", text)))
+        }
+    }
+
+    /// Convert a number, or a string holding one, to a Decimal, optionally rounded to the given number of places after the decimal point.
+    fn to_decimal(args : &[ShyValue]) -> Result<ShyValue, ShyValue> {
+        let decimal = match &args[0] {
+            ShyValue::Scalar(ShyScalar::Decimal(d)) => Some(*d),
+            ShyValue::Scalar(ShyScalar::Integer(i)) => Some(Decimal::from_i64(*i)),
+            ShyValue::Scalar(ShyScalar::Rational(r)) => Decimal::from_f64(*r),
+            ShyValue::Scalar(ShyScalar::String(s)) => Decimal::parse(s),
+            other => return Err(ShyValue::error(format!("'decimal' function requires a number or string, not {}", other.type_name())))
+        };
+        let decimal = decimal.ok_or_else(|| ShyValue::error(format!("Cannot convert {:?} to a decimal", args[0])))?;
+        if args.len() < 2 { return Ok(decimal.into()); }
+        let places = Ctx::integer_arg("decimal", args, 1)?;
+        match u32::try_from(places).ok().filter(|places| *places <= MAX_SCALE).and_then(|places| decimal.round(places)) {
+            Some(rounded) => Ok(rounded.into()),
+            None => Err(ShyValue::error(format!("'decimal' function cannot round {} to {} places", decimal, places)))
+        }
+    }
+
+    /// Round a number to the given number of places after the decimal point (zero if omitted), with halves rounded away from zero. 
+    /// Negative places round to tens, hundreds and so on, as in round(1250, -2) == 1300. The result has the type of the number.
+    fn round(args : &[ShyValue]) -> Result<ShyValue, ShyValue> {
+        let places = if args.len() > 1 { Ctx::integer_arg("round", args, 1)? } else { 0 };
+        let scale = 10_f64.powi(places.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+        match &args[0] {
+            ShyValue::Scalar(ShyScalar::Integer(i)) if places >= 0 => Ok((*i).into()),
+            ShyValue::Scalar(ShyScalar::Integer(i)) => Ok(ShyValue::from((((*i as f64) * scale).round() / scale).round() as i64)),
+            ShyValue::Scalar(ShyScalar::Rational(x)) => Ok(((x * scale).round() / scale).into()),
+            ShyValue::Scalar(ShyScalar::Decimal(d)) => match u32::try_from(places).ok().filter(|places| *places <= MAX_SCALE).and_then(|places| d.round(places)) {
+                Some(rounded) => Ok(rounded.into()),
+                None => Err(ShyValue::error(format!("'round' function cannot round {} to {} places", d, places)))
+            },
+            other => Err(ShyValue::error(format!("'round' function requires a number, not {}", other.type_name())))
+        }
+    }
+
+    /// Take the characters of a string from `start` (counting from zero), either to the end or limited to `length` characters.
+    fn substring(args : &[ShyValue]) -> Result<ShyValue, ShyValue> {
+        let s = Ctx::string_arg("substr", args, 0)?;
+        let start = Ctx::integer_arg("substr", args, 1)?;
+        let length = if args.len() > 2 { Some(Ctx::integer_arg("substr", args, 2)?) } else { None };
+        if start < 0 || length.map_or(false, |length| length < 0) {
+            return Err(ShyValue::error("'substr' function requires a start and length that are not negative".into()));
+        }
+        let characters = s.chars().skip(start as usize);
+        Ok(match length {
+            Some(length) => characters.take(length as usize).collect::<String>(),
+            None => characters.collect::<String>()
+        }.into())
+    }
 
     pub fn standard_functions() -> HashMap<String, ShyFunction<'a>> {
         let mut map = HashMap::new();
@@ -266,6 +460,47 @@ impl<'a> ExecutionContext<'a> {
             |a,b| { { if let ShyValue::Scalar(result) = ShyValue::multiply(&ShyValue::Scalar(a.clone()), &ShyValue::Scalar(b.clone())) { return result; } }
                 ShyScalar::Error(format!("Unable to multiply {:?} * {:?}", a, b))}));
 
+        // String functions. Positions and lengths count characters, not bytes.
+        map.insert("len".into(), Ctx::shy_multi_func("len".into(), 1, 1, |args| match &args[0] {
+            ShyValue::Scalar(ShyScalar::String(s)) => Ok((s.chars().count() as i64).into()),
+            ShyValue::Vector(items) => Ok((items.len() as i64).into()),
+            other => Err(ShyValue::error(format!("'len' function requires a string or vector, not {}", other.type_name())))
+        }));
+        map.insert("upper".into(), Ctx::shy_string_func("upper".into(), |s| s.to_uppercase()));
+        map.insert("lower".into(), Ctx::shy_string_func("lower".into(), |s| s.to_lowercase()));
+        map.insert("trim".into(), Ctx::shy_string_func("trim".into(), |s| s.trim().to_string()));
+        map.insert("substr".into(), Ctx::shy_multi_func("substr".into(), 2, 3, Ctx::substring));
+        map.insert("contains".into(), Ctx::shy_multi_func("contains".into(), 2, 2, |args| match &args[0] {
+            ShyValue::Vector(items) => Ok(items.contains(&args[1]).into()),
+            _ => Ok(Ctx::string_arg("contains", args, 0)?.contains(&Ctx::string_arg("contains", args, 1)?).into())
+        }));
+        map.insert("starts_with".into(), Ctx::shy_multi_func("starts_with".into(), 2, 2,
+            |args| Ok(Ctx::string_arg("starts_with", args, 0)?.starts_with(&Ctx::string_arg("starts_with", args, 1)?).into())));
+        map.insert("ends_with".into(), Ctx::shy_multi_func("ends_with".into(), 2, 2,
+            |args| Ok(Ctx::string_arg("ends_with", args, 0)?.ends_with(&Ctx::string_arg("ends_with", args, 1)?).into())));
+        map.insert("replace".into(), Ctx::shy_multi_func("replace".into(), 3, 3,
+            |args| Ok(Ctx::string_arg("replace", args, 0)?.replace(&Ctx::string_arg("replace", args, 1)?, &Ctx::string_arg("replace", args, 2)?).into())));
+        map.insert("split".into(), Ctx::shy_multi_func("split".into(), 1, 2, |args| {
+            let s = Ctx::string_arg("split", args, 0)?;
+            let parts : Vec<ShyValue> = if args.len() > 1 { s.split(Ctx::string_arg("split", args, 1)?.as_str()).map(|part| part.into()).collect() }
+                else { s.split_whitespace().map(|part| part.into()).collect() };
+            Ok(ShyValue::Vector(parts))
+        }));
+        map.insert("join".into(), Ctx::shy_multi_func("join".into(), 1, 2, |args| {
+            let items = match &args[0] {
+                ShyValue::Vector(items) => items,
+                other => return Err(ShyValue::error(format!("'join' function requires a vector for argument 1, not {}", other.type_name())))
+            };
+            let separator = if args.len() > 1 { Ctx::string_arg("join", args, 1)? } else { String::new() };
+            Ok(items.iter().map(Ctx::display_string).collect::<Result<Vec<String>, ShyValue>>()?.join(&separator).into())
+        }));
+        map.insert("concat".into(), Ctx::shy_multi_func("concat".into(), 1, usize::MAX,
+            |args| Ok(args.iter().map(Ctx::display_string).collect::<Result<Vec<String>, ShyValue>>()?.concat().into())));
+        map.insert("to_string".into(), Ctx::shy_multi_func("to_string".into(), 1, 1, |args| Ok(Ctx::display_string(&args[0])?.into())));
+        map.insert("decimal".into(), Ctx::shy_multi_func("decimal".into(), 1, 2, Ctx::to_decimal));
+        map.insert("round".into(), Ctx::shy_multi_func("round".into(), 1, 2, Ctx::round));
+        map.insert("parse_number".into(), Ctx::shy_multi_func("parse_number".into(), 1, 1, |args| Ctx::parse_number(&Ctx::string_arg("parse_number", args, 0)?)));
+
         map
     }
 
@@ -284,27 +519,39 @@ impl<'a> ExecutionContext<'a> {
         vars.extend(ExecutionContext::standard_variables());
         funcs.extend(ExecutionContext::standard_functions());
         ExecutionContext {
-            variables: vars,
+            variables: vars.into(),
             functions: funcs,
-            is_applicable : true
+            user_functions: HashMap::new(),
+            scopes: Vec::new(),
+            is_applicable : true,
+            integer_overflow: IntegerOverflow::default(),
+            decimal_json: DecimalJson::default()
         }
     }
 
     /// Create a default context that only defines the standard math functions and constants.
     pub fn default() -> Self {
         ExecutionContext {
-            variables: ExecutionContext::standard_variables(),
+            variables: ExecutionContext::standard_variables().into(),
             functions: ExecutionContext::standard_functions(),
-            is_applicable: true
+            user_functions: HashMap::new(),
+            scopes: Vec::new(),
+            is_applicable: true,
+            integer_overflow: IntegerOverflow::default(),
+            decimal_json: DecimalJson::default()
         }
     }    
 
     /// Create an empty context that has neither variables nor functions.
     pub fn empty() -> Self {
         ExecutionContext {
-            variables: HashMap::new(),
+            variables: Frame::new(),
             functions: HashMap::new(),
-            is_applicable: true
+            user_functions: HashMap::new(),
+            scopes: Vec::new(),
+            is_applicable: true,
+            integer_overflow: IntegerOverflow::default(),
+            decimal_json: DecimalJson::default()
         }
     } 
 
@@ -317,16 +564,36 @@ impl<'a> ExecutionContext<'a> {
 
     /// Store a new value in the object indicated by the path of property names. 
     /// Returns a Result to indicate success or failure, since it may not be possible to set the value for the given path.
-    pub fn store_chain(&mut self, path: &Vec<String>, val: ShyValue) -> Result<(), ShyValue> {
+    pub fn store_chain(&mut self, path: &[ChainLink], val: ShyValue) -> Result<(), ShyValue> {
+        if path.iter().any(ChainLink::is_null_safe) {
+            return Err(ShyValue::error(format!("Cannot assign to a null-safe property chain: {}", ChainLink::join(path))));
+        }
         let path_len = path.len();
         match path_len {
             0 => Ok(()),
-            1 => { self.variables.insert(path[0].clone(), val); Ok(()) }
+            1 => { self.variables.insert(path[0].name().to_string(), val); Ok(()) }
             _ => {
+                // Vectors are values, not shared objects, so setting one of their elements means 
+                // storing a modified copy of the whole vector back where it came from.
+                // Storing by integer index into a missing vector first creates it empty, so only index zero may be set.
+                let parent_path = path[..path_len-1].to_vec();
+                let parent = match (self.load_chain(&parent_path), &path[path_len-1]) {
+                    (None, ChainLink::Index(_)) => Some(ShyValue::Vector(vec![])),
+                    (Some(missing), ChainLink::Index(_)) if missing.is_error() => Some(ShyValue::Vector(vec![])),
+                    (parent, _) => parent
+                };
+                if let Some(vector @ ShyValue::Vector(_)) = parent {
+                    let updated_vector = vector.with_element(path[path_len-1].name(), val);
+                    if updated_vector.is_error() {
+                        return Err(updated_vector);
+                    }
+                    return self.store_chain(&parent_path, updated_vector);
+                }
+
                 // We strip one property off the path, because the lvalue must be one link back in the chain 
                 // so that we can perform the final assignment using the last property in the chain.
-                if let Some(ShyValue::Object(lvalue)) = self.vivify(&path[..path_len-1].to_vec()) {
-                    lvalue.as_deref_mut().set(&path[path_len-1], val);
+                if let Some(ShyValue::Object(lvalue)) = self.vivify(&parent_path) {
+                    lvalue.as_deref_mut().set(path[path_len-1].name(), val);
                     Ok(())
                 }
                 else {
@@ -355,12 +622,12 @@ impl<'a> ExecutionContext<'a> {
     ///     - if part of the path already exists but is not a ShyObject
     ///     - if part of the path refers to a ShyObject whose underlying ShyAssociation does not permit that property to be set 
     /// Otherwise, returns a Some(ShyValue::Object). This may be used as an lvalue for setting a property.
-    fn vivify(&mut self, path: &Vec<String>) -> Option<ShyValue> {
+    fn vivify(&mut self, path: &[ChainLink]) -> Option<ShyValue> {
         let path_len = path.len();
         match path_len {
             0 => None, 
             1 => {
-                let variable = path[0].clone();
+                let variable = path[0].name().to_string();
                 match self.variables.get(&variable) {
                     None => {
                         let obj = ShyObject::empty();
@@ -372,12 +639,13 @@ impl<'a> ExecutionContext<'a> {
                 }
             }
             _ => {
-                // We can't use ShyObject.vivify from the top, because self.variables is a HashMap.
+                // We can't use ShyObject.vivify from the top, because self.variables is a Frame.
                 // We must manually vivify the first level, then we can use ShyObject.vivify for the rest.
-                let top_key = &path[0].to_string();
+                let top_key = &path[0].name().to_string();
+                let properties: Vec<&str> = path[1..].iter().map(ChainLink::name).collect();
                 match self.variables.get(top_key) {
                     Some(ShyValue::Object(top_obj)) => {
-                        let deep_obj_option = top_obj.shallow_clone().vivify(path[1..].to_vec(), path_len - 1, || ShyObject::empty());
+                        let deep_obj_option = top_obj.shallow_clone().vivify(properties, path_len - 1, || ShyObject::empty());
                         match deep_obj_option {
                             Some(deep_obj) => Some(ShyValue::Object(deep_obj.shallow_clone())),
                             None => None
@@ -387,7 +655,7 @@ impl<'a> ExecutionContext<'a> {
                         let mut top_obj = ShyObject::empty();
                         let new_top = ShyValue::Object(top_obj.shallow_clone());
                         self.variables.insert(top_key.clone(), new_top);
-                        let deep_obj_option = top_obj.vivify(path[1..].to_vec(), path_len - 1, || ShyObject::empty());
+                        let deep_obj_option = top_obj.vivify(properties, path_len - 1, || ShyObject::empty());
                         match deep_obj_option {
                             Some(deep_obj) => Some(ShyValue::Object(deep_obj.shallow_clone())),
                             None => None
@@ -400,14 +668,12 @@ impl<'a> ExecutionContext<'a> {
     }
 
     /// Retrieve the current value of the variable from the context, or None.
-    /// Scalar values are cloned; ShyOjects are shallow cloned, because we need changes
+    /// Scalar values are cloned; ShyObjects (including those inside vectors) are shallow cloned, because we need changes
     /// made to the context to be visible to the caller.
     pub fn load<T>(&self, name: &T) -> Option<ShyValue>
-    where T : Into<String> + Sized + Clone { 
-        let string_name : &String = &(name.clone()).into();
-        match self.variables.get(string_name) {
-            Some(ShyValue::Object(obj)) => Some(ShyValue::Object(obj.shallow_clone())),
-            Some(val) => Some(val.clone()),
+    where T : AsRef<str> + ?Sized { 
+        match self.variables.get(name.as_ref()) {
+            Some(val) => Some(val.shallow_clone()),
             None => None
         }
     }
@@ -416,26 +682,53 @@ impl<'a> ExecutionContext<'a> {
     /// Scalar values are cloned; ShyObjects are shallow cloned, because we need changes
     /// made to the context to be visible to the caller.
     /// The first name in the chain must be a variable name in the context. 
-    /// The remaining names must be property names that can be traversed from object to object via get.
-    pub fn load_chain(&self, chain: &Vec<String>) -> Option<ShyValue> { 
+    /// The remaining names must be property names that can be traversed from object to object via get,
+    /// or indices into vectors.
+    pub fn load_chain(&self, chain: &[ChainLink]) -> Option<ShyValue> { 
         match chain.first() {
             None => None,
-            Some(variable) => {
-                let variable_str: &String = &variable;
-                match self.load(variable_str) {
-                    Some(ShyValue::Object(ref obj)) if chain.len() == 1 => {
-                        Some(ShyValue::Object(obj.shallow_clone()))
-                    },
-                    Some(ShyValue::Object(ref obj)) if chain.len() > 1 => {
-                        let value_clone = ShyValue::Object(obj.shallow_clone());
-                        let properties = &chain[1..];
-                        Some(value_clone.get_chain(properties))
-                    },
-                    Some(_) if chain.len() > 1 => { None },
-                    Some(ref first_value) if chain.len() == 1 => { Some(first_value.clone()) }
-                    _ => None,
-                }
-            }
+            Some(variable) => Self::follow_chain(self.load(variable.name()), chain)
+        }
+    }
+
+    /// Retrieve the current value of the variable in the given slot of the frame, or None.
+    /// Values are shallow cloned, as by load.
+    pub fn load_slot(&self, slot: usize) -> Option<ShyValue> {
+        self.variables.get_slot(slot).map(|val| val.shallow_clone())
+    }
+
+    /// Retrieve the current value of the property chain whose first variable is in the given slot of the frame, or None.
+    pub fn load_chain_slot(&self, slot: usize, chain: &[ChainLink]) -> Option<ShyValue> {
+        Self::follow_chain(self.load_slot(slot), chain)
+    }
+
+    /// Store a new value for the variable in the given slot of the frame.
+    pub fn store_slot(&mut self, slot: usize, val: ShyValue) {
+        self.variables.set_slot(slot, val);
+    }
+
+    /// Follow the rest of the property chain from the value of its first variable.
+    /// If the chain begins with a null-safe step, as in well?.casing, or its first variable is itself null-safe, 
+    /// a missing first variable is treated as null.
+    fn follow_chain(first_value: Option<ShyValue>, chain: &[ChainLink]) -> Option<ShyValue> {
+        if chain.iter().take(2).any(ChainLink::is_null_safe) {
+            return Some(first_value.unwrap_or(ShyValue::Scalar(ShyScalar::Null)).get_chain(&chain[1..]));
+        }
+        match first_value {
+            Some(ShyValue::Object(ref obj)) if chain.len() == 1 => {
+                Some(ShyValue::Object(obj.shallow_clone()))
+            },
+            Some(ShyValue::Object(ref obj)) if chain.len() > 1 => {
+                let value_clone = ShyValue::Object(obj.shallow_clone());
+                let properties = &chain[1..];
+                Some(value_clone.get_chain(properties))
+            },
+            Some(ref vector @ ShyValue::Vector(_)) if chain.len() > 1 => {
+                Some(vector.get_chain(&chain[1..]))
+            },
+            Some(_) if chain.len() > 1 => { None },
+            Some(ref first_value) if chain.len() == 1 => { Some(first_value.clone()) }
+            _ => None,
         }
     }
 
@@ -445,8 +738,8 @@ impl<'a> ExecutionContext<'a> {
     }
 
     /// Convert a string slice into a property chain vector.
-    pub fn str_to_property_chain(chain_as_string : &str) -> Vec<String> {
-        chain_as_string.split(".").map(|s| s.to_string()).collect()
+    pub fn str_to_property_chain(chain_as_string : &str) -> Vec<ChainLink> {
+        ChainLink::parse_chain(chain_as_string)
     }
 
     /// Perform the common tasks associated with updating a variable associated with a property chain.
@@ -467,7 +760,7 @@ impl<'a> ExecutionContext<'a> {
     /// If there is no previous value, use infer_previous_value as that value to be returned, if needed.
     pub fn property_chain_update(
         &mut self, 
-        path: &Vec<String>,
+        path: &[ChainLink],
         infer_prior_value: &ShyValue,
         present_cb: &dyn Fn(&ShyValue) -> ShyValue, 
         absent_cb: &dyn Fn() -> ShyValue,
@@ -511,14 +804,69 @@ impl<'a> ExecutionContext<'a> {
         }
     }
 
-    /// Call a function that is stored in the context.
-    pub fn call(&self, function_name: String, args: ShyValue) -> ShyValue {
+    /// Call a function that is stored in the context, preferring one declared by an expression over a predefined one.
+    /// A variable holding a lambda may also be called like a function.
+    /// The arguments are those written in the call, one item per argument.
+    pub fn call(&mut self, function_name: String, args: &[ShyValue]) -> ShyValue {
+        if let Some(user_function) = self.user_functions.get(&function_name).cloned() {
+            return user_function.call(args, self);
+        }
+        if let Some(ShyValue::Lambda(lambda)) = self.variables.get(&function_name).cloned() {
+            return lambda.call(args, self);
+        }
+        if let Some(result) = higher_order::call(&function_name, args, self) {
+            return result;
+        }
         match self.functions.get(&function_name) {
             Some(func) => func(args),
             None => ShyValue::error(format!("No function named {} in context", function_name))
         }
     }
 
+    /// Register a function declared by an expression, replacing any earlier declaration of the same name.
+    pub fn define_function(&mut self, function: UserFunction) {
+        self.user_functions.insert(function.name.clone(), function);
+    }
+
+    /// Bind variables in a new local scope, hiding any variables of the same names until pop_scope is called.
+    pub fn push_scope(&mut self, bindings: Vec<(String, ShyValue)>) {
+        let mut hidden = Vec::with_capacity(bindings.len());
+        for (name, value) in bindings {
+            hidden.push((name.clone(), self.variables.insert(name, value)));
+        }
+        self.scopes.push(hidden);
+    }
+
+    /// Discard the innermost local scope, restoring the variables it hid.
+    pub fn pop_scope(&mut self) {
+        if let Some(hidden) = self.scopes.pop() {
+            self.restore_variables(hidden);
+        }
+    }
+
+    /// Save the current values of the named variables (or None for any that has no value), 
+    /// so that restore_variables can undo any assignments made to them in the meantime. 
+    /// Unlike push_scope, this does not count as a function call.
+    pub fn save_variables<'n, I>(&self, names: I) -> Vec<(String, Option<ShyValue>)> where I: IntoIterator<Item = &'n String> {
+        names.into_iter().map(|name| (name.clone(), self.variables.get(name).cloned())).collect()
+    }
+
+    /// Put back the values of variables saved by save_variables or hidden by push_scope, 
+    /// removing any variable that had no value.
+    pub fn restore_variables(&mut self, saved: Vec<(String, Option<ShyValue>)>) {
+        for (name, value) in saved.into_iter().rev() {
+            match value {
+                Some(value) => self.variables.insert(name, value),
+                None => self.variables.remove(&name)
+            };
+        }
+    }
+
+    /// Number of local scopes in effect, which is the depth of nested user function calls.
+    pub fn call_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
     /// Get the value of a string property, but return a default value if absent. 
     pub fn get_string_property_chain(&self, property : &str, default_value : String) -> String {
         match self.load_str_chain(property) {
@@ -6,15 +6,24 @@ use std::result::Result;
 use std::borrow::BorrowMut;
 
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::IgnoredAny;
 use log::{trace};
 
-use super::shy_token::{ShyToken, ShyValue};
+use super::shy_token::{ShyToken, ShyValue, SpannedToken};
+use super::chain_link::ChainLink;
 use super::ShuntingYard;
+use super::dialect::Dialect;
 use super::execution_context::ExecutionContext;
 use super::shy_operator::ShyOperator;
 use super::shy_scalar::ShyScalar;
 use crate::graph::Graph;
 use super::references::References;
+use super::user_function::UserFunction;
+use super::frame::{self, Binding};
+use super::bytecode::Bytecode;
+use super::optimizer;
+use super::shy_error::ShyError;
+use crate::lexer::span::Span;
 
 //..................................................................
 
@@ -27,8 +36,23 @@ pub struct Expression<'a> {
     pub expression_source: String,
 
     /// The constants, variable references and operators parsed from the expression_source and rearranged into postfix order.
-    /// This list of tokens was generated by the shunting yard algorithm.
-    pub postfix_order: Vec<ShyToken>,
+    /// This list of tokens was generated by the shunting yard algorithm. 
+    /// Each token has the span of the expression_source from which it was compiled, which is empty if the expression was deserialized.
+    pub postfix_order: Vec<SpannedToken>,
+
+    #[serde(default)]
+    /// The local variables declared with `let`. 
+    /// Their values last only for a single execution, after which any variables of the same names in the context are restored.
+    pub locals: Vec<LocalVariable>,
+
+    #[serde(skip)]
+    /// The errors that prevented the expression from compiling, if any, in the order they appear in the expression_source.
+    compile_errors: Vec<ShyError>,
+
+    #[serde(skip)]
+    /// Warnings about parts of the expression that compiled, but likely do not mean what was intended,
+    /// such as a comparison of the result of another comparison. They are located in the expression_source, in the order they appear.
+    pub compile_warnings: Vec<ShyError>,
 
     #[serde(serialize_with = "serialize_references")]
     #[serde(deserialize_with = "deserialize_references")]
@@ -37,7 +61,18 @@ pub struct Expression<'a> {
     references: Arc<RwLock<Option<References>>>,
 
     /// If true, a trace of the execution of the expression is printed as a diagnostic.
-    pub trace_on: bool
+    pub trace_on: bool,
+
+    #[serde(skip)]
+    /// The postfix order with its variables resolved to slots, set by bind.
+    /// It is used in place of postfix_order when executing against a context whose variables have the same layout.
+    /// Boxed, since a lambda's body is an Expression held within a ShyValue, and ShyValues should be small.
+    binding: Option<Box<Binding>>,
+
+    #[serde(skip)]
+    /// The bytecode assembled from postfix_order, which is what exec runs. 
+    /// Created lazily on first execution.
+    bytecode: Arc<RwLock<Option<Arc<Bytecode>>>>
 }
 
 
@@ -51,12 +86,23 @@ fn serialize_references<S>(references_rrro : &Arc<RwLock<Option<References>>>, s
     }
 }
 
-/// When deserializing, set references to None, since they can be recomputed lazily as needed.
-fn deserialize_references<'de, D>(_deserializer : D) -> Result<Arc<RwLock<Option<References>>>, D::Error> where D: Deserializer<'de> {
+/// When deserializing, skip over the serialized references and set references to None, since they can be recomputed lazily as needed.
+fn deserialize_references<'de, D>(deserializer : D) -> Result<Arc<RwLock<Option<References>>>, D::Error> where D: Deserializer<'de> {
+    IgnoredAny::deserialize(deserializer)?;
     Ok(Arc::new(RwLock::new(None)))
 }
 
 
+/// A local variable declared with `let`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LocalVariable {
+    pub name: String,
+
+    /// Position in postfix_order just after the statement that declares the variable. 
+    /// Before it, as in `let tmp = tmp + 1`, the name is read from the context.
+    pub scope_start: usize
+}
+
 pub trait Expressive<'a> {
     fn express(&self) -> &Expression<'a>;
     fn express_mut(&mut self) -> &mut Expression<'a>;
@@ -71,38 +117,98 @@ impl<'a> Expression<'a> {
 
     /// Create and compile a new Expression from a String or &str slice.
     /// If compilation fails, return an Expression with a single Error token 
-    /// for which had_compile_error() will return true.
+    /// for which had_compile_error() will return true, and compile_errors() will describe every error found.
     pub fn new<S>(expr_source: S) -> Expression<'a> where S: Into<String> {
+        Expression::new_in_dialect(expr_source, Dialect::Standard)
+    }
+
+    /// Create and compile a new Expression written in the given dialect, such as the spreadsheet dialect, 
+    /// which accepts `<>`, `=` for equals and capitalized function names like `IF` and `ROUND`.
+    pub fn new_in_dialect<S>(expr_source: S, dialect: Dialect) -> Expression<'a> where S: Into<String> {
         let expr_string: String = expr_source.into();
-        let shy : ShuntingYard = expr_string.clone().into(); 
-        match shy.compile() {
+        let mut shy : ShuntingYard = expr_string.clone().into(); 
+        shy.dialect = dialect;
+        match shy.compile_with_recovery() {
             Ok(expr) => expr,
-            _ => Expression {
+            Err(errors) => Expression {
                 marker: PhantomData,
                 expression_source: expr_string.clone(),
-                postfix_order: vec![ShyToken::Error],
+                postfix_order: vec![ShyToken::Error.into()],
+                locals: vec![],
+                compile_errors: errors,
+                compile_warnings: vec![],
                 references: Arc::new(RwLock::new(None)),
-                trace_on: false
+                trace_on: false,
+                binding: None,
+                bytecode: Arc::new(RwLock::new(None))
             }
         }
     }
 
     /// Create an Expression given its already compiled form. 
-    pub fn new_from_parser<S>(expr_source: S, postfix : &Vec<ShyToken>) -> Expression<'a> where S: Into<String> {
+    pub fn new_from_parser<S>(expr_source: S, postfix : &[SpannedToken]) -> Expression<'a> where S: Into<String> {
         Expression { 
             marker: PhantomData,
             expression_source: expr_source.into(),
-            postfix_order: postfix.clone(),
+            postfix_order: postfix.to_vec(),
+            locals: vec![],
+            compile_errors: vec![],
+            compile_warnings: vec![],
             references: Arc::new(RwLock::new(None)),
-            trace_on: false
+            trace_on: false,
+            binding: None,
+            bytecode: Arc::new(RwLock::new(None))
         }
     }
 
+    /// Functions declared by this expression with `fn`, which are registered in the context when the expression is executed.
+    pub fn function_definitions(&self) -> Vec<&UserFunction> {
+        self.postfix_order.iter().filter_map(|spanned| match &spanned.token {
+            ShyToken::FunctionDefinition(function) => Some(function),
+            _ => None
+        }).collect()
+    }
+
     /// Return true if an error occurred while compiling the expression. 
     /// The error is likely due to a syntax error in the expression, not
     /// a failure of the parser.
     pub fn had_compile_error(&self) -> bool {
-        self.postfix_order.len() == 0 || self.postfix_order.iter().any(|token| token.is_error() )
+        self.postfix_order.len() == 0 || self.postfix_order.iter().any(|spanned| spanned.token.is_error() )
+    }
+
+    /// The first error that prevented the expression from compiling, located in the expression_source.
+    pub fn compile_error(&self) -> Option<&ShyError> {
+        self.compile_errors.first()
+    }
+
+    /// Every error that prevented the expression from compiling, located in the expression_source. 
+    /// After each error, compilation resumed at the next statement, so there is at most one error per statement.
+    pub fn compile_errors(&self) -> &[ShyError] {
+        &self.compile_errors
+    }
+
+    /// Resolve the variables of the expression to slots in the variables of the context, adding slots for any that are missing.
+    /// When later executed against this context (or a clone of it that has not since added variables of its own),
+    /// the expression accesses those variables by slot instead of by name. 
+    /// Against any other context, it still works, but looks up its variables by name.
+    /// 
+    /// Standard constants, like π, that the context holds with their standard values are inlined. 
+    /// Should the context later hold some other value for one of them, as after `e = 5`, the expression again looks up its variables by name.
+    pub fn bind(&mut self, context: &mut ExecutionContext<'a>) {
+        let (inlined_order, constants) = optimizer::inline_constants(&self.postfix_order, context);
+        let mut binding = frame::bind(&inlined_order, &mut context.variables);
+        binding.constants = constants.into_iter().map(|(name, value)| (context.variables.slot(&name), value)).collect();
+        self.binding = Some(Box::new(binding));
+    }
+
+    /// True if the expression accesses the variables of the context by slot, 
+    /// which requires that the context still hold the values of any constants inlined when it was bound.
+    pub fn is_bound_to(&self, context: &ExecutionContext<'a>) -> bool {
+        match self.binding {
+            Some(ref binding) => binding.layout_id == context.variables.layout_id()
+                && binding.constants.iter().all(|(slot, value)| context.variables.get_slot(*slot) == Some(value)),
+            None => false
+        }
     }
 
     /// Execute an already compiled expression against the given ExecutionContext.  
@@ -113,22 +219,126 @@ impl<'a> Expression<'a> {
     /// if it encounters a `false` value. 
     /// 
     /// There are two ways that the returned value can indicate an error: 
-    ///   - an Err(error), 
+    ///   - an Err(ShyError), a runtime error located in the expression source,
     ///   - an Ok(ShyValue::Scalar(ShyScalar::Error(error)))
-    pub fn exec(&self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue,String> {
+    /// 
+    /// The expression is executed as bytecode, unless trace is on, in which case it is interpreted.
+    /// Any local variables declared with `let` are removed from the context afterwards.
+    pub fn exec(&self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue,ShyError> {
+        if self.trace_on {
+            return self.interpret(context);
+        }
+        let (result, span) = self.with_locals(context, |context| match self.binding {
+            Some(ref binding) if self.is_bound_to(context) => binding.bytecode.run(context, false),
+            _ => self.bytecode().run(context, false)
+        });
+        result.map_err(|message| self.runtime_error(message, span))
+    }
+
+    /// Make a runtime error located in the expression_source.
+    fn runtime_error(&self, message: String, span: Option<Span>) -> ShyError {
+        ShyError::runtime(message, span).locate(&self.expression_source)
+    }
+
+    /// Run the expression, then restore any variables in the context that share a name with one of its local variables,
+    /// or remove them if there were none. 
+    fn with_locals<T, F>(&self, context: &mut ExecutionContext<'a>, run: F) -> T where F: FnOnce(&mut ExecutionContext<'a>) -> T {
+        if self.locals.is_empty() {
+            return run(context);
+        }
+        let saved = context.save_variables(self.locals.iter().map(|local| &local.name));
+        let result = run(context);
+        context.restore_variables(saved);
+        result
+    }
+
+    /// Execute the expression as exec does, but report any failure as a ShyError located in the expression_source: 
+    /// a compile error, an Err from execution, or an error value as the result. 
+    /// A runtime error gets the span of the token that first produced an error value.
+    pub fn try_exec(&self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue,ShyError> {
+        if let Some(error) = self.compile_error() {
+            return Err(error.clone());
+        }
+        let (result, span) = self.with_locals(context, |context| match self.binding {
+            Some(ref binding) if self.is_bound_to(context) => binding.bytecode.run(context, true),
+            _ => self.bytecode().run(context, true)
+        });
+        match result {
+            Ok(ShyValue::Scalar(ShyScalar::Error(message))) => Err(self.runtime_error(message, span)),
+            Ok(value) => Ok(value),
+            Err(message) => Err(self.runtime_error(message, span))
+        }
+    }
+
+    /// Get the bytecode assembled from the postfix order, assembling it if this is the first time.
+    fn bytecode(&self) -> Arc<Bytecode> {
+        if let Some(ref bytecode) = *self.bytecode.as_ref().read().unwrap() {
+            return bytecode.clone();
+        }
+        let bytecode = Arc::new(Bytecode::assemble(&self.postfix_order));
+        self.bytecode.as_ref().write().unwrap().replace(bytecode.clone());
+        bytecode
+    }
+
+    /// Execute the expression by interpreting its postfix tokens one at a time, with the same effect as exec. 
+    /// This is the reference implementation against which the bytecode is tested, and it is used when tracing.
+    pub fn interpret(&self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue,ShyError> {
+        self.with_locals(context, |context| self.interpret_tokens(context))
+            .map_err(|message| self.runtime_error(message, None))
+    }
+
+    fn interpret_tokens(&self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue,String> {
         let mut output_stack : Vec<ShyValue> = vec![];
         context.is_applicable = true;
+        let postfix_order = match self.binding {
+            Some(ref binding) if self.is_bound_to(context) => &binding.postfix_order,
+            _ => &self.postfix_order
+        };
         if self.trace_on {
             trace!(target: "parser::expression", "Tracing: {}", self.expression_source);
-            Self::dump_postfix(&self.postfix_order);
+            Self::dump_postfix(postfix_order);
         }
-        for token in self.postfix_order.iter().cloned() {
+        let mut position = 0;
+        while position < postfix_order.len() {
+            let token = postfix_order[position].token.clone();
+            position += 1;
             if self.trace_on {
                 Self::dump_stack(&output_stack);
                 trace!(target: "parser::expression", "  Token: {:?}", token);
             }
             match token {
                 ShyToken::Value(value) => output_stack.push(value),
+                ShyToken::Branch(op, target) => {
+                    if let Some(next_position) = Self::branch(&mut output_stack, op, target) {
+                        position = next_position;
+                    }
+                },
+                ShyToken::FunctionDefinition(function) => {
+                    output_stack.push(ShyValue::FunctionName(function.name.clone()));
+                    context.define_function(function);
+                },
+                ShyToken::LoadSlot(slot) => {
+                    let value = match context.load_slot(slot) {
+                        Some(value) => value,
+                        None => ShyValue::no_such_variable(context.variables.name_of(slot))
+                    };
+                    output_stack.push(value);
+                },
+                ShyToken::LoadChain(slot, chain) => {
+                    let value = match context.load_chain_slot(slot, &chain) {
+                        Some(value) => value,
+                        None => ShyValue::bad_property_chain(&chain)
+                    };
+                    output_stack.push(value);
+                },
+                ShyToken::StoreSlot(slot) => {
+                    // Like Assign, the value is left on the stack as the result, but an error is not stored.
+                    match output_stack.last() {
+                        Some(value) if !value.is_error() => context.store_slot(slot, value.clone()),
+                        Some(_) => (),
+                        None => output_stack.push(ShyValue::error("Too few values on stack for operation Assign. Size = 0".to_string()))
+                    }
+                },
                 ShyToken::Operator(ShyOperator::QuitIfFalse) => {
                     // Shortcut the expression evaluation at the question mark, cease execution and return false. 
                     let test_result = Self::operate(&mut output_stack, ShyOperator::QuitIfFalse, context);
@@ -163,10 +373,10 @@ impl<'a> Expression<'a> {
         }
     }
 
-    fn dump_postfix(postfix_order: &Vec<ShyToken>) {
+    fn dump_postfix(postfix_order: &[SpannedToken]) {
         trace!(target: "parser::expression", "Postfix order:");
         let mut i = 0;
-        for token in postfix_order.iter().cloned() {
+        for token in postfix_order.iter().map(|spanned| &spanned.token) {
             i = i + 1;
             trace!(target: "parser::expression", "  {}. {:?}", i, token);
         }
@@ -174,7 +384,7 @@ impl<'a> Expression<'a> {
 
     /// Execute the expression with trace turned on, to print diagnostics to the console.
     /// This may have side effects upon the context!
-    pub fn trace(&mut self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue, String> {
+    pub fn trace(&mut self, context: &mut ExecutionContext<'a>) -> std::result::Result<ShyValue, ShyError> {
         self.trace_on = true;
         let exec_result = self.exec(context);
         match &exec_result {
@@ -186,13 +396,117 @@ impl<'a> Expression<'a> {
         exec_result
     }
 
+    /// Apply a branching operator, returning the position of the next token to execute if the branch is taken, 
+    /// or None if execution should continue with the following token.
+    ///   - Jump always branches to its target.
+    ///   - And branches to its target if the left operand on top of the stack is falsey, replacing it with false.
+    ///     Otherwise it leaves the left operand for the And operator that follows the right operand.
+    ///   - Or branches to its target if the left operand on top of the stack is truthy, replacing it with true.
+    ///     Errors never cause And or Or to branch, so that the And or Or operator can decide how to combine them.
+    ///   - Coalesce branches to its target if the left operand on top of the stack is not null, leaving it as the result.
+    ///   - Ternary pops the test value. Its target is the position of the Jump that ends the true branch.
+    ///     A truthy test continues into the true branch, a falsey test resumes just after the Jump (the false branch),
+    ///     and an error resumes at the Jump itself, skipping both branches and leaving the error as the result.
+    pub fn branch(output_stack: &mut Vec<ShyValue>, op: ShyOperator, target: usize) -> Option<usize> {
+        match op {
+            ShyOperator::Jump => Some(target),
+            ShyOperator::And | ShyOperator::Or => {
+                let decided = match output_stack.last() {
+                    Some(left) if left.is_error() => None,
+                    Some(left) if op == ShyOperator::And && left.is_falsey() => Some(false),
+                    Some(left) if op == ShyOperator::Or && left.is_truthy() => Some(true),
+                    _ => None
+                };
+                match decided {
+                    Some(result) => {
+                        output_stack.pop();
+                        output_stack.push(result.into());
+                        Some(target)
+                    },
+                    None => None
+                }
+            },
+            ShyOperator::Coalesce => {
+                match output_stack.last() {
+                    Some(left) if !left.is_null() => Some(target),
+                    _ => None
+                }
+            },
+            // A comparison that continues a chain, as in `0 < x <= 10`. If it is false (or an error), its result ends the chain,
+            // otherwise its right operand is left to be the left operand of the next comparison.
+            op if op.is_ordering() => {
+                if output_stack.len() < 2 {
+                    output_stack.push(ShyValue::error(format!("Too few values on stack for operation {}. Size = {}", op, output_stack.len())));
+                    return Some(target);
+                }
+                let right = output_stack.pop().unwrap();
+                let left = output_stack.pop().unwrap();
+                let result = match op {
+                    ShyOperator::LessThan => ShyValue::less_than(&left, &right),
+                    ShyOperator::LessThanOrEqualTo => ShyValue::less_than_or_equal_to(&left, &right),
+                    ShyOperator::GreaterThan => ShyValue::greater_than(&left, &right),
+                    _ => ShyValue::greater_than_or_equal_to(&left, &right)
+                };
+                if result.is_error() || result.is_falsey() {
+                    output_stack.push(result);
+                    Some(target)
+                }
+                else {
+                    output_stack.push(right);
+                    None
+                }
+            },
+            // The test of an arm of a match expression, with the subject of the match beneath it. 
+            // If the test passes, the subject is discarded and the arm's result follows. If it fails, the subject is kept for the test 
+            // of the next arm, which begins just after the Jump at the target. An error in the subject or the test ends the match 
+            // with the error as its result.
+            ShyOperator::Case => {
+                match output_stack.pop() {
+                    Some(_) if Self::does_stack_have_error(output_stack) => Some(target),
+                    Some(test) if test.is_error() => {
+                        output_stack.pop();
+                        output_stack.push(test);
+                        Some(target)
+                    },
+                    Some(test) if test.is_truthy() => {
+                        output_stack.pop();
+                        None
+                    },
+                    Some(_) => Some(target + 1),
+                    None => {
+                        output_stack.push(ShyValue::error("Too few values on stack for operation Case. Size = 0".to_string()));
+                        Some(target)
+                    }
+                }
+            },
+            ShyOperator::Ternary => {
+                match output_stack.pop() {
+                    Some(test) if test.is_error() => {
+                        output_stack.push(test);
+                        Some(target)
+                    },
+                    Some(test) if test.is_truthy() => None,
+                    Some(_) => Some(target + 1),
+                    None => {
+                        output_stack.push(ShyValue::error("Too few values on stack for operation Ternary. Size = 0".to_string()));
+                        Some(target)
+                    }
+                }
+            },
+            _ => {
+                output_stack.push(ShyValue::error(format!("Operation {} cannot branch", op.to_string())));
+                None
+            }
+        }
+    }
+
     /// Check if the stack has enough items to satisfy the needs of the operator
-    fn is_stack_size_sufficient(output_stack: &mut Vec<ShyValue>, op: ShyOperator) -> bool {
+    pub fn is_stack_size_sufficient(output_stack: &mut Vec<ShyValue>, op: ShyOperator) -> bool {
         op.arguments() <= output_stack.len() 
     }
 
     /// Check if the stack is topped by an error value
-    fn does_stack_have_error(output_stack: &mut Vec<ShyValue>) -> bool {
+    pub fn does_stack_have_error(output_stack: &mut Vec<ShyValue>) -> bool {
         match output_stack.last() {
             Some(ShyValue::Scalar(ShyScalar::Error(_))) => true,
             _ => false
@@ -200,7 +514,21 @@ impl<'a> Expression<'a> {
     }
 
     /// Apply an operator, removing tokens from the stack, computing a result, and pushing the result back on the stack.
-    fn operate(output_stack: &mut Vec<ShyValue>, op: ShyOperator, context: &mut ExecutionContext<'a>) -> ShyValue {
+    pub fn operate(output_stack: &mut Vec<ShyValue>, op: ShyOperator, context: &mut ExecutionContext<'a>) -> ShyValue {
+        // The subject of a match expression is copied even if it is an error, so that the test of each arm has it as an operand.
+        if op == ShyOperator::CaseSubject {
+            return match output_stack.last().cloned() {
+                Some(subject) => {
+                    output_stack.push(subject.clone());
+                    subject
+                },
+                None => {
+                    let stack_empty = ShyValue::error("Too few values on stack for operation CaseSubject. Size = 0".to_string());
+                    output_stack.push(stack_empty.clone());
+                    stack_empty
+                }
+            };
+        }
         if Self::does_stack_have_error(output_stack) { return output_stack.last().unwrap().clone(); }
         if !Self::is_stack_size_sufficient(output_stack, op)   {
             let stack_empty = ShyValue::error(format!("Too few values on stack for operation {:?}. Size = {}", op, output_stack.len()));
@@ -215,7 +543,7 @@ impl<'a> Expression<'a> {
         // If a unary operator, arg2 is unused.
         // If a binary operator, arg2 is the right operand.
         let mut arg2: ShyValue = 0.into();
-        let mut _arg3: ShyValue = 0.into();
+        let mut arg3: ShyValue = 0.into();
 
         match op.arguments() {
             1 => {
@@ -226,16 +554,13 @@ impl<'a> Expression<'a> {
                 arg1 = output_stack.pop().unwrap();
             },
             3 => {
-                _arg3 = output_stack.pop().unwrap();
+                arg3 = output_stack.pop().unwrap();
                 arg2 = output_stack.pop().unwrap();
                 arg1 = output_stack.pop().unwrap();
             },
             _ => ()
         }
-        let unimplemented = ShyValue::error(format!("Operation {} unimplemented", op.to_string()));
         let result = match op {
-            ShyOperator::Load => ShyValue::load(&arg1, context),
-            ShyOperator::Store => unimplemented,
             ShyOperator::Semicolon => {
                 // Semicolons separate individual statements.
                 // When we encounter one, wipe the stack clear to prepare for the next statement. 
@@ -248,46 +573,6 @@ impl<'a> Expression<'a> {
                 output_stack.clear();
                 return intermediate_result;
             },
-            ShyOperator::FunctionCall => ShyValue::call(&arg1, &arg2, context),
-            ShyOperator::OpenParenthesis => unimplemented,
-            ShyOperator::CloseParenthesis => unimplemented,
-            ShyOperator::Comma => ShyValue::comma(&arg1, &arg2),
-            ShyOperator::OpenBracket => unimplemented,
-            ShyOperator::CloseBracket => unimplemented,
-            ShyOperator::Member => unimplemented,
-            ShyOperator::Power => ShyValue::power(&arg1, &arg2),
-            ShyOperator::Exponentiation => ShyValue::power(&arg1, &arg2),
-            ShyOperator::PrefixPlusSign => ShyValue::prefix_plus(&arg1),
-            ShyOperator::PrefixMinusSign => ShyValue::prefix_minus(&arg1),
-            ShyOperator::PostIncrement => ShyValue::post_increment(&arg1, context),
-            ShyOperator::PostDecrement => ShyValue::post_decrement(&arg1, context),
-            ShyOperator::SquareRoot => ShyValue::sqrt(&arg1),
-            ShyOperator::LogicalNot => ShyValue::not(&arg1),
-            ShyOperator::Factorial => ShyValue::factorial(&arg1),
-            ShyOperator::Match => ShyValue::matches(&arg1, &arg2),
-            ShyOperator::NotMatch => ShyValue::not_matches(&arg1, &arg2),
-            ShyOperator::Multiply => ShyValue::multiply(&arg1, &arg2),
-            ShyOperator::Divide => ShyValue::divide(&arg1, &arg2),
-            ShyOperator::Mod => ShyValue::modulo(&arg1, &arg2),
-            ShyOperator::Add => ShyValue::add(&arg1, &arg2),
-            ShyOperator::Subtract => ShyValue::subtract(&arg1, &arg2),
-            ShyOperator::LessThan => ShyValue::less_than(&arg1, &arg2),
-            ShyOperator::LessThanOrEqualTo => ShyValue::less_than_or_equal_to(&arg1, &arg2),
-            ShyOperator::GreaterThan => ShyValue::greater_than(&arg1, &arg2),
-            ShyOperator::GreaterThanOrEqualTo => ShyValue::greater_than_or_equal_to(&arg1, &arg2),
-            ShyOperator::Equals => ShyValue::equals(&arg1, &arg2),
-            ShyOperator::NotEquals => ShyValue::not_equals(&arg1, &arg2),
-            ShyOperator::And => ShyValue::and(&arg1, &arg2), 
-            ShyOperator::Or => ShyValue::or(&arg1, &arg2), 
-            ShyOperator::Ternary => unimplemented,
-            ShyOperator::Assign => ShyValue::assign(&arg1, &arg2, context),
-            ShyOperator::PlusAssign => ShyValue::plus_assign(&arg1, &arg2, context),
-            ShyOperator::MinusAssign => ShyValue::minus_assign(&arg1, &arg2, context),
-            ShyOperator::MultiplyAssign => ShyValue::multiply_assign(&arg1, &arg2, context),
-            ShyOperator::DivideAssign => ShyValue::divide_assign(&arg1, &arg2, context),
-            ShyOperator::ModAssign => ShyValue::modulo_assign(&arg1, &arg2, context),
-            ShyOperator::AndAssign => ShyValue::and_assign(&arg1, &arg2, context),
-            ShyOperator::OrAssign => ShyValue::or_assign(&arg1, &arg2, context),
             ShyOperator::QuitIfFalse => {
                 if arg1.is_falsey() {
                     output_stack.push(false.into());
@@ -298,11 +583,14 @@ impl<'a> Expression<'a> {
                     true.into()
                 }
             },
-            _ => {
-                output_stack.clear();
-                let unsupported = ShyValue::error(format!("Invalid operator {:?}", op));
-                output_stack.push(unsupported.clone());
-                unsupported
+            _ => match Self::evaluate(op, &arg1, &arg2, &arg3, context) {
+                Some(result) => result,
+                None => {
+                    output_stack.clear();
+                    let unsupported = ShyValue::error(format!("Invalid operator {:?}", op));
+                    output_stack.push(unsupported.clone());
+                    unsupported
+                }
             }
         };
         output_stack.push(result.clone());
@@ -310,6 +598,69 @@ impl<'a> Expression<'a> {
     }
 
 
+    /// Compute the result of applying an operator to its arguments (of which only as many as the operator takes are used),
+    /// or None if the operator does not simply compute a value, as is the case for Semicolon and QuitIfFalse, which act upon the stack.
+    /// Integer arithmetic that overflows is handled as the context's integer_overflow directs.
+    pub fn evaluate(op: ShyOperator, arg1: &ShyValue, arg2: &ShyValue, arg3: &ShyValue, context: &mut ExecutionContext<'a>) -> Option<ShyValue> {
+        let overflow = context.integer_overflow;
+        let result = match op {
+            ShyOperator::Load => ShyValue::load(arg1, context),
+            ShyOperator::FunctionCall => ShyValue::call(arg1, arg2, context),
+            ShyOperator::Comma => ShyValue::comma(arg1, arg2),
+            ShyOperator::Append => ShyValue::append(arg1, arg2),
+            ShyOperator::Store 
+            | ShyOperator::OpenParenthesis | ShyOperator::CloseParenthesis 
+            | ShyOperator::OpenBracket | ShyOperator::CloseBracket 
+            | ShyOperator::OpenBrace | ShyOperator::CloseBrace => ShyValue::error(format!("Operation {} unimplemented", op.to_string())),
+            ShyOperator::Property => ShyValue::property(arg1, arg2, arg3),
+            ShyOperator::Member => ShyValue::member(arg1, arg2),
+            ShyOperator::Index => ShyValue::index(arg1, arg2),
+            ShyOperator::Power => ShyValue::with_overflow(arg1, arg2, overflow, ShyValue::checked_power),
+            ShyOperator::Exponentiation => ShyValue::with_overflow(arg1, arg2, overflow, ShyValue::checked_power),
+            ShyOperator::PrefixPlusSign => ShyValue::prefix_plus(arg1),
+            ShyOperator::PrefixMinusSign => ShyValue::with_overflow(arg1, arg2, overflow, |arg1, _| ShyValue::checked_prefix_minus(arg1)),
+            ShyOperator::PostIncrement => ShyValue::post_increment(arg1, context),
+            ShyOperator::PostDecrement => ShyValue::post_decrement(arg1, context),
+            ShyOperator::SquareRoot => ShyValue::sqrt(arg1),
+            ShyOperator::LogicalNot => ShyValue::not(arg1),
+            ShyOperator::Factorial => ShyValue::with_overflow(arg1, arg2, overflow, |arg1, _| ShyValue::checked_factorial(arg1)),
+            ShyOperator::Match => ShyValue::matches(arg1, arg2),
+            ShyOperator::NotMatch => ShyValue::not_matches(arg1, arg2),
+            ShyOperator::Capture => ShyValue::captures(arg1, arg2),
+            ShyOperator::Multiply => ShyValue::with_overflow(arg1, arg2, overflow, ShyValue::checked_multiply),
+            ShyOperator::Divide => ShyValue::divide(arg1, arg2),
+            ShyOperator::Mod => ShyValue::modulo(arg1, arg2),
+            ShyOperator::ConvertTo => ShyValue::convert_to(arg1, arg2),
+            ShyOperator::Add => ShyValue::with_overflow(arg1, arg2, overflow, ShyValue::checked_add),
+            ShyOperator::Subtract => ShyValue::with_overflow(arg1, arg2, overflow, ShyValue::checked_subtract),
+            ShyOperator::LessThan => ShyValue::less_than(arg1, arg2),
+            ShyOperator::LessThanOrEqualTo => ShyValue::less_than_or_equal_to(arg1, arg2),
+            ShyOperator::GreaterThan => ShyValue::greater_than(arg1, arg2),
+            ShyOperator::GreaterThanOrEqualTo => ShyValue::greater_than_or_equal_to(arg1, arg2),
+            ShyOperator::In => ShyValue::is_in(arg1, arg2),
+            ShyOperator::NotIn => ShyValue::not_in(arg1, arg2),
+            ShyOperator::Between => ShyValue::between(arg1, arg2),
+            ShyOperator::Range => ShyValue::in_range(arg1, arg2),
+            ShyOperator::Equals => ShyValue::equals(arg1, arg2),
+            ShyOperator::NotEquals => ShyValue::not_equals(arg1, arg2),
+            ShyOperator::And => ShyValue::and(arg1, arg2), 
+            ShyOperator::Or => ShyValue::or(arg1, arg2), 
+            ShyOperator::Coalesce => ShyValue::coalesce(arg1, arg2),
+            ShyOperator::CaseDefault => arg2.clone(),
+            ShyOperator::Ternary => ShyValue::ternary(arg1, arg2, arg3),
+            ShyOperator::Assign => ShyValue::assign(arg1, arg2, context),
+            ShyOperator::PlusAssign => ShyValue::plus_assign(arg1, arg2, context),
+            ShyOperator::MinusAssign => ShyValue::minus_assign(arg1, arg2, context),
+            ShyOperator::MultiplyAssign => ShyValue::multiply_assign(arg1, arg2, context),
+            ShyOperator::DivideAssign => ShyValue::divide_assign(arg1, arg2, context),
+            ShyOperator::ModAssign => ShyValue::modulo_assign(arg1, arg2, context),
+            ShyOperator::AndAssign => ShyValue::and_assign(arg1, arg2, context),
+            ShyOperator::OrAssign => ShyValue::or_assign(arg1, arg2, context),
+            _ => return None
+        };
+        Some(result)
+    }
+
     /// Gathers the names of all variables and property chains that are referenced by the expression,
     /// either as definitions or dependencies. 
     /// 
@@ -365,15 +716,22 @@ impl<'a> Expression<'a> {
         let already_recorded = |name : &String, vec1: &Vec<String>, vec2 : &Vec<String>| -> bool { vec1.contains(name) || vec2.contains(name) };
 
         // If a ShyToken::Value(ShyValue::Variable(...)) or a ShyToken::Value(ShyValue::PropertyChain(...)) 
-        // is followed immediately by a ShyValue:: ShyOperator::Load, it could be a definition. 
-        // Otherwise it could be a dependency. Only the first occurrence of that name in the 
+        // is loaded, it could be a dependency. 
+        // Otherwise it could be a definition. Only the first occurrence of that name in the 
         // expression defines whether it is a definition or a dependency. 
-        let peekaboo = &mut self.postfix_order.iter().peekable();
-        while let Some(item) = peekaboo.next() {
-            let followed_by_load = if let Some(ShyToken::Operator(op)) = peekaboo.peek() { *op == ShyOperator::Load } else { false };
-            match item {
+        // Local variables declared with `let` (and property chains rooted in them) are neither, 
+        // since they never leave the expression: even an assignment before the `let` is undone afterwards. 
+        // But where one is read before the end of the statement that declares it, the value read is the one in the context.
+        for (position, spanned) in self.postfix_order.iter().enumerate() {
+            let followed_by_load = self.is_loaded(position);
+            let is_local = |name: &str| self.locals.iter()
+                .any(|local| local.name == name && (position >= local.scope_start || !followed_by_load));
+            match &spanned.token {
+                ShyToken::Value(ShyValue::PropertyChain(chain)) if is_local(chain[0].name()) => (),
+                ShyToken::Value(ShyValue::Variable(variable)) if is_local(variable) => (),
                 ShyToken::Value(ShyValue::PropertyChain(chain)) => {
-                    let chain_string = chain.join(".");
+                    // A null-safe chain like well?.casing depends on the same properties as well.casing.
+                    let chain_string = ChainLink::join_names(chain);
                     let skip = already_recorded(&chain_string, &definitions, &dependencies);
                     if !skip {
                         if followed_by_load { dependencies.push(chain_string); }
@@ -396,6 +754,30 @@ impl<'a> Expression<'a> {
         true
     }
 
+    /// Decide whether the variable or property chain at the given position in postfix order will be loaded 
+    /// (an rvalue) rather than assigned (an lvalue). 
+    /// Usually a Load immediately follows it, but when indexed, as in `readings[i]`, the reference is first 
+    /// extended by Member operators, interleaved with the tokens that compute each index.
+    fn is_loaded(&self, position: usize) -> bool {
+        // Number of values pushed onto the stack above the reference being followed.
+        let mut depth = 0;
+        for spanned in self.postfix_order[position + 1..].iter() {
+            match &spanned.token {
+                ShyToken::Operator(ShyOperator::Load) if depth == 0 => return true,
+                // Member consumes the reference and its index, and yields the extended reference.
+                ShyToken::Operator(ShyOperator::Member) if depth == 1 => depth = 0,
+                ShyToken::Operator(op) => {
+                    // Any other operator that consumes the reference does not load it.
+                    if op.arguments() > depth { return false; }
+                    depth = depth + 1 - op.arguments();
+                },
+                ShyToken::Value(_) => depth += 1,
+                _ => return false
+            }
+        }
+        false
+    }
+
     /// Build the dependency graph for a list of Expressions. 
     ///   - Variables used in an expression that are not defined are dependencies, to be represented as nodes
     ///     that point via an incoming edge to the node corresponding to the Expression. 
@@ -536,6 +918,8 @@ mod tests {
 
     use std::borrow::Borrow;
     use super::ShuntingYard;
+    use super::super::shy_error::ShyErrorKind;
+    use super::super::execution_context::IntegerOverflow;
 
     #[test]
     /// Check that variables_used finds the correct sets of variable dependencies and definitions.
@@ -556,6 +940,40 @@ mod tests {
         asserting("Dependencies match").that(&do_vecs_match(&expected_dependencies, &used.dependencies)).is_equal_to(true);
     }
 
+    #[test]
+    /// Check that null-safe property chains and the left operand of ?? are dependencies under their plain names.
+    fn variables_used_null_safe() {
+        let test_expression_text = "depth = well?.casing?.depth ?? fallback ?? 0";
+        let shy : ShuntingYard = test_expression_text.into();
+        let test_expression = shy.compile().unwrap();
+        let used = test_expression.get_references();
+        let expected_definitions : Vec<String> = vec!["depth".into()];
+        let expected_dependencies : Vec<String> = vec!["well.casing.depth".into(), "fallback".into()];
+        asserting("Definitions match").that(&do_vecs_match(&expected_definitions, &used.definitions)).is_equal_to(true);
+        asserting("Dependencies match").that(&do_vecs_match(&expected_dependencies, &used.dependencies)).is_equal_to(true);
+    }
+
+    #[test]
+    /// Check that indexed variables are dependencies when read and definitions when assigned,
+    /// and that local variables declared with let are neither, unless read before or while they are declared.
+    fn variables_used_with_index_and_locals() {
+        let cases = vec![
+            ("totals[i] = readings[i + 1] * 2", vec!["totals"], vec!["i", "readings"]),
+            ("let tmp = price * 2; let car = auto; total = tmp + car.tax", vec!["total"], vec!["price", "auto"]),
+            ("before = tmp * 2; let tmp = 1; after = tmp", vec!["before", "after"], vec!["tmp"]),
+            ("let count = count + 1; total = count", vec!["total"], vec!["count"]),
+            ("let tmp = 1; x = 2; y = tmp + x", vec!["x", "y"], vec![]),
+            ("tmp = 5; let tmp = 1; y = tmp", vec!["y"], vec![])
+        ];
+        for (source, definitions, dependencies) in cases {
+            let used = Expression::new(source).get_references();
+            let expected_definitions: Vec<String> = definitions.into_iter().map(|name| name.to_string()).collect();
+            let expected_dependencies: Vec<String> = dependencies.into_iter().map(|name| name.to_string()).collect();
+            asserting(&format!("definitions of {}", source)).that(&used.definitions).is_equal_to(expected_definitions);
+            asserting(&format!("dependencies of {}", source)).that(&used.dependencies).is_equal_to(expected_dependencies);
+        }
+    }
+
     #[test]
     /// Test the untangle function, which sorts expressions so that no expression that expects a given variable to be defined
     /// is evaluated before the expression that defines it. 
@@ -597,6 +1015,195 @@ mod tests {
         asserting("value of z").that(&variable_equals("z", z, &context)).is_equal_to(true);
     }
 
+    #[test]
+    /// Verify that an expression bound to a context yields the same results and variables as when it looks up variables by name.
+    fn bound_execution_matches_unbound() {
+        let setup = "x = 1; y = 0; well.depth = 100; readings = [3, 5, 8]";
+        let expressions = vec![
+            "z = x * 2 + y",
+            "z = well.depth / 4; well.depth = z",
+            "z = x > 0 ? (y = 5) : (w = 6); z + y",
+            "z = x < 0 ? 1 : (w = 6)",
+            "x > 0 && (z = 2); y > 0 || (w = 3); z + w",
+            "z = readings[x] + readings[2]; readings[0] = z",
+            "z = unknown + 1",
+            "z = well.height",
+            "z = \"a\" + 2; z",
+            "fn twice(x) = 2 * x; z = twice(y + 4); x",
+            "z = map(readings, r => r + x)",
+            "x += 2; y++; z = x + y",
+            "x > 5? ; z = 1",
+            "let t = x * 2; z = t + 1"
+        ];
+        for expression in expressions {
+            let mut unbound_ctx = ExecutionContext::default();
+            let _ = Expression::new(setup).exec(&mut unbound_ctx);
+            let mut bound_ctx = unbound_ctx.clone();
+
+            let unbound = Expression::new(expression);
+            let mut bound = unbound.clone();
+            bound.bind(&mut bound_ctx);
+            asserting(&format!("{} is bound", expression)).that(&bound.is_bound_to(&bound_ctx)).is_true();
+
+            let expected = unbound.exec(&mut unbound_ctx);
+            let actual = bound.exec(&mut bound_ctx);
+            asserting(&format!("result of {}", expression)).that(&actual).is_equal_to(&expected);
+            asserting(&format!("variables after {}", expression)).that(&bound_ctx.variables).is_equal_to(&unbound_ctx.variables);
+            asserting(&format!("applicability of {}", expression)).that(&bound_ctx.is_applicable).is_equal_to(&unbound_ctx.is_applicable);
+        }
+    }
+
+    #[test]
+    /// Verify that a bound expression falls back to looking up variables by name in a context it was not bound to.
+    fn bound_expression_in_other_context() {
+        let mut ctx = ExecutionContext::default();
+        ctx.store(&"x".to_string(), 2);
+        let mut expr = Expression::new("y = x * 10");
+        expr.bind(&mut ctx);
+
+        let mut other_ctx = ExecutionContext::default();
+        other_ctx.store(&"a".to_string(), 1);
+        other_ctx.store(&"x".to_string(), 3);
+        asserting("not bound to other context").that(&expr.is_bound_to(&other_ctx)).is_false();
+        asserting("result in other context").that(&expr.exec(&mut other_ctx)).is_equal_to(&Ok(30.into()));
+        asserting("result in bound context").that(&expr.exec(&mut ctx)).is_equal_to(&Ok(20.into()));
+        asserting("y stored by slot").that(&ctx.load(&"y".to_string())).is_equal_to(&Some(20.into()));
+    }
+
+    #[test]
+    /// Verify that a standard constant assigned a new value by one expression is read with that value by the next,
+    /// whether or not the next was bound to the context while the constant held its standard value.
+    fn constant_overridden_by_context() {
+        let mut ctx = ExecutionContext::default();
+        let mut bound = Expression::new("e * 2");
+        bound.bind(&mut ctx);
+        asserting("standard value inlined").that(&bound.exec(&mut ctx)).is_equal_to(Ok((std::f64::consts::E * 2.0).into()));
+
+        let _ = Expression::new("e = 5").exec(&mut ctx);
+        asserting("unbound reads new value").that(&Expression::new("e * 2").exec(&mut ctx)).is_equal_to(Ok(10.into()));
+        asserting("binding set aside").that(&bound.is_bound_to(&ctx)).is_false();
+        asserting("bound reads new value").that(&bound.exec(&mut ctx)).is_equal_to(Ok(10.into()));
+        bound.bind(&mut ctx);
+        asserting("rebound reads new value").that(&bound.exec(&mut ctx)).is_equal_to(Ok(10.into()));
+    }
+
+    #[test]
+    /// Verify that try_exec locates runtime errors at the token that first produced an error, whether bound or not,
+    /// and reports compile errors kept by Expression::new.
+    fn try_exec_locates_errors() {
+        for is_bound in vec![false, true] {
+            let mut ctx = ExecutionContext::default();
+            let mut expr = Expression::new("x = 1;\ny = x + missing * 2");
+            if is_bound {
+                expr.bind(&mut ctx);
+            }
+            let error = expr.try_exec(&mut ctx).err().unwrap();
+            asserting("kind").that(&error.kind).is_equal_to(ShyErrorKind::Runtime);
+            asserting("location of missing variable").that(&(error.line, error.column)).is_equal_to((2, 9));
+        }
+
+        let mut ctx = ExecutionContext::default();
+        let error = Expression::new("s = \"abc\"; n = upper(5)").try_exec(&mut ctx).err().unwrap();
+        asserting("location of failed function").that(&(error.line, error.column)).is_equal_to((1, 16));
+        asserting("no error").that(&Expression::new("1 + 1").try_exec(&mut ctx)).is_equal_to(Ok(2.into()));
+
+        let expr = Expression::new("y = (1 + 2");
+        asserting("compile error kept").that(&expr.compile_error().map(|error| (error.line, error.column))).is_equal_to(Some((1, 5)));
+        asserting("compile error returned").that(&expr.try_exec(&mut ctx).err().map(|error| error.kind)).is_equal_to(Some(ShyErrorKind::Syntax));
+
+        // exec and interpret report a failed execution as a ShyError too, but leave error values as results.
+        let expr = Expression::new(";");
+        for result in [expr.exec(&mut ctx), expr.interpret(&mut ctx)] {
+            asserting("empty stack").that(&result.err().map(|error| (error.kind, error.message))).is_equal_to(Some((ShyErrorKind::Runtime, "Expression stack is empty".to_string())));
+        }
+        asserting("error value").that(&Expression::new("upper(5)").exec(&mut ctx).map(|value| value.is_error())).is_equal_to(Ok(true));
+    }
+
+    #[test]
+    /// Verify that local variables declared with let last only for one execution, 
+    /// restoring any variable of the same name, however the expression is executed.
+    fn local_variables_not_written_to_context() {
+        for mode in vec!["exec", "bound", "interpret", "try_exec"] {
+            let mut ctx = ExecutionContext::default();
+            ctx.store(&"price".to_string(), 10);
+            ctx.store(&"tmp".to_string(), 100);
+            let mut expr = Expression::new("let tmp = price * 2; let extra = 1; total = tmp + extra");
+            if mode == "bound" {
+                expr.bind(&mut ctx);
+            }
+            let result = match mode {
+                "interpret" => expr.interpret(&mut ctx),
+                "try_exec" => expr.try_exec(&mut ctx),
+                _ => expr.exec(&mut ctx)
+            };
+            asserting(&format!("{} result", mode)).that(&result).is_equal_to(&Ok(21.into()));
+            asserting(&format!("{} total", mode)).that(&ctx.load(&"total".to_string())).is_equal_to(&Some(21.into()));
+            asserting(&format!("{} tmp restored", mode)).that(&ctx.load(&"tmp".to_string())).is_equal_to(&Some(100.into()));
+            asserting(&format!("{} extra removed", mode)).that(&ctx.load(&"extra".to_string())).is_equal_to(&None);
+        }
+
+        // Locals are removed even when the expression quits early.
+        let mut ctx = ExecutionContext::default();
+        let _ = Expression::new("let flag = 1 > 2; flag?; total = 1").exec(&mut ctx);
+        asserting("flag removed after quitting").that(&ctx.load(&"flag".to_string())).is_equal_to(&None);
+
+        let error = Expression::new("let tmp += 1").compile_error().cloned().unwrap();
+        asserting("let requires =").that(&(error.message.as_str(), error.column)).is_equal_to(("Local variable tmp must be declared as let tmp = value", 1));
+    }
+
+    #[test]
+    /// Verify that integer overflow is promoted to Rational or reported as an error, as the context directs, 
+    /// whether by an operator, a compound assignment or an increment, and even when the operands are literals.
+    fn integer_overflow_follows_context() {
+        let cases = vec![
+            ("big + 1", i64::MAX as f64 + 1.0),
+            ("big * big", i64::MAX as f64 * i64::MAX as f64),
+            ("2 ^ 64", 2_f64.powi(64)),
+            ("21!", 51090942171709440000.0),
+            ("9223372036854775807 + 1", i64::MAX as f64 + 1.0),
+            ("x = big; x += 1; x", i64::MAX as f64 + 1.0),
+            ("x = small; x -= 1; x", i64::MIN as f64 - 1.0),
+            ("x = big; x *= 2; x", i64::MAX as f64 * 2.0),
+            ("x = big; x++; x", i64::MAX as f64 + 1.0),
+            ("x = small; x--; x", i64::MIN as f64 - 1.0),
+            ("y -= small; y", -(i64::MIN as f64)),
+            ("-small", -(i64::MIN as f64)),
+            ("x = small; -x", -(i64::MIN as f64)),
+            ("2 * -small", -2.0 * i64::MIN as f64)
+        ];
+        for (expression, promoted) in cases {
+            for overflow in vec![IntegerOverflow::Promote, IntegerOverflow::Error] {
+                let mut ctx = ExecutionContext::default();
+                ctx.integer_overflow = overflow;
+                ctx.store(&"big".to_string(), i64::MAX);
+                ctx.store(&"small".to_string(), i64::MIN);
+                let result = Expression::new(expression).exec(&mut ctx).unwrap();
+                match overflow {
+                    IntegerOverflow::Promote => asserting(&format!("{} promoted", expression)).that(&result).is_equal_to(ShyValue::from(promoted)),
+                    IntegerOverflow::Error => asserting(&format!("{} is an overflow", expression))
+                        .that(&matches!(result, ShyValue::Scalar(ShyScalar::Error(ref message)) if message.starts_with("Integer overflow"))).is_true()
+                };
+            }
+        }
+        let mut ctx = ExecutionContext::default();
+        asserting("exact at the limit").that(&Expression::new("9223372036854775806 + 1").exec(&mut ctx)).is_equal_to(Ok(i64::MAX.into()));
+        ctx.store(&"small".to_string(), i64::MIN);
+        asserting("remainder at the limit").that(&Expression::new("small % -1").exec(&mut ctx)).is_equal_to(Ok(0.into()));
+    }
+
+    #[test]
+    /// Verify that an expression with several errors reports all of them, one per statement.
+    fn compile_errors_reported_together() {
+        let expr = Expression::new("a = (1 + 2;\nb = 3 ` 4;\nc = a + b;\nd = [1, 2))");
+        let locations: Vec<(ShyErrorKind, usize, usize)> = expr.compile_errors().iter().map(|error| (error.kind, error.line, error.column)).collect();
+        asserting("all errors").that(&locations).is_equal_to(vec![
+            (ShyErrorKind::Syntax, 1, 5), 
+            (ShyErrorKind::Lexical, 2, 7), 
+            (ShyErrorKind::Syntax, 4, 5)
+        ]);
+        asserting("first error").that(&expr.compile_error().map(|error| error.line)).is_equal_to(Some(1));
+    }
+
     fn do_vecs_match<T : PartialEq>(a : &Vec<T>, b : &Vec<T>) -> bool {
         let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
         matching == a.len() && matching == b.len()
@@ -0,0 +1,347 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::ser::SerializeMap;
+
+use super::shy_token::{ShyToken, ShyValue, SpannedToken};
+use super::shy_operator::ShyOperator;
+use super::bytecode::Bytecode;
+
+//..................................................................
+
+// Variables of an ExecutionContext are held in a Frame, a compact vector of values indexed by slot.
+//
+//   - The Layout of a Frame maps each variable name to its slot. Slots are only ever added, never reused,
+//     so a slot stays valid for as long as its Layout lives. Removing a variable empties its slot.
+//   - Cloning a Frame (as a RuleSet does with its context) shares the Layout. When a Frame adds a slot to a shared Layout,
+//     it gets its own copy first, with a new id, so Frames that have gone their separate ways never disagree about a slot.
+//   - Binding an Expression to a context resolves its variables to slots, allocating any that are missing.
+//     The bound postfix order is used only when executing against a Frame whose Layout has the same id,
+//     otherwise the Expression falls back to looking variables up by name.
+//
+// Name lookups remain for the callers that set up a context or read back its results (as JSON or otherwise),
+// for property chains that are indexed or assigned, and for the compound assignment operators.
+
+static NEXT_LAYOUT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Maps variable names to slots.
+struct Layout {
+    id: usize,
+    names: Vec<String>,
+    slots: HashMap<String, usize>
+}
+
+impl Layout {
+    fn new() -> Self {
+        Layout { id: NEXT_LAYOUT_ID.fetch_add(1, Ordering::Relaxed), names: Vec::new(), slots: HashMap::new() }
+    }
+}
+
+impl Clone for Layout {
+    /// A copy of a Layout gets a new id, because the copy and the original may add different slots from here on.
+    fn clone(&self) -> Self {
+        Layout { id: NEXT_LAYOUT_ID.fetch_add(1, Ordering::Relaxed), names: self.names.clone(), slots: self.slots.clone() }
+    }
+}
+
+/// Variables stored by slot, which may also be accessed by name.
+#[derive(Clone)]
+pub struct Frame {
+    layout: Arc<Layout>,
+    values: Vec<Option<ShyValue>>
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Frame { layout: Arc::new(Layout::new()), values: Vec::new() }
+    }
+
+    /// Identifies the Layout, so that a bound Expression can tell if its slots are valid for this Frame.
+    pub fn layout_id(&self) -> usize {
+        self.layout.id
+    }
+
+    /// Get the slot for the named variable, adding one if necessary.
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(slot) = self.layout.slots.get(name) {
+            return *slot;
+        }
+        let layout = Arc::make_mut(&mut self.layout);
+        let slot = layout.names.len();
+        layout.names.push(name.to_string());
+        layout.slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    /// Name of the variable held in the given slot.
+    pub fn name_of(&self, slot: usize) -> &str {
+        &self.layout.names[slot]
+    }
+
+    pub fn get_slot(&self, slot: usize) -> Option<&ShyValue> {
+        match self.values.get(slot) {
+            Some(Some(value)) => Some(value),
+            _ => None
+        }
+    }
+
+    /// Store the value in the slot, returning the value it replaced, if any.
+    pub fn set_slot(&mut self, slot: usize, value: ShyValue) -> Option<ShyValue> {
+        if slot >= self.values.len() {
+            self.values.resize(slot + 1, None);
+        }
+        self.values[slot].replace(value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ShyValue> {
+        match self.layout.slots.get(name) {
+            Some(slot) => self.get_slot(*slot),
+            None => None
+        }
+    }
+
+    /// Store the value of the named variable, returning the value it replaced, if any.
+    pub fn insert(&mut self, name: String, value: ShyValue) -> Option<ShyValue> {
+        let slot = self.slot(&name);
+        self.set_slot(slot, value)
+    }
+
+    /// Remove the named variable, returning its value, if any. Its slot remains, empty.
+    pub fn remove(&mut self, name: &str) -> Option<ShyValue> {
+        match self.layout.slots.get(name) {
+            Some(slot) if *slot < self.values.len() => self.values[*slot].take(),
+            _ => None
+        }
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The variables that have values, in the order their slots were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ShyValue)> {
+        self.layout.names.iter().zip(self.values.iter())
+            .filter_map(|(name, value)| value.as_ref().map(|value| (name, value)))
+    }
+
+    /// Number of variables that have values.
+    pub fn len(&self) -> usize {
+        self.values.iter().filter(|value| value.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self { Frame::new() }
+}
+
+impl From<HashMap<String, ShyValue>> for Frame {
+    fn from(variables: HashMap<String, ShyValue>) -> Self {
+        let mut frame = Frame::new();
+        for (name, value) in variables {
+            frame.insert(name, value);
+        }
+        frame
+    }
+}
+
+impl<'f> IntoIterator for &'f Frame {
+    type Item = (&'f String, &'f ShyValue);
+    type IntoIter = Box<dyn Iterator<Item = (&'f String, &'f ShyValue)> + 'f>;
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+/// Two Frames are equal if they hold the same variables with the same values, regardless of slots.
+impl PartialEq for Frame {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(name, value)| other.get(name) == Some(value))
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A Frame is serialized as a map from variable name to value. Slots are not preserved.
+impl Serialize for Frame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (name, value) in self.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Frame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::<String, ShyValue>::deserialize(deserializer).map(Frame::from)
+    }
+}
+
+//..................................................................
+
+/// The postfix order of an Expression with its variables resolved to the slots of a Frame's Layout, and the bytecode assembled from it.
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub layout_id: usize,
+    pub postfix_order: Vec<SpannedToken>,
+    pub bytecode: Bytecode,
+
+    /// The slot and value of each standard constant inlined into the postfix order. 
+    /// The binding may only be used while the frame still holds these values.
+    pub constants: Vec<(usize, ShyValue)>
+}
+
+/// Resolve the variables of the postfix order to slots in the frame, adding slots as needed.
+/// Everything but the following is left to be looked up by name. Branch targets are adjusted for the tokens removed.
+///   - A variable followed by Load becomes a LoadSlot.
+///   - A property chain followed by Load becomes a LoadChain, which finds its first variable by slot.
+///   - A variable assigned by `=` becomes a StoreSlot after the value being assigned,
+///     provided that the value is computed without branching.
+///   - Every other variable, and the first variable of every other property chain, gets a slot, 
+///     so that storing it by name does not change the Layout.
+/// 
+/// A token that replaces another keeps its span, which the bytecode uses to locate errors.
+pub fn bind(postfix_order: &[SpannedToken], frame: &mut Frame) -> Binding {
+    let landings = landings(postfix_order);
+    // The replacement for each token, or None if it is to be removed.
+    let mut replacements: Vec<Option<ShyToken>> = postfix_order.iter().map(|spanned| Some(spanned.token.clone())).collect();
+    for (position, spanned) in postfix_order.iter().enumerate() {
+        let loaded = position + 1 < postfix_order.len()
+            && postfix_order[position + 1].token == ShyToken::Operator(ShyOperator::Load)
+            && !landings.contains(&(position + 1));
+        match &spanned.token {
+            ShyToken::Value(ShyValue::Variable(name)) if loaded => {
+                replacements[position] = Some(ShyToken::LoadSlot(frame.slot(name)));
+                replacements[position + 1] = None;
+            },
+            ShyToken::Value(ShyValue::PropertyChain(chain)) if loaded => {
+                replacements[position] = Some(ShyToken::LoadChain(frame.slot(chain[0].name()), chain.clone()));
+                replacements[position + 1] = None;
+            },
+            ShyToken::Value(ShyValue::Variable(name)) => {
+                let slot = frame.slot(name);
+                if let Some(assign_position) = find_assignment(postfix_order, position, &landings) {
+                    replacements[position] = None;
+                    replacements[assign_position] = Some(ShyToken::StoreSlot(slot));
+                }
+            },
+            // A property chain that is assigned is still stored by name, but give its first variable a slot now,
+            // so that vivifying it does not add a slot to the Layout, which would leave every expression bound to it unbound.
+            ShyToken::Value(ShyValue::PropertyChain(chain)) => {
+                frame.slot(chain[0].name());
+            },
+            // Parameters are stored in the context whenever the function is called, so give them slots now,
+            // rather than adding slots to a Layout that may by then be shared.
+            ShyToken::FunctionDefinition(function) | ShyToken::Value(ShyValue::Lambda(function)) => {
+                for parameter in function.parameters.iter() {
+                    frame.slot(parameter);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    // The new position of each old position, which for a removed token is the position of the next token kept.
+    let mut new_positions = Vec::with_capacity(postfix_order.len() + 1);
+    let mut kept = 0;
+    for replacement in replacements.iter() {
+        new_positions.push(kept);
+        if replacement.is_some() { kept += 1; }
+    }
+    new_positions.push(kept);
+    let bound_order: Vec<SpannedToken> = postfix_order.iter().zip(replacements)
+        .filter_map(|(spanned, replacement)| replacement.map(|token| match token {
+            ShyToken::Branch(op, target) => SpannedToken::new(ShyToken::Branch(op, new_positions[target]), spanned.span),
+            _ => SpannedToken::new(token, spanned.span)
+        }))
+        .collect();
+    let bytecode = Bytecode::assemble(&bound_order);
+    Binding { layout_id: frame.layout_id(), postfix_order: bound_order, bytecode, constants: vec![] }
+}
+
+/// Positions at which execution may resume after a branch.
+pub fn landings(postfix_order: &[SpannedToken]) -> HashSet<usize> {
+    let mut landings = HashSet::new();
+    for spanned in postfix_order.iter() {
+        if let ShyToken::Branch(op, target) = spanned.token {
+            landings.insert(target);
+            // A Ternary or Case whose test is false resumes just after the Jump at its target.
+            if op == ShyOperator::Ternary || op == ShyOperator::Case {
+                landings.insert(target + 1);
+            }
+        }
+    }
+    landings
+}
+
+/// If the variable at the given position is the left operand of an `=` whose right operand is computed
+/// without branching, return the position of the Assign operator.
+fn find_assignment(postfix_order: &[SpannedToken], position: usize, landings: &HashSet<usize>) -> Option<usize> {
+    // Number of values pushed onto the stack above the variable.
+    let mut depth = 0;
+    for (offset, spanned) in postfix_order[position + 1..].iter().enumerate() {
+        let token_position = position + 1 + offset;
+        if landings.contains(&token_position) {
+            return None;
+        }
+        match &spanned.token {
+            ShyToken::Operator(ShyOperator::Assign) if depth == 1 => return Some(token_position),
+            ShyToken::Operator(op) => {
+                // Any other operator that consumes the variable is not a simple assignment.
+                if op.arguments() > depth { return None; }
+                depth = depth + 1 - op.arguments();
+            },
+            ShyToken::Value(_) => depth += 1,
+            _ => return None
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    #[test]
+    /// Verify that a clone shares slots until one of them adds a variable.
+    fn frame_clone_shares_layout() {
+        let mut frame = Frame::new();
+        frame.insert("x".to_string(), 1.into());
+        let mut the_clone = frame.clone();
+        asserting("clone shares layout").that(&the_clone.layout_id()).is_equal_to(&frame.layout_id());
+        the_clone.insert("x".to_string(), 2.into());
+        asserting("clone keeps layout when setting existing variable").that(&the_clone.layout_id()).is_equal_to(&frame.layout_id());
+        asserting("original value unchanged").that(&frame.get("x")).is_equal_to(&Some(&1.into()));
+        the_clone.insert("y".to_string(), 3.into());
+        asserting("clone gets its own layout when adding a variable").that(&(the_clone.layout_id() != frame.layout_id())).is_true();
+        asserting("slots survive the copy").that(&the_clone.get_slot(frame.slot("x"))).is_equal_to(&Some(&2.into()));
+        asserting("original does not see new variable").that(&frame.get("y")).is_none();
+    }
+
+    #[test]
+    /// Verify that removing a variable empties its slot without reusing it.
+    fn frame_remove() {
+        let mut frame = Frame::new();
+        frame.insert("x".to_string(), 1.into());
+        frame.insert("y".to_string(), 2.into());
+        asserting("removed value").that(&frame.remove("x")).is_equal_to(&Some(1.into()));
+        asserting("length").that(&frame.len()).is_equal_to(&1);
+        frame.insert("z".to_string(), 3.into());
+        asserting("new slot").that(&frame.slot("z")).is_equal_to(&2);
+    }
+}
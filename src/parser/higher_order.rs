@@ -0,0 +1,128 @@
+use super::shy_token::ShyValue;
+use super::shy_scalar::ShyScalar;
+use super::execution_context::ExecutionContext;
+use super::user_function::UserFunction;
+
+//..................................................................
+
+// Functions that take a vector and a lambda, as in `count_if(readings, x => x > limit) > 3`.
+//
+// Unlike the functions in ExecutionContext::standard_functions, these must execute the lambda against the context,
+// so ExecutionContext::call hands them the context.
+//
+//   - map(vector, x => ...) ............... Vector of the lambda's results for each item.
+//   - filter(vector, x => ...) ............ Vector of the items for which the lambda is truthy.
+//   - reduce(vector, (total, x) => ..., initial)
+//                                           Combine the items, starting with initial (or the first item, if omitted).
+//   - count_if(vector, x => ...) .......... Number of items for which the lambda is truthy.
+//   - exists(vector, x => ...) ............ True if the lambda is truthy for any item. Also available as any(vector, x => ...).
+//   - forall(vector, x => ...) ............ True if the lambda is truthy for every item. Also available as all(vector, x => ...).
+//
+// If the lambda returns an error for any item, that error is the result.
+
+/// Names of the functions that take a vector and a lambda.
+pub const HIGHER_ORDER_FUNCTIONS: [&str; 6] = ["count_if", "exists", "filter", "forall", "map", "reduce"];
+
+/// Call the named higher-order function, or return None if there is no such function,
+/// so that the caller may look for an ordinary function of that name.
+/// The voting functions any and all are only treated as higher-order functions when their last argument is a lambda.
+pub fn call(function_name: &str, args: &[ShyValue], context: &mut ExecutionContext) -> Option<ShyValue> {
+    let name = match function_name {
+        "any" | "all" => match args.last() {
+            Some(ShyValue::Lambda(_)) => if function_name == "any" { "exists" } else { "forall" },
+            _ => return None
+        },
+        _ if HIGHER_ORDER_FUNCTIONS.contains(&function_name) => function_name,
+        _ => return None
+    };
+    let (items, function, initial) = match args {
+        [ShyValue::Vector(items), ShyValue::Lambda(function)] => (items, function, None),
+        [ShyValue::Vector(items), ShyValue::Lambda(function), initial] if name == "reduce" => (items, function, Some(initial)),
+        _ => return Some(usage_error(function_name))
+    };
+    let result = match name {
+        "map" => map(items, function, context),
+        "filter" => filter(items, function, context),
+        "reduce" => reduce(items, function, initial, context),
+        "count_if" => count_if(items, function, context),
+        "exists" => exists(items, function, context),
+        _ => forall(items, function, context)
+    };
+    Some(result.unwrap_or_else(|error| error))
+}
+
+fn usage_error(function_name: &str) -> ShyValue {
+    if function_name == "reduce" {
+        ShyValue::error("'reduce' function requires a vector, a lambda and an optional initial value, as in reduce(readings, (total, x) => total + x, 0)".into())
+    }
+    else {
+        ShyValue::error(format!("'{}' function requires a vector and a lambda, as in {}(readings, x => x > 100)", function_name, function_name))
+    }
+}
+
+/// Call the lambda with the given arguments, treating an error result as a failure.
+fn apply(function: &UserFunction, args: &[ShyValue], context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    let result = function.call(args, context);
+    if result.is_error() { Err(result) } else { Ok(result) }
+}
+
+fn map(items: &[ShyValue], function: &UserFunction, context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        results.push(apply(function, std::slice::from_ref(item), context)?);
+    }
+    Ok(ShyValue::Vector(results))
+}
+
+fn filter(items: &[ShyValue], function: &UserFunction, context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    let mut results = Vec::new();
+    for item in items {
+        if apply(function, std::slice::from_ref(item), context)?.is_truthy() {
+            results.push(item.shallow_clone());
+        }
+    }
+    Ok(ShyValue::Vector(results))
+}
+
+fn reduce(items: &[ShyValue], function: &UserFunction, initial: Option<&ShyValue>, context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    if function.parameters.len() != 2 {
+        return Err(ShyValue::error("'reduce' function requires a lambda with two parameters, as in (total, x) => total + x".into()));
+    }
+    let (mut total, rest) = match (initial, items.split_first()) {
+        (Some(initial), _) => (initial.shallow_clone(), items),
+        (None, Some((first, rest))) => (first.shallow_clone(), rest),
+        (None, None) => return Ok(ShyValue::Scalar(ShyScalar::Null))
+    };
+    for item in rest {
+        total = apply(function, &[total, item.shallow_clone()], context)?;
+    }
+    Ok(total)
+}
+
+fn count_if(items: &[ShyValue], function: &UserFunction, context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    let mut count = 0_i64;
+    for item in items {
+        if apply(function, std::slice::from_ref(item), context)?.is_truthy() {
+            count += 1;
+        }
+    }
+    Ok(count.into())
+}
+
+fn exists(items: &[ShyValue], function: &UserFunction, context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    for item in items {
+        if apply(function, std::slice::from_ref(item), context)?.is_truthy() {
+            return Ok(true.into());
+        }
+    }
+    Ok(false.into())
+}
+
+fn forall(items: &[ShyValue], function: &UserFunction, context: &mut ExecutionContext) -> Result<ShyValue, ShyValue> {
+    for item in items {
+        if apply(function, std::slice::from_ref(item), context)?.is_falsey() {
+            return Ok(false.into());
+        }
+    }
+    Ok(true.into())
+}
@@ -1,9 +1,14 @@
 use std::f64;
 use serde_json::{Value, Number, Map};
 use super::shy_token::ShyValue;
+use super::chain_link::ChainLink;
 use super::shy_scalar::ShyScalar;
 use super::shy_object::ShyObject;
-use super::execution_context::ExecutionContext;
+use super::execution_context::{ExecutionContext, DecimalJson};
+use super::temporal;
+use super::units;
+use super::shy_regex::ShyRegex;
+use super::decimal::Decimal;
 
 //  Convert ShyValues to and from Value enums in the serde crate.
 //  Not all ShyValue variants can be expressed as a Value in serde, and vice versa.
@@ -15,25 +20,32 @@ use super::execution_context::ExecutionContext;
 //     ShyScalar::Boolean           Value::Bool
 //     ShyScalar::Integer           Value::Number, Number::PosInt or Number::NegInt
 //     ShyScalar::Rational          Value::Number, Number::Float
+//     ShyScalar::Decimal           Value::String (String starts with "Decimal:", followed by the digits, like "12.50"),
+//                                  or Value::Number if DecimalJson::Number is requested and the number holds the same value
 //     ShyScalar::String            Value::String (String does not start with special prefix)
+//     ShyScalar::DateTime          Value::String (String starts with "DateTime:", followed by an RFC3339 date/time like "2024-01-05T10:00:00+00:00")
+//     ShyScalar::Duration          Value::String (String starts with "Duration:", followed by a duration literal like "5min")
+//     ShyScalar::Quantity          Value::String (String starts with "Quantity:", followed by a quantity literal like "15 psi")
+//     ShyScalar::Regex             Value::String (String starts with "Regex:", followed by the pattern)
 //     ShyScalar::Error             Value::String (String starts with "Error:")
 //     ShyValue::Vector             Value::Array
 //     ShyValue::Object             Value::Object
 //     ShyValue::Variable           Value::String (String starts with "Variable:")
 //     ShyValue::PropertyChain      Value::String (String starts with "PropertyChain:")
 //     ShyValue::FunctionName       Value::String (String starts with "FunctionName:")
+//     ShyValue::Lambda             Value::String (String starts with "Lambda:", followed by the compiled lambda as JSON)
 //  
-//  ShyValue::Vector is limited in Shy, because it only holds ShyScalars.
-//  That needs to be refactored so that it can hold other ShyValue::Vectors and ShyObjects.
+//  A ShyValue::Vector may hold any ShyValue, so arrays of objects and arrays of arrays round-trip.
+//  The From traits write Decimals as strings; call to_json to choose how they are written.
 //  
 
-impl From<&ShyScalar> for Value { 
-    /// Create a Serde Value from a ShyScalar. 
+impl ShyScalar {
+    /// Create a Serde Value from a ShyScalar, writing a Decimal as directed. 
     /// Since Serde Values can only represent what is valid for JSON (e.g. no NaN values),
     /// encode unsupported values as Value::Strings, often by prepending a suffix.
     /// The reverse conversion will need to parse these strings out and reconstruct the proper ShyValue.
-    fn from(s : &ShyScalar) -> Self { 
-        match s {
+    pub fn to_json(&self, decimals : DecimalJson) -> Value { 
+        match self {
             ShyScalar::Null => Value::Null,
             ShyScalar::Boolean(b) => Value::Bool(*b),
             ShyScalar::String(s) => Value::String(s.clone()),
@@ -41,19 +53,38 @@ impl From<&ShyScalar> for Value {
             ShyScalar::Rational(r) => Value::Number(Number::from_f64(*r).unwrap()),
             ShyScalar::Integer(ref i) if *i >= 0 => Value::Number((*i).into()),
             ShyScalar::Integer(i) => Value::Number((*i as u64).into()),
+            ShyScalar::Decimal(d) => match (decimals, Number::from_f64(d.to_f64())) {
+                (DecimalJson::Number, Some(n)) if Decimal::from_f64(d.to_f64()) == Some(*d) => Value::Number(n),
+                _ => Value::String(format!("Decimal: {}", d))
+            },
+            ShyScalar::DateTime(d) => Value::String(format!("DateTime: {}", temporal::format_date_time(d))),
+            ShyScalar::Duration(d) => Value::String(format!("Duration: {}", temporal::format_duration(d))),
+            ShyScalar::Quantity(value, unit) => Value::String(format!("Quantity: {}", units::format_quantity(*value, unit))),
+            ShyScalar::Regex(regex) => Value::String(format!("Regex: {}", regex)),
             ShyScalar::Error(e) => Value::String(format!("Error: {}", e))
         }
     } 
 }
 
-impl From<&ShyValue> for Value {
-    fn from(v : &ShyValue) -> Self {
-        match v {
-            ShyValue::Scalar(scalar) => scalar.into(),
-            ShyValue::Vector(shy_vec) => Value::Array(shy_vec.iter().map(|shy_val| shy_val.into()).collect()),
+impl From<&ShyScalar> for Value { 
+    fn from(s : &ShyScalar) -> Self { 
+        s.to_json(DecimalJson::default())
+    } 
+}
+
+impl ShyValue {
+    /// Create a Serde Value from a ShyValue, writing any Decimals it holds as directed.
+    pub fn to_json(&self, decimals : DecimalJson) -> Value {
+        match self {
+            ShyValue::Scalar(scalar) => scalar.to_json(decimals),
+            ShyValue::Vector(shy_vec) => Value::Array(shy_vec.iter().map(|shy_val| shy_val.to_json(decimals)).collect()),
             ShyValue::FunctionName(func_name) => Value::String(format!("FunctionName: {}", func_name)),
-            ShyValue::PropertyChain(prop_chain) => Value::String(format!("PropertyChain: {}", prop_chain.join("."))),
+            ShyValue::PropertyChain(prop_chain) => Value::String(format!("PropertyChain: {}", ChainLink::join(prop_chain))),
             ShyValue::Variable(var_name) => Value::String(format!("Variable: {}", var_name)),
+            ShyValue::Lambda(function) => match serde_json::to_string(function) {
+                Ok(json) => Value::String(format!("Lambda: {}", json)),
+                Err(e) => Value::String(format!("Error: Unable to serialize lambda: {}", e))
+            },
             ShyValue::Object(shy_obj) => {
                 let deref = shy_obj.as_deref();
                 let property_count = deref.keys().count();
@@ -61,7 +92,7 @@ impl From<&ShyValue> for Value {
                 for key in deref.keys() {
                     match deref.get(&key) {
                       Some(shy_value) => {
-                          serde_map.insert(key, shy_value.into());
+                          serde_map.insert(key, shy_value.to_json(decimals));
                       },
                       None => panic!("key '{}' in ShyObject has no value", key)
                     }
@@ -72,6 +103,12 @@ impl From<&ShyValue> for Value {
     }
 }
 
+impl From<&ShyValue> for Value {
+    fn from(v : &ShyValue) -> Self {
+        v.to_json(DecimalJson::default())
+    }
+}
+
 impl From<ShyValue> for Value {
     fn from(v : ShyValue) -> Self {
         (&v).into()
@@ -91,23 +128,35 @@ impl From<&Value> for ShyValue {
             Value::String(ref func_name) if func_name.starts_with("FunctionName: ") => ShyValue::FunctionName(func_name[13..].into()),
             Value::String(ref prop_chain) if prop_chain.starts_with("PropertyChain: ") => ShyValue::property_chain(prop_chain[15..].into()),
             Value::String(ref variable) if variable.starts_with("Variable: ") => ShyValue::Variable(variable[10..].into()),
+            Value::String(ref lambda) if lambda.starts_with("Lambda: ") => match serde_json::from_str(&lambda[8..]) {
+                Ok(function) => ShyValue::Lambda(function),
+                Err(e) => ShyValue::error(format!("Unable to deserialize lambda: {}", e))
+            },
+            Value::String(ref duration) if duration.starts_with("Duration: ") => match temporal::parse_duration(&duration[10..]) {
+                Some(d) => d.into(),
+                None => duration.clone().into()
+            },
+            Value::String(ref quantity) if quantity.starts_with("Quantity: ") => match units::parse_quantity(&quantity[10..]) {
+                Some((value, unit)) => ShyValue::Scalar(ShyScalar::Quantity(value, unit)),
+                None => quantity.clone().into()
+            },
+            Value::String(ref decimal) if decimal.starts_with("Decimal: ") => match Decimal::parse(&decimal[9..]) {
+                Some(d) => d.into(),
+                None => decimal.clone().into()
+            },
+            Value::String(ref regex) if regex.starts_with("Regex: ") => match ShyRegex::new(&regex[7..]) {
+                Ok(regex) => ShyValue::Scalar(ShyScalar::Regex(regex)),
+                Err(message) => ShyValue::error(message)
+            },
+            Value::String(ref date_time) if date_time.starts_with("DateTime: ") => match temporal::parse_rfc3339(&date_time[10..]) {
+                Some(d) => d.into(),
+                None => date_time.clone().into()
+            },
             Value::String(s) => s.clone().into(),
 
             Value::Number(ref n) if (*n).is_i64() => ShyValue::Scalar(ShyScalar::Integer(n.as_i64().unwrap())),
             Value::Number(ref f) => ShyValue::Scalar(ShyScalar::Rational(f.as_f64().unwrap())),
-            Value::Array(a) => ShyValue::Vector(
-                a.iter()
-                .map(|item|
-                  match item {
-                      Value::Null => ShyScalar::Null,
-                      Value::Bool(b) => (*b).into(),
-                      Value::String(s) => s.clone().into(),
-                      Value::Number(ref n) if (*n).is_i64() => ShyScalar::Integer(n.as_i64().unwrap()),
-                      Value::Number(ref f) => ShyScalar::Rational(f.as_f64().unwrap()),
-                      _ => ShyScalar::Error("Unsupported type of scalar".into())
-                  })
-                .collect()
-            ),
+            Value::Array(a) => ShyValue::Vector(a.iter().map(|item| item.into()).collect()),
             Value::Object(o) => {
                 let shy_object = ShyObject::empty();
                 {
@@ -139,7 +188,7 @@ impl<'a> From<&ExecutionContext<'a>> for Value {
     fn from(ctx : &ExecutionContext<'a>) -> Self {
         let mut variables_serde_map : Map<String, Value> = Map::with_capacity(ctx.variables.len());
         for (key, val) in &ctx.variables {
-            variables_serde_map.insert(key.clone(), val.into());
+            variables_serde_map.insert(key.clone(), val.to_json(ctx.decimal_json));
         }
         let mut ctx_serde_map : Map<String, Value> = Map::with_capacity(2);
         ctx_serde_map.insert("is_applicable".into(), Value::Bool(ctx.is_applicable));
@@ -247,6 +296,100 @@ mod tests {
           .is_equal_to(true);
     }
 
+    #[test]
+    /// Test that date/times convert to prefixed RFC3339 strings and durations to prefixed duration literals, and back, 
+    /// while strings without a prefix stay strings, even if they look like date/times.
+    fn temporal_round_trip() {
+        let json = r#"{ "taken": "DateTime: 2024-01-05T10:00:00+00:00", "interval": "Duration: 5min", "label": "2024-01-05", "stamp": "2020-01-01T00:00:00Z" }"#;
+        let serde_value : Value = serde_json::from_str(json).unwrap();
+        let shy_value : ShyValue = (&serde_value).into();
+        asserting("date/time converted")
+          .that(&shy_value.get_safe("taken").type_name())
+          .is_equal_to(&"DateTime");
+        asserting("duration converted")
+          .that(&shy_value.get_safe("interval"))
+          .is_equal_to(&ShyValue::Scalar(ShyScalar::Duration(chrono::Duration::minutes(5))));
+        asserting("plain date string left alone")
+          .that(&shy_value.get_safe("label"))
+          .is_equal_to(&"2024-01-05".into());
+        asserting("unprefixed date/time string left alone")
+          .that(&shy_value.get_safe("stamp"))
+          .is_equal_to(ShyValue::from("2020-01-01T00:00:00Z"));
+        let round_trip : Value = shy_value.into();
+        asserting("round trip").that(&round_trip).is_equal_to(&serde_value);
+    }
+
+    #[test]
+    /// Test that a compiled regex survives a round trip through a Serde Value.
+    fn regex_round_trip() {
+        let shy_value = ShyValue::Scalar(ShyScalar::Regex(ShyRegex::new(r"^(\w+)-(\d+)$").unwrap()));
+        let serde_value : Value = (&shy_value).into();
+        asserting("regex as string").that(&serde_value).is_equal_to(&Value::String(r"Regex: ^(\w+)-(\d+)$".into()));
+        let round_trip : ShyValue = (&serde_value).into();
+        asserting("round trip").that(&round_trip).is_equal_to(&shy_value);
+    }
+
+    #[test]
+    /// Test that quantities convert to prefixed quantity literals, and back.
+    fn quantity_round_trip() {
+        let json = r#"{ "pressure": "Quantity: 15 psi" }"#;
+        let serde_value : Value = serde_json::from_str(json).unwrap();
+        let shy_value : ShyValue = (&serde_value).into();
+        asserting("quantity converted")
+          .that(&shy_value.get_safe("pressure"))
+          .is_equal_to(&ShyValue::Scalar(ShyScalar::Quantity(15.0, units::Unit::lookup("psi").unwrap())));
+        let round_trip : Value = shy_value.into();
+        asserting("round trip").that(&round_trip).is_equal_to(&serde_value);
+    }
+
+    #[test]
+    /// Test that decimals convert to prefixed strings by default, or to numbers when asked, and that no digits are lost either way.
+    fn decimal_round_trip() {
+        let price = ShyValue::from(Decimal::parse("12.50").unwrap());
+        let serde_value : Value = (&price).into();
+        asserting("decimal as string").that(&serde_value).is_equal_to(&Value::String("Decimal: 12.50".into()));
+        let round_trip : ShyValue = (&serde_value).into();
+        asserting("round trip keeps scale").that(&format!("{:?}", round_trip)).is_equal_to(&format!("{:?}", price));
+        asserting("decimal as number").that(&price.to_json(DecimalJson::Number).to_string()).is_equal_to(&"12.5".to_string());
+        let precise = ShyValue::from(Decimal::parse("0.1000000000000000000000000001").unwrap());
+        asserting("too precise for a number")
+          .that(&precise.to_json(DecimalJson::Number))
+          .is_equal_to(&Value::String("Decimal: 0.1000000000000000000000000001".into()));
+
+        let mut ctx = ExecutionContext::empty();
+        ctx.store(&"price".to_string(), price);
+        ctx.decimal_json = DecimalJson::Number;
+        asserting("context honors setting")
+          .that(&serde_json::to_value(&ctx).unwrap()["variables"]["price"].to_string())
+          .is_equal_to(&"12.5".to_string());
+    }
+
+    #[test]
+    /// Test that a compiled lambda survives a round trip through a Serde Value.
+    fn lambda_round_trip() {
+        let expression = crate::parser::expression::Expression::new("x => x > 100");
+        let lambda = match expression.postfix_order.first().map(|spanned| &spanned.token) {
+            Some(crate::parser::shy_token::ShyToken::Value(value)) => value.clone(),
+            other => panic!("Expected a lambda, found {:?}", other)
+        };
+        let serde_value : Value = (&lambda).into();
+        let round_trip : ShyValue = (&serde_value).into();
+        asserting("lambda round trip").that(&round_trip).is_equal_to(&lambda);
+    }
+
+    #[test]
+    /// Test that arrays of objects and arrays of arrays survive a round trip through Serde Values.
+    fn array_round_trip() {
+        let json = r#"{ "wells": [ { "depth": 1500, "readings": [1, 2.5] }, { "depth": 800, "readings": [] } ] }"#;
+        let serde_value : Value = serde_json::from_str(json).unwrap();
+        let shy_value : ShyValue = (&serde_value).into();
+        asserting("nested array converted")
+          .that(&shy_value.get_safe("wells").get_safe("0").get_safe("readings").get_safe("1"))
+          .is_equal_to(&2.5.into());
+        let round_trip : Value = shy_value.into();
+        asserting("round trip").that(&round_trip).is_equal_to(&serde_value);
+    }
+
     #[test]
     /// Test conversion from ExecutionContext into Serde Values using the From/Into Traits.
     fn execution_context_to_serde_value() {
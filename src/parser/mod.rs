@@ -3,8 +3,10 @@
 #[allow(unused_imports)]
 use std::fmt::Result;
 use std::marker::PhantomData;
+use std::mem;
 use crate::lexer::parser_token::ParserToken;
 use crate::lexer::Lexer;
+use crate::lexer::span::Span;
 use log::{warn, debug};
 
 pub mod indent;
@@ -14,7 +16,9 @@ pub mod indent;
 pub mod shy_token;
 #[allow(unused_imports)]
 use shy_token::ShyToken;
-use shy_token::ShyValue;
+use shy_token::{ShyValue, SpannedToken};
+pub mod chain_link;
+use chain_link::ChainLink;
 
 pub mod factorial;
 pub mod associativity;
@@ -22,8 +26,16 @@ pub mod voting_rule;
 use associativity::Associativity;
 
 pub mod execution_context;
+pub mod frame;
+pub mod bytecode;
 
 pub mod shy_scalar;
+use shy_scalar::ShyScalar;
+pub mod temporal;
+pub mod units;
+use units::Unit;
+pub mod decimal;
+pub mod shy_regex;
 pub mod json_conversion;
 
 pub mod shy_operator;
@@ -33,7 +45,17 @@ pub mod shy_association;
 pub mod shy_object;
 pub mod expression;
 pub mod references;
-use expression::Expression;
+pub mod user_function;
+pub mod higher_order;
+pub mod optimizer;
+#[cfg(test)]
+pub mod bench;
+pub mod dialect;
+use dialect::Dialect;
+pub mod shy_error;
+use shy_error::{ShyError, ShyErrorKind};
+use user_function::UserFunction;
+use expression::{Expression, LocalVariable};
 
 
 //..................................................................
@@ -50,14 +72,42 @@ pub struct ShuntingYard<'a> {
     /// The input expression prior to parsing.
     pub expression_source: String,
 
-    /// Tokenized form of the input expression, still in infix order.
-    infix_order: Vec<ShyToken>,
+    /// Tokenized form of the input expression, still in infix order, each token with the span of the source text it was read from.
+    infix_order: Vec<SpannedToken>,
 
     /// Tokens rearranged into postfix order as a result of shunting yard.
     /// This form may have additional tokens added that were not present in the infix_order list of tokens.
-    postfix_order: Vec<ShyToken>,
+    /// Such a token gets the span of the token that caused it to be added.
+    postfix_order: Vec<SpannedToken>,
+
+    /// Operators waiting to be moved to postfix_order, each with the span of the token that pushed it.
+    operator_stack: Vec<(ShyOperator, Span)>,
+
+    /// Span of the infix token being shunted.
+    current_span: Span,
+
+    /// Positions in postfix_order of ShyToken::Branch tokens whose jump targets are not yet known.
+    /// Each entry corresponds to a Ternary or Colon marker or an And or Or operator on the operator_stack, in the same order.
+    branch_stack: Vec<usize>,
+
+    /// Positions in postfix_order of the ShyToken::Branch tokens of the earlier comparisons in each unfinished chain of comparisons, 
+    /// as in `0 < x <= 10`, along with the depth of the operator_stack at which the last comparison of the chain waits.
+    /// Their targets are filled in when that comparison is moved to postfix_order.
+    chain_branches: Vec<(usize, Vec<usize>)>,
 
-    operator_stack: Vec<ShyOperator>,
+    /// Warnings about parts of the expression that compile, but likely do not mean what was intended.
+    warnings: Vec<ShyError>,
+
+    /// Positions in postfix_order where each statement after the first begins. 
+    /// Semicolons leave no token in postfix_order, so the optimizer relies on these to find the statements.
+    statement_starts: Vec<usize>,
+
+    /// Names of the local variables declared with `let`, in the order first declared, 
+    /// each with the number of the statement that declares it, counting from zero.
+    locals: Vec<(String, usize)>,
+
+    /// Dialect in which the expression is written. Its tokens are translated into the standard language as they are read.
+    pub dialect: Dialect
 }
 
 impl<'a> From<String> for ShuntingYard<'a> {
@@ -67,7 +117,14 @@ impl<'a> From<String> for ShuntingYard<'a> {
             expression_source: expression,
             infix_order: vec![],
             postfix_order: vec![],
-            operator_stack: vec![]
+            operator_stack: vec![],
+            current_span: Span::default(),
+            branch_stack: vec![],
+            chain_branches: vec![],
+            warnings: vec![],
+            statement_starts: vec![],
+            locals: vec![],
+            dialect: Dialect::Standard
         }
     }
 }
@@ -79,61 +136,193 @@ impl<'a> From<&str> for ShuntingYard<'a> {
             expression_source: expression.to_string(),
             infix_order: vec![],
             postfix_order: vec![],
-            operator_stack: vec![]
+            operator_stack: vec![],
+            current_span: Span::default(),
+            branch_stack: vec![],
+            chain_branches: vec![],
+            warnings: vec![],
+            statement_starts: vec![],
+            locals: vec![],
+            dialect: Dialect::Standard
         }
     }
 }
 
 impl<'a> ShuntingYard<'a> {
     /// Parse the expression into tokens and apply the shunting yard algorithm to rearrange the tokens into postfix order.
-    /// Return the number of tokens parsed, or an error.
-    fn parse(&mut self) -> std::result::Result<usize,String> {
-        let lexer = Lexer::new(&self.expression_source);
-        let mut parser_tokens = Vec::new();
+    /// Return the number of tokens parsed, or every error found, in the order they appear in the expression.
+    /// 
+    /// After an error, parsing resumes at the next statement boundary: a semicolon, or a blank line.
+    /// The statement with the error contributes no tokens, so at most one error is reported per statement.
+    fn parse(&mut self) -> std::result::Result<usize,Vec<ShyError>> {
+        let source_characters: Vec<char> = self.expression_source.chars().collect();
+        let mut errors = self.tokenize(&source_characters);
+        let infix_order = mem::take(&mut self.infix_order);
+        if errors.is_empty() {
+            match self.compile_tokens(&infix_order) {
+                Ok(token_count) => return Ok(token_count),
+                Err(error) => errors.push(error)
+            }
+        }
+
+        // Check the statements one at a time, so that errors after the first may be found.
+        let statement_errors = self.find_statement_errors(&infix_order, &source_characters);
+        if !statement_errors.is_empty() {
+            errors.retain(|error| error.kind == ShyErrorKind::Lexical);
+            errors.extend(statement_errors);
+        }
+        errors.sort_by_key(|error| error.span.map_or(0, |span| span.start));
+        Err(errors)
+    }
 
-        // Read expression and parse it into ParserTokens.
-        parser_tokens.extend(lexer);
-        for ptoken in parser_tokens.iter() {
-            if let ParserToken::Error(_) = ptoken {
-                return Err(format!("Lexical Analyzer found this error: {}", ptoken.to_string()));
+    /// Read the expression and translate it into ShyTokens in infix_order, noting where in the expression each was found.
+    /// Return the lexical errors. The Lexer resumes at the next statement boundary after each, 
+    /// and the part of the statement read before the error is discarded.
+    fn tokenize(&mut self, source_characters: &[char]) -> Vec<ShyError> {
+        let mut lexer = Lexer::new(&self.expression_source);
+        lexer.resynchronize = true;
+        let mut errors = Vec::new();
+        for (ptoken, span) in lexer.with_spans() {
+            if let ParserToken::Error(ref err) = ptoken {
+                debug!(target: "parser", "Lexical Analyzer found this error: {}", ptoken.to_string());
+                errors.push(ShyError::new(ShyErrorKind::Lexical, err.message(), Some(span)));
+                let mut statement_start = self.infix_order.len();
+                let mut following_span = span;
+                while statement_start > 0 
+                  && self.infix_order[statement_start - 1].token != ShyToken::Operator(ShyOperator::Semicolon)
+                  && !Self::is_blank_line_between(source_characters, self.infix_order[statement_start - 1].span, following_span) {
+                    statement_start -= 1;
+                    following_span = self.infix_order[statement_start].span;
+                }
+                self.infix_order.truncate(statement_start);
+                continue;
+            }
+            let ptoken = match self.dialect {
+                Dialect::Standard => ptoken,
+                _ => self.dialect.translate(ptoken, self.follows_assignment_target())
+            };
+            let stoken: ShyToken = ptoken.clone().into();
+            if stoken.is_error() {
+                warn!("Parser unable to translate ParserToken {} '{}' into a ShyToken", ptoken.name(), ptoken.to_string());
+                // Stand an error value in its place, to be reported as a syntax error at its span.
+                self.infix_order.push(SpannedToken::new(ShyToken::Value(ShyValue::error(format!("Parser could not use {}", ptoken.name()))), span));
+            }
+            else {
+                self.infix_order.push(SpannedToken::new(stoken, span));
             }
         }
+        errors
+    }
 
-        // Transform ParserTokens into ShyTokens.
-        self.infix_order.extend(parser_tokens.iter().map(
-          |ptoken: &ParserToken| {
-              let stoken: ShyToken = ptoken.clone().into();
-              if stoken.is_error() {
-                  warn!("Parser unable to translate ParserToken {} '{}' into a ShyToken", ptoken.name(), ptoken.to_string());
-              }
-              stoken
-          }
-        ));
+    /// True if the tokens read so far in the current statement are a name that an equals sign assigns to, 
+    /// as in `x =`, `well.depth =` or `let x =`, or a function declaration, as in `fn f(x) =`.
+    fn follows_assignment_target(&self) -> bool {
+        let statement_start = self.infix_order.iter().rposition(|spanned| spanned.token == ShyToken::Operator(ShyOperator::Semicolon)).map_or(0, |position| position + 1);
+        let statement: Vec<&ShyToken> = self.infix_order[statement_start..].iter().map(|spanned| &spanned.token).collect();
+        match statement[..] {
+            [ShyToken::Value(ShyValue::Variable(_))] | [ShyToken::Value(ShyValue::PropertyChain(_))] => true,
+            [ShyToken::Value(ShyValue::Variable(keyword)), ShyToken::Value(ShyValue::Variable(_))] => keyword == "let",
+            [ShyToken::Value(ShyValue::Variable(keyword)), ..] => keyword == "fn" && !statement.contains(&&ShyToken::Operator(ShyOperator::Assign)),
+            _ => false
+        }
+    }
+
+    /// True if a statement begins at the given position in the tokens: 
+    /// at the first token, after a semicolon, or after a blank line.
+    fn starts_statement(tokens: &[SpannedToken], source_characters: &[char], position: usize) -> bool {
+        position == 0 
+            || tokens[position - 1].token == ShyToken::Operator(ShyOperator::Semicolon) 
+            || Self::is_blank_line_between(source_characters, tokens[position - 1].span, tokens[position].span)
+    }
 
-        // Check the ShyTokens for errors.
-        if self.infix_order.iter().any(|token| token.is_error()) { 
-            return Err(format!("Parser found errors")); 
+    /// True if the source text between two spans includes a line holding nothing but whitespace.
+    fn is_blank_line_between(source_characters: &[char], before: Span, after: Span) -> bool {
+        let gap = &source_characters[before.end.min(after.start)..after.start];
+        match (gap.iter().position(|c| *c == '\n'), gap.iter().rposition(|c| *c == '\n')) {
+            (Some(first), Some(last)) => first < last && gap[first..last].iter().all(|c| c.is_whitespace()),
+            _ => false
         }
+    }
 
-        // Time for Shunting Yard!
-        let shunt_status = self.shunt();
+    /// Compile each statement of the tokens separately, returning the first error from each. 
+    /// Within a statement, checking resumes after the first blank line that follows the error, if there is one.
+    fn find_statement_errors(&self, tokens: &[SpannedToken], source_characters: &[char]) -> Vec<ShyError> {
+        let mut errors = Vec::new();
+        let mut start = 0;
+        while start < tokens.len() {
+            let end = Self::find_statement_end(tokens, start);
+            let mut resume = start;
+            while resume < end {
+                let mut statement_yard: ShuntingYard = self.expression_source.clone().into();
+                match statement_yard.compile_tokens(&tokens[resume..end]) {
+                    Ok(_) => break,
+                    Err(error) => {
+                        let error_start = error.span.map_or(0, |span| span.start);
+                        errors.push(error);
+                        resume = (resume + 1..end)
+                            .find(|position| tokens[*position].span.start > error_start 
+                                && Self::starts_statement(tokens, source_characters, *position))
+                            .unwrap_or(end);
+                    }
+                }
+            }
+            start = end + 1;
+        }
+        errors
+    }
+
+    /// Check infix tokens for errors, rewrite them with the passes that precede shunting yard, then shunt them into postfix_order.
+    /// Return the number of tokens in postfix_order, or the first error found.
+    fn compile_tokens(&mut self, tokens: &[SpannedToken]) -> std::result::Result<usize,ShyError> {
+        // A literal that could not be converted into a value, such as a malformed regex, is an error.
+        // So is a ParserToken that could not be translated into a ShyToken.
+        if let Some((message, span)) = tokens.iter().find_map(|spanned| match &spanned.token {
+            ShyToken::Value(ShyValue::Scalar(ShyScalar::Error(message))) => Some((message.clone(), spanned.span)),
+            _ => None
+        }) {
+            return Err(ShyError::syntax(message, span));
+        }
+
+        // Lower match and case expressions into chains of conditional arms, so that only the arm selected is evaluated.
+        let tokens = Self::lower_conditional_expressions(tokens)?;
+
+        // Lower calls to the if function into ternary operators so that the untaken branch is skipped.
+        let tokens = Self::lower_if_calls(&tokens);
+
+        // Attach units of measure to the numbers they follow, and recognize unit conversions.
+        let tokens = Self::attach_units(&tokens);
 
-        shunt_status
+        // Join the words of not in, and gather the two bounds of each between into one operand.
+        let tokens = Self::lower_word_operators(&tokens)?;
+
+        // Compile each lambda into a single value, then compile the bodies of function declarations separately, 
+        // leaving a single token for each declaration.
+        let tokens = self.compile_lambdas(&tokens)?;
+        let tokens = self.compile_function_declarations(&tokens)?;
+
+        // Strip the let keyword from declarations of local variables, remembering their names.
+        self.infix_order = self.declare_locals(&tokens)?;
+
+        // Time for Shunting Yard!
+        self.shunt()
     }
 
     /// Perform the Shunting yard algorithm.
-    fn shunt(&mut self) -> std::result::Result<usize,String> {
+    fn shunt(&mut self) -> std::result::Result<usize,ShyError> {
         // Need to clone infix_order to placate the borrow-checker, otherwise I cannot call the reduce method.
         let infix_order_copy = self.infix_order.clone();
         let mut op_count_since_value = 0;
-        for stoken in infix_order_copy.iter() {
+        let mut previous_token: Option<&ShyToken> = None;
+        for SpannedToken { token: stoken, span } in infix_order_copy.iter() {
+            let span = *span;
+            self.current_span = span;
             // Variable Rule: Check for rvalues on postfix-ordered output stack.
             //                If we find an rvalue, push a Load operator onto the postfix-ordered output stack.
             //                Variable values must be loaded from context before the other operators can act upon them. 
             //                Only the assignment, post-increment and post-decrement operators perform their own 
             //                loading from and saving to the context.
             if self.is_rvalue_on_stack(&stoken) && op_count_since_value == 0 {
-                self.postfix_order.push(ShyToken::Operator(ShyOperator::Load));
+                self.emit_load();
             }
             op_count_since_value += 1;
             match stoken {
@@ -142,128 +331,1198 @@ impl<'a> ShuntingYard<'a> {
                 //                     a Load token at the top of the next loop.
                 ShyToken::Value(_) => { 
                     op_count_since_value = 0;
-                    self.postfix_order.push(stoken.clone())
+                    self.emit(stoken.clone(), span)
                 },
 
                 // Semicolon Rule: Force the moving of all operators on the operator_stack to the postfix_order stack,
                 //                 followed by the semicolon itself.
                 ShyToken::Operator(ShyOperator::Semicolon) => {
-                    self.reduce_all()
+                    self.reduce_all()?;
+                    self.statement_starts.push(self.postfix_order.len())
                 },
 
-                // Left Parenthesis Rule: Push all Left Parentheses onto the Operator Stack
-                ShyToken::Operator(ShyOperator::OpenParenthesis) =>
-                    self.operator_stack.push(ShyOperator::OpenParenthesis),
+                // Left Parenthesis Rule: Push all Left Parentheses onto the Operator Stack.
+                //                        Parentheses after a function name hold its arguments, which are built 
+                //                        into a vector as for a vector literal, starting from an empty vector.
+                ShyToken::Operator(ShyOperator::OpenParenthesis) => {
+                    if self.top_operator() == Some(ShyOperator::FunctionCall) {
+                        self.emit(ShyToken::Value(ShyValue::Vector(vec![])), span);
+                    }
+                    self.push_operator(ShyOperator::OpenParenthesis)
+                },
 
                 // Right Parenthesis Rule: Pop all operators off the Operator Stack 
                 //                         and push them onto the postfix-ordered output stack 
                 //                         until we find matching Left Parenthesis.
+                //                         After a function name, append the last argument, unless there are none.
                 ShyToken::Operator(ShyOperator::CloseParenthesis) => {
                     loop {
-                        match self.operator_stack.pop() {
-                            Some(ShyOperator::OpenParenthesis) => break,
-                            Some(op) => self.postfix_order.push(ShyToken::Operator(op)),
+                        match self.pop_operator() {
+                            Some((ShyOperator::OpenParenthesis, _)) => break,
+                            Some((op, op_span)) => self.move_to_postfix(op, op_span)?,
                             None => { 
                                 debug!(target: "parser", "Unbalanced closing parenthesis:\n{:?}", self);
-                                return Err("Unbalanced closing parenthesis".to_string())
+                                return Err(self.syntax_error("Unbalanced closing parenthesis"))
                             }
                         }
                     }
+                    if self.top_operator() == Some(ShyOperator::FunctionCall) 
+                      && previous_token != Some(&ShyToken::Operator(ShyOperator::OpenParenthesis)) {
+                        self.emit(ShyToken::Operator(ShyOperator::Append), span);
+                    }
+                },
+
+                // Sign Rule: A plus or minus sign that does not follow an operand is a prefix sign, as in `-x` or `2 * -(a + b)`. 
+                //            With no left operand, nothing can be reduced before it, so push it straight onto the operator stack.
+                ShyToken::Operator(ShyOperator::Add) if !Self::ends_operand(previous_token) => 
+                    self.push_operator(ShyOperator::PrefixPlusSign),
+                ShyToken::Operator(ShyOperator::Subtract) if !Self::ends_operand(previous_token) => 
+                    self.push_operator(ShyOperator::PrefixMinusSign),
+
+                // Vector Literal Rule, Part 1: An opening bracket that does not follow a value begins a vector literal, as in `[1, 2, 3]`.
+                //                             Push an empty vector onto the postfix-ordered output stack; each item will be appended to it.
+                //                             The Append operator waits on the operator stack beneath the bracket as a marker.
+                ShyToken::Operator(ShyOperator::OpenBracket) if !Self::ends_operand(previous_token) => {
+                    self.emit(ShyToken::Value(ShyValue::Vector(vec![])), span);
+                    self.push_operator(ShyOperator::Append);
+                    self.push_operator(ShyOperator::OpenBracket)
+                },
+
+                // Index Rule, Part 1: An opening bracket that follows a value indexes into that value. 
+                //                     If the value is a variable or property chain, it is not loaded; instead the Member operator 
+                //                     will extend the reference with the key, so that the element may be either loaded or assigned.
+                //                     Otherwise the Index operator will retrieve the element from the computed value.
+                //                     Like a function call, the operator waits on the operator stack beneath the bracket.
+                ShyToken::Operator(ShyOperator::OpenBracket) => {
+                    self.reduce(ShyOperator::Index)?;
+                    let indexer = if self.is_last_token_variable() { ShyOperator::Member } else { ShyOperator::Index };
+                    self.push_operator(indexer);
+                    self.push_operator(ShyOperator::OpenBracket)
+                },
+
+                // Index Rule, Part 2: Pop all operators off the Operator Stack and push them onto the postfix-ordered output stack 
+                //                     until we find the matching opening bracket, then immediately push the Member or Index operator, 
+                //                     so that the Variable Rule can decide whether a Member reference must be loaded.
+                // Vector Literal Rule, Part 2: For a vector literal, the operator beneath the bracket is the Append marker, 
+                //                              which appends the last item unless the literal is empty.
+                ShyToken::Operator(ShyOperator::CloseBracket) => {
+                    loop {
+                        match self.pop_operator() {
+                            Some((ShyOperator::OpenBracket, _)) => break,
+                            Some((ShyOperator::OpenParenthesis, _)) | None => { 
+                                debug!(target: "parser", "Unbalanced closing bracket:\n{:?}", self);
+                                return Err(self.syntax_error("Unbalanced closing bracket"))
+                            },
+                            Some((op, op_span)) => self.move_to_postfix(op, op_span)?
+                        }
+                    }
+                    match self.pop_operator() {
+                        Some((ShyOperator::Append, _)) if previous_token == Some(&ShyToken::Operator(ShyOperator::OpenBracket)) => (),
+                        Some((indexer, indexer_span)) => self.emit(ShyToken::Operator(indexer), indexer_span.to(span)),
+                        None => return Err(self.syntax_error("Unbalanced closing bracket"))
+                    }
+                    op_count_since_value = 0;
                 },
 
-                // TODO: Handle Unary operators.
+                // Comma Rule: The first comma of a list pairs two values into a vector. 
+                //             Later commas of the same list append to that vector, so a vector passed as an item is not flattened.
+                //             Inside a vector literal or the arguments of a function call, every item is appended directly to the vector,
+                //             so that a function receives exactly the arguments written in the call.
+                //             Inside an object literal, the comma ends a key: value entry, so the Property operator is moved.
+                ShyToken::Operator(ShyOperator::Comma) if self.is_in_object_literal() => self.close_object_entry()?,
+                ShyToken::Operator(ShyOperator::Comma) => {
+                    let postfix_length = self.postfix_order.len();
+                    self.reduce(ShyOperator::Comma)?;
+                    let continues_list = self.postfix_order.len() > postfix_length 
+                        && match self.postfix_order.last().map(|spanned| &spanned.token) {
+                            Some(ShyToken::Operator(ShyOperator::Comma)) | Some(ShyToken::Operator(ShyOperator::Append)) => true,
+                            _ => false
+                        };
+                    let stack_depth = self.operator_stack.len();
+                    let in_vector_literal = stack_depth >= 2 
+                        && self.operator_stack[stack_depth - 1].0 == ShyOperator::OpenBracket 
+                        && self.operator_stack[stack_depth - 2].0 == ShyOperator::Append;
+                    let in_arguments = stack_depth >= 2 
+                        && self.operator_stack[stack_depth - 1].0 == ShyOperator::OpenParenthesis 
+                        && self.operator_stack[stack_depth - 2].0 == ShyOperator::FunctionCall;
+                    if in_vector_literal || in_arguments {
+                        self.emit(ShyToken::Operator(ShyOperator::Append), span)
+                    }
+                    else if continues_list {
+                        self.push_operator(ShyOperator::Append)
+                    }
+                    else {
+                        self.push_operator(ShyOperator::Comma)
+                    }
+                },
+
+                // Object Literal Rule, Part 1: An opening brace begins an object literal, as in `{ level: 2, msg: "high pressure" }`.
+                //                             Push an empty object onto the postfix-ordered output stack; each entry will set one of its properties.
+                //                             Executing the expression copies the empty object, so every execution builds a new object.
+                ShyToken::Operator(ShyOperator::OpenBrace) => {
+                    if Self::ends_operand(previous_token) {
+                        return Err(self.syntax_error("Opening brace must begin an object literal"));
+                    }
+                    self.emit(ShyToken::Value(ShyValue::empty()), span);
+                    self.push_operator(ShyOperator::OpenBrace)
+                },
+
+                // Object Literal Rule, Part 2: A colon directly inside the braces follows a property name. 
+                //                              The name is pushed as a string and the Property operator waits for the value.
+                ShyToken::Operator(ShyOperator::Colon) if self.top_operator() == Some(ShyOperator::OpenBrace) => 
+                    self.shunt_object_key()?,
+
+                // Object Literal Rule, Part 3: Pop all operators off the Operator Stack and push them onto the postfix-ordered output stack 
+                //                              until we find the matching opening brace.
+                ShyToken::Operator(ShyOperator::CloseBrace) => {
+                    if !self.is_in_object_literal() {
+                        debug!(target: "parser", "Unbalanced closing brace:\n{:?}", self);
+                        return Err(self.syntax_error("Unbalanced closing brace"))
+                    }
+                    if previous_token != Some(&ShyToken::Operator(ShyOperator::OpenBrace)) {
+                        self.close_object_entry()?;
+                    }
+                    self.pop_operator();
+                    op_count_since_value = 0;
+                },
+
+                // Ternary Rule, Part 1: The question mark ends the test of a ternary conditional. 
+                //                       Emit a branch that will skip the true branch if the test is false; 
+                //                       its target is filled in when the matching colon is found.
+                //                       The Ternary operator stays on the operator stack as a marker.
+                //                       The arrow of an arm of a match expression is compiled the same way, but with a Case branch.
+                ShyToken::Operator(op @ ShyOperator::Ternary) | ShyToken::Operator(op @ ShyOperator::Case) => {
+                    self.reduce(ShyOperator::Ternary)?;
+                    self.branch_stack.push(self.postfix_order.len());
+                    self.emit(ShyToken::Branch(*op, 0), span);
+                    self.push_operator(ShyOperator::Ternary)
+                },
+
+                // Match Rule: The subject of a match expression stays on the stack while the patterns of its arms are tested. 
+                //             Before each test, copy the subject to be its left operand.
+                ShyToken::Operator(ShyOperator::CaseSubject) => self.emit(stoken.clone(), span),
+
+                // Ternary Rule, Part 2: The colon ends the true branch. 
+                //                       Emit a jump over the false branch and replace the Ternary marker with a Colon marker. 
+                //                       The jump's target is filled in when the Colon marker is popped off the operator stack.
+                ShyToken::Operator(ShyOperator::Colon) => self.shunt_colon()?,
+
+                // Short-circuit Rule: After the left operand of a logical and, or or null-coalescing operator, emit a branch that 
+                //                     skips the right operand if the left operand alone decides the result. Its target is filled in when 
+                //                     the operator is moved from the operator stack to the postfix-ordered output stack.
+                //                     A variable that is the whole left operand of a null-coalescing operator is loaded null-safely, 
+                //                     so that `missing ?? 0` treats an undefined variable as null.
+                ShyToken::Operator(op @ ShyOperator::And) | ShyToken::Operator(op @ ShyOperator::Or) 
+                | ShyToken::Operator(op @ ShyOperator::Coalesce) => {
+                    self.reduce(*op)?;
+                    if *op == ShyOperator::Coalesce {
+                        self.load_null_safe();
+                    }
+                    self.branch_stack.push(self.postfix_order.len());
+                    self.emit(ShyToken::Branch(*op, 0), span);
+                    self.push_operator(*op)
+                },
+
+                // Chained Comparison Rule: In a chain of comparisons like `0 < x <= 10`, every comparison but the last becomes a branch 
+                //                          that compares its operands and, if the result is false, ends the chain with it. 
+                //                          Otherwise the branch leaves its right operand as the left operand of the next comparison, 
+                //                          so that the chain means `0 < x and x <= 10`, but evaluates x only once. 
+                //                          The targets of the branches are filled in when the last comparison of the chain 
+                //                          is moved to the postfix-ordered output stack.
+                ShyToken::Operator(op) if op.is_ordering() => self.shunt_comparison(*op)?,
 
                 // Precedence & Associativity Rules:
                 ShyToken::Operator(op) => {
-                    self.reduce(op.clone());
-                    self.operator_stack.push(*op)
+                    self.reduce(op.clone())?;
+                    self.push_operator(*op)
                 },
                 
                 // Function Rule: Functions call for an operator to be pushed on the operator stack and a value (the function name)
                 //                to be pushed on the postfix-ordered output stack.
                 //                Assume that the value is a ShyValue::FunctionName.
                 ShyToken::OperatorWithValue(ShyOperator::FunctionCall, value) => {
-                    self.postfix_order.push(ShyToken::Value(value.clone()));
-                    self.operator_stack.push(ShyOperator::FunctionCall)
+                    self.emit(ShyToken::Value(value.clone()), span);
+                    self.push_operator(ShyOperator::FunctionCall)
                 },
 
                 // Power Rule: Power operations call for an exponentiation operator to be pushed on the operator stack and a value (the exponent)
                 //             to be pushed on the postfix-ordered output stack.
                 //             Assume that the value is a ShyValue::Integer.
                 ShyToken::OperatorWithValue(ShyOperator::Exponentiation, value) => {
-                    self.reduce(ShyOperator::Exponentiation);
-                    self.postfix_order.push(ShyToken::Value(value.clone()));
-                    self.operator_stack.push(ShyOperator::Exponentiation)
+                    self.reduce(ShyOperator::Exponentiation)?;
+                    self.emit(ShyToken::Value(value.clone()), span);
+                    self.push_operator(ShyOperator::Exponentiation)
                 },
                 
                 // This is an error case that should not occur currently.
-                // Once when we support shortcut operators for and (&&) and or (||),
-                // an error in a branch not taken should be overlooked, so defer to the evaluation of the expression.
-                _ => self.postfix_order.push(stoken.clone())
+                // Since and (&&) and or (||) short-circuit, an error in a branch not taken should be overlooked, 
+                // so defer to the evaluation of the expression.
+                _ => self.emit(stoken.clone(), span)
             }
+            previous_token = Some(stoken);
         }
         // Variable Rule, Part 2: If the last token of the expression is a variable, 
         //                        we cannot look ahead to see what the next operator is.
         //                        Automatically add a Load operator. 
         //                        This must be done before copying the remaining operators from the operator stack!
         if self.is_last_token_variable() {
-            self.postfix_order.push(ShyToken::Operator(ShyOperator::Load));
+            self.emit_load();
         }
 
         // End of Input Rule: Once there are no more operators expected, transfer all remaining operators 
         //                    from the operator stack to the postfix-ordered output stack.
         //                    This reverses the token's original order, by popping from one stack and pushing onto the other.
         loop {
-            match self.operator_stack.pop() {
-                Some(ShyOperator::OpenParenthesis) => return Err("Unbalanced opening parenthesis".to_string()),
-                Some(op) => self.postfix_order.push(ShyToken::Operator(op)),
+            match self.pop_operator() {
+                Some((ShyOperator::OpenParenthesis, op_span)) => return Err(ShyError::syntax("Unbalanced opening parenthesis", op_span)),
+                Some((op, op_span)) => self.move_to_postfix(op, op_span)?,
                 None => break
             }
         }
-        Ok(self.postfix_order.len())
+        Ok(self.postfix_order.len())
+    }
+
+    /// Push a token onto the postfix_order stack, along with the span of the source text it came from.
+    fn emit(&mut self, token: ShyToken, span: Span) {
+        self.postfix_order.push(SpannedToken::new(token, span));
+    }
+
+    /// Push a Load operator onto the postfix_order stack, giving it the span of the variable it loads.
+    fn emit_load(&mut self) {
+        let span = self.postfix_order.last().map_or(self.current_span, |spanned| spanned.span);
+        self.emit(ShyToken::Operator(ShyOperator::Load), span);
+    }
+
+    /// If the postfix-ordered output stack ends by loading a variable, load it as a null-safe property chain instead, 
+    /// which yields null if the variable is undefined.
+    fn load_null_safe(&mut self) {
+        let length = self.postfix_order.len();
+        if length < 2 || self.postfix_order[length - 1].token != ShyToken::Operator(ShyOperator::Load) {
+            return;
+        }
+        if let ShyToken::Value(ShyValue::Variable(name)) = &self.postfix_order[length - 2].token {
+            let chain = vec![ChainLink::NullSafe(name.clone())];
+            self.postfix_order[length - 2].token = ShyToken::Value(ShyValue::PropertyChain(chain));
+        }
+    }
+
+    /// Push an operator onto the operator_stack, along with the span of the token being shunted.
+    fn push_operator(&mut self, op: ShyOperator) {
+        self.operator_stack.push((op, self.current_span));
+    }
+
+    /// Pop an operator and its span off the operator_stack.
+    fn pop_operator(&mut self) -> Option<(ShyOperator, Span)> {
+        self.operator_stack.pop()
+    }
+
+    /// The operator on top of the operator_stack.
+    fn top_operator(&self) -> Option<ShyOperator> {
+        self.operator_stack.last().map(|(op, _)| *op)
+    }
+
+    /// A syntax error found at the token being shunted.
+    fn syntax_error<S>(&self, message: S) -> ShyError where S: Into<String> {
+        ShyError::syntax(message, self.current_span)
+    }
+
+    /// Handle a comparison that orders its operands, such as <=, which may continue a chain of comparisons.
+    fn shunt_comparison(&mut self, op: ShyOperator) -> std::result::Result<(), ShyError> {
+        // Only operators of higher precedence are moved, so that an earlier comparison of the chain is left on top.
+        loop {
+            match self.top_operator() {
+                Some(ShyOperator::OpenParenthesis) | Some(ShyOperator::CloseParenthesis) 
+                | Some(ShyOperator::OpenBracket) | Some(ShyOperator::OpenBrace) => break,
+                Some(higher_precedence_op) if higher_precedence_op.precedence() > op.precedence() => {
+                    let (higher_precedence_op, span) = self.pop_operator().unwrap();
+                    self.move_to_postfix(higher_precedence_op, span)?
+                },
+                _ => break
+            }
+        }
+        match self.top_operator() {
+            Some(previous_op) if previous_op.is_ordering() => {
+                let (previous_op, previous_span) = self.pop_operator().unwrap();
+                self.warn_if_comparison_operand(previous_span);
+                let depth = self.operator_stack.len();
+                let branch_position = self.postfix_order.len();
+                match self.chain_branches.last_mut() {
+                    Some((chain_depth, positions)) if *chain_depth == depth => positions.push(branch_position),
+                    _ => self.chain_branches.push((depth, vec![branch_position]))
+                }
+                self.emit(ShyToken::Branch(previous_op, 0), previous_span);
+            },
+            _ => {
+                self.reduce(op)?;
+                self.warn_if_comparison_operand(self.current_span);
+            }
+        }
+        self.push_operator(op);
+        Ok(())
+    }
+
+    /// Warn if the operand last moved to the postfix-ordered output stack is the result of a comparison, 
+    /// as when a comparison that orders its operands is written `(0 < x) < 10`, which compares a boolean to a number.
+    fn warn_if_comparison_operand(&mut self, span: Span) {
+        if let Some(ShyToken::Operator(operand_op)) = self.postfix_order.last().map(|spanned| &spanned.token) {
+            if operand_op.is_comparison() {
+                self.warnings.push(ShyError::warning(
+                    "Comparison of the result of another comparison. To test that a value lies between two bounds, chain the comparisons, as in 0 < x <= 10", 
+                    span
+                ));
+            }
+        }
+    }
+
+    /// Handle the colon that separates the true and false branches of a ternary conditional.
+    /// Operators from the true branch are moved to the postfix_order stack until the Ternary marker is found.
+    fn shunt_colon(&mut self) -> std::result::Result<(), ShyError> {
+        loop {
+            match self.pop_operator() {
+                Some((ShyOperator::Ternary, _)) => break,
+                // A question mark directly followed by a colon is read as quit-if-false, as in "x ? : 2".
+                Some((ShyOperator::QuitIfFalse, op_span)) => return Err(ShyError::syntax("Ternary operator is missing its true branch", op_span)),
+                Some((ShyOperator::OpenParenthesis, _)) | Some((ShyOperator::OpenBrace, _)) | None => return Err(self.syntax_error("Colon without a matching question mark")),
+                Some((op, op_span)) => self.move_to_postfix(op, op_span)?
+            }
+        }
+        let jump_position = self.postfix_order.len();
+        match self.branch_stack.last() {
+            Some(test_position) if *test_position + 1 == jump_position => return Err(self.syntax_error("Ternary operator is missing its true branch")),
+            _ => ()
+        }
+        self.emit(ShyToken::Branch(ShyOperator::Jump, 0), self.current_span);
+        match self.branch_stack.pop() {
+            Some(test_position) => if let ShyToken::Branch(_, target) = &mut self.postfix_order[test_position].token {
+                *target = jump_position;
+            },
+            None => return Err(self.syntax_error("Ternary operator has no branch"))
+        }
+        self.branch_stack.push(jump_position);
+        self.push_operator(ShyOperator::Colon);
+        Ok(())
+    }
+
+    /// Handle the colon that separates a property name from its value in an object literal.
+    /// The name may be written as an identifier or a string, and must directly follow the opening brace or a comma.
+    fn shunt_object_key(&mut self) -> std::result::Result<(), ShyError> {
+        let key_position = self.postfix_order.len() - 1;
+        let follows_separator = match key_position.checked_sub(1).map(|position| &self.postfix_order[position].token) {
+            Some(ShyToken::Value(ShyValue::Object(_))) | Some(ShyToken::Operator(ShyOperator::Property)) => true,
+            _ => false
+        };
+        let key = match &self.postfix_order[key_position].token {
+            ShyToken::Value(ShyValue::Variable(name)) if follows_separator => name.clone(),
+            ShyToken::Value(ShyValue::Scalar(shy_scalar::ShyScalar::String(name))) if follows_separator => name.clone(),
+            _ => return Err(ShyError::syntax("Object literal keys must be names or strings", self.postfix_order[key_position].span))
+        };
+        self.postfix_order[key_position].token = ShyToken::Value(key.into());
+        self.push_operator(ShyOperator::Property);
+        Ok(())
+    }
+
+    /// True if the innermost grouping symbol on the operator stack is the opening brace of an object literal.
+    fn is_in_object_literal(&self) -> bool {
+        for (op, _) in self.operator_stack.iter().rev() {
+            match op {
+                ShyOperator::OpenBrace => return true,
+                ShyOperator::OpenParenthesis | ShyOperator::OpenBracket => return false,
+                _ => ()
+            }
+        }
+        false
+    }
+
+    /// End a key: value entry of an object literal by moving all operators of the value
+    /// and then the Property operator to the postfix_order stack. 
+    /// If there is no Property operator over the opening brace, the entry was not written as key: value.
+    fn close_object_entry(&mut self) -> std::result::Result<(), ShyError> {
+        self.reduce(ShyOperator::Property)?;
+        match self.top_operator() {
+            Some(ShyOperator::Property) => {
+                let (op, op_span) = self.pop_operator().unwrap();
+                self.emit(ShyToken::Operator(op), op_span);
+                Ok(())
+            },
+            _ => {
+                debug!(target: "parser", "Malformed object literal:\n{:?}", self);
+                Err(self.syntax_error("Object literal entries must have the form key: value"))
+            }
+        }
+    }
+
+    /// Move an operator that was popped off the operator_stack onto the postfix_order stack.
+    /// A Colon marker is not copied; instead, the jump that skips the false branch of its ternary 
+    /// is pointed at the end of the false branch, which is the current end of the postfix_order stack.
+    /// Likewise, once And, Or or Coalesce is copied, the branch that skips its right operand is pointed just past it.
+    /// The span is that of the operator's own token, so that an error reported by the operator points at it.
+    fn move_to_postfix(&mut self, op: ShyOperator, span: Span) -> std::result::Result<(), ShyError> {
+        match op {
+            ShyOperator::And | ShyOperator::Or | ShyOperator::Coalesce => {
+                self.emit(ShyToken::Operator(op), span);
+                match self.branch_stack.pop() {
+                    Some(branch_position) => {
+                        self.postfix_order[branch_position].token = ShyToken::Branch(op, self.postfix_order.len());
+                        Ok(())
+                    },
+                    None => Err(ShyError::syntax(format!("{} operator has no branch", op), span))
+                }
+            },
+            op if op.is_ordering() => {
+                self.warn_if_comparison_operand(span);
+                self.emit(ShyToken::Operator(op), span);
+                // The last comparison of a chain ends it, so the branches of the earlier comparisons land just past it.
+                if let Some((depth, _)) = self.chain_branches.last() {
+                    if *depth == self.operator_stack.len() {
+                        let (_, positions) = self.chain_branches.pop().unwrap();
+                        let end = self.postfix_order.len();
+                        for position in positions {
+                            if let ShyToken::Branch(_, target) = &mut self.postfix_order[position].token {
+                                *target = end;
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            },
+            // A question mark whose colon lies ahead was ended early by an operator in its true branch that binds more loosely, 
+            // as in "x ? y = 1 : 2", so that branch is what is malformed.
+            ShyOperator::Ternary => match self.loose_operator_before_colon() {
+                Some(loose_op) => Err(self.syntax_error(format!(
+                    "The true branch of the ternary operator is malformed: {} binds more loosely than ? and :, so put the branch in parentheses", loose_op))),
+                None => Err(ShyError::syntax("Ternary operator is missing its colon", span))
+            },
+            ShyOperator::OpenBracket => Err(ShyError::syntax("Unbalanced opening bracket", span)),
+            ShyOperator::OpenBrace => Err(ShyError::syntax("Unbalanced opening brace", span)),
+            ShyOperator::Colon => {
+                match self.branch_stack.pop() {
+                    Some(jump_position) if jump_position + 1 == self.postfix_order.len() => 
+                        Err(ShyError::syntax("Ternary operator is missing its false branch", span)),
+                    Some(jump_position) => {
+                        self.postfix_order[jump_position].token = ShyToken::Branch(ShyOperator::Jump, self.postfix_order.len());
+                        Ok(())
+                    },
+                    None => Err(ShyError::syntax("Ternary operator has no branch", span))
+                }
+            },
+            _ => {
+                self.emit(ShyToken::Operator(op), span);
+                Ok(())
+            }
+        }
+    }
+
+    /// If the current token is an operator and the colon of a ternary operator follows it in the same statement and grouping,
+    /// return that operator, which ended the true branch of the ternary before its colon.
+    fn loose_operator_before_colon(&self) -> Option<ShyOperator> {
+        let start = self.infix_order.iter().position(|spanned| spanned.span == self.current_span)?;
+        let loose_op = match &self.infix_order[start].token {
+            ShyToken::Operator(op) => *op,
+            _ => return None
+        };
+        let mut depth = 0;
+        let mut open_ternaries = 0;
+        for spanned in &self.infix_order[start..] {
+            match spanned.token {
+                ShyToken::Operator(ShyOperator::OpenParenthesis) | ShyToken::Operator(ShyOperator::OpenBracket) 
+                | ShyToken::Operator(ShyOperator::OpenBrace) => depth += 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::CloseBrace) => {
+                    if depth == 0 {
+                        return None;
+                    }
+                    depth -= 1
+                },
+                ShyToken::Operator(ShyOperator::Semicolon) if depth == 0 => return None,
+                ShyToken::Operator(ShyOperator::Ternary) if depth == 0 => open_ternaries += 1,
+                ShyToken::Operator(ShyOperator::Colon) if depth == 0 && open_ternaries > 0 => open_ternaries -= 1,
+                ShyToken::Operator(ShyOperator::Colon) if depth == 0 => return Some(loose_op),
+                _ => ()
+            }
+        }
+        None
+    }
+
+    /// Lower each match expression, as in `match pressure { < 100 => "low", 100..500 => "normal", _ => "high" }`, 
+    /// and each case expression, as in `case when pressure < 100 then "low" else "high" end`, into a chain of conditional arms.
+    ///
+    /// A case becomes the ternary conditionals `((test) ? (result) : ... : (default))`.
+    /// A match evaluates its subject once and keeps it on the stack while its patterns are tested, each arm becoming 
+    /// `CaseSubject pattern Case (result) :`, where CaseSubject copies the subject to be the left operand of the pattern, 
+    /// Case is compiled like the question mark of a ternary, and the default arm becomes `CaseDefault (result)`.
+    /// Either kind of expression must end with a default, so that it always has a value.
+    /// The words match, case, when, then, else and end are only keywords where they begin these expressions or their parts.
+    fn lower_conditional_expressions(tokens: &[SpannedToken]) -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let mut lowered = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            if let Some(brace_position) = Self::find_match_brace(tokens, i) {
+                let (commas, close_position) = Self::find_arguments(tokens, brace_position)
+                    .ok_or_else(|| ShyError::syntax("match is missing the closing brace after its arms", tokens[brace_position].span))?;
+                let arms = Self::lower_match_arms(tokens, (i + 1, brace_position), &commas, close_position)?;
+                lowered.push(SpannedToken::new(ShyOperator::OpenParenthesis.into(), tokens[i].span.to(tokens[close_position].span)));
+                lowered.extend(arms);
+                lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), tokens[close_position].span));
+                i = close_position + 1;
+            }
+            else if Self::starts_case(tokens, i) {
+                let end_position = Self::lower_case_expression(tokens, i, &mut lowered)?;
+                i = end_position + 1;
+            }
+            else if tokens[i].token == ShyToken::Operator(ShyOperator::Range) {
+                return Err(ShyError::syntax("A range like 100..500 may only be the pattern of an arm of a match", tokens[i].span));
+            }
+            else {
+                lowered.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+        Ok(lowered)
+    }
+
+    /// True if the token is a variable with the given name, which may be a keyword where it appears.
+    /// A word followed by an opening parenthesis, as in `then (z = 1)`, is read as the name of a function call, so that counts too.
+    fn is_word(token: &ShyToken, word: &str) -> bool {
+        match token {
+            ShyToken::Value(ShyValue::Variable(name)) 
+            | ShyToken::OperatorWithValue(ShyOperator::FunctionCall, ShyValue::FunctionName(name)) => name == word,
+            _ => false
+        }
+    }
+
+    /// True if a case expression begins at the given position, where case is followed by when.
+    fn starts_case(tokens: &[SpannedToken], position: usize) -> bool {
+        Self::is_word(&tokens[position].token, "case") && position + 1 < tokens.len() && Self::is_word(&tokens[position + 1].token, "when")
+    }
+
+    /// If a match expression begins at the given position, return the position of the opening brace that follows its subject.
+    /// The keyword is followed by the subject, as in `match pressure {` or `match(depth / 2) {`, 
+    /// and the brace must come before the statement ends.
+    fn find_match_brace(tokens: &[SpannedToken], start: usize) -> Option<usize> {
+        let subject_start = match (&tokens[start].token, tokens.get(start + 1).map(|spanned| &spanned.token)) {
+            (ShyToken::OperatorWithValue(ShyOperator::FunctionCall, ShyValue::FunctionName(name)), _) if name == "match" => start + 1,
+            (keyword, Some(ShyToken::Value(_))) | (keyword, Some(ShyToken::Operator(ShyOperator::OpenParenthesis)))
+            | (keyword, Some(ShyToken::OperatorWithValue(ShyOperator::FunctionCall, _))) if Self::is_word(keyword, "match") => start + 1,
+            _ => return None
+        };
+        let mut depth = 0;
+        for (position, spanned) in tokens.iter().enumerate().skip(subject_start) {
+            match spanned.token {
+                ShyToken::Operator(ShyOperator::OpenBrace) if depth == 0 => return if position > subject_start { Some(position) } else { None },
+                ShyToken::Operator(ShyOperator::OpenParenthesis) | ShyToken::Operator(ShyOperator::OpenBracket) => depth += 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) if depth > 0 => depth -= 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::Semicolon) | ShyToken::Operator(ShyOperator::CloseBrace) => return None,
+                _ => ()
+            }
+        }
+        None
+    }
+
+    /// Lower the subject and the arms of a match expression, whose arms lie between the opening brace and the close position,
+    /// separated by the commas. A comma after the last arm is permitted.
+    fn lower_match_arms(tokens: &[SpannedToken], (subject_start, brace_position): (usize, usize), commas: &[usize], close_position: usize)
+      -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let subject = Self::lower_conditional_expressions(&tokens[subject_start..brace_position])?;
+        let mut lowered = vec![SpannedToken::new(ShyOperator::OpenParenthesis.into(), tokens[subject_start - 1].span)];
+        lowered.extend(subject);
+        lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), tokens[brace_position].span));
+
+        let mut boundaries = vec![brace_position];
+        boundaries.extend(commas.iter().cloned());
+        boundaries.push(close_position);
+        let mut arms: Vec<(usize, usize)> = boundaries.windows(2).map(|pair| (pair[0] + 1, pair[1])).collect();
+        let (last_start, last_end) = arms[arms.len() - 1];
+        if arms.len() > 1 && last_start == last_end {
+            arms.pop();
+        }
+        let mut has_default = false;
+        for (arm_start, arm_end) in arms {
+            let arm_span = tokens[arm_start - 1].span;
+            if has_default {
+                return Err(ShyError::syntax("The default arm, _ => value, must be the last arm of a match", arm_span));
+            }
+            let arrow = (arm_start..arm_end).find(|position| tokens[*position].token == ShyToken::Operator(ShyOperator::Lambda))
+                .filter(|arrow| *arrow > arm_start && *arrow + 1 < arm_end)
+                .ok_or_else(|| ShyError::syntax("Each arm of a match must be written pattern => result", arm_span))?;
+            if arm_start > brace_position + 1 {
+                lowered.push(SpannedToken::new(ShyOperator::Colon.into(), arm_span));
+            }
+            let pattern = &tokens[arm_start..arrow];
+            if pattern.len() == 1 && Self::is_word(&pattern[0].token, "_") {
+                has_default = true;
+                lowered.push(SpannedToken::new(ShyOperator::CaseDefault.into(), tokens[arm_start].span));
+            }
+            else {
+                lowered.push(SpannedToken::new(ShyOperator::CaseSubject.into(), tokens[arm_start].span));
+                lowered.extend(Self::lower_match_pattern(pattern)?);
+                lowered.push(SpannedToken::new(ShyOperator::Case.into(), tokens[arrow].span));
+            }
+            let result = Self::lower_conditional_expressions(&tokens[arrow + 1..arm_end])?;
+            lowered.push(SpannedToken::new(ShyOperator::OpenParenthesis.into(), tokens[arrow].span));
+            lowered.extend(result);
+            lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), tokens[arm_end - 1].span));
+        }
+        if !has_default {
+            return Err(ShyError::syntax("A match must end with a default arm, as in _ => value", tokens[close_position].span));
+        }
+        Ok(lowered)
+    }
+
+    /// Lower the pattern of an arm of a match into the operator and operand that test the subject, 
+    /// which the CaseSubject before it supplies as the left operand.
+    ///   - A pattern that begins with a comparison, in, not in, ~ or !~ applies it to the subject, as in `< 100` or `in [3, 7]`.
+    ///   - A range, as in `100..500`, becomes `.. (100, 500)`, which tests that the subject is at least the lower bound 
+    ///     but less than the upper bound.
+    ///   - Any other pattern must equal the subject.
+    fn lower_match_pattern(pattern: &[SpannedToken]) -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let (op, operand_start) = match (&pattern[0].token, pattern.get(1).map(|spanned| &spanned.token)) {
+            (ShyToken::Operator(ShyOperator::LogicalNot), Some(ShyToken::Operator(ShyOperator::In))) => (ShyOperator::NotIn, 2),
+            (ShyToken::Operator(op), _) if op.is_comparison() || *op == ShyOperator::In 
+                || *op == ShyOperator::Match || *op == ShyOperator::NotMatch => (*op, 1),
+            _ => (ShyOperator::Equals, 0)
+        };
+        let range = pattern.iter().position(|spanned| spanned.token == ShyToken::Operator(ShyOperator::Range));
+        let mut lowered = Vec::with_capacity(pattern.len() + 6);
+        match range {
+            Some(range) if operand_start == 0 => {
+                let range_span = pattern[range].span;
+                if range == 0 || range + 1 == pattern.len() {
+                    return Err(ShyError::syntax("A range must have a lower and an upper bound, as in 100..500", range_span));
+                }
+                let low = Self::lower_conditional_expressions(&pattern[..range])?;
+                let high = Self::lower_conditional_expressions(&pattern[range + 1..])?;
+                let at_range = |op: ShyOperator| SpannedToken::new(op.into(), range_span);
+                lowered.extend(vec![at_range(ShyOperator::Range), at_range(ShyOperator::OpenParenthesis), at_range(ShyOperator::OpenParenthesis)]);
+                lowered.extend(low);
+                lowered.extend(vec![at_range(ShyOperator::CloseParenthesis), at_range(ShyOperator::Comma), at_range(ShyOperator::OpenParenthesis)]);
+                lowered.extend(high);
+                lowered.extend(vec![at_range(ShyOperator::CloseParenthesis), at_range(ShyOperator::CloseParenthesis)]);
+            },
+            _ => {
+                let pattern_span = pattern[0].span;
+                if operand_start == pattern.len() {
+                    return Err(ShyError::syntax(format!("The {} pattern of a match must be followed by a value", op), pattern_span));
+                }
+                let operand = Self::lower_conditional_expressions(&pattern[operand_start..])?;
+                lowered.push(SpannedToken::new(op.into(), pattern_span));
+                lowered.push(SpannedToken::new(ShyOperator::OpenParenthesis.into(), pattern_span));
+                lowered.extend(operand);
+                lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), pattern[pattern.len() - 1].span));
+            }
+        }
+        Ok(lowered)
+    }
+
+    /// Lower the case expression that begins at the given position, as in `case when x < 0 then "low" else "high" end`,
+    /// adding its ternary conditionals to the lowered tokens, and return the position of its end.
+    fn lower_case_expression(tokens: &[SpannedToken], start: usize, lowered: &mut Vec<SpannedToken>) -> std::result::Result<usize, ShyError> {
+        // Find the when, then, else and end keywords of this case, skipping those of any case nested inside it.
+        let mut keywords: Vec<(usize, &str)> = Vec::new();
+        let mut nesting = 0;
+        let mut end_position = None;
+        for (position, spanned) in tokens.iter().enumerate().skip(start + 1) {
+            if Self::starts_case(tokens, position) {
+                nesting += 1;
+            }
+            else if Self::is_word(&spanned.token, "end") {
+                if nesting == 0 {
+                    end_position = Some(position);
+                    break;
+                }
+                nesting -= 1;
+            }
+            else if nesting == 0 {
+                if let Some(word) = ["when", "then", "else"].iter().find(|word| Self::is_word(&spanned.token, word)) {
+                    keywords.push((position, word));
+                }
+            }
+        }
+        let end_position = end_position.ok_or_else(|| ShyError::syntax("case is missing its end", tokens[start].span))?;
+        keywords.push((end_position, "end"));
+
+        // The keywords must run: when, then, (when, then)*, else, end.
+        let words: Vec<&str> = keywords.iter().map(|(_, word)| *word).collect();
+        let is_well_formed = words.len() >= 4 && words.ends_with(&["else", "end"])
+            && words[..words.len() - 2].chunks(2).all(|pair| pair == ["when", "then"]);
+        if !is_well_formed {
+            return Err(ShyError::syntax("A case must be written case when test then result ... else default end", tokens[start].span));
+        }
+        lowered.push(SpannedToken::new(ShyOperator::OpenParenthesis.into(), tokens[start].span.to(tokens[end_position].span)));
+        for (index, pair) in keywords.windows(2).enumerate() {
+            let ((keyword_position, word), (next_position, _)) = (pair[0], pair[1]);
+            let keyword_span = tokens[keyword_position].span;
+            if keyword_position + 1 == next_position {
+                return Err(ShyError::syntax(format!("Nothing follows {} in a case", word), keyword_span));
+            }
+            match word {
+                "then" => lowered.push(SpannedToken::new(ShyOperator::Ternary.into(), keyword_span)),
+                _ if index > 0 => lowered.push(SpannedToken::new(ShyOperator::Colon.into(), keyword_span)),
+                _ => ()
+            }
+            let part = Self::lower_conditional_expressions(&tokens[keyword_position + 1..next_position])?;
+            lowered.push(SpannedToken::new(ShyOperator::OpenParenthesis.into(), keyword_span));
+            lowered.extend(part);
+            lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), tokens[next_position - 1].span));
+        }
+        lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), tokens[end_position].span));
+        Ok(end_position)
+    }
+
+    /// Lower calls to the if function into ternary operators, so that only the branch selected is evaluated.
+    /// `if(test, a, b)` becomes `((test) ? (a) : (b))`. Calls with other than three arguments are left alone,
+    /// so that the if function can report the error.
+    /// Each added parenthesis gets the span of the parenthesis or comma it stands beside, and the ternary operators get the spans of the commas.
+    fn lower_if_calls(tokens: &[SpannedToken]) -> Vec<SpannedToken> {
+        let open = ShyToken::Operator(ShyOperator::OpenParenthesis);
+        let close = ShyToken::Operator(ShyOperator::CloseParenthesis);
+        let mut lowered = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            if let (ShyToken::OperatorWithValue(ShyOperator::FunctionCall, ShyValue::FunctionName(name)), Some(next)) = (&tokens[i].token, tokens.get(i + 1)) {
+                if name == "if" && next.token == open {
+                    if let Some((commas, close_position)) = Self::find_arguments(tokens, i + 1) {
+                        if commas.len() == 2 {
+                            let call_span = tokens[i].span.to(tokens[close_position].span);
+                            lowered.push(SpannedToken::new(open.clone(), call_span));
+                            let arguments = vec![
+                                (None, i + 2..commas[0]), 
+                                (Some(ShyOperator::Ternary), commas[0] + 1..commas[1]), 
+                                (Some(ShyOperator::Colon), commas[1] + 1..close_position)
+                            ];
+                            for (separator, argument) in arguments {
+                                let separator_span = tokens[argument.start - 1].span;
+                                if let Some(op) = separator {
+                                    lowered.push(SpannedToken::new(op.into(), separator_span));
+                                }
+                                lowered.push(SpannedToken::new(open.clone(), separator_span));
+                                lowered.extend(Self::lower_if_calls(&tokens[argument.clone()]));
+                                lowered.push(SpannedToken::new(close.clone(), tokens[argument.end].span));
+                            }
+                            lowered.push(SpannedToken::new(close.clone(), call_span));
+                            i = close_position + 1;
+                            continue;
+                        }
+                    }
+                }
+            }
+            lowered.push(tokens[i].clone());
+            i += 1;
+        }
+        lowered
+    }
+
+    /// Fold a number followed by the name of a unit, as in `15 psi`, into a single quantity value,
+    /// and a number followed by a unit of time, as in `5 min`, into a duration.
+    /// (A unit written with no space, as in `15psi` or `5min`, is already recognized by the lexer.)
+    /// The word `to` between an operand and a unit name, as in `p to kPa`, becomes the ConvertTo operator, 
+    /// and the unit name becomes a string, so that it is not loaded as a variable.
+    /// Anywhere else, unit names and `to` are ordinary variables.
+    fn attach_units(tokens: &[SpannedToken]) -> Vec<SpannedToken> {
+        let mut attached: Vec<SpannedToken> = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let next = tokens.get(i + 1).map(|spanned| &spanned.token);
+            let unit_name = match next {
+                Some(ShyToken::Value(ShyValue::Variable(name))) if Unit::is_unit(name) => Some(name),
+                _ => None
+            };
+            let duration_literal = match (&tokens[i].token, next) {
+                (ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(number))), Some(ShyToken::Value(ShyValue::Variable(name)))) 
+                    if temporal::is_duration_unit(name) => Some(format!("{}{}", number, name)),
+                (ShyToken::Value(ShyValue::Scalar(ShyScalar::Rational(number))), Some(ShyToken::Value(ShyValue::Variable(name)))) 
+                    if temporal::is_duration_unit(name) => Some(format!("{}{}", number, name)),
+                _ => None
+            };
+            let pair_span = || tokens[i].span.to(tokens[i + 1].span);
+            match (&tokens[i].token, unit_name) {
+                (ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(number))), Some(name)) => {
+                    let quantity = ShyToken::Value(ShyValue::Scalar(ShyScalar::Quantity(*number as f64, Unit::lookup(name).unwrap())));
+                    attached.push(SpannedToken::new(quantity, pair_span()));
+                    i += 2;
+                },
+                (ShyToken::Value(ShyValue::Scalar(ShyScalar::Rational(number))), Some(name)) => {
+                    let quantity = ShyToken::Value(ShyValue::Scalar(ShyScalar::Quantity(*number, Unit::lookup(name).unwrap())));
+                    attached.push(SpannedToken::new(quantity, pair_span()));
+                    i += 2;
+                },
+                (_, None) if duration_literal.is_some() => {
+                    let literal = duration_literal.unwrap();
+                    let duration = match temporal::parse_duration(&literal) {
+                        Some(duration) => ShyToken::Value(ShyValue::Scalar(ShyScalar::Duration(duration))),
+                        None => ShyToken::Value(ShyValue::error(format!("Duration {} is out of range", literal)))
+                    };
+                    attached.push(SpannedToken::new(duration, pair_span()));
+                    i += 2;
+                },
+                (ShyToken::Value(ShyValue::Variable(word)), Some(name))
+                  if word == "to" && Self::ends_operand(attached.last().map(|spanned| &spanned.token)) => {
+                    attached.push(SpannedToken::new(ShyOperator::ConvertTo.into(), tokens[i].span));
+                    attached.push(SpannedToken::new(ShyToken::Value(name.clone().into()), tokens[i + 1].span));
+                    i += 2;
+                },
+                _ => {
+                    attached.push(tokens[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        attached
+    }
+
+    /// Join the words `not in` into the NotIn operator, and turn the bounds of `x between lo and hi` into the operand `(lo, hi)`, 
+    /// so that the Between operator receives both bounds as one vector. The upper bound ends before the first operator 
+    /// that binds no more tightly than between, as in `x between 1 and 5 && y`. 
+    /// The added parentheses get the spans of the between and of the end of the upper bound, and the comma gets the span of the and.
+    fn lower_word_operators(tokens: &[SpannedToken]) -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let mut lowered: Vec<SpannedToken> = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            match (&tokens[i].token, tokens.get(i + 1).map(|spanned| &spanned.token)) {
+                (ShyToken::Operator(ShyOperator::LogicalNot), Some(ShyToken::Operator(ShyOperator::In))) 
+                  if Self::ends_operand(lowered.last().map(|spanned| &spanned.token)) => {
+                    lowered.push(SpannedToken::new(ShyOperator::NotIn.into(), tokens[i].span.to(tokens[i + 1].span)));
+                    i += 2;
+                },
+                (ShyToken::Operator(ShyOperator::Between), _) => {
+                    let between_span = tokens[i].span;
+                    let malformed = || ShyError::syntax("between must be followed by a lower bound, and, then an upper bound", between_span);
+                    let and_position = Self::find_between_and(tokens, i + 1).ok_or_else(malformed)?;
+                    let end = Self::find_operand_end(tokens, and_position + 1, ShyOperator::Between);
+                    if and_position == i + 1 || end == and_position + 1 {
+                        return Err(malformed());
+                    }
+                    lowered.push(tokens[i].clone());
+                    lowered.push(SpannedToken::new(ShyOperator::OpenParenthesis.into(), between_span));
+                    lowered.extend(Self::lower_word_operators(&tokens[i + 1..and_position])?);
+                    lowered.push(SpannedToken::new(ShyOperator::Comma.into(), tokens[and_position].span));
+                    lowered.extend(Self::lower_word_operators(&tokens[and_position + 1..end])?);
+                    lowered.push(SpannedToken::new(ShyOperator::CloseParenthesis.into(), tokens[end - 1].span));
+                    i = end;
+                },
+                _ => {
+                    lowered.push(tokens[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        Ok(lowered)
+    }
+
+    /// Replace each function declaration, as in `fn c_to_f(c) = c * 9/5 + 32`, with a FunctionDefinition token 
+    /// that holds the compiled body. A declaration must begin a statement, and its body extends to the end of that statement.
+    fn compile_function_declarations(&mut self, tokens: &[SpannedToken]) -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let mut compiled: Vec<SpannedToken> = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let starts_statement = match compiled.last().map(|spanned| &spanned.token) {
+                None | Some(ShyToken::Operator(ShyOperator::Semicolon)) => true,
+                _ => false
+            };
+            match (&tokens[i].token, tokens.get(i + 1).map(|spanned| &spanned.token)) {
+                (ShyToken::Value(ShyValue::Variable(keyword)), Some(ShyToken::OperatorWithValue(ShyOperator::FunctionCall, ShyValue::FunctionName(name)))) 
+                  if keyword == "fn" && starts_statement => {
+                    let declaration_span = tokens[i].span.to(tokens[i + 1].span);
+                    let (parameters, body_start) = Self::parse_parameters(tokens, i + 2)
+                        .ok_or_else(|| ShyError::syntax(format!("Function {} must be declared as fn {}(parameters) = body", name, name), declaration_span))?;
+                    let body_end = Self::find_statement_end(tokens, body_start);
+                    if body_start == body_end {
+                        return Err(ShyError::syntax(format!("Function {} has no body", name), declaration_span));
+                    }
+                    let mut body_yard: ShuntingYard<'static> = format!("fn {}({})", name, parameters.join(", ")).into();
+                    body_yard.infix_order = self.compile_function_declarations(&tokens[body_start..body_end])?;
+                    body_yard.shunt().map_err(|error| ShyError { message: format!("In body of function {}: {}", name, error.message), ..error })?;
+                    self.warnings.extend(body_yard.warnings.drain(..)
+                        .map(|warning| ShyError { message: format!("In body of function {}: {}", name, warning.message), ..warning }));
+                    let (optimized, _) = optimizer::optimize(&body_yard.postfix_order, &body_yard.statement_starts);
+                    let body = Expression::new_from_parser(body_yard.expression_source, &optimized);
+                    let definition = ShyToken::FunctionDefinition(UserFunction::new(name.clone(), parameters, body));
+                    compiled.push(SpannedToken::new(definition, tokens[i].span.to(tokens[body_end - 1].span)));
+                    i = body_end;
+                },
+                _ => {
+                    compiled.push(tokens[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        Ok(compiled)
+    }
+
+    /// Remove the `let` keyword from each declaration of a local variable, as in `let tmp = price * 2`, 
+    /// and record the names declared in locals, along with the statement that declares each. A declaration must begin a statement and assign with `=`. 
+    /// A `let` not followed by a name is left alone as an ordinary variable.
+    fn declare_locals(&mut self, tokens: &[SpannedToken]) -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let mut declared = Vec::with_capacity(tokens.len());
+        let mut statement = 0;
+        for (i, spanned) in tokens.iter().enumerate() {
+            let starts_statement = i == 0 || tokens[i - 1].token == ShyToken::Operator(ShyOperator::Semicolon);
+            if i > 0 && starts_statement {
+                statement += 1;
+            }
+            match (&spanned.token, tokens.get(i + 1).map(|next| &next.token), tokens.get(i + 2).map(|assignment| &assignment.token)) {
+                (ShyToken::Value(ShyValue::Variable(keyword)), Some(ShyToken::Value(ShyValue::Variable(name))), assignment) 
+                  if keyword == "let" && starts_statement => {
+                    if assignment != Some(&ShyToken::Operator(ShyOperator::Assign)) {
+                        return Err(ShyError::syntax(format!("Local variable {} must be declared as let {} = value", name, name), spanned.span.to(tokens[i + 1].span)));
+                    }
+                    if !self.locals.iter().any(|(local, _)| local == name) {
+                        self.locals.push((name.clone(), statement));
+                    }
+                },
+                _ => declared.push(spanned.clone())
+            }
+        }
+        Ok(declared)
+    }
+
+    /// Replace each lambda, as in `x => x > 100` or `(total, x) => total + x`, with a value holding its compiled body.
+    /// The parameters are the name or parenthesized list of names before the arrow. The body extends to the first comma, 
+    /// closing parenthesis, bracket or brace that is not nested inside the body, or to the end of the statement.
+    fn compile_lambdas(&mut self, tokens: &[SpannedToken]) -> std::result::Result<Vec<SpannedToken>, ShyError> {
+        let mut compiled: Vec<SpannedToken> = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            if tokens[i].token != ShyToken::Operator(ShyOperator::Lambda) {
+                compiled.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+            let (parameters, lambda_start) = Self::pop_lambda_parameters(&mut compiled)
+                .ok_or_else(|| ShyError::syntax("Lambda parameters must be names, as in x => x > 100 or (total, x) => total + x", tokens[i].span))?;
+            let body_start = i + 1;
+            let body_end = Self::find_lambda_end(tokens, body_start);
+            if body_start == body_end {
+                return Err(ShyError::syntax("Lambda has no body", tokens[i].span));
+            }
+            let mut body_yard: ShuntingYard<'static> = format!("({}) => ...", parameters.join(", ")).into();
+            body_yard.infix_order = self.compile_lambdas(&tokens[body_start..body_end])?;
+            body_yard.shunt().map_err(|error| ShyError { message: format!("In body of lambda: {}", error.message), ..error })?;
+            self.warnings.extend(body_yard.warnings.drain(..)
+                .map(|warning| ShyError { message: format!("In body of lambda: {}", warning.message), ..warning }));
+            let (optimized, _) = optimizer::optimize(&body_yard.postfix_order, &body_yard.statement_starts);
+            let body = Expression::new_from_parser(body_yard.expression_source, &optimized);
+            let lambda = ShyToken::Value(ShyValue::Lambda(UserFunction::new("lambda".to_string(), parameters, body)));
+            compiled.push(SpannedToken::new(lambda, lambda_start.to(tokens[body_end - 1].span)));
+            i = body_end;
+        }
+        Ok(compiled)
+    }
+
+    /// Remove the parameters of a lambda from the end of tokens and return their names and the span of the first token removed,
+    /// or None if the tokens before the arrow are not a name or a parenthesized list of names.
+    fn pop_lambda_parameters(tokens: &mut Vec<SpannedToken>) -> Option<(Vec<String>, Span)> {
+        let last = tokens.pop()?;
+        match last.token {
+            ShyToken::Value(ShyValue::Variable(name)) => Some((vec![name], last.span)),
+            ShyToken::Operator(ShyOperator::CloseParenthesis) => {
+                let mut parameters = Vec::new();
+                let open_span = loop {
+                    let spanned = tokens.pop()?;
+                    match (spanned.token, parameters.is_empty()) {
+                        (ShyToken::Operator(ShyOperator::OpenParenthesis), _) => break spanned.span,
+                        (ShyToken::Value(ShyValue::Variable(name)), true) => parameters.push(name),
+                        (ShyToken::Operator(ShyOperator::Comma), false) => match tokens.pop().map(|spanned| spanned.token) {
+                            Some(ShyToken::Value(ShyValue::Variable(name))) if !parameters.contains(&name) => parameters.push(name),
+                            _ => return None
+                        },
+                        _ => return None
+                    }
+                };
+                // Parentheses that follow a function name hold arguments, not parameters.
+                if let Some(ShyToken::OperatorWithValue(ShyOperator::FunctionCall, _)) = tokens.last().map(|spanned| &spanned.token) {
+                    return None;
+                }
+                parameters.reverse();
+                Some((parameters, open_span))
+            },
+            _ => None
+        }
+    }
+
+    /// Find the position just past the end of the body of a lambda that begins at the given position.
+    fn find_lambda_end(tokens: &[SpannedToken], start: usize) -> usize {
+        let mut depth = 0;
+        for (position, spanned) in tokens.iter().enumerate().skip(start) {
+            match spanned.token {
+                ShyToken::Operator(ShyOperator::OpenParenthesis) | ShyToken::Operator(ShyOperator::OpenBracket) 
+                | ShyToken::Operator(ShyOperator::OpenBrace) => depth += 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::CloseBrace) if depth == 0 => return position,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::CloseBrace) => depth -= 1,
+                ShyToken::Operator(ShyOperator::Comma) | ShyToken::Operator(ShyOperator::Semicolon) if depth == 0 => return position,
+                _ => ()
+            }
+        }
+        tokens.len()
+    }
+
+    /// Parse the parenthesized parameter names and equals sign of a function declaration, starting at the opening parenthesis.
+    /// Return the names and the position of the first token of the body, or None if the declaration is malformed.
+    fn parse_parameters(tokens: &[SpannedToken], open_position: usize) -> Option<(Vec<String>, usize)> {
+        let token_at = |position: usize| tokens.get(position).map(|spanned| &spanned.token);
+        if token_at(open_position) != Some(&ShyToken::Operator(ShyOperator::OpenParenthesis)) {
+            return None;
+        }
+        let mut parameters = Vec::new();
+        let mut position = open_position + 1;
+        loop {
+            match (token_at(position), parameters.is_empty()) {
+                (Some(ShyToken::Operator(ShyOperator::CloseParenthesis)), _) => break,
+                (Some(ShyToken::Value(ShyValue::Variable(name))), true) => parameters.push(name.clone()),
+                (Some(ShyToken::Operator(ShyOperator::Comma)), false) => {
+                    match token_at(position + 1) {
+                        Some(ShyToken::Value(ShyValue::Variable(name))) if !parameters.contains(name) => parameters.push(name.clone()),
+                        _ => return None
+                    }
+                    position += 1;
+                },
+                _ => return None
+            }
+            position += 1;
+        }
+        match token_at(position + 1) {
+            Some(ShyToken::Operator(ShyOperator::Assign)) => Some((parameters, position + 2)),
+            _ => None
+        }
+    }
+
+    /// Find the position of the semicolon that ends the statement beginning at the given position, 
+    /// or the length of tokens if it is the last statement.
+    fn find_statement_end(tokens: &[SpannedToken], start: usize) -> usize {
+        tokens.iter().skip(start).position(|spanned| spanned.token == ShyToken::Operator(ShyOperator::Semicolon))
+            .map_or(tokens.len(), |offset| start + offset)
+    }
+
+    /// Find the position of the and that separates the bounds of a between, at the same depth of nesting as the start. 
+    /// Since the Lexer reads the word and as a logical and, the bounds may not themselves hold a logical and unless it is in parentheses.
+    /// Return None if the statement or enclosing group ends first.
+    fn find_between_and(tokens: &[SpannedToken], start: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (position, spanned) in tokens.iter().enumerate().skip(start) {
+            match spanned.token {
+                ShyToken::Operator(ShyOperator::OpenParenthesis) | ShyToken::Operator(ShyOperator::OpenBracket) 
+                | ShyToken::Operator(ShyOperator::OpenBrace) => depth += 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::CloseBrace) => {
+                    if depth == 0 {
+                        return None;
+                    }
+                    depth -= 1;
+                },
+                ShyToken::Operator(ShyOperator::Semicolon) => return None,
+                ShyToken::Operator(ShyOperator::And) if depth == 0 => return Some(position),
+                _ => ()
+            }
+        }
+        None
+    }
+
+    /// Find the position just past the end of the operand that begins at the start, which is the first operator at the same 
+    /// depth of nesting that binds no more tightly than the given operator, or the end of the statement or enclosing group.
+    fn find_operand_end(tokens: &[SpannedToken], start: usize, op: ShyOperator) -> usize {
+        let mut depth = 0;
+        for (position, spanned) in tokens.iter().enumerate().skip(start) {
+            match spanned.token {
+                ShyToken::Operator(ShyOperator::OpenParenthesis) | ShyToken::Operator(ShyOperator::OpenBracket) 
+                | ShyToken::Operator(ShyOperator::OpenBrace) => depth += 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::CloseBrace) => {
+                    if depth == 0 {
+                        return position;
+                    }
+                    depth -= 1;
+                },
+                ShyToken::Operator(ShyOperator::Semicolon) => return position,
+                ShyToken::Operator(other) if depth == 0 && other.precedence() <= op.precedence() => return position,
+                _ => ()
+            }
+        }
+        tokens.len()
+    }
+
+    /// Given the position of the opening parenthesis of a function call, find the positions of the commas 
+    /// that separate its arguments and of the matching closing parenthesis. 
+    /// Commas nested inside other parentheses or brackets are ignored.
+    fn find_arguments(tokens: &[SpannedToken], open_position: usize) -> Option<(Vec<usize>, usize)> {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        for (position, spanned) in tokens.iter().enumerate().skip(open_position) {
+            match spanned.token {
+                ShyToken::Operator(ShyOperator::OpenParenthesis) | ShyToken::Operator(ShyOperator::OpenBracket) 
+                | ShyToken::Operator(ShyOperator::OpenBrace) => depth += 1,
+                ShyToken::Operator(ShyOperator::CloseParenthesis) | ShyToken::Operator(ShyOperator::CloseBracket) 
+                | ShyToken::Operator(ShyOperator::CloseBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((commas, position));
+                    }
+                },
+                ShyToken::Operator(ShyOperator::Comma) if depth == 1 => commas.push(position),
+                _ => ()
+            }
+        }
+        None
     }
 
     /// Decide if the top of the postfix_order stack is a Variable or PropertyChain AND it should be considered 
     /// an rvalue whose value should be loaded from context,
     /// not an lvalue into which a result should be stored.
     /// If an rvalue, we should push a Load operator token onto postfix_order. (Pushing Load is not done here.)
-    /// It is an rvalue if the top of the stack holds a ShyToken::Value(ShyValue::Variable) or a ShyToken::Value(ShyValue::PropertyChain)
-    /// and the given stoken is NOT an assignment operator or post-increment or post-decrement operator.
+    /// It is an rvalue if the top of the stack holds a ShyToken::Value(ShyValue::Variable), a ShyToken::Value(ShyValue::PropertyChain)
+    /// or a Member operator, and the given stoken is NOT an assignment operator, post-increment or post-decrement operator, 
+    /// or an opening bracket that indexes into the variable.
     fn is_rvalue_on_stack(&self, stoken: &ShyToken) -> bool {
-        let is_assignment_operator = match stoken {
+        let keeps_reference = match stoken {
+            ShyToken::Operator(ShyOperator::OpenBracket) => true,
+            ShyToken::Operator(ShyOperator::Colon) => self.top_operator() == Some(ShyOperator::OpenBrace),
             ShyToken::Operator(op) => op.is_assignment(), 
             _ => false 
         };
-        match &self.postfix_order.last() {
-            Some(ShyToken::Value(ShyValue::Variable(_))) => !is_assignment_operator,
-            Some(ShyToken::Value(ShyValue::PropertyChain(_))) => !is_assignment_operator,
-            _ => false // Top of stack is NOT a Variable
-        }
+        self.is_last_token_variable() && !keeps_reference
     }
 
-    /// True if the last token in postfix order is a variable or a property chain.
+    /// True if the last token in postfix order is a variable, a property chain, or a Member operator (which yields a property chain).
     fn is_last_token_variable(&self) -> bool {
-        match &self.postfix_order.last() {
+        match self.postfix_order.last().map(|spanned| &spanned.token) {
             Some(ShyToken::Value(ShyValue::Variable(_))) => true,
             Some(ShyToken::Value(ShyValue::PropertyChain(_))) => true,
+            Some(ShyToken::Operator(ShyOperator::Member)) => true,
             _ => false // Top of stack is NOT a Variable
         }
     }
 
+    /// True if the given infix token can end an operand, so that an opening bracket after it indexes into that operand
+    /// and a plus or minus sign after it adds or subtracts.
+    fn ends_operand(token: Option<&ShyToken>) -> bool {
+        match token {
+            Some(ShyToken::Value(_)) => true,
+            Some(ShyToken::OperatorWithValue(ShyOperator::Exponentiation, _)) => true,
+            Some(ShyToken::Operator(ShyOperator::Factorial)) => true,
+            Some(ShyToken::Operator(ShyOperator::PostIncrement)) => true,
+            Some(ShyToken::Operator(ShyOperator::PostDecrement)) => true,
+            Some(ShyToken::Operator(ShyOperator::CloseParenthesis)) => true,
+            Some(ShyToken::Operator(ShyOperator::CloseBracket)) => true,
+            Some(ShyToken::Operator(ShyOperator::CloseBrace)) => true,
+            _ => false
+        }
+    }
+
     /// Apply the rules for precedence and associativity to reduce the operator_stack
     /// by moving some operators to the postfix_order stack.
-    fn reduce(&mut self, op: ShyOperator) {
+    fn reduce(&mut self, op: ShyOperator) -> std::result::Result<(), ShyError> {
         loop {
-            match self.operator_stack.last() {
-                Some(ShyOperator::OpenParenthesis) | Some(ShyOperator::CloseParenthesis) => break,
+            match self.top_operator() {
+                Some(ShyOperator::OpenParenthesis) | Some(ShyOperator::CloseParenthesis) 
+                | Some(ShyOperator::OpenBracket) | Some(ShyOperator::OpenBrace) => break,
 
                 // Higher Precedence Rule: Operator on operator stack has higher precedence than current operator, 
                 //                         so pop operator stack and push that operator onto the postfix-ordered output stack
                 //                         before pushing the current operator onto the operator stack.
                 Some(higher_precedence_op) if higher_precedence_op.precedence() > op.precedence()  => {
-                    self.postfix_order.push(ShyToken::Operator(higher_precedence_op.clone()));
-                    self.operator_stack.pop();
-                    ()
+                    let (higher_precedence_op, span) = self.pop_operator().unwrap();
+                    self.move_to_postfix(higher_precedence_op, span)?
                 },
                 // Lower Precedence Rule:  Operator on operator stack has lower precedence than current operator, 
                 //                         so stop popping off operators.  
@@ -274,40 +1533,61 @@ impl<'a> ShuntingYard<'a> {
                 //                         so pop operator stack and push it onto postfix-ordered output stack.
                 Some(equal_precedence_op) if equal_precedence_op.precedence() == op.precedence() 
                                               && equal_precedence_op.associativity() == Associativity::Left  => {
-                    self.postfix_order.push(ShyToken::Operator(*equal_precedence_op));
-                    self.operator_stack.pop();
-                    ()
+                    let (equal_precedence_op, span) = self.pop_operator().unwrap();
+                    self.move_to_postfix(equal_precedence_op, span)?
                 },
                 // Right Associative Rule: Operators have same precedence, and operator on stack has right associativity,
                 //                         so stop popping off operators.
                 _ => break
             }
         }
+        Ok(())
     }
 
     /// Move all tokens from operator_stack to postfix_order stack, in LIFO order.
-    fn reduce_all(&mut self) {
+    fn reduce_all(&mut self) -> std::result::Result<(), ShyError> {
         loop {
-            match self.operator_stack.last() {
-                Some(op) => {
-                    self.postfix_order.push(ShyToken::Operator(op.clone()));
-                    self.operator_stack.pop();
-                    ()
-                },
+            match self.pop_operator() {
+                Some((op, span)) => self.move_to_postfix(op, span)?,
                 // No more tokens
-                _ => break
+                None => break
             }
         }
+        Ok(())
+    }
+
+    /// Compile the expression into a postfix ordered series of tokens and return the Expression, 
+    /// or the first error in the expression, located in it.
+    pub fn compile(self) -> std::result::Result<Expression<'a>,ShyError> {
+        self.compile_with_recovery().map_err(|mut errors| errors.remove(0))
     }
 
-    /// Compile the expression into a postfix ordered series of tokens and return the Expression.
-    pub fn compile(mut self) -> std::result::Result<Expression<'a>,String> {
+    /// Compile the expression as compile does, but on failure return every error found in the expression, 
+    /// each located in it, in the order they appear. 
+    /// After each error, the search for the next resumes at the following semicolon or blank line.
+    pub fn compile_with_recovery(mut self) -> std::result::Result<Expression<'a>,Vec<ShyError>> {
         match self.parse() {
             Ok(_) => {
-                // TODO: Optimizations like constant folding, And/Or operator short-cutting, branching.
-                Ok(Expression::new_from_parser(self.expression_source, &self.postfix_order))
+                let (optimized, statement_starts) = optimizer::optimize(&self.postfix_order, &self.statement_starts);
+                let mut expression = Expression::new_from_parser(self.expression_source, &optimized);
+                // Each local variable's scope begins where the statement after the one that declares it begins.
+                expression.locals = self.locals.into_iter()
+                    .map(|(name, statement)| LocalVariable { name, scope_start: statement_starts.get(statement).cloned().unwrap_or(optimized.len()) })
+                    .collect();
+                let source = &expression.expression_source;
+                let mut warnings: Vec<ShyError> = self.warnings.into_iter().map(|warning| warning.locate(source)).collect();
+                warnings.sort_by_key(|warning| warning.span.map_or(0, |span| span.start));
+                expression.compile_warnings = warnings;
+                Ok(expression)
             },
-            Err(s) => Err(format!("{}\n{:?}", s, self))
+            Err(errors) => {
+                for error in errors.iter() {
+                    debug!(target: "parser", "{}", error);
+                }
+                debug!(target: "parser", "{:?}", self);
+                let source = &self.expression_source;
+                Err(errors.into_iter().map(|error| error.locate(source)).collect())
+            }
         }
     }
 
@@ -326,8 +1606,9 @@ mod tests {
     use spectral::prelude::*;
 
     use super::shy_object::ShyObject;
-    use execution_context::ExecutionContext;
+    use execution_context::{ExecutionContext, IntegerOverflow};
     use shy_scalar::ShyScalar;
+    use shy_regex::ShyRegex;
 
     use crate::cache::{ApproximateLRUCache, Cache};
 
@@ -381,7 +1662,7 @@ mod tests {
     fn unbalanced_closing_parentheses() {
         let shy: ShuntingYard = "(2 + 3) * (4 - 5))".into();
         match shy.compile() {
-            Err(msg) => assert_that(&msg).contains("Unbalanced"),
+            Err(error) => assert_that(&error.message).contains("Unbalanced"),
             _ => assert!(false, "Did not return error")
         }
     }
@@ -391,7 +1672,7 @@ mod tests {
     fn unbalanced_opening_parentheses() {
         let shy: ShuntingYard = "((2 + 3) * (4 - 5)".into();
         match shy.compile() {
-            Err(msg) => assert_that(&msg).contains("Unbalanced"),
+            Err(error) => assert_that(&error.message).contains("Unbalanced"),
             _ => assert!(false, "Did not return error")
         }
     }
@@ -425,6 +1706,7 @@ mod tests {
             vec![
             ShyToken::Value(ShyValue::Variable("a".to_string())),
             ShyOperator::Load.into(),
+            ShyToken::Branch(ShyOperator::Or, 6),
             ShyToken::Value(ShyValue::Variable("b".to_string())),
             ShyOperator::Load.into(),
             ShyOperator::Or.into(),
@@ -439,9 +1721,9 @@ mod tests {
             vec![
             ShyToken::Value(ShyValue::Variable("name".to_string())),
             ShyOperator::Load.into(),
-            // TODO: Implement ShyScalar::Regex
-            ShyToken::Value(ShyValue::Scalar(ShyScalar::String("^Paul".to_string()))),
+            ShyToken::Value(ShyValue::Scalar(ShyScalar::Regex(ShyRegex::new("^Paul").unwrap()))),
             ShyOperator::Match.into(),
+            ShyToken::Branch(ShyOperator::And, 10),
             ShyToken::Value(ShyValue::Variable("color".to_string())),
             ShyOperator::Load.into(),
             ShyToken::Value(ShyValue::Scalar(ShyScalar::String("blue".to_string()))),
@@ -458,10 +1740,12 @@ mod tests {
             vec![
             ShyToken::Value(ShyValue::Scalar(ShyScalar::Rational(0.5))),
             ShyToken::Value(ShyValue::FunctionName("sin".to_string())),
+            ShyToken::Value(ShyValue::Vector(vec![])),
             ShyToken::Value(ShyValue::Variable("π".to_string())),
             ShyOperator::Load.into(),
             ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(6))),
             ShyOperator::Divide.into(),
+            ShyOperator::Append.into(),
             ShyOperator::FunctionCall.into(),
             ShyOperator::Add.into(),
         ]);
@@ -498,8 +1782,8 @@ mod tests {
         ]);
     }
 
-    /// Verify that the comma operator causes the division in the first argument
-    /// to be performed before the comma and second argument are copied to the output stack.
+    /// Verify that the comma between arguments causes the division in the first argument
+    /// to be appended to the arguments before the second argument is copied to the output stack.
     #[test]
     fn comma() {
         compile_test_case(
@@ -507,12 +1791,14 @@ mod tests {
             vec![
             ShyToken::Value(ShyValue::Variable("good_price".into())),
             ShyToken::Value(ShyValue::FunctionName("min".into())),
+            ShyToken::Value(ShyValue::Vector(vec![])),
             ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(50000))),
-            ShyToken::Value(ShyValue::PropertyChain(vec!["car".into(), "age".into()].into())),
+            ShyToken::Value(ShyValue::PropertyChain(vec!["car".into(), "age".into()])),
             ShyOperator::Load.into(),
             ShyOperator::Divide.into(),
+            ShyOperator::Append.into(),
             ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(30000))),
-            ShyOperator::Comma.into(),
+            ShyOperator::Append.into(),
             ShyOperator::FunctionCall.into(),
             ShyOperator::Assign.into()
         ]);
@@ -520,8 +1806,8 @@ mod tests {
 
     #[test]
     fn context_call() {
-        let ctx = ExecutionContext::default();
-        let actual = ctx.call("exp".to_string(), 0_f64.into());
+        let mut ctx = ExecutionContext::default();
+        let actual = ctx.call("exp".to_string(), &[0_f64.into()]);
         match actual {
             ShyValue::Scalar(ShyScalar::Rational(x)) => assert_that(&x).is_close_to(1_f64, 0.000001),
             _ => assert!(false, format!("Wrong type of value returned from call {:?}", actual))
@@ -567,7 +1853,7 @@ mod tests {
         compile_test_case(
             "wedding_gifts.count ++", 
             vec![
-            ShyToken::Value(ShyValue::PropertyChain(vec!["wedding_gifts".to_string(), "count".to_string()].into())),
+            ShyToken::Value(ShyValue::PropertyChain(vec!["wedding_gifts".into(), "count".into()])),
             ShyOperator::Load.into(),
             ShyOperator::PostIncrement.into()
         ]);
@@ -653,7 +1939,22 @@ mod tests {
         let mut ctx = ExecutionContext::default();
         ctx.store(&"a".into(), "A9123");
         let expected: ShyValue = true.into();
-        execute_test_case("a ~ /9[0-9]+3/", &mut ctx, &expected, true); 
+        execute_test_case("a ~ /9[0-9]+3/", &mut ctx, &expected, true);
+    }
+
+    /// Verify that the capture operator yields numbered groups as a vector and named groups as an object,
+    /// and that a malformed regex is a compile error.
+    #[test]
+    fn exec_regex_capture() {
+        let mut ctx = ExecutionContext::default();
+        ctx.store(&"serial".into(), "PUMP-0042");
+        execute_test_case(r#"m = serial ~> "^(\w+)-(\d+)$"; m[1] + ":" + m[2]"#, &mut ctx, &"PUMP:0042".into(), false);
+        execute_test_case(r#"m = serial ~> /^(?P<kind>\w+)-(?P<number>\d+)$/; m.kind == "PUMP" && parse_number(m.number) == 42 && m[0] == serial"#, &mut ctx, &true.into(), false);
+        execute_test_case(r#"isnull(serial ~> "^VALVE")"#, &mut ctx, &true.into(), false);
+        execute_test_case(r#"serial ~ "\d{4}$" && serial !~ "\s""#, &mut ctx, &true.into(), false);
+
+        let shy: ShuntingYard = r#"serial ~ "(unclosed""#.into();
+        asserting("malformed regex").that(&shy.compile().err().map_or(false, |error| error.message.starts_with("malformed regular expression (unclosed"))).is_equal_to(true);
     }
 
     /// Verify that we can execute multiple assignments separated by semicolons and all are performed.
@@ -668,6 +1969,17 @@ mod tests {
         asserting("third result written to context").that(&ctx.load(&"z").unwrap()).is_equal_to(&80.into());
     }
 
+    #[test]
+    /// Verify that comments are ignored, wherever they appear.
+    fn exec_comments() {
+        let mut ctx = ExecutionContext::default();
+        ctx.store(&"a".into(), 10);
+        let expected: ShyValue = 6.0.into();
+        execute_test_case("# Setup\nx = a / 5; // halve... twice\ny = x * /* triple */ 3 /* multi\nline */; y", &mut ctx, &expected, false);
+        execute_test_case("a > 5 ? /* big */ 1 : 2", &mut ctx, &1.into(), false);
+        execute_test_case("a > 5? # must be big\n", &mut ctx, &true.into(), false);
+    }
+
     /// Verify that a value stored in a ShyObject can be retrieved by its property path and used in an expression.
     #[test]
     fn exec_path_load() {
@@ -825,6 +2137,645 @@ mod tests {
             .is_equal_to(&expected_y);
     }    
 
+    /// Verify that the ternary operator compiles into branches around the true and false operands.
+    #[test]
+    fn compile_ternary() {
+        compile_test_case(
+            "x = a > 5 ? 1 : 2", 
+            vec![
+            ShyToken::Value(ShyValue::Variable("x".to_string())),
+            ShyToken::Value(ShyValue::Variable("a".to_string())),
+            ShyOperator::Load.into(),
+            ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(5))),
+            ShyOperator::GreaterThan.into(),
+            ShyToken::Branch(ShyOperator::Ternary, 7),
+            ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(1))),
+            ShyToken::Branch(ShyOperator::Jump, 9),
+            ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(2))),
+            ShyOperator::Assign.into(),
+        ]);
+    }
+
+    /// Verify that a ternary operator without a colon is reported as an error.
+    #[test]
+    fn ternary_missing_colon() {
+        let shy: ShuntingYard = "x = a > 5 ? 1, 2".into();
+        match shy.compile() {
+            Err(error) => assert_that(&error.message).contains("colon"),
+            _ => assert!(false, "Did not return error")
+        }
+    }
+
+    #[test]
+    /// Verify that the ternary operator selects the correct branch, including when nested.
+    fn exec_ternary() {
+        let mut ctx = ExecutionContext::default();
+        let expected: ShyValue = "medium".into();
+        let expr = r#"depth = 1200; level = depth > 1500 ? "deep" : depth > 1000 ? "medium" : "shallow""#;
+        execute_test_case(expr, &mut ctx, &expected, true); 
+        asserting("nested ternary")
+            .that(&ctx.load(&"level".to_string()).unwrap())
+            .is_equal_to(&expected);
+    }
+
+    #[test]
+    /// Verify that the branch of a ternary operator not taken is not evaluated.
+    fn exec_ternary_skips_untaken_branch() {
+        let mut ctx = ExecutionContext::default();
+        let expected: ShyValue = 10.into();
+        let expr = "x = 1; y = 0; x > 0 ? (y = 10) : (y = 20 / 0)";
+        execute_test_case(expr, &mut ctx, &expected, false); 
+        asserting("ternary skips false branch")
+            .that(&ctx.load(&"y".to_string()).unwrap())
+            .is_equal_to(&expected);
+
+        let expected: ShyValue = 3.into();
+        let expr = "z = 3; x < 0 ? (z = 4) : z";
+        execute_test_case(expr, &mut ctx, &expected, false); 
+        asserting("ternary skips true branch")
+            .that(&ctx.load(&"z".to_string()).unwrap())
+            .is_equal_to(&expected);
+    }
+
+    /// Verify that indexing a variable extends its reference with Member, then loads it.
+    #[test]
+    fn compile_index() {
+        compile_test_case(
+            "x = readings[i + 1]", 
+            vec![
+            ShyToken::Value(ShyValue::Variable("x".to_string())),
+            ShyToken::Value(ShyValue::Variable("readings".to_string())),
+            ShyToken::Value(ShyValue::Variable("i".to_string())),
+            ShyOperator::Load.into(),
+            ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(1))),
+            ShyOperator::Add.into(),
+            ShyOperator::Member.into(),
+            ShyOperator::Load.into(),
+            ShyOperator::Assign.into(),
+        ]);
+    }
+
+    /// Verify that a bracket that does not follow a value is reported as an error.
+    #[test]
+    fn unbalanced_brackets() {
+        for expression in vec!["readings[1", "readings 1]", "[1, 2"].iter() {
+            let shy: ShuntingYard = (*expression).into();
+            match shy.compile() {
+                Err(error) => assert_that(&error.message).contains("bracket"),
+                _ => assert!(false, "Did not return error for {}", expression)
+            }
+        }
+    }
+
+    #[test]
+    /// Verify that vectors may be indexed by integer, including into a computed value.
+    fn exec_index_vector() {
+        let mut ctx = ExecutionContext::default();
+        ctx.store(&"readings".to_string(), ShyValue::Vector(vec![10.into(), 20.into(), 30.into()]));
+        let expected: ShyValue = 50.into();
+        execute_test_case("i = 1; readings[i] + (readings)[i + 1]", &mut ctx, &expected, false); 
+
+        let out_of_range = ShyValue::error("No element at index '3'".to_string());
+        execute_test_case("readings[3]", &mut ctx, &out_of_range, false); 
+    }
+
+    #[test]
+    /// Verify that objects may be indexed by string keys, including computed keys.
+    fn exec_index_object() {
+        let mut ctx = ExecutionContext::default();
+        let expected: ShyValue = 1500.into();
+        execute_test_case(r#"well.depth = 1500; prop_name = "dep" + "th"; well[prop_name]"#, &mut ctx, &expected, false); 
+    }
+
+    #[test]
+    /// Verify that assigning through an index updates a vector element, vivifies missing objects, 
+    /// and vivifies a missing vector for an integer index, but only at index zero.
+    fn exec_assign_through_index() {
+        let mut ctx = ExecutionContext::default();
+        ctx.store(&"readings".to_string(), ShyValue::Vector(vec![10.into(), 20.into()]));
+        let expected: ShyValue = 30.into();
+        execute_test_case(r#"readings[0] = 5; readings[1] += 5; site["well"]["depth"] = 10; readings[0] + readings[1]"#, &mut ctx, &expected, false); 
+        asserting("vector element assigned")
+            .that(&ctx.load(&"readings".to_string()).unwrap())
+            .is_equal_to(&ShyValue::Vector(vec![5.into(), 25.into()]));
+        asserting("missing objects vivified")
+            .that(&ctx.load_str_chain("site.well.depth").unwrap())
+            .is_equal_to(&10.into());
+
+        let expected = ShyValue::Vector(vec![1.into(), 2.into()]);
+        execute_test_case("w[0] = 1; w[1] = 2; site.pumps[0] = 1; site.pumps[1] = 2; w", &mut ctx, &expected, false); 
+        asserting("missing vector vivified").that(&ctx.load_str_chain("site.pumps").unwrap()).is_equal_to(&expected);
+        let beyond_end = ShyValue::error("No element at index '2'".to_string());
+        execute_test_case("gap[2] = 1", &mut ctx, &beyond_end, false); 
+        asserting("no object keyed by the index").that(&ctx.load(&"gap".to_string())).is_equal_to(None);
+    }
+
+    #[test]
+    fn compile_vector_literal() {
+        compile_test_case(
+            "[1, x, [2]]", 
+            vec![
+                ShyToken::Value(ShyValue::Vector(vec![])),
+                ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(1))),
+                ShyOperator::Append.into(),
+                ShyToken::Value(ShyValue::Variable("x".to_string())),
+                ShyOperator::Load.into(),
+                ShyOperator::Append.into(),
+                ShyToken::Value(ShyValue::Vector(vec![])),
+                ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(2))),
+                ShyOperator::Append.into(),
+                ShyOperator::Append.into()
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify that vector literals may be empty, nested, hold objects and be indexed.
+    fn exec_vector_literal() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("[]", &mut ctx, &ShyValue::Vector(vec![]), false); 
+        let nested = ShyValue::Vector(vec![1.into(), ShyValue::Vector(vec![2.into(), 3.into()]), ShyValue::Vector(vec![])]);
+        execute_test_case("[1, [2, 3], []]", &mut ctx, &nested, false); 
+        execute_test_case(r#"well.depth = 1500; wells = [well, well]; wells[1]["depth"]"#, &mut ctx, &1500.into(), false); 
+        execute_test_case("[10, 20, 30][2] + [[1, 2]][0][1]", &mut ctx, &32.into(), false); 
+    }
+
+    #[test]
+    /// Verify that a vector passed as a function argument is not flattened into the argument list.
+    fn exec_vector_argument_not_flattened() {
+        let mut ctx = ExecutionContext::default();
+        let expected = ShyValue::Vector(vec![ShyValue::Vector(vec![1.into(), 2.into()]), 3.into(), 4.into()]);
+        execute_test_case("v = ([1, 2], 3, 4); v", &mut ctx, &expected, false); 
+        execute_test_case("max(1, 5, 3)", &mut ctx, &5.into(), false); 
+    }
+
+    #[test]
+    fn compile_object_literal() {
+        compile_test_case(
+            r#"{ level: x = 2, "msg": s.id }"#, 
+            vec![
+                ShyToken::Value(ShyValue::empty()),
+                ShyToken::Value("level".into()),
+                ShyToken::Value(ShyValue::Variable("x".to_string())),
+                ShyToken::Value(ShyValue::Scalar(ShyScalar::Integer(2))),
+                ShyOperator::Assign.into(),
+                ShyOperator::Property.into(),
+                ShyToken::Value("msg".into()),
+                ShyToken::Value(ShyValue::property_chain("s.id")),
+                ShyOperator::Load.into(),
+                ShyOperator::Property.into()
+            ]
+        );
+    }
+
+    #[test]
+    /// Verify that malformed object literals are rejected.
+    fn malformed_object_literals() {
+        for expression in vec!["{ level: 2", "level: 2 }", "{ 1 + 2 }", "{ a.b: 2 }", "{ x y: 2 }", "{ a: 1, }", "x { a: 1 }"].iter() {
+            let shy: ShuntingYard = (*expression).into();
+            match shy.compile() {
+                Err(_) => (),
+                _ => assert!(false, "Did not return error for {}", expression)
+            }
+        }
+    }
+
+    #[test]
+    /// Verify that object literals may nest and be indexed, and that their values may be any expression.
+    fn exec_object_literal() {
+        let mut ctx = ExecutionContext::default();
+        let alert = r#"s.id = "P-101"; s.pressure = 250; alert = { level: s.pressure > 200 ? 2 : 1, msg: "high pressure", sensor: { id: s.id, readings: [1, 2] }, empty: {} }; alert"#;
+        let expected = ShyValue::Object(ShyObject::empty());
+        expected.set("level", 2.into()).unwrap();
+        expected.set("msg", "high pressure".into()).unwrap();
+        let sensor = ShyValue::Object(ShyObject::empty());
+        sensor.set("id", "P-101".into()).unwrap();
+        sensor.set("readings", ShyValue::Vector(vec![1.into(), 2.into()])).unwrap();
+        expected.set("sensor", sensor).unwrap();
+        expected.set("empty", ShyValue::empty()).unwrap();
+        execute_test_case(alert, &mut ctx, &expected, false); 
+        execute_test_case("alert.sensor.id", &mut ctx, &"P-101".into(), false); 
+        execute_test_case(r#"{ "a": 1, b: 2 }["b"]"#, &mut ctx, &2.into(), false); 
+    }
+
+    #[test]
+    /// Verify that executing the same compiled object literal twice builds two distinct objects.
+    fn exec_object_literal_builds_new_object() {
+        let mut ctx = ExecutionContext::default();
+        let shy: ShuntingYard = "{ level: 1 }".into();
+        let expression = shy.compile().unwrap();
+        let first = expression.exec(&mut ctx).unwrap();
+        let second = expression.exec(&mut ctx).unwrap();
+        first.set("level", 5.into()).unwrap();
+        asserting("second object unchanged").that(&second.get("level").unwrap()).is_equal_to(&1.into());
+    }
+
+    #[test]
+    /// Verify arithmetic and comparisons on date/times and durations.
+    fn exec_temporal() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("@2024-01-05T10:00Z + 90min == @2024-01-05T11:30Z", &mut ctx, &true.into(), false); 
+        execute_test_case("@2024-01-05T10:00Z - @2024-01-05", &mut ctx, &ShyValue::Scalar(ShyScalar::Duration(chrono::Duration::hours(10))), false); 
+        execute_test_case("reading = @2024-01-05T10:00:30Z; reading - 5min < @2024-01-05T09:56Z", &mut ctx, &true.into(), false); 
+        execute_test_case("2 * 1.5h + 30min / 2 - 15s == 3h + 14min + 45s", &mut ctx, &true.into(), false); 
+        execute_test_case("1h / 20min", &mut ctx, &3.0.into(), false); 
+        execute_test_case("-5min < 0s && 1day > 23h", &mut ctx, &true.into(), false); 
+        let incompatible = ShyValue::error("Operands for add operator have incompatible types DateTime and DateTime".to_string());
+        execute_test_case("@2024-01-05 + @2024-01-06", &mut ctx, &incompatible, false); 
+        let incompatible = ShyValue::error("Operands for add operator have incompatible types Duration and Integer".to_string());
+        execute_test_case("5min + 5", &mut ctx, &incompatible, false); 
+    }
+
+    #[test]
+    /// Verify that quantities with units are converted as needed by arithmetic and comparisons, 
+    /// and that mixing quantities that measure different things is an error.
+    fn exec_units() {
+        let mut ctx = ExecutionContext::default();
+        let meters = units::Unit::lookup("m").unwrap();
+        let kpa = units::Unit::lookup("kPa").unwrap();
+        execute_test_case("1 m + 50 cm", &mut ctx, &ShyValue::Scalar(ShyScalar::Quantity(1.5, meters.clone())), false); 
+        execute_test_case("-2.5m * 2", &mut ctx, &ShyValue::Scalar(ShyScalar::Quantity(-5.0, meters.clone())), false); 
+        execute_test_case("6 m / 2 m", &mut ctx, &3.0.into(), false); 
+        execute_test_case("200 kPa to bar == 2 bar && 1 atm > 14 psi && 100°C > 211°F && 100°C < 213°F", &mut ctx, &true.into(), false); 
+        execute_test_case("p = 29 psi; p to kPa > 199 kPa && p to kPa < 200 kPa", &mut ctx, &true.into(), false); 
+        execute_test_case("2 bar to kPa", &mut ctx, &ShyValue::Scalar(ShyScalar::Quantity(200.0, kpa)), false); 
+        execute_test_case("to = 2; to + 1", &mut ctx, &3.into(), false); 
+        let area = ShyValue::multiply(&ShyValue::Scalar(ShyScalar::Quantity(2.0, meters.clone())), &ShyValue::Scalar(ShyScalar::Quantity(3.0, meters.clone())));
+        execute_test_case("(2 m) * 3m", &mut ctx, &area, false); 
+        execute_test_case("2 m ^ 2 > 3.9 m * 1 m", &mut ctx, &true.into(), false); 
+
+        let incompatible = ShyValue::error("Operands for add operator have incompatible units psi and m".to_string());
+        execute_test_case("15 psi + 3 m", &mut ctx, &incompatible, false); 
+        let incompatible = ShyValue::error("Operands for comparison operator have incompatible units psi and m".to_string());
+        execute_test_case("15 psi < 3 m", &mut ctx, &incompatible, false); 
+        let unitless = ShyValue::error("Operands for subtract operator must both have units of measure, not Quantity and Integer".to_string());
+        execute_test_case("15 psi - 3", &mut ctx, &unitless, false); 
+        let bad_conversion = ShyValue::error("Cannot convert psi to m, which measures something different".to_string());
+        execute_test_case("15 psi to m", &mut ctx, &bad_conversion, false); 
+    }
+
+    #[test]
+    /// Verify that a duration counts as seconds when it multiplies or divides a quantity, as in a speed, 
+    /// that a unit of pure time becomes a duration again, and that the time suffixes may follow a space.
+    fn exec_units_with_durations() {
+        let mut ctx = ExecutionContext::default();
+        let speed = ShyValue::divide(&ShyValue::Scalar(ShyScalar::Quantity(5.0, units::Unit::lookup("m").unwrap())), &ShyValue::Scalar(ShyScalar::Duration(chrono::Duration::seconds(1))));
+        asserting("symbol of speed").that(&format!("{:?}", speed)).contains("\"m/s\"");
+        execute_test_case("10 m / 2 s", &mut ctx, &speed, false); 
+        execute_test_case("10 m / 2s == 10 m / 2 s && 5 min == 5min && 1.5 h == 90min", &mut ctx, &true.into(), false); 
+        execute_test_case("(10 m / 2 s) * 1 min", &mut ctx, &ShyValue::Scalar(ShyScalar::Quantity(300.0, units::Unit::lookup("m").unwrap())), false); 
+        execute_test_case("10 m / (5 m / 2 min)", &mut ctx, &ShyValue::Scalar(ShyScalar::Duration(chrono::Duration::minutes(4))), false); 
+        let rate = ShyValue::multiply(&ShyValue::Scalar(ShyScalar::Quantity(2.0, units::Unit::lookup("kg").unwrap())), &ShyValue::divide(&1.into(), &ShyValue::Scalar(ShyScalar::Duration(chrono::Duration::seconds(1)))));
+        execute_test_case("12 kg / 3 s / 2", &mut ctx, &rate, false); 
+        execute_test_case("3 m * 3 m == (3 m)^2", &mut ctx, &true.into(), false); 
+    }
+
+    #[test]
+    /// Verify that decimal arithmetic is exact, keeps the scale of its operands and reports division by zero and overflow.
+    fn exec_decimals() {
+        let mut ctx = ExecutionContext::default();
+        let decimal = |text: &str| ShyValue::from(decimal::Decimal::parse(text).unwrap());
+        execute_test_case("0.10d + 0.20d == 0.30d && 0.1 + 0.2 != 0.3", &mut ctx, &true.into(), false); 
+        execute_test_case("price = 19.99d; qty = 3; price * qty", &mut ctx, &decimal("59.97"), false); 
+        execute_test_case("to_string(10.00d / 4) + \" \" + to_string(-1.5d * 2)", &mut ctx, &"2.50 -3.0".into(), false); 
+        execute_test_case("1d / 3 * 3", &mut ctx, &decimal("0.9999999999999999999999999999"), false); 
+        execute_test_case("12.50d == 12.5d && 3d == 3 && 2.5d > 2 && 2.5d < 2.6 && 1.1d ^ 2 == 1.21d", &mut ctx, &true.into(), false); 
+        execute_test_case("sum([0.10d, 0.20d, 1])", &mut ctx, &decimal("1.30"), false); 
+        execute_test_case("decimal(2.675, 2) == 2.68d && decimal(\"0.075\") * 100 == 7.5d", &mut ctx, &true.into(), false); 
+        execute_test_case("1.5d * 2.0", &mut ctx, &3.0.into(), false); 
+        execute_test_case("1d / 0", &mut ctx, &ShyValue::error("Division by zero in divide operator".to_string()), false); 
+        let incompatible = ShyValue::error("Operands for add operator have incompatible types Decimal and String".to_string());
+        execute_test_case("1d + \"a\"", &mut ctx, &incompatible, false); 
+        let overflow = ShyValue::multiply(&decimal("100000000000000000000"), &decimal("10000000000000000000"));
+        assert!(overflow.is_error());
+        asserting("decimal overflow is not promoted").that(&ShyValue::with_overflow(&decimal("100000000000000000000"), &decimal("10000000000000000000"), 
+            IntegerOverflow::Promote, ShyValue::checked_multiply)).is_equal_to(&overflow);
+    }
+
+    #[test]
+    /// Verify that null-safe property chains yield null for missing objects and properties, 
+    /// and that the null-coalescing operator replaces null, evaluating its right operand only when needed.
+    fn exec_null_safe_navigation() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("well.depth = 100; well.casing = null; well?.depth", &mut ctx, &100.into(), false); 
+        execute_test_case("well?.casing?.depth ?? 0", &mut ctx, &0.into(), false); 
+        execute_test_case("well?.pump?.rate ?? pump?.rate ?? -1", &mut ctx, &(-1).into(), false); 
+        execute_test_case("isnull(nowhere?.casing)", &mut ctx, &true.into(), false); 
+        execute_test_case("n = 0; well.depth ?? (n = 5); n", &mut ctx, &0.into(), false); 
+        execute_test_case("x = null ?? 2 + 3; y = null ?? null; x == 5 && isnull(y)", &mut ctx, &true.into(), false); 
+        execute_test_case("false ?? 1", &mut ctx, &false.into(), false); 
+        execute_test_case("v = [1, 2]; v?.5 ?? v?.1", &mut ctx, &2.into(), false); 
+        execute_test_case("missing ?? 0", &mut ctx, &0.into(), false); 
+        execute_test_case("(missing) ?? 1", &mut ctx, &1.into(), false); 
+        execute_test_case("present = 4; present ?? 0", &mut ctx, &4.into(), false); 
+        execute_test_case("1 + missing ?? 0", &mut ctx, &ShyValue::error("No variable named missing".to_string()), false); 
+
+        let bad_assignment = ShyValue::error("Cannot assign to a null-safe property chain: well?.depth".to_string());
+        execute_test_case("well?.depth = 5", &mut ctx, &bad_assignment, false); 
+    }
+
+    #[test]
+    /// Verify the membership operators in and not in on vectors, strings and objects, and the inclusive range operator between.
+    fn exec_membership_and_range() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("code = 7; code in [3, 7, 9] && !(code in (1, 2)) && code not in [1, 2]", &mut ctx, &true.into(), false); 
+        execute_test_case("message = \"ERR 42: pump\"; \"ERR\" in message && \"WARN\" not in message", &mut ctx, &true.into(), false); 
+        execute_test_case("well.depth = 100; \"depth\" in well && !(\"casing\" in well)", &mut ctx, &true.into(), false); 
+        execute_test_case("x = 15; x between 10 and 20 && x between 15 and 15 && !(x between 16 and 2 * 10)", &mut ctx, &true.into(), false); 
+        execute_test_case("y = x between code + 1 and code * 3; y", &mut ctx, &true.into(), false); 
+        execute_test_case("filter([5, 12, 30], r => r between 10 and 20 || r in [30])", &mut ctx, &ShyValue::Vector(vec![12.into(), 30.into()]), false); 
+        execute_test_case("inch = 2; inch in [2]", &mut ctx, &true.into(), false); 
+        execute_test_case("3 in 5", &mut ctx, &ShyValue::error("Operands for in operator have incompatible types Integer and Integer".to_string()), false); 
+        let shy: ShuntingYard = "x between 10".into();
+        asserting("between without and").that(&shy.compile().err().map(|error| error.message))
+            .is_equal_to(Some("between must be followed by a lower bound, and, then an upper bound".to_string()));
+    }
+
+    #[test]
+    /// Verify that a chain of comparisons tests each pair of neighboring operands, evaluates each operand once, 
+    /// and stops at the first comparison that fails.
+    fn exec_chained_comparisons() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("x = 5; 0 < x <= 10 && 10 >= x > 4 >= 4 && !(0 < x < 5) && !(x < 0 < 10)", &mut ctx, &true.into(), false); 
+        execute_test_case("y = 1; 0 < y++ < 10 && y == 2", &mut ctx, &true.into(), false); 
+        execute_test_case("z = 0; 5 < 3 < (z = 1); z", &mut ctx, &0.into(), false); 
+        execute_test_case("a = [2, 7]; 1 < a[0] < a[1] < 7 || (1 < a[0] < 3 ? \"low\" : \"high\") == \"low\"", &mut ctx, &true.into(), false); 
+        execute_test_case("filter([0, 5, 20], r => 1 <= r <= 10)", &mut ctx, &ShyValue::Vector(vec![5.into()]), false); 
+    }
+
+    #[test]
+    /// Verify that a comparison of the result of another comparison compiles, but with a warning located where it was written.
+    fn comparison_of_comparison_warnings() {
+        let warnings_of = |source: &str| -> Vec<(usize, usize)> {
+            Expression::new(source).compile_warnings.iter().map(|warning| (warning.line, warning.column)).collect()
+        };
+        asserting("chain").that(&warnings_of("x = 5; 0 < x < 10")).is_equal_to(vec![]);
+        asserting("equality of comparisons").that(&warnings_of("x = 5; (x < 1) == (x > 9)")).is_equal_to(vec![]);
+        asserting("left operand").that(&warnings_of("x = 5; (0 < x) < 10")).is_equal_to(vec![(1, 16)]);
+        asserting("right operand").that(&warnings_of("x = 5;\n10 > (0 < x)")).is_equal_to(vec![(2, 4)]);
+        asserting("chained operand").that(&warnings_of("x = 5; 1 < (x == 5) < 3")).is_equal_to(vec![(1, 10)]);
+        asserting("function body").that(&warnings_of("fn f(n) = (n > 1) >= 0 != n < 9 >= 0; f(2)")).is_equal_to(vec![(1, 19)]);
+
+        let expression = Expression::new("fn f(n) = (n > 1) < 2; f(3)");
+        asserting("kind").that(&expression.compile_warnings[0].kind).is_equal_to(ShyErrorKind::Warning);
+        asserting("message").that(&expression.compile_warnings[0].message.starts_with("In body of function f: Comparison of the result")).is_equal_to(true);
+    }
+
+    #[test]
+    /// Verify that and, or and not are synonyms for &&, || and !, and that between still takes its bounds around an and.
+    fn exec_word_logical_operators() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("x = 5; x > 1 and not (x > 10) or false", &mut ctx, &true.into(), false); 
+        execute_test_case("x between 1 and 9 and x not in [4, 6] and not (x in [1])", &mut ctx, &true.into(), false); 
+        execute_test_case("y = 0; false and (y = 1); true or (y = 2); y", &mut ctx, &0.into(), false); 
+    }
+
+    #[test]
+    /// Verify that a match selects the first arm whose pattern the subject satisfies, evaluating the subject once, 
+    /// and that a case selects the result of its first true test.
+    fn exec_match_and_case() {
+        let mut ctx = ExecutionContext::default();
+        let levels = r#"match pressure { < 100 => "low", 100..500 => "normal", _ => "high" }"#;
+        for (pressure, level) in [(50, "low"), (100, "normal"), (499, "normal"), (500, "high")] {
+            execute_test_case(&format!("pressure = {}; {}", pressure, levels), &mut ctx, &level.into(), false); 
+        }
+        execute_test_case("match 500 { 100..500 => 1, 500..900 => 2, _ => 3 }", &mut ctx, &2.into(), false); 
+        execute_test_case("x = 3; 1 + match x * 2 { 1 => 10, in [5, 6] => 20, _ => 30, } * 2", &mut ctx, &41.into(), false); 
+        execute_test_case("y = 1; match y++ { 0 => 0, 1 => y * 10, _ => -1 }", &mut ctx, &20.into(), false); 
+        execute_test_case("z = 0; match 2 { 1 => (z = 1), 2 => 2, _ => (z = 3) }; z", &mut ctx, &0.into(), false); 
+        execute_test_case(r#"low = 5; high = 10; map([1, 7, 12], n => match n { low..high => "in", _ => "out" })"#, &mut ctx, 
+            &ShyValue::Vector(vec!["out".into(), "in".into(), "out".into()]), false); 
+        execute_test_case(r#"fn size(s) = match len(s) { 0 => "empty", > 3 => match s { ~ /^a/ => "long a", _ => "long" }, _ => "short" }; size("abcd")"#, 
+            &mut ctx, &"long a".into(), false); 
+        execute_test_case(r#"t = 7; case when t < 5 then "cold" when t < 10 then "cool" else "warm" end"#, &mut ctx, &"cool".into(), false); 
+        execute_test_case(r#"case when t > 5 then case when t > 8 then 2 else 1 end else 0 end + 10"#, &mut ctx, &11.into(), false); 
+        execute_test_case("u = 0; case when (t > 9) then (u = 1) when t == 7 then 2 else (u = 3) end + u", &mut ctx, &2.into(), false); 
+        execute_test_case("match 1d / 0 { 0 => 1, _ => 2 }", &mut ctx, &ShyValue::error("Division by zero in divide operator".to_string()), false); 
+    }
+
+    #[test]
+    /// Verify that a match or case without a default arm, or a range outside a match, is a compile error.
+    fn match_and_case_compile_errors() {
+        let message_of = |source: &str| -> String {
+            let shy: ShuntingYard = source.into();
+            shy.compile().err().map(|error| error.message).unwrap_or_default()
+        };
+        asserting("no default").that(&message_of("match x { 1 => 2 }")).is_equal_to("A match must end with a default arm, as in _ => value".to_string());
+        asserting("default not last").that(&message_of("match x { _ => 1, 2 => 3 }")).is_equal_to("The default arm, _ => value, must be the last arm of a match".to_string());
+        asserting("no arrow").that(&message_of("match x { 1, _ => 2 }")).is_equal_to("Each arm of a match must be written pattern => result".to_string());
+        asserting("no else").that(&message_of("case when x then 1 end")).is_equal_to("A case must be written case when test then result ... else default end".to_string());
+        asserting("range").that(&message_of("x = 1..5")).is_equal_to("A range like 100..500 may only be the pattern of an arm of a match".to_string());
+        asserting("match variable").that(&message_of("match = 2; match + 1")).is_equal_to(String::new());
+    }
+
+    #[test]
+    /// Verify that the spreadsheet dialect accepts <>, = for equals, AND, OR and NOT, and capitalized standard functions, 
+    /// while = still assigns to the name that begins a statement.
+    fn exec_spreadsheet_dialect() {
+        let mut ctx = ExecutionContext::default();
+        let spreadsheet = |source: &str, ctx: &mut ExecutionContext| Expression::new_in_dialect(source, Dialect::Spreadsheet).exec(ctx).unwrap();
+        asserting("equals").that(&spreadsheet("depth = 1500; ok = depth = 1500 AND depth <> 0; ok", &mut ctx)).is_equal_to(&true.into());
+        asserting("functions").that(&spreadsheet("IF(depth > 1000 OR NOT(depth = 0), ROUND(2.345, 2), 0)", &mut ctx)).is_equal_to(&2.35.into());
+        asserting("AND and OR").that(&spreadsheet("AND(depth > 0, SUM(1, 2) > 2) = OR(false, true)", &mut ctx)).is_equal_to(&true.into());
+        asserting("let and fn").that(&spreadsheet("let half = depth - 750; fn twice(n) = 2 * n; twice(half) = depth", &mut ctx)).is_equal_to(&true.into());
+        asserting("round").that(&spreadsheet("ROUND(1250, -2) = 1300 AND ROUND(-7.5) = -8.0 AND ROUND(2.35d, 1) = 2.4d", &mut ctx)).is_equal_to(&true.into());
+
+        let standard: ShuntingYard = "depth <> 0".into();
+        asserting("<> is not standard").that(&standard.compile().is_err()).is_equal_to(true);
+    }
+
+    #[test]
+    /// Verify that functions declared with fn may be called later in the same expression or by other expressions, 
+    /// and that their parameters do not disturb variables of the same name.
+    fn exec_user_function() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("c = 7; fn c_to_f(c) = c * 9/5 + 32; c_to_f(100) == 212.0 && c == 7", &mut ctx, &true.into(), false); 
+        execute_test_case("c_to_f(-40)", &mut ctx, &(-40.0).into(), false); 
+        execute_test_case("fn hypotenuse(a, b) = sqrt(a*a + b*b); hypotenuse(3, 4)", &mut ctx, &5.0.into(), false); 
+        execute_test_case("fn fact(n) = n <= 1 ? 1 : n * fact(n - 1); fact(5)", &mut ctx, &120.into(), false); 
+        execute_test_case("fn answer() = 42; answer() + 1", &mut ctx, &43.into(), false); 
+        asserting("parameters are not left behind").that(&ctx.load(&"a".to_string())).is_equal_to(&None);
+        asserting("user functions are listed").that(&ctx.function_names().contains(&"fact".to_string())).is_equal_to(true);
+
+        let wrong_count = ShyValue::error("Function hypotenuse expects 2 arguments but was given 3".to_string());
+        execute_test_case("hypotenuse(1, 2, 3)", &mut ctx, &wrong_count, false); 
+        let vector_for_two = ShyValue::error("Function hypotenuse expects 2 arguments but was given 1".to_string());
+        execute_test_case("hypotenuse([3, 4])", &mut ctx, &vector_for_two, false); 
+        let two_for_one = ShyValue::error("Function c_to_f expects 1 arguments but was given 2".to_string());
+        execute_test_case("c_to_f(1, 2)", &mut ctx, &two_for_one, false); 
+        execute_test_case("fn first(v) = v[0]; first([5, 6])", &mut ctx, &5.into(), false); 
+        let lambda_count = ShyValue::error("Function lambda expects 2 arguments but was given 1".to_string());
+        execute_test_case("add = (a, b) => a + b; add([1, 2])", &mut ctx, &lambda_count, false); 
+        let runaway = ShyValue::error("Function forever exceeded the maximum call depth of 100".to_string());
+        execute_test_case("fn forever(n) = forever(n + 1); forever(0)", &mut ctx, &runaway, false); 
+    }
+
+    #[test]
+    /// Verify the higher-order functions that apply a lambda to each item of a vector.
+    fn exec_higher_order_functions() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("readings = [90, 120, 101, 80, 150]; count_if(readings, x => x > 100) > 2", &mut ctx, &true.into(), false); 
+        execute_test_case("map([0, 100], x => x * 9 / 5 + 32)", &mut ctx, &ShyValue::Vector(vec![32.0.into(), 212.0.into()]), false); 
+        execute_test_case("filter(readings, r => r < 100)", &mut ctx, &ShyValue::Vector(vec![90.into(), 80.into()]), false); 
+        execute_test_case("reduce(readings, (total, x) => total + x, 0)", &mut ctx, &541.into(), false); 
+        execute_test_case("reduce(readings, (a, b) => a > b ? a : b)", &mut ctx, &150.into(), false); 
+        execute_test_case("exists(readings, x => x > 140) && !forall(readings, x => x > 85) && all(readings, x => x >= 80)", &mut ctx, &true.into(), false); 
+        execute_test_case("any(false, true)", &mut ctx, &true.into(), false); 
+        execute_test_case("limit = 100; hot = x => x > limit; hot(120) && count_if(readings, hot) == 3", &mut ctx, &true.into(), false); 
+        execute_test_case("fn over(v, limit) = count_if(v, x => x > limit); over(readings, 100) == 3 && limit == 100", &mut ctx, &true.into(), false); 
+        execute_test_case("map([[1, 2], [3, 4]], pair => pair[0] * pair[1])", &mut ctx, &ShyValue::Vector(vec![2.into(), 12.into()]), false); 
+
+        let usage = ShyValue::error("'map' function requires a vector and a lambda, as in map(readings, x => x > 100)".to_string());
+        execute_test_case("map(readings, 2)", &mut ctx, &usage, false); 
+        let failure = ShyValue::error("Operands for add operator have incompatible types String and Integer".to_string());
+        execute_test_case("map([1, \"a\"], x => x + 1)", &mut ctx, &failure, false);
+    }
+
+    #[test]
+    /// Verify the string functions, including those with optional and variable numbers of arguments.
+    fn exec_string_functions() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("name = \"  Pump Station 7 \"; len(trim(name))", &mut ctx, &14.into(), false);
+        execute_test_case("len([1, 2, 3]) == 3 && len(\"°F\") == 2", &mut ctx, &true.into(), false);
+        execute_test_case("upper(\"psi\") + lower(\"KPA\")", &mut ctx, &"PSIkpa".into(), false);
+        execute_test_case("substr(\"Station\", 1, 3) + substr(\"Station\", 4)", &mut ctx, &"tation".into(), false);
+        execute_test_case("contains(name, \"Station\") && !contains([1, 2], 3) && starts_with(trim(name), \"Pump\") && ends_with(name, \" \")", &mut ctx, &true.into(), false);
+        execute_test_case("replace(\"a-b-c\", \"-\", \"+\")", &mut ctx, &"a+b+c".into(), false);
+        execute_test_case("split(\"a,b,,c\", \",\")", &mut ctx, &ShyValue::Vector(vec!["a".into(), "b".into(), "".into(), "c".into()]), false);
+        execute_test_case("split(name)", &mut ctx, &ShyValue::Vector(vec!["Pump".into(), "Station".into(), "7".into()]), false);
+        execute_test_case("join(split(name), \"-\") + join([\"x\", \"y\"])", &mut ctx, &"Pump-Station-7xy".into(), false);
+        execute_test_case("concat(\"P\", 7, \" at \", 15 psi, true)", &mut ctx, &"P7 at 15 psitrue".into(), false);
+        execute_test_case("to_string([1, \"a\"]) + to_string(2.5)", &mut ctx, &"[1,\"a\"]2.5".into(), false);
+        execute_test_case("parse_number(\" 42 \") + parse_number(\"0.5\")", &mut ctx, &42.5.into(), false);
+
+        execute_test_case("parse_number(\"many\")", &mut ctx, &ShyValue::error("Cannot parse 'many' as a number".to_string()), false);
+        execute_test_case("upper(3)", &mut ctx, &ShyValue::error("'upper' function requires a string for argument 1, not Integer".to_string()), false);
+        execute_test_case("replace(\"a\", \"b\")", &mut ctx, &ShyValue::error("'replace' function requires 3 arguments".to_string()), false);
+        execute_test_case("substr(\"abc\", -1)", &mut ctx, &ShyValue::error("'substr' function requires a start and length that are not negative".to_string()), false);
+    }
+
+    #[test]
+    /// Verify that a vector passed as a single argument is not mistaken for several arguments.
+    fn exec_vector_argument() {
+        let mut ctx = ExecutionContext::default();
+        execute_test_case("substr([\"hello\", 1])", &mut ctx, &ShyValue::error("'substr' function requires from 2 to 3 arguments".to_string()), false);
+        execute_test_case("v = [\"a\", \"b\"]; concat(v)", &mut ctx, &"[\"a\",\"b\"]".into(), false);
+        execute_test_case("concat(v, \"c\") + concat(\"a\", \"b\")", &mut ctx, &"[\"a\",\"b\"]cab".into(), false);
+        execute_test_case("len(v) + len([v]) + len()", &mut ctx, &ShyValue::error("'len' function requires 1 argument".to_string()), false);
+        execute_test_case("len(v) + len([v])", &mut ctx, &3.into(), false);
+        execute_test_case("readings = [3, 9, 4]; max(readings) + max(3, 9, 4) + sum(5)", &mut ctx, &23.into(), false);
+        execute_test_case("isnull(v) || any([false, false]) || exp(1, 2) == exp(1, 2)", &mut ctx, &false.into(), false);
+    }
+
+    #[test]
+    /// Verify that a lambda's parameters must be names.
+    fn malformed_lambdas() {
+        for expression in vec!["3 => 4", "f(x) => x", "(a, 2) => a", "x =>", "(a, a) => a"].iter() {
+            let shy: ShuntingYard = (*expression).into();
+            asserting(expression).that(&shy.compile().is_err()).is_equal_to(true);
+        }
+    }
+
+    #[test]
+    /// Verify that malformed function declarations are compile errors.
+    fn malformed_function_declarations() {
+        for expression in vec!["fn f(1) = 2", "fn f(x, x) = x", "fn f(x) 2", "fn f(x) =", "fn f(x,) = x"].iter() {
+            let shy: ShuntingYard = (*expression).into();
+            asserting(expression).that(&shy.compile().is_err()).is_equal_to(true);
+        }
+    }
+
+    #[test]
+    /// Verify that compile errors report the line and column of the token responsible.
+    fn compile_error_locations() {
+        let cases = vec![
+            ("(2 + 3))", ShyErrorKind::Syntax, "Unbalanced closing parenthesis", 1, 8),
+            ("x = (2 + 3", ShyErrorKind::Syntax, "Unbalanced opening parenthesis", 1, 5),
+            ("a = 1;\nb = a ` 2", ShyErrorKind::Lexical, "Unexpected character '`'", 2, 7),
+            ("y = [1, 2", ShyErrorKind::Syntax, "Unbalanced opening bracket", 1, 5),
+            ("z = 5 ? 1", ShyErrorKind::Syntax, "Ternary operator is missing its colon", 1, 7),
+            ("x = 1; x ? y = 1 : 2", ShyErrorKind::Syntax, "The true branch of the ternary operator is malformed: Assign binds more loosely than ? and :, so put the branch in parentheses", 1, 14),
+            ("x = 1; x ? : 2", ShyErrorKind::Syntax, "Ternary operator is missing its true branch", 1, 10),
+            ("x = 1; (x ? 1 :) + 2", ShyErrorKind::Syntax, "Ternary operator is missing its false branch", 1, 15),
+            ("fn f(x) = (x + 1; f(2)", ShyErrorKind::Syntax, "In body of function f: Unbalanced opening parenthesis", 1, 11),
+            ("x = 1;\n  name ~ /(ab/", ShyErrorKind::Syntax, "malformed regular expression", 2, 10)
+        ];
+        for (expression, kind, message, line, column) in cases {
+            let shy: ShuntingYard = expression.into();
+            match shy.compile() {
+                Ok(_) => assert!(false, "{} should not compile", expression),
+                Err(error) => {
+                    asserting(&format!("kind of error in {}", expression)).that(&error.kind).is_equal_to(kind);
+                    asserting(&format!("message for {}", expression)).that(&error.message).starts_with(message);
+                    asserting(&format!("location of error in {}", expression)).that(&(error.line, error.column)).is_equal_to((line, column));
+                }
+            }
+        }
+    }
+
+    #[test]
+    /// Verify that after an error, compilation resumes at the next blank line as well as the next semicolon.
+    fn compile_with_recovery_resumes_after_blank_lines() {
+        let cases = vec![
+            ("a = [1, 2)\n\nb = 3)", vec![("Unbalanced opening bracket", 1, 5), ("Unbalanced closing parenthesis", 3, 6)]),
+            ("a = 2 @ 3\nb = 4\n  \nc = (5", vec![("Invalid date/time '@'", 1, 7), ("Unbalanced opening parenthesis", 4, 5)]),
+            ("a = 1; b = 2", vec![])
+        ];
+        for (expression, expected) in cases {
+            let shy: ShuntingYard = expression.into();
+            let actual: Vec<(String, usize, usize)> = match shy.compile_with_recovery() {
+                Ok(_) => vec![],
+                Err(errors) => errors.into_iter().map(|error| (error.message, error.line, error.column)).collect()
+            };
+            let expected: Vec<(String, usize, usize)> = expected.into_iter().map(|(message, line, column)| (message.to_string(), line, column)).collect();
+            asserting(expression).that(&actual).is_equal_to(expected);
+        }
+    }
+
+    #[test]
+    /// Verify that a compile error is rendered with a caret under the offending token.
+    fn render_compile_error() {
+        let expression = "total = price * (1 + rate))";
+        let shy: ShuntingYard = expression.into();
+        let error = shy.compile().err().unwrap();
+        asserting("rendered").that(&error.render(expression)).is_equal_to(
+            "syntax error: Unbalanced closing parenthesis\n --> line 1, column 27\n  |\n1 | total = price * (1 + rate))\n  |                           ^".to_string()
+        );
+    }
+
+    #[test]
+    /// Verify that the right operand of && and || is not evaluated when the left operand decides the result.
+    fn exec_short_circuit() {
+        let mut ctx = ExecutionContext::default();
+        let expected: ShyValue = false.into();
+        execute_test_case("x = 0; y = 1; x != 0 && 10 / x > 2 && (y = 2) > 0", &mut ctx, &expected, false); 
+        asserting("&& skips right operand")
+            .that(&ctx.load(&"y".to_string()).unwrap())
+            .is_equal_to(&1.into());
+
+        let expected: ShyValue = true.into();
+        execute_test_case("x == 0 || (y = 3) > 0", &mut ctx, &expected, false); 
+        asserting("|| skips right operand")
+            .that(&ctx.load(&"y".to_string()).unwrap())
+            .is_equal_to(&1.into());
+
+        execute_test_case("x != 0 || (y = 4) > 0", &mut ctx, &expected, false); 
+        asserting("|| evaluates right operand when needed")
+            .that(&ctx.load(&"y".to_string()).unwrap())
+            .is_equal_to(&4.into());
+    }
+
+    #[test]
+    /// Verify that the "if" function only evaluates the argument selected by the test, even when nested.
+    fn exec_if_short_circuit() {
+        let mut ctx = ExecutionContext::default();
+        let expected: ShyValue = 5.into();
+        execute_test_case("x = 0; y = 1; if(x == 0, if(y > 0, 5, y = 6), y = 10 / x)", &mut ctx, &expected, false); 
+        asserting("if skips untaken branches")
+            .that(&ctx.load(&"y".to_string()).unwrap())
+            .is_equal_to(&1.into());
+    }
+
     #[test]
     /// Verify that if expressions are cached, they still execute properly and it takes less time to execute them.
     /// On a Windows Tablet, for a typical formula: 
@@ -832,7 +2783,15 @@ mod tests {
     ///    - 4.4 evals per ms without cache 
     ///    - 42.6 evals per ms with a cache
     /// 
-    /// This is running unoptimized.
+    /// On a Linux workstation, for a release build, the 10,000 evals took (median of seven runs): 
+    /// 
+    ///    - 269 ms without cache
+    ///    - 27 ms with a cache
+    ///    - 16 ms executing the compiled expressions directly, looking up variables by name
+    ///    - 5 ms executing them once bound to the context, accessing variables by slot
+    ///    - 11 ms interpreting the bound postfix tokens instead of executing bytecode
+    /// 
+    /// The expressions are optimized, so the arithmetic on constants is folded away, leaving mostly variable loads.
     fn expression_cache_performance() {
         let mut cache : ApproximateLRUCache<String, Expression> = ApproximateLRUCache::new(10000);
         let mut ctx = ExecutionContext::default();
@@ -858,7 +2817,30 @@ mod tests {
             expr.execute_without_cache(&mut ctx);
         }
         let elapsed_millis_without_cache : i64 = timer_without_cache.elapsed().as_millis() as i64;
-        let message = format!("With cache: {}ms    Without cache: {}ms", elapsed_millis_with_cache, elapsed_millis_without_cache);
+
+        // Compare looking up variables by name with accessing them by slot, without the overhead of the cache lookup.
+        let timer_by_name = Instant::now();
+        for expr in expressions.iter_mut() {
+            expr.execute_compiled(&mut ctx);
+        }
+        let elapsed_millis_by_name : i64 = timer_by_name.elapsed().as_millis() as i64;
+
+        for expr in expressions.iter_mut() {
+            expr.bind(&mut ctx);
+        }
+        let timer_interpreted = Instant::now();
+        for expr in expressions.iter_mut() {
+            expr.execute_interpreted(&mut ctx);
+        }
+        let elapsed_millis_interpreted : i64 = timer_interpreted.elapsed().as_millis() as i64;
+
+        let timer_by_slot = Instant::now();
+        for expr in expressions.iter_mut() {
+            expr.execute_compiled(&mut ctx);
+        }
+        let elapsed_millis_by_slot : i64 = timer_by_slot.elapsed().as_millis() as i64;
+        let message = format!("With cache: {}ms    Without cache: {}ms    Variables by name: {}ms    Variables by slot: {}ms    Interpreted: {}ms", 
+            elapsed_millis_with_cache, elapsed_millis_without_cache, elapsed_millis_by_name, elapsed_millis_by_slot, elapsed_millis_interpreted);
         asserting(&message).that(&elapsed_millis_without_cache).is_greater_than(&(5*elapsed_millis_with_cache));
     }
 
@@ -867,18 +2849,19 @@ mod tests {
 // Test helper methods
 
     /// Compile an expression but do not execute it; compare the tokens generated to the expected sequence.
+    /// The tokens are compared before optimization, to test the shunting yard algorithm itself.
     fn compile_test_case(expression: &str, expected_tokens: Vec<ShyToken>) {
-        let shy: ShuntingYard = expression.into();
-        match shy.compile() {
-            Ok(expr) => {
-                if expr.postfix_order.len() != expected_tokens.len() {
-                    println!("Expression:\n{:?}", expr);
+        let mut shy: ShuntingYard = expression.into();
+        match shy.parse() {
+            Ok(_) => {
+                if shy.postfix_order.len() != expected_tokens.len() {
+                    println!("ShuntingYard:\n{:?}", shy);
                 }
-                assert_that!(expr.postfix_order.len()).is_equal_to(expected_tokens.len());
-                assert!(expected_tokens.iter().eq(expr.postfix_order.iter()), )
+                assert_that!(shy.postfix_order.len()).is_equal_to(expected_tokens.len());
+                assert!(expected_tokens.iter().eq(shy.postfix_order.iter().map(|spanned| &spanned.token)), )
             },
-            Err(msg) => {
-                assert!(false, format!("Error compiling: {}", msg))
+            Err(errors) => {
+                assert!(false, format!("Error compiling: {}", errors[0]))
             }
         }
     }
@@ -931,6 +2914,24 @@ mod tests {
             }
         }
 
+        pub fn bind(&mut self, ctx : &mut ExecutionContext<'a>) {
+            self.compiled_expression.bind(ctx);
+        }
+
+        pub fn execute_compiled(&mut self, ctx : &mut ExecutionContext<'a>) {
+            let actual_result = self.compiled_expression.exec(ctx).unwrap();
+            if actual_result != self.expected_result {
+                panic!("Actual result {:?} does not match expected {:?}", actual_result, self.expected_result);
+            }
+        }
+
+        pub fn execute_interpreted(&mut self, ctx : &mut ExecutionContext<'a>) {
+            let actual_result = self.compiled_expression.interpret(ctx).unwrap();
+            if actual_result != self.expected_result {
+                panic!("Actual result {:?} does not match expected {:?}", actual_result, self.expected_result);
+            }
+        }
+
         pub fn execute_with_cache<C>(&mut self, ctx : &mut ExecutionContext<'a>, cache : &mut C) 
         where C : Cache<String, Expression<'a>> {
             match cache.get(&self.text_expression) {
@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+
+use super::shy_token::{ShyToken, ShyValue, SpannedToken};
+use super::shy_operator::ShyOperator;
+use super::shy_scalar::ShyScalar;
+use super::expression::Expression;
+use super::execution_context::{ExecutionContext, IntegerOverflow};
+
+//..................................................................
+
+// Optimizations applied to the postfix order of an expression (or the body of a function or lambda) once the ShuntingYard has compiled it.
+//
+//   - Pure operators whose operands are all literals are folded into their result, so `2 * 3` becomes a single value.
+//     Operators with side effects (assignments, increments, function calls and QuitIfFalse) are never folded,
+//     nor are operations whose result is an error, which are left to report the error when executed.
+//   - Statements that have no side effects are dropped, since their results are never used.
+//     The last statement is always kept, because its result is the result of the expression.
+//
+// Standard constants, like π, are inlined only when an expression is bound to a context (see inline_constants),
+// because until then it is not known whether the context holds a variable of the same name with some other value.
+//
+// Branch targets are adjusted as tokens are removed. Tokens are never folded across a position
+// at which a branch may resume execution, so every branch lands where it did before.
+// A folded value gets a span covering the spans of all the tokens it replaced.
+
+/// Optimize the postfix order of a compiled expression or function body.
+/// `statement_starts` holds the positions in postfix_order where each statement after the first begins.
+/// 
+/// Return the tokens and the positions where each statement now begins. A statement that was dropped 
+/// begins where the statement after it does.
+pub fn optimize(postfix_order: &[SpannedToken], statement_starts: &[usize]) -> (Vec<SpannedToken>, Vec<usize>) {
+    let mut optimizer = Optimizer { tokens: postfix_order.to_vec(), statement_starts: statement_starts.to_vec() };
+    optimizer.fold_constants();
+    optimizer.drop_unused_statements();
+    (optimizer.tokens, optimizer.statement_starts)
+}
+
+/// Replace each load of a standard constant by its value, provided that the context holds the standard value 
+/// and the expression does not assign to it, then fold the operators that the inlined values make foldable, so `2 * π` becomes a single value.
+/// 
+/// Return the tokens and the name and value of each constant inlined. 
+/// The result is only valid while the context still holds those values.
+pub fn inline_constants(postfix_order: &[SpannedToken], context: &ExecutionContext) -> (Vec<SpannedToken>, Vec<(String, ShyValue)>) {
+    let mut optimizer = Optimizer { tokens: postfix_order.to_vec(), statement_starts: vec![] };
+    let inlined = optimizer.inline_constants(context);
+    if !inlined.is_empty() {
+        optimizer.fold_constants();
+    }
+    (optimizer.tokens, inlined)
+}
+
+struct Optimizer {
+    tokens: Vec<SpannedToken>,
+    statement_starts: Vec<usize>
+}
+
+impl Optimizer {
+    /// Replace each load of a standard constant that the context holds with its standard value, and return the name and value of those inlined.
+    fn inline_constants(&mut self, context: &ExecutionContext) -> Vec<(String, ShyValue)> {
+        let constants: Vec<(String, ShyValue)> = ExecutionContext::standard_variables().into_iter()
+            .filter(|(name, value)| context.variables.get(name) == Some(value))
+            .collect();
+        let landings = self.landings();
+        let mut assigned: HashSet<&str> = HashSet::new();
+        for (position, spanned) in self.tokens.iter().enumerate() {
+            match (&spanned.token, self.tokens.get(position + 1).map(|next| &next.token)) {
+                (ShyToken::Value(ShyValue::Variable(_)), Some(ShyToken::Operator(ShyOperator::Load))) => (),
+                (ShyToken::Value(ShyValue::Variable(name)), _) => { assigned.insert(name.as_str()); },
+                (ShyToken::Value(ShyValue::PropertyChain(chain)), _) => { assigned.insert(chain[0].name()); },
+                _ => ()
+            }
+        }
+        let inlined: Vec<(usize, &String, ShyValue)> = self.tokens.iter().enumerate()
+            .filter_map(|(position, spanned)| match &spanned.token {
+                ShyToken::Value(ShyValue::Variable(name)) if !assigned.contains(name.as_str()) && self.is_uninterrupted(&landings, position, position + 1) =>
+                    constants.iter().find(|(constant, _)| constant == name).map(|(constant, value)| (position, constant, value.clone())),
+                _ => None
+            })
+            .collect();
+        let mut used: Vec<(String, ShyValue)> = inlined.iter().map(|(_, name, value)| (name.to_string(), value.clone())).collect();
+        used.sort_by(|a, b| a.0.cmp(&b.0));
+        used.dedup_by(|a, b| a.0 == b.0);
+        // Replace from the end, so that the positions of the earlier constants do not shift.
+        for (position, _, value) in inlined.into_iter().rev() {
+            self.replace(position, position + 1, Some(ShyToken::Value(value)));
+        }
+        used
+    }
+
+    /// Replace each pure operator whose operands are all literals (along with those operands) by its result.
+    fn fold_constants(&mut self) {
+        // An integer overflow is left to be handled at run time, as the context then directs.
+        let mut context = ExecutionContext::empty();
+        context.integer_overflow = IntegerOverflow::Error;
+        let mut landings = self.landings();
+        let mut position = 0;
+        while position < self.tokens.len() {
+            // The folded value may itself be an operand of a later operator, which will be examined as we continue.
+            // Folding shifts the positions that follow, so the landings must then be found again.
+            position = match self.fold_at(position, &landings, &mut context) {
+                Some(folded_position) => {
+                    landings = self.landings();
+                    folded_position + 1
+                },
+                None => position + 1
+            };
+        }
+    }
+
+    /// If the token at the given position is a pure operator whose operands are all literals,
+    /// replace the operands and operator by the result and return the position of the result.
+    fn fold_at(&mut self, position: usize, landings: &HashMap<usize, Vec<usize>>, context: &mut ExecutionContext) -> Option<usize> {
+        let op = match self.tokens[position].token {
+            ShyToken::Operator(op) if is_pure(op) => op,
+            _ => return None
+        };
+        let argument_count = op.arguments();
+        if argument_count == 0 || argument_count > position {
+            return None;
+        }
+        let start = position - argument_count;
+        if !self.is_uninterrupted(landings, start, position) {
+            return None;
+        }
+        let mut stack = Vec::with_capacity(argument_count);
+        for spanned in self.tokens[start..position].iter() {
+            match &spanned.token {
+                ShyToken::Value(value) if is_literal(value) => stack.push(value.clone()),
+                _ => return None
+            }
+        }
+        let result = Expression::operate(&mut stack, op, context);
+        if stack.len() != 1 || !is_literal(&result) {
+            return None;
+        }
+        self.replace(start, position, Some(ShyToken::Value(result)));
+        Some(start)
+    }
+
+    /// Remove every statement that has no side effects, except the last, whose result is the result of the expression.
+    fn drop_unused_statements(&mut self) {
+        let mut boundaries = vec![0];
+        boundaries.extend(self.statement_starts.iter().cloned());
+        boundaries.push(self.tokens.len());
+        let statements: Vec<(usize, usize)> = boundaries.windows(2).map(|pair| (pair[0], pair[1])).collect();
+        let last_used = match statements.iter().rposition(|(start, end)| start < end) {
+            Some(last_used) => last_used,
+            None => return
+        };
+        // Remove from the end, so that the positions of the earlier statements, and the landings within them, do not shift.
+        let landings = self.landings();
+        for (start, end) in statements[..last_used].iter().cloned().rev() {
+            if start < end && self.tokens[start..end].iter().all(|spanned| is_free_of_side_effects(&spanned.token)) 
+                && !self.is_entered_from_outside(&landings, start, end) {
+                self.replace(start, end - 1, None);
+            }
+        }
+    }
+
+    /// Positions at which execution may resume after a branch, each with the positions of the branches that resume there.
+    fn landings(&self) -> HashMap<usize, Vec<usize>> {
+        let mut landings: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (position, spanned) in self.tokens.iter().enumerate() {
+            if let ShyToken::Branch(op, target) = spanned.token {
+                landings.entry(target).or_default().push(position);
+                // A Ternary or Case whose test is false resumes just after the Jump at its target.
+                if op == ShyOperator::Ternary || op == ShyOperator::Case {
+                    landings.entry(target + 1).or_default().push(position);
+                }
+            }
+        }
+        landings
+    }
+
+    /// True if no branch or statement begins after start and at or before end,
+    /// so that the tokens from start to end are always executed from first to last.
+    fn is_uninterrupted(&self, landings: &HashMap<usize, Vec<usize>>, start: usize, end: usize) -> bool {
+        (start + 1..=end).all(|position| !landings.contains_key(&position) && !self.statement_starts.contains(&position))
+    }
+
+    /// True if a branch outside the tokens from start up to (but not including) end may resume execution in their midst.
+    fn is_entered_from_outside(&self, landings: &HashMap<usize, Vec<usize>>, start: usize, end: usize) -> bool {
+        (start + 1..end).filter_map(|position| landings.get(&position))
+            .any(|branches| branches.iter().any(|branch| *branch < start || *branch >= end))
+    }
+
+    /// Replace the tokens from start to end (inclusive) with the replacement, if any, spanning all the tokens it replaces,
+    /// and adjust the branch targets and statement starts that follow.
+    fn replace(&mut self, start: usize, end: usize, replacement: Option<ShyToken>) {
+        let removed = end + 1 - start - if replacement.is_some() { 1 } else { 0 };
+        let replacement_span = self.tokens[start].span.to(self.tokens[end].span);
+        self.tokens.splice(start..=end, replacement.map(|token| SpannedToken::new(token, replacement_span)));
+        for spanned in self.tokens.iter_mut() {
+            if let ShyToken::Branch(_, target) = &mut spanned.token {
+                if *target > end { *target -= removed; }
+            }
+        }
+        for statement_start in self.statement_starts.iter_mut() {
+            if *statement_start > end { *statement_start -= removed; }
+            else if *statement_start > start { *statement_start = start; }
+        }
+    }
+}
+
+/// True for operators whose result depends only on their operands and that have no side effects.
+fn is_pure(op: ShyOperator) -> bool {
+    match op {
+        ShyOperator::Comma | ShyOperator::Append | ShyOperator::Index
+        | ShyOperator::Power | ShyOperator::Exponentiation
+        | ShyOperator::PrefixPlusSign | ShyOperator::PrefixMinusSign
+        | ShyOperator::SquareRoot | ShyOperator::LogicalNot | ShyOperator::Factorial
+        | ShyOperator::Match | ShyOperator::NotMatch | ShyOperator::Capture
+        | ShyOperator::Multiply | ShyOperator::Divide | ShyOperator::Mod
+        | ShyOperator::Add | ShyOperator::Subtract | ShyOperator::ConvertTo
+        | ShyOperator::LessThan | ShyOperator::LessThanOrEqualTo
+        | ShyOperator::GreaterThan | ShyOperator::GreaterThanOrEqualTo
+        | ShyOperator::In | ShyOperator::NotIn | ShyOperator::Between | ShyOperator::Range
+        | ShyOperator::Equals | ShyOperator::NotEquals
+        | ShyOperator::And | ShyOperator::Or | ShyOperator::Coalesce => true,
+        _ => false
+    }
+}
+
+/// True for values that may be folded: scalars other than errors, and vectors of them.
+/// Objects are excluded, because every execution of an object literal must build a new object.
+fn is_literal(value: &ShyValue) -> bool {
+    match value {
+        ShyValue::Scalar(ShyScalar::Error(_)) => false,
+        ShyValue::Scalar(_) => true,
+        ShyValue::Vector(items) => items.iter().all(is_literal),
+        _ => false
+    }
+}
+
+/// True for tokens that neither change the context nor stop the execution of the expression.
+fn is_free_of_side_effects(token: &ShyToken) -> bool {
+    match token {
+        ShyToken::Value(_) | ShyToken::Branch(_, _) => true,
+        ShyToken::Operator(op) => match op {
+            ShyOperator::Store | ShyOperator::Assign
+            | ShyOperator::PlusAssign | ShyOperator::MinusAssign | ShyOperator::MultiplyAssign
+            | ShyOperator::DivideAssign | ShyOperator::ModAssign | ShyOperator::AndAssign | ShyOperator::OrAssign
+            | ShyOperator::PostIncrement | ShyOperator::PostDecrement
+            | ShyOperator::FunctionCall | ShyOperator::QuitIfFalse => false,
+            _ => true
+        },
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    use std::f64;
+    use super::super::ShuntingYard;
+
+    /// Compile the expression without optimizing it.
+    fn unoptimized(expression: &str) -> Expression<'static> {
+        let mut shy: ShuntingYard<'static> = expression.into();
+        shy.parse().unwrap();
+        Expression::new_from_parser(expression, &shy.postfix_order)
+    }
+
+    fn optimized(expression: &str) -> Vec<ShyToken> {
+        tokens(&compiled(expression))
+    }
+
+    fn compiled(expression: &str) -> Vec<SpannedToken> {
+        let shy: ShuntingYard = expression.into();
+        shy.compile().unwrap().postfix_order
+    }
+
+    fn tokens(postfix_order: &[SpannedToken]) -> Vec<ShyToken> {
+        postfix_order.iter().map(|spanned| spanned.token.clone()).collect()
+    }
+
+    fn variable(name: &str) -> ShyToken { ShyToken::Value(ShyValue::Variable(name.to_string())) }
+    fn value<V: Into<ShyValue>>(v: V) -> ShyToken { ShyToken::Value(v.into()) }
+    fn operator(op: ShyOperator) -> ShyToken { ShyToken::Operator(op) }
+
+    /// Inline the standard constants that the context holds into the optimized expression.
+    fn inlined(expression: &str, context: &ExecutionContext) -> Vec<ShyToken> {
+        tokens(&inline_constants(&compiled(expression), context).0)
+    }
+
+    #[test]
+    /// Verify that pure operators on literals are folded, and that standard constants are not inlined when compiling.
+    fn fold_constants() {
+        asserting("fold").that(&optimized("2 * 3 + x")).is_equal_to(vec![
+            value(6), variable("x"), operator(ShyOperator::Load), operator(ShyOperator::Add)
+        ]);
+        asserting("vector literal").that(&optimized("[1, 2 + 3, -4]")).is_equal_to(vec![value(ShyValue::Vector(vec![1.into(), 5.into(), (-4).into()]))]);
+        asserting("error not folded").that(&optimized("\"a\" + 2")).is_equal_to(vec![value("a"), value(2), operator(ShyOperator::Add)]);
+        asserting("constant not inlined").that(&optimized("2 * π")).is_equal_to(vec![
+            value(2), variable("π"), operator(ShyOperator::Load), operator(ShyOperator::Multiply)
+        ]);
+    }
+
+    #[test]
+    /// Verify that standard constants are inlined and folded only if the context holds their standard values and the expression does not assign them.
+    fn inline_standard_constants() {
+        let mut ctx = ExecutionContext::default();
+        asserting("inline π").that(&inlined("r = 5; area = π * r²", &ctx)).is_equal_to(vec![
+            variable("r"), value(5), operator(ShyOperator::Assign),
+            variable("area"), value(f64::consts::PI), variable("r"), operator(ShyOperator::Load), value(2),
+            operator(ShyOperator::Exponentiation), operator(ShyOperator::Multiply), operator(ShyOperator::Assign)
+        ]);
+        asserting("fold").that(&inlined("2 * π * 3 + x", &ctx)).is_equal_to(vec![
+            value(6.0 * f64::consts::PI), variable("x"), operator(ShyOperator::Load), operator(ShyOperator::Add)
+        ]);
+        asserting("assigned constant").that(&inlined("e = 2; e * 3", &ctx)).is_equal_to(vec![
+            variable("e"), value(2), operator(ShyOperator::Assign), variable("e"), operator(ShyOperator::Load), value(3), operator(ShyOperator::Multiply)
+        ]);
+        ctx.store(&"e".into(), 5);
+        asserting("overridden constant").that(&inlined("e * 3", &ctx)).is_equal_to(vec![
+            variable("e"), operator(ShyOperator::Load), value(3), operator(ShyOperator::Multiply)
+        ]);
+    }
+
+    #[test]
+    /// Verify that statements without side effects are dropped, except the last.
+    fn drop_unused_statements() {
+        asserting("dropped").that(&optimized("x + 1; y = 2; 3 * 4; y")).is_equal_to(vec![
+            variable("y"), value(2), operator(ShyOperator::Assign), variable("y"), operator(ShyOperator::Load)
+        ]);
+        asserting("quit-if-false kept").that(&optimized("x > 3?; 5")).is_equal_to(vec![
+            variable("x"), operator(ShyOperator::Load), value(3), operator(ShyOperator::GreaterThan), operator(ShyOperator::QuitIfFalse), value(5)
+        ]);
+        asserting("trailing semicolon").that(&optimized("1 + 1; 2 + 2;")).is_equal_to(vec![value(4)]);
+    }
+
+    #[test]
+    /// Verify that optimized expressions produce the same results and side effects as unoptimized ones.
+    fn optimization_preserves_results() {
+        let expressions = vec![
+            "x && 2 * 3 > 5 ? 1 + 1 : 4 * 2",
+            "!x || 1 + 1 == 2 ? \"yes\" : \"no\"",
+            "1 + 2 > 2 && x ? [1, 2][1] : 10 % 3",
+            "y = 1; 2 * 3; x > 0?; y = y + π; y",
+            "z = 0; x && (z = 1 + 1); 3 * 4; z",
+            "s = \"PUMP-7\" ~> \"^(\\w+)-(\\d+)$\"; s[2]",
+            "fn twice(e) = e * 2; twice(3) + e",
+            "map([1, 2], e => e * 2 + π - π)",
+            "if(x, 1 + 2, 3 + 4) * 2",
+            "q = 2 psi + 1 psi; q to kPa"
+        ];
+        for x in vec![1, 0].into_iter() {
+            for expression in expressions.iter() {
+                let mut expected_ctx = ExecutionContext::default();
+                expected_ctx.store(&"x".into(), x);
+                let mut actual_ctx = expected_ctx.clone();
+                let expected = unoptimized(expression).exec(&mut expected_ctx);
+                let actual = Expression::new(*expression).exec(&mut actual_ctx);
+                asserting(&format!("result of {} with x = {}", expression, x)).that(&actual).is_equal_to(&expected);
+                asserting(&format!("variables after {} with x = {}", expression, x)).that(&actual_ctx.variables).is_equal_to(&expected_ctx.variables);
+                asserting(&format!("applicability of {} with x = {}", expression, x)).that(&actual_ctx.is_applicable).is_equal_to(&expected_ctx.is_applicable);
+            }
+        }
+    }
+}
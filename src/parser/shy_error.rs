@@ -0,0 +1,165 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use serde::{Serialize, Deserialize};
+
+use crate::lexer::span::Span;
+
+//..................................................................
+
+/// The stage at which an error was found.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ShyErrorKind {
+    /// The Lexer could not break the source text into tokens.
+    Lexical,
+    /// The tokens do not form a valid expression.
+    Syntax,
+    /// The expression compiled, but executing it failed or produced an error value.
+    Runtime,
+    /// The expression compiled, but is written in a way that likely does not mean what was intended.
+    Warning
+}
+
+impl Display for ShyErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ShyErrorKind::Lexical => write!(f, "lexical error"),
+            ShyErrorKind::Syntax => write!(f, "syntax error"),
+            ShyErrorKind::Runtime => write!(f, "runtime error"),
+            ShyErrorKind::Warning => write!(f, "warning")
+        }
+    }
+}
+
+/// An error found while compiling or executing an expression, along with where in the source text it was found.
+///
+/// The span is relative to the text that was compiled. Once the error is located in that text,
+/// `line` and `column` (both one-based, with the column counted in characters) give the position where the span starts.
+/// They can then be shifted, as when the text was one rule in a larger `RuleSet`, so that `render` can be given the larger text.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ShyError {
+    pub kind: ShyErrorKind,
+    pub message: String,
+
+    /// Part of the source text responsible for the error, if known.
+    pub span: Option<Span>,
+
+    /// Line where the span starts, or zero if the error has not been located.
+    pub line: usize,
+
+    /// Column where the span starts, or zero if the error has not been located.
+    pub column: usize
+}
+
+impl ShyError {
+    pub fn new<S>(kind: ShyErrorKind, message: S, span: Option<Span>) -> Self where S: Into<String> {
+        ShyError { kind, message: message.into(), span, line: 0, column: 0 }
+    }
+
+    pub fn syntax<S>(message: S, span: Span) -> Self where S: Into<String> {
+        ShyError::new(ShyErrorKind::Syntax, message, Some(span))
+    }
+
+    pub fn runtime<S>(message: S, span: Option<Span>) -> Self where S: Into<String> {
+        ShyError::new(ShyErrorKind::Runtime, message, span)
+    }
+
+    pub fn warning<S>(message: S, span: Span) -> Self where S: Into<String> {
+        ShyError::new(ShyErrorKind::Warning, message, Some(span))
+    }
+
+    /// Set the line and column from the position of the span in the source text.
+    pub fn locate(mut self, source: &str) -> Self {
+        if let Some(span) = self.span {
+            let (line, column) = span.line_and_column(source);
+            self.line = line;
+            self.column = column;
+        }
+        self
+    }
+
+    /// True if the line and column are known.
+    pub fn is_located(&self) -> bool { self.line > 0 }
+
+    /// Move the error down by the given number of lines, for source text that begins on a later line of a larger text.
+    pub fn shift_lines(mut self, lines: usize) -> Self {
+        if self.is_located() {
+            self.line += lines;
+        }
+        self
+    }
+
+    /// Describe the error, followed by the line of the source text where it was found with a caret underneath
+    /// pointing out the span.
+    /// The source is the text that was compiled, or a larger text in which the error's lines have been shifted to match.
+    ///
+    /// ```text
+    /// syntax error: Unbalanced closing parenthesis
+    ///  --> line 1, column 8
+    ///   |
+    /// 1 | (2 + 3))
+    ///   |        ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let heading = format!("{}: {}", self.kind, self.message);
+        let source_line = match source.lines().nth(self.line.wrapping_sub(1)) {
+            Some(source_line) if self.is_located() => source_line,
+            // The end of the source, just past its last line.
+            None if self.is_located() && self.column == 1 => "",
+            _ => return heading
+        };
+        let gutter = " ".repeat(self.line.to_string().len());
+        // Reproduce tabs, so that the caret lines up with the text above it.
+        let indent: String = source_line.chars().take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let available = source_line.chars().count().saturating_sub(self.column - 1).max(1);
+        let width = self.span.map_or(1, |span| span.len()).max(1).min(available);
+        format!("{}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}{}",
+            heading,
+            gutter, self.line, self.column,
+            gutter,
+            self.line, source_line,
+            gutter, indent, "^".repeat(width))
+    }
+}
+
+impl Display for ShyError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.is_located() {
+            write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+        }
+        else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
+
+impl From<ShyError> for String {
+    fn from(error: ShyError) -> Self { error.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    #[test]
+    fn render_points_at_span() {
+        let source = "x = 1;\n\ty = (x + 2))";
+        let error = ShyError::syntax("Unbalanced closing parenthesis", Span::new(19, 20)).locate(source);
+        asserting("line").that(&error.line).is_equal_to(2);
+        asserting("column").that(&error.column).is_equal_to(13);
+        asserting("rendered").that(&error.render(source)).is_equal_to(
+            "syntax error: Unbalanced closing parenthesis\n --> line 2, column 13\n  |\n2 | \ty = (x + 2))\n  | \t           ^".to_string()
+        );
+    }
+
+    #[test]
+    fn render_without_location() {
+        let error = ShyError::runtime("Expression stack is empty", None);
+        asserting("rendered").that(&error.render("")).is_equal_to("runtime error: Expression stack is empty".to_string());
+    }
+}
@@ -28,9 +28,27 @@ use crate::lexer::parser_token::ParserToken;
         OpenParenthesis,
         CloseParenthesis,
         Comma,
+
+        /// Pushes a value onto the end of a copy of a vector. Used to continue a comma-separated list 
+        /// once its first two items have been paired by Comma, and to build vector literals like `[1, 2, 3]`.
+        Append,
         OpenBracket,
         CloseBracket,
+
+        /// Begins an object literal, as in `{ level: 2, msg: "high pressure" }`.
+        OpenBrace,
+        CloseBrace,
+
+        /// Sets a property of the object under construction by an object literal. 
+        /// Takes the object, the property name and the property value, and yields the object.
+        Property,
+
+        /// Extends a reference to a variable or property chain with a property name or vector index taken from inside brackets,
+        /// as in `well["depth"]` or `readings[3]`. The result is a property chain that may be loaded or assigned.
         Member,
+
+        /// Retrieves an element of a computed value (one not held in a variable) by vector index or property name.
+        Index,
         PrefixPlusSign,
         PrefixMinusSign,
         PostIncrement,
@@ -42,20 +60,70 @@ use crate::lexer::parser_token::ParserToken;
         Exponentiation,
         Match,
         NotMatch,
+
+        /// Matches a string against a regex, yielding the captured groups, as in `m = serial ~> "^(\w+)-(\d+)$"`.
+        Capture,
         Multiply,
         Divide,
         Mod,
         Add,
         Subtract,
+
+        /// Expresses a quantity in another unit of the same dimension, as in `p to kPa`. 
+        /// The ShuntingYard turns the unit name that follows `to` into a string operand.
+        ConvertTo,
         LessThan,
         LessThanOrEqualTo,
         GreaterThan,
         GreaterThanOrEqualTo,
+
+        /// Membership, as in `code in [3, 7, 9]`, `"ERR" in message` or `key in obj`.
+        In,
+
+        /// The negation of In, written as `not in`, which the ShuntingYard joins into one operator.
+        NotIn,
+
+        /// Inclusive range test, as in `x between 10 and 20`. 
+        /// The ShuntingYard turns the two bounds into a single vector operand, so that this is a binary operator.
+        Between,
         Equals,
         NotEquals,
         And, 
         Or, 
+
+        /// The null-coalescing operator `a ?? b`, which yields b only if a is null. 
+        /// Like And and Or, it is compiled with a branch that skips its right operand when the left operand decides the result.
+        Coalesce,
+
+        /// The question mark of the ternary conditional `test ? a : b`. 
+        /// The ShuntingYard compiles it into a branch that skips whichever of the two operands is not selected.
         Ternary,
+
+        /// Separates the two branches of a ternary conditional.
+        Colon,
+
+        /// Half-open range test of a pattern in a match expression, as in `100..500 => "normal"`, 
+        /// which includes the lower bound but not the upper bound. 
+        /// Like Between, the ShuntingYard turns the two bounds into a single vector operand, so that this is a binary operator.
+        Range,
+
+        /// The arrow that ends the pattern of an arm of a match expression, or the then of a case expression. 
+        /// Like Ternary, it is compiled into a branch that skips the arm's result if the pattern does not match; 
+        /// unlike Ternary, it also discards the subject of the match when the pattern matches, or when testing it fails.
+        Case,
+
+        /// Copies the subject of a match expression, which stays on the stack while its arms are tested, 
+        /// to be the left operand of the test of the next arm.
+        CaseSubject,
+
+        /// Discards the subject of a match expression whose patterns have not matched, 
+        /// yielding the result of the default arm, as in `_ => "high"`.
+        CaseDefault,
+
+        /// Unconditional jump emitted by the ShuntingYard in a ShyToken::Branch, used to skip the false branch of a ternary conditional.
+        /// (And, Or, Coalesce, Ternary, Case and the ordering comparisons also appear in ShyToken::Branch tokens, to skip operands whose values are not needed.)
+        Jump,
+
         /// The QuitIfFalse operator is also called the applicability operator. 
         /// If while executing a `Rule` we evaluate this operator and it finds a false value and quits the evaluation,
         /// that means that the `Rule` is not applicable. 
@@ -71,6 +139,10 @@ use crate::lexer::parser_token::ParserToken;
         AndAssign,
         OrAssign,
         
+        /// The arrow of a lambda, as in `x => x > 100`. 
+        /// The ShuntingYard compiles the parameters and body of the lambda into a single value, so this never reaches the operator stack.
+        Lambda,
+
         /// Operands are not operators - this is how the Operator parser tells the Operand parser (ShyValue) to kick in.
         Operand,
         Error
@@ -95,7 +167,10 @@ impl ShyOperator {
             ShyOperator::CloseParenthesis => 15,
             ShyOperator::OpenBracket => 15,
             ShyOperator::CloseBracket => 15,
+            ShyOperator::OpenBrace => 15,
+            ShyOperator::CloseBrace => 15,
             ShyOperator::Member => 15,
+            ShyOperator::Index => 15,
             ShyOperator::Power => 14,
             ShyOperator::Exponentiation => 14,
             ShyOperator::PrefixPlusSign => 13,
@@ -107,22 +182,35 @@ impl ShyOperator {
             ShyOperator::Factorial => 12,
             ShyOperator::Match => 11,
             ShyOperator::NotMatch => 11,
+            ShyOperator::Capture => 11,
             ShyOperator::Multiply => 10,
             ShyOperator::Divide => 10,
             ShyOperator::Mod => 10,
             ShyOperator::Add => 9,
             ShyOperator::Subtract => 9,
+            ShyOperator::ConvertTo => 9,
             ShyOperator::LessThan => 8,
             ShyOperator::LessThanOrEqualTo => 8,
             ShyOperator::GreaterThan => 8,
             ShyOperator::GreaterThanOrEqualTo => 8,
+            ShyOperator::In => 8,
+            ShyOperator::NotIn => 8,
+            ShyOperator::Between => 8,
+            ShyOperator::Range => 8,
             ShyOperator::Equals => 7,
             ShyOperator::NotEquals => 7,
             ShyOperator::And => 6, 
             ShyOperator::Or => 5, 
+            ShyOperator::Coalesce => 5, // Shares the precedence of or, so `a ?? b || c` groups from the left.
             ShyOperator::Ternary => 4,
+            ShyOperator::Case => 4,
+            ShyOperator::Colon => 4,
+            ShyOperator::CaseSubject => 13,
+            ShyOperator::CaseDefault => 13,
+            ShyOperator::Jump => 4,
             ShyOperator::QuitIfFalse => 4,
             ShyOperator::Comma => 3,
+            ShyOperator::Append => 3,
             ShyOperator::Assign => 2,
             ShyOperator::PlusAssign => 2,
             ShyOperator::MinusAssign => 2,
@@ -131,6 +219,8 @@ impl ShyOperator {
             ShyOperator::ModAssign => 2,
             ShyOperator::AndAssign => 2,
             ShyOperator::OrAssign => 2,
+            ShyOperator::Property => 1, // Lower than assignment, so a property value may be any expression but a list.
+            ShyOperator::Lambda => 1,
             ShyOperator::Operand => 1,
             ShyOperator::Error => 0
         }
@@ -148,6 +238,11 @@ impl ShyOperator {
             ShyOperator::OrAssign => Associativity::Right,
             ShyOperator::Exponentiation => Associativity::Right,
             ShyOperator::Power => Associativity::Right,
+            ShyOperator::Ternary => Associativity::Right,
+            ShyOperator::Case => Associativity::Right,
+            ShyOperator::CaseDefault => Associativity::Right,
+            ShyOperator::Colon => Associativity::Right,
+            ShyOperator::Property => Associativity::Right,
             _ => Associativity::Left
         }
     }
@@ -169,6 +264,27 @@ impl ShyOperator {
         }
     }
 
+    /// Determines if the given operator orders its operands, as the RelationalOp tokens <, <=, > and >= do.
+    /// Only these comparisons may be chained, as in `0 < x <= 10`.
+    pub fn is_ordering(&self) -> bool  {
+        match self {
+            ShyOperator::LessThan => true,
+            ShyOperator::LessThanOrEqualTo => true,
+            ShyOperator::GreaterThan => true,
+            ShyOperator::GreaterThanOrEqualTo => true,
+            _ => false
+        }
+    }
+
+    /// Determines if the given operator compares its operands and yields a boolean.
+    pub fn is_comparison(&self) -> bool  {
+        match self {
+            ShyOperator::Equals => true,
+            ShyOperator::NotEquals => true,
+            _ => self.is_ordering()
+        }
+    }
+
     /// Number of arguments that each operator takes.
     pub fn arguments(&self) -> usize {
         match self {
@@ -176,14 +292,19 @@ impl ShyOperator {
             ShyOperator::Store => 1,
             ShyOperator::Semicolon => 0,
             ShyOperator::QuitIfFalse => 1,
-            // FunctionCall is variable, but the first argument is the function name while the rest of the arguments are packed into a single Vec by the comma operators.
+            // FunctionCall is variable, but the first argument is the function name while the rest of the arguments are appended to a single Vec, one item per argument.
             ShyOperator::FunctionCall => 2,
             ShyOperator::OpenParenthesis => 0,
             ShyOperator::CloseParenthesis => 0,
             ShyOperator::Comma => 2,
+            ShyOperator::Append => 2,
             ShyOperator::OpenBracket => 0,
             ShyOperator::CloseBracket => 1,
+            ShyOperator::OpenBrace => 0,
+            ShyOperator::CloseBrace => 0,
+            ShyOperator::Property => 3,
             ShyOperator::Member => 2,
+            ShyOperator::Index => 2,
             ShyOperator::Power => 2,
             ShyOperator::Exponentiation => 2,
             ShyOperator::PrefixPlusSign => 1,
@@ -195,20 +316,28 @@ impl ShyOperator {
             ShyOperator::Factorial => 1,
             ShyOperator::Match => 2,
             ShyOperator::NotMatch => 2,
+            ShyOperator::Capture => 2,
             ShyOperator::Multiply => 2,
             ShyOperator::Divide => 2,
             ShyOperator::Mod => 2,
             ShyOperator::Add => 2,
             ShyOperator::Subtract => 2,
+            ShyOperator::ConvertTo => 2,
             ShyOperator::LessThan => 2,
             ShyOperator::LessThanOrEqualTo => 2,
             ShyOperator::GreaterThan => 2,
             ShyOperator::GreaterThanOrEqualTo => 2,
+            ShyOperator::In => 2,
+            ShyOperator::NotIn => 2,
+            ShyOperator::Between => 2,
+            ShyOperator::Range => 2,
             ShyOperator::Equals => 2,
             ShyOperator::NotEquals => 2,
             ShyOperator::And => 2, 
             ShyOperator::Or => 2, 
+            ShyOperator::Coalesce => 2,
             ShyOperator::Ternary => 3,
+            ShyOperator::CaseDefault => 2,
             ShyOperator::Assign => 2,
             ShyOperator::PlusAssign => 2,
             ShyOperator::MinusAssign => 2,
@@ -232,6 +361,8 @@ impl From<ParserToken> for ShyOperator {
             ParserToken::Comma => ShyOperator::Comma,
             ParserToken::OpenBracket => ShyOperator::OpenBracket,
             ParserToken::CloseBracket => ShyOperator::CloseBracket,
+            ParserToken::OpenBrace => ShyOperator::OpenBrace,
+            ParserToken::CloseBrace => ShyOperator::CloseBrace,
             ParserToken::MemberOp => ShyOperator::Member,
 
             ParserToken::SignOp(ref s) if *s == "+" => ShyOperator::PrefixPlusSign,
@@ -248,6 +379,7 @@ impl From<ParserToken> for ShyOperator {
 
             ParserToken::MatchOp(ref s) if *s == "~" => ShyOperator::Match,
             ParserToken::MatchOp(ref s) if *s == "!~" => ShyOperator::NotMatch,
+            ParserToken::MatchOp(ref s) if *s == "~>" => ShyOperator::Capture,
 
             ParserToken::MultiplicativeOp(ref s) if *s == "*" || *s == "·" => ShyOperator::Multiply,
             ParserToken::MultiplicativeOp(ref s) if *s == "/" => ShyOperator::Divide,
@@ -261,16 +393,22 @@ impl From<ParserToken> for ShyOperator {
             ParserToken::RelationalOp(ref s) if *s == ">" => ShyOperator::GreaterThan,
             ParserToken::RelationalOp(ref s) if *s == ">="  || *s == "≥" => ShyOperator::GreaterThanOrEqualTo,
 
+            ParserToken::InOp => ShyOperator::In,
+            ParserToken::BetweenOp => ShyOperator::Between,
+            ParserToken::RangeOp => ShyOperator::Range,
+
             ParserToken::EqualityOp(ref s) if *s == "==" => ShyOperator::Equals, 
             ParserToken::EqualityOp(ref s) if (*s == "!=" || *s == "≠")  => ShyOperator::NotEquals, 
 
-            ParserToken::LogicalOp(ref s) if *s == "&&" => ShyOperator::And, 
-            ParserToken::LogicalOp(ref s) if *s == "||"  => ShyOperator::Or, 
+            ParserToken::LogicalOp(ref s) if *s == "&&" || *s == "and" => ShyOperator::And, 
+            ParserToken::LogicalOp(ref s) if *s == "||" || *s == "or" => ShyOperator::Or, 
+            ParserToken::LogicalOp(ref s) if *s == "??"  => ShyOperator::Coalesce, 
 
-            // Will not support ternary operator. Using ? for another purpose. 
-            // ParserToken::QuestionMark => ShyOperator::Ternary,
-            // ParserToken::Colon => ShyOperator::Ternary,
+            // The Lexer distinguishes the ternary question mark from the postfix quit-if-false question mark.
+            ParserToken::TernaryOp => ShyOperator::Ternary,
+            ParserToken::Colon => ShyOperator::Colon,
             ParserToken::QuestionMark => ShyOperator::QuitIfFalse,
+            ParserToken::Arrow => ShyOperator::Lambda,
 
             ParserToken::AssignmentOp(ref op) if *op == "=" => ShyOperator::Assign, 
             ParserToken::AssignmentOp(ref op) if *op == "+=" => ShyOperator::PlusAssign, 
@@ -283,7 +421,11 @@ impl From<ParserToken> for ShyOperator {
 
             ParserToken::Integer(_) => ShyOperator::Operand,
             ParserToken::Rational(_) => ShyOperator::Operand,
+            ParserToken::Decimal(_) => ShyOperator::Operand,
             ParserToken::Regex(_) => ShyOperator::Operand,
+            ParserToken::DateTime(_) => ShyOperator::Operand,
+            ParserToken::Duration(_) => ShyOperator::Operand,
+            ParserToken::Quantity(_) => ShyOperator::Operand,
             ParserToken::StringLiteral(_) => ShyOperator::Operand,
             ParserToken::Identifier(_) => ShyOperator::Operand,
             ParserToken::PropertyChain(_) => ShyOperator::Operand,
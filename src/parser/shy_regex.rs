@@ -0,0 +1,132 @@
+use std::fmt::{Debug, Display, Formatter, Result};
+use regex::Regex;
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error;
+
+use super::shy_scalar::ShyScalar;
+use super::shy_token::ShyValue;
+use super::shy_object::ShyObject;
+
+//..................................................................
+
+// Regular expressions used by the match operators ~ and !~ and the capture operator ~>.
+//
+//   - A regex literal follows a match operator, between slashes (name ~ /^Bob/) or double quotes (serial ~> "^(\w+)-(\d+)$").
+//     Backslashes are kept as written, so that the regex sees them, except that \" stands for a double quote inside a quoted regex.
+//   - Regex literals are compiled once, when the Expression is compiled, and stored in the postfix tokens.
+//     A malformed regex is a compile error.
+//   - The capture operator yields the groups of the first match:
+//       - Null if there is no match.
+//       - A Vector if the regex has no named groups. Item zero is the whole match, item one the first group, and so on.
+//       - An Object if the regex has named groups. It has a property for each named group,
+//         and the numbered groups are available by index, as in m[1].
+//     A group that took no part in the match is Null.
+
+/// A compiled regular expression.
+#[derive(Clone)]
+pub struct ShyRegex(Regex);
+
+impl ShyRegex {
+    /// Compile the pattern, or return an error message if it is malformed.
+    pub fn new(pattern: &str) -> std::result::Result<Self, String> {
+        Regex::new(pattern).map(ShyRegex).map_err(|_| format!("malformed regular expression {}", pattern))
+    }
+
+    /// The pattern from which the regex was compiled.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// Get the groups of the first match in the text, as a Vector or Object (see above), or Null if there is no match.
+    pub fn captures(&self, text: &str) -> ShyValue {
+        let captures = match self.0.captures(text) {
+            Some(captures) => captures,
+            None => return ShyValue::Scalar(ShyScalar::Null)
+        };
+        let groups: Vec<ShyValue> = captures.iter()
+            .map(|group| match group {
+                Some(matched) => matched.as_str().into(),
+                None => ShyValue::Scalar(ShyScalar::Null)
+            })
+            .collect();
+        if self.0.capture_names().all(|name| name.is_none()) {
+            return ShyValue::Vector(groups);
+        }
+        let object = ShyObject::empty();
+        {
+            let mut assoc = object.as_deref_mut();
+            for (position, group) in groups.into_iter().enumerate() {
+                assoc.set(&position.to_string(), group);
+            }
+            for name in self.0.capture_names().flatten() {
+                let group = match captures.name(name) {
+                    Some(matched) => matched.as_str().into(),
+                    None => ShyValue::Scalar(ShyScalar::Null)
+                };
+                assoc.set(name, group);
+            }
+        }
+        ShyValue::Object(object)
+    }
+}
+
+impl PartialEq for ShyRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Debug for ShyRegex {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "/{}/", self.as_str())
+    }
+}
+
+impl Display for ShyRegex {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A regex is serialized as its pattern, and compiled again when deserialized.
+impl Serialize for ShyRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ShyRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        ShyRegex::new(&pattern).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    #[test]
+    /// Verify that captures yield a vector for numbered groups, an object for named groups and null for no match.
+    fn regex_captures() {
+        let numbered = ShyRegex::new(r"^(\w+)-(\d+)(x)?$").unwrap();
+        asserting("numbered").that(&numbered.captures("PUMP-42"))
+            .is_equal_to(&ShyValue::Vector(vec!["PUMP-42".into(), "PUMP".into(), "42".into(), ShyValue::Scalar(ShyScalar::Null)]));
+        asserting("no match").that(&numbered.captures("PUMP")).is_equal_to(&ShyValue::Scalar(ShyScalar::Null));
+
+        let named = ShyRegex::new(r"^(?P<kind>\w+)-(\d+)$").unwrap();
+        let groups = named.captures("PUMP-42");
+        asserting("named").that(&groups.get_safe("kind")).is_equal_to(&"PUMP".into());
+        asserting("numbered in object").that(&groups.get_safe("2")).is_equal_to(&"42".into());
+
+        assert!(ShyRegex::new("(unclosed").is_err());
+    }
+}
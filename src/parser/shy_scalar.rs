@@ -2,7 +2,12 @@
 use std::convert::TryFrom;
 use std::cmp::Ordering;
 use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Duration, Utc};
 use super::shy_token::{is_truthy, ShyValue};
+use super::temporal;
+use super::units::{self, Unit};
+use super::shy_regex::ShyRegex;
+use super::decimal::Decimal;
 
 //..................................................................
 
@@ -14,7 +19,22 @@ pub enum ShyScalar {
     Boolean(bool),
     Integer(i64),
     Rational(f64),
+
+    /// An exact fixed point number, such as 12.50d, for monetary and regulatory values.
+    Decimal(Decimal),
     String(String),
+
+    /// A point in time, always held in UTC.
+    DateTime(#[serde(with = "temporal::date_time_serde")] DateTime<Utc>),
+
+    /// A signed length of time, such as the difference between two DateTimes.
+    Duration(#[serde(with = "temporal::duration_serde")] Duration),
+
+    /// A number with a unit of measure, such as 15 psi.
+    Quantity(f64, Unit),
+
+    /// A regular expression, compiled when the expression holding its literal is compiled.
+    Regex(ShyRegex),
     Error(String)
 }
 
@@ -24,7 +44,11 @@ impl ShyScalar {
             ShyScalar::Boolean(value) => *value,
             ShyScalar::Integer(value) => *value != 0,
             ShyScalar::Rational(value) => *value != 0.0,
+            ShyScalar::Decimal(value) => !value.is_zero(),
             ShyScalar::String(value) => is_truthy(value),
+            ShyScalar::DateTime(_) => true,
+            ShyScalar::Duration(value) => !value.is_zero(),
+            ShyScalar::Quantity(value, _) => *value != 0.0,
             _ => false
         }
     }
@@ -41,8 +65,11 @@ impl From<bool> for ShyScalar { fn from(b: bool) -> Self { ShyScalar::Boolean(b)
 impl From<i64> for ShyScalar { fn from(i: i64) -> Self { ShyScalar::Integer(i) } }
 impl From<i32> for ShyScalar { fn from(i: i32) -> Self { ShyScalar::Integer(i.into()) } }
 impl From<f64> for ShyScalar { fn from(f: f64) -> Self { ShyScalar::Rational(f) } }
+impl From<Decimal> for ShyScalar { fn from(d: Decimal) -> Self { ShyScalar::Decimal(d) } }
 impl From<String> for ShyScalar { fn from(s: String) -> Self { ShyScalar::String(s) } }
 impl From<&str> for ShyScalar { fn from(s: &str) -> Self { ShyScalar::String(s.to_string()) } }
+impl From<DateTime<Utc>> for ShyScalar { fn from(d: DateTime<Utc>) -> Self { ShyScalar::DateTime(d) } }
+impl From<Duration> for ShyScalar { fn from(d: Duration) -> Self { ShyScalar::Duration(d) } }
 
 impl TryFrom<ShyScalar> for bool { 
     type Error = &'static str;
@@ -68,6 +95,7 @@ impl TryFrom<ShyScalar> for i64 {
                 if r2 == r { Ok(i) }
                 else { Err("Value is a floating point that cannot be converted to an integer without loss of precision")}
             },
+            ShyScalar::Decimal(d) => d.to_i64().ok_or("Value is a decimal that cannot be converted to an integer without loss of precision"),
             ShyScalar::String(_) => Err("Value is a string, not an integer"),
             _ => Err("Value is not an integer")
         }
@@ -82,6 +110,7 @@ impl TryFrom<ShyScalar> for f64 {
             ShyScalar::Boolean(_) => Err("Value is a boolean, not a floating point number"),
             ShyScalar::Integer(i) => Ok(i as f64),
             ShyScalar::Rational(r) => Ok(r),
+            ShyScalar::Decimal(d) => Ok(d.to_f64()),
             ShyScalar::String(_) => Err("Value is a string, not a floating point number"),
             _ => Err("Value is not a floating point number")
         }
@@ -97,7 +126,12 @@ impl TryFrom<ShyScalar> for String {
             ShyScalar::Boolean(false) => Ok("false".to_string()),
             ShyScalar::Integer(i) => Ok(i.to_string()),
             ShyScalar::Rational(r) => Ok(r.to_string()),
+            ShyScalar::Decimal(d) => Ok(d.to_string()),
             ShyScalar::String(s) => Ok(s),
+            ShyScalar::DateTime(d) => Ok(temporal::format_date_time(&d)),
+            ShyScalar::Duration(d) => Ok(temporal::format_duration(&d)),
+            ShyScalar::Quantity(value, unit) => Ok(units::format_quantity(value, &unit)),
+            ShyScalar::Regex(regex) => Ok(regex.as_str().to_string()),
             _ => Err("Value is an error")
         }
     }
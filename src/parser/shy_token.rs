@@ -3,12 +3,13 @@
 #[allow(unused_imports)]
 
 use crate::lexer::parser_token::ParserToken;
+use crate::lexer::span::Span;
 use std::mem::discriminant;
 use std::f64;
 use std::convert::TryFrom;
 use std::collections::HashSet;
 use std::cmp::Ordering;
-use regex::Regex;
+use chrono::{DateTime, Duration, Utc};
 
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use serde_json::{Value};
@@ -17,8 +18,14 @@ use super::factorial::factorial;
 use super::factorial::factorial_approx;
 use super::shy_operator::ShyOperator;
 use super::shy_scalar::ShyScalar;
-use super::execution_context::ExecutionContext;
+use super::execution_context::{ExecutionContext, IntegerOverflow};
 use super::shy_object::ShyObject;
+use super::temporal;
+use super::units::{self, Unit};
+use super::decimal::Decimal;
+use super::shy_regex::ShyRegex;
+use super::user_function::UserFunction;
+use super::chain_link::ChainLink;
 
 
 /*
@@ -80,23 +87,42 @@ pub enum ShyValue {
     /// A scalar value
     Scalar(ShyScalar),
 
-    /// A vector value
-    Vector(Vec<ShyScalar>),
+    /// A vector value, which may hold scalars, objects or other vectors
+    Vector(Vec<ShyValue>),
 
     /// Name of a variable in the context to be read from or written to.
     Variable(String),
 
     /// A variable name followed by a series of nested property references
-    PropertyChain(Vec<String>),
+    PropertyChain(Vec<ChainLink>),
 
     /// Name of a function in the context to be called.
     FunctionName(String),
 
     /// An object which has properties that you can get and set. 
-    Object(ShyObject)
+    Object(ShyObject),
+
+    /// An anonymous function, as in `x => x > 100`, which may be passed to functions like map and filter, or stored in a variable and called.
+    Lambda(UserFunction)
 }
 const TRUE_STRING: &str = "True";
 const FALSE_STRING: &str = "False";
+
+
+/// The error from integer arithmetic whose result does not fit in an i64.
+/// The checked arithmetic methods return it apart from any other error, so that with_overflow may promote the operands instead.
+#[derive(Clone, PartialEq, Debug)]
+pub struct IntegerOverflowError {
+    pub message: String
+}
+
+impl IntegerOverflowError {
+    /// The error value to return when the overflow is not promoted.
+    pub fn into_error(self) -> ShyValue {
+        ShyValue::error(self.message)
+    }
+}
+
 impl PartialOrd for ShyValue {
 
     fn partial_cmp(&self, right_operand: &Self) -> Option<Ordering> {
@@ -117,12 +143,30 @@ impl PartialOrd for ShyValue {
             // Integer comparison
             (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => Some(left.cmp(right)),
 
+            // Decimals compare exactly with decimals and integers, and as floating point with rationals
+            (ShyValue::Scalar(ShyScalar::Decimal(left)), ShyValue::Scalar(ShyScalar::Decimal(right))) => Some(left.cmp(right)),
+            (ShyValue::Scalar(ShyScalar::Decimal(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => Some(left.cmp(&Decimal::from_i64(*right))),
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Decimal(right))) => Some(Decimal::from_i64(*left).cmp(right)),
+            (ShyValue::Scalar(ShyScalar::Decimal(left)), ShyValue::Scalar(ShyScalar::Rational(right))) 
+                => left.to_f64().partial_cmp(right),
+            (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Decimal(right))) 
+                => left.partial_cmp(&right.to_f64()),
+
             // String comparison
             (ShyValue::Scalar(ShyScalar::String(left)), ShyValue::Scalar(ShyScalar::String(right))) => Some(left.cmp(right)),
 
             // Bool comparison
             (ShyValue::Scalar(ShyScalar::Boolean(left)), ShyValue::Scalar(ShyScalar::Boolean(right))) => Some(left.cmp(right)),
 
+            // Date/time and duration comparison
+            (ShyValue::Scalar(ShyScalar::DateTime(left)), ShyValue::Scalar(ShyScalar::DateTime(right))) => Some(left.cmp(right)),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Duration(right))) => Some(left.cmp(right)),
+
+            // Quantities are compared in SI units, if they have the same dimension
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+              if left_unit.is_compatible(right_unit)
+                => left_unit.to_si(*left).partial_cmp(&right_unit.to_si(*right)),
+
             // Bool to String comparison - assume false is "False" and true is "True"
             (ShyValue::Scalar(ShyScalar::Boolean(left)), ShyValue::Scalar(ShyScalar::String(right))) 
                 => Some(if *left { t.cmp(right) } else { f.cmp(right) } ),
@@ -155,12 +199,31 @@ impl<'a> From<ParserToken> for ShyValue {
             ParserToken::Integer(s) => ShyValue::Scalar(ShyScalar::Integer(s.parse::<i64>().unwrap())),
             ParserToken::Rational(s) => ShyValue::Scalar(ShyScalar::Rational(s.parse::<f64>().unwrap())),
             ParserToken::StringLiteral(s) => ShyValue::Scalar(ShyScalar::String(s)),
+            ParserToken::DateTime(ref s) => match temporal::parse_date_time(s) {
+                Some(date_time) => date_time.into(),
+                None => ShyValue::error(format!("Invalid date/time literal '@{}'", s))
+            },
+            ParserToken::Duration(ref s) => match temporal::parse_duration(s) {
+                Some(duration) => duration.into(),
+                None => ShyValue::error(format!("Invalid duration literal '{}'", s))
+            },
+            ParserToken::Decimal(ref s) => match Decimal::parse_literal(s) {
+                Some(decimal) => decimal.into(),
+                None => ShyValue::error(format!("Invalid decimal literal '{}'", s))
+            },
+            ParserToken::Quantity(ref s) => match units::parse_quantity(s) {
+                Some((value, unit)) => ShyValue::Scalar(ShyScalar::Quantity(value, unit)),
+                None => ShyValue::error(format!("Invalid quantity literal '{}'", s))
+            },
 
             // Two tokens will be made from a PowerOp, an operator and this scalar value
             ParserToken::PowerOp(s) => ShyValue::Scalar(ShyScalar::Integer(s.parse::<i64>().unwrap())),
 
-            // TODO: Create ShyScalar::Regex to use in place of String.
-            ParserToken::Regex(s) => ShyValue::Scalar(ShyScalar::String(s)),
+            // Regex literals are compiled once, here, rather than each time the expression is executed.
+            ParserToken::Regex(s) => match ShyRegex::new(&s) {
+                Ok(regex) => ShyValue::Scalar(ShyScalar::Regex(regex)),
+                Err(message) => ShyValue::error(message)
+            },
             _ => ShyValue::error(format!("Error parsing token '{}'", parser_token))
         }
     }
@@ -193,6 +256,7 @@ impl ShyValue {
     pub fn shallow_clone(&self) -> Self {
         match self {
             ShyValue::Object(child_obj) => ShyValue::Object(child_obj.shallow_clone()),
+            ShyValue::Vector(v) => ShyValue::Vector(v.iter().map(|element| element.shallow_clone()).collect()),
             _ => self.clone()
         }        
     }
@@ -200,6 +264,7 @@ impl ShyValue {
     pub fn type_name(&self) -> &'static str {
         match self {
             ShyValue::FunctionName(_) => "FunctionName",
+            ShyValue::Lambda(_) => "Lambda",
             ShyValue::Variable(_) => "Variable",
             ShyValue::PropertyChain(_) => "PropertyChain",
             ShyValue::Vector(_) => "Vector",
@@ -208,7 +273,12 @@ impl ShyValue {
             ShyValue::Scalar(ShyScalar::Boolean(_)) => "Boolean",
             ShyValue::Scalar(ShyScalar::Integer(_)) => "Integer",
             ShyValue::Scalar(ShyScalar::Rational(_)) => "Rational",
+            ShyValue::Scalar(ShyScalar::Decimal(_)) => "Decimal",
             ShyValue::Scalar(ShyScalar::String(_)) => "String",
+            ShyValue::Scalar(ShyScalar::DateTime(_)) => "DateTime",
+            ShyValue::Scalar(ShyScalar::Duration(_)) => "Duration",
+            ShyValue::Scalar(ShyScalar::Quantity(_, _)) => "Quantity",
+            ShyValue::Scalar(ShyScalar::Regex(_)) => "Regex",
             ShyValue::Scalar(ShyScalar::Error(_)) => "Error",
         }
     }
@@ -217,9 +287,9 @@ impl ShyValue {
         ShyValue::Object(ShyObject::empty())
     }
 
-    /// Create a PropertyChain from a delimited string, instead of a Vec<String>. 
+    /// Create a PropertyChain from a delimited string, instead of a Vec<ChainLink>. 
     pub fn property_chain(s : &str) -> Self {
-        ShyValue::PropertyChain(s.split(".").map(|s| s.to_string()).collect())
+        ShyValue::PropertyChain(ChainLink::parse_chain(s))
     }
 
     //..................................................................
@@ -238,6 +308,13 @@ impl ShyValue {
         }
     }
 
+    pub fn is_scalar(&self) -> bool {
+        match self {
+            ShyValue::Scalar(_) => true,
+            _ => false
+        }
+    }
+
     pub fn is_null(&self) -> bool {
         match self {
             ShyValue::Scalar(ShyScalar::Null) => true,
@@ -255,6 +332,159 @@ impl ShyValue {
         ShyValue::error(format!("Operand for {} operator has {} value {:?} that is out of range", operator_name, left.type_name(), left))
     }
 
+    /// Asserts that two quantities cannot be combined by the given operator, because they measure different things (like psi and m).
+    fn incompatible_units(left_unit: &Unit, right_unit: &Unit, operator_name: &str) -> Self {
+        ShyValue::error(format!("Operands for {} operator have incompatible units {} and {}", operator_name, left_unit, right_unit))
+    }
+
+    /// Asserts that a quantity cannot be combined by the given operator with a value that has no unit of measure.
+    fn missing_unit(left: &Self, right: &Self, operator_name: &str) -> Self {
+        ShyValue::error(format!("Operands for {} operator must both have units of measure, not {} and {}", operator_name, left.type_name(), right.type_name()))
+    }
+
+    /// Reports why two values could not be compared, with a more specific message if either is a quantity.
+    fn incomparable(left: &Self, right: &Self) -> Self {
+        match (left, right) {
+            (ShyValue::Scalar(ShyScalar::Quantity(_, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(_, right_unit))) 
+                => ShyValue::incompatible_units(left_unit, right_unit, "comparison"),
+            (ShyValue::Scalar(ShyScalar::Quantity(_, _)), _) | (_, ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => ShyValue::missing_unit(left, right, "comparison"),
+            _ => ShyValue::incomparable_types()
+        }
+    }
+
+    /// Add, subtract or take the modulus of two quantities, expressing the result in the unit of the left operand.
+    fn combine_quantities<F>(left: f64, left_unit: &Unit, right: f64, right_unit: &Unit, operator_name: &str, combiner: F) -> Self 
+    where F: Fn(f64, f64) -> f64 {
+        match right_unit.convert(right, left_unit) {
+            Ok(converted_right) => ShyValue::Scalar(ShyScalar::Quantity(combiner(left, converted_right), left_unit.clone())),
+            Err(_) => ShyValue::incompatible_units(left_unit, right_unit, operator_name)
+        }
+    }
+
+    /// Multiply (exponent 1) or divide (exponent -1) two quantities. If the units cancel, the result is a plain number,
+    /// and if they leave only a time, the result is a duration.
+    fn scale_quantities(left: f64, left_unit: &Unit, right: f64, right_unit: &Unit, exponent: i8) -> Self {
+        let value = if exponent > 0 { left * right } else { left / right };
+        match left_unit.combine(right_unit, exponent) {
+            Ok(unit) if unit.is_dimensionless() => (value * unit.scale).into(),
+            Ok(unit) if unit.is_time() => match temporal::scale_duration(&Duration::seconds(1), value * unit.scale) {
+                Some(duration) => ShyValue::Scalar(ShyScalar::Duration(duration)),
+                None => ShyValue::error(format!("A duration of {} is out of range", units::format_quantity(value, &unit)))
+            },
+            Ok(unit) => ShyValue::Scalar(ShyScalar::Quantity(value, unit)),
+            Err(message) => ShyValue::error(message)
+        }
+    }
+
+    /// Compare quantities for equality in SI units. Returns None if neither operand is a quantity.
+    fn quantity_equality(left: &Self, right: &Self) -> Option<Self> {
+        match (left, right) {
+            (ShyValue::Scalar(ShyScalar::Quantity(_, _)), ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => Some(match left.partial_cmp(right) {
+                    Some(ordering) => (ordering == Ordering::Equal).into(),
+                    None => ShyValue::incomparable(left, right)
+                }),
+            (ShyValue::Scalar(ShyScalar::Quantity(_, _)), other) | (other, ShyValue::Scalar(ShyScalar::Quantity(_, _))) if other.is_number() 
+                => Some(ShyValue::missing_unit(left, right, "comparison")),
+            _ => None
+        }
+    }
+
+    /// Compare decimals with numbers for equality by value, so that 12.50d == 12.5d and 3d == 3. 
+    /// Returns None unless one operand is a decimal and the other a number.
+    fn decimal_equality(left: &Self, right: &Self) -> Option<Self> {
+        match (left, right) {
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), other) | (other, ShyValue::Scalar(ShyScalar::Decimal(_))) if other.is_number() 
+                => Some((left.partial_cmp(right) == Some(Ordering::Equal)).into()),
+            _ => None
+        }
+    }
+
+    /// Apply an arithmetic operation where at least one operand is a decimal. 
+    /// If the other operand is a decimal or integer, the exact operation is used and the result is a decimal.
+    /// Otherwise the decimal is converted to a Rational and the inexact operation is used, so 1.5d * 2.0 is a Rational.
+    fn decimal_arithmetic<F, G>(left_operand: &Self, right_operand: &Self, operator_name: &str, exact: F, inexact: G) -> Self 
+    where F: Fn(&Decimal, &Decimal) -> Option<Decimal>, G: Fn(&Self, &Self) -> Self {
+        match (left_operand.as_decimal(), right_operand.as_decimal()) {
+            (Some(left), Some(right)) => Self::decimal_result(exact(&left, &right), &[left_operand, right_operand], operator_name),
+            _ => match inexact(&left_operand.promote_decimal(), &right_operand.promote_decimal()) {
+                result if result.is_error() => Self::incompatible(left_operand, right_operand, operator_name),
+                result => result
+            }
+        }
+    }
+
+    /// Get a decimal or integer as a Decimal, or None for any other value.
+    fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            ShyValue::Scalar(ShyScalar::Decimal(d)) => Some(*d),
+            ShyValue::Scalar(ShyScalar::Integer(i)) => Some(Decimal::from_i64(*i)),
+            _ => None
+        }
+    }
+
+    /// Convert a Decimal to a Rational, leaving any other value as is.
+    fn promote_decimal(&self) -> Self {
+        match self {
+            ShyValue::Scalar(ShyScalar::Decimal(d)) => d.to_f64().into(),
+            _ => self.clone()
+        }
+    }
+
+    /// Wrap the result of decimal arithmetic, which is None if the result does not fit. 
+    /// Decimals are never promoted to Rational, as that would lose the exactness they were chosen for.
+    fn decimal_result(result: Option<Decimal>, operands: &[&Self], operator_name: &str) -> Self {
+        match result {
+            Some(value) => value.into(),
+            None => {
+                let operand_list: Vec<String> = operands.iter().map(|operand| format!("{:?}", operand)).collect();
+                ShyValue::error(format!("Decimal overflow in {} operator with {} {}", operator_name, 
+                    if operands.len() == 1 { "operand" } else { "operands" }, operand_list.join(" and ")))
+            }
+        }
+    }
+
+    /// Wrap the result of checked integer arithmetic, which is None if the result does not fit in an i64.
+    fn integer_result(result: Option<i64>, operands: &[&Self], operator_name: &str) -> Result<Self, IntegerOverflowError> {
+        match result {
+            Some(value) => Ok(value.into()),
+            None => {
+                let operand_list: Vec<String> = operands.iter().map(|operand| format!("{:?}", operand)).collect();
+                Err(IntegerOverflowError { message: format!("Integer overflow in {} operator with {} {}", operator_name, 
+                    if operands.len() == 1 { "operand" } else { "operands" }, operand_list.join(" and ")) })
+            }
+        }
+    }
+
+    /// Apply a checked arithmetic operation. If its integer result overflowed and overflow is Promote, 
+    /// repeat it upon the operands converted to Rational, otherwise return the overflow error.
+    pub fn with_overflow<F>(left_operand: &Self, right_operand: &Self, overflow: IntegerOverflow, op: F) -> Self 
+    where F: Fn(&Self, &Self) -> Result<Self, IntegerOverflowError> {
+        match (op(left_operand, right_operand), overflow) {
+            (Ok(result), _) => result,
+            (Err(_), IntegerOverflow::Promote) 
+                => op(&left_operand.promote_integer(), &right_operand.promote_integer()).unwrap_or_else(IntegerOverflowError::into_error),
+            (Err(error), IntegerOverflow::Error) => error.into_error()
+        }
+    }
+
+    /// Convert an Integer to a Rational, leaving any other value as is.
+    fn promote_integer(&self) -> Self {
+        match self {
+            ShyValue::Scalar(ShyScalar::Integer(i)) => (*i as f64).into(),
+            _ => self.clone()
+        }
+    }
+
+    /// Wrap the result of date/time or duration arithmetic, which is None if the result would overflow.
+    fn temporal_result<T: Into<ShyValue>>(result: Option<T>, left: &Self, operator_name: &str) -> Self {
+        match result {
+            Some(value) => value.into(),
+            None => ShyValue::out_of_range(left, operator_name)
+        }
+    }
+
     fn not_an_object(&self) -> Self {
         ShyValue::error(format!("Cannot get or set properties on a non-object of type {}", self.type_name()))
     }
@@ -263,16 +493,20 @@ impl ShyValue {
         ShyValue::error(format!("No such property '{}'", property_name))
     }
 
-    fn invalid_path(path: &Vec<String>) -> Self {
-        ShyValue::error(format!("Invalid or uninitialized property chain '{}'", path.join(".")))
+    fn invalid_index(index: &str) -> Self {
+        ShyValue::error(format!("No element at index '{}'", index))
+    }
+
+    fn invalid_path(path: &[ChainLink]) -> Self {
+        ShyValue::error(format!("Invalid or uninitialized property chain '{}'", ChainLink::join(path)))
     }
 
     fn incomparable_types() -> Self {
         ShyValue::error("Incomparable types".to_string())
     }
 
-    fn cannot_infer_previous_value(path: &Vec<String>) -> Self {
-        ShyValue::error(format!("Cannot infer previous value for property chain '{}'", path.join(".")))
+    fn cannot_infer_previous_value(path: &[ChainLink]) -> Self {
+        ShyValue::error(format!("Cannot infer previous value for property chain '{}'", ChainLink::join(path)))
     }
 
 
@@ -336,14 +570,42 @@ impl ShyValue {
                     }
                 }
             }
+            ShyValue::Vector(v) => {
+                match key.parse::<usize>().ok().and_then(|i| v.get(i)) {
+                    Some(element) => element.shallow_clone(),
+                    None => ShyValue::invalid_index(key)
+                }
+            },
             _ => ShyValue::invalid_property(key)
         }
     }
 
+    /// Return a copy of this Vector with the element at the given index replaced by the new value.
+    /// If the index is one past the end of the Vector, the value is appended.
+    pub fn with_element(&self, key: &str, element: ShyValue) -> ShyValue {
+        match (self, key.parse::<usize>()) {
+            (ShyValue::Vector(v), Ok(i)) if i <= v.len() => {
+                let mut v_clone = v.clone();
+                if i == v.len() { v_clone.push(element); }
+                else { v_clone[i] = element; }
+                ShyValue::Vector(v_clone)
+            },
+            (ShyValue::Vector(_), _) => ShyValue::invalid_index(key),
+            _ => ShyValue::error(format!("Cannot set an element of a {}", self.type_name()))
+        }
+    }
+
     /// Given a series of property names, recursively perform a series of gets to obtain the value at the end of the chain.
-    pub fn get_chain(&self, keys: &[String]) -> ShyValue {
+    /// For a null-safe link (as in well?.casing), if this value is null or lacks that property, 
+    /// the result is null instead of an error, and the rest of the chain is skipped.
+    pub fn get_chain(&self, keys: &[ChainLink]) -> ShyValue {
         match keys.first() {
-            Some(key) => {
+            Some(ChainLink::NullSafe(key)) => {
+                let next_value = self.get_safe(key);
+                if next_value.is_error() { ShyValue::Scalar(ShyScalar::Null) }
+                else { next_value.get_chain(&keys[1..]) }
+            },
+            Some(ChainLink::Field(key)) | Some(ChainLink::Index(key)) => {
                 let next_value = self.get_safe(key);
                 next_value.get_chain(&keys[1..])
             },
@@ -375,7 +637,11 @@ impl ShyValue {
             ShyValue::Scalar(ShyScalar::Boolean(value)) => *value,
             ShyValue::Scalar(ShyScalar::Integer(value)) => *value != 0,
             ShyValue::Scalar(ShyScalar::Rational(value)) => *value != 0.0,
+            ShyValue::Scalar(ShyScalar::Decimal(value)) => !value.is_zero(),
             ShyValue::Scalar(ShyScalar::String(value)) => is_truthy(value),
+            ShyValue::Scalar(ShyScalar::DateTime(_)) => true,
+            ShyValue::Scalar(ShyScalar::Duration(value)) => !value.is_zero(),
+            ShyValue::Scalar(ShyScalar::Quantity(value, _)) => *value != 0.0,
             _ => false
         }
     }
@@ -388,6 +654,7 @@ impl ShyValue {
         match self {
             ShyValue::Scalar(ShyScalar::Integer(_)) => true,
             ShyValue::Scalar(ShyScalar::Rational(value)) => !value.is_nan(),
+            ShyValue::Scalar(ShyScalar::Decimal(_)) => true,
             _ => false
         }
     }
@@ -396,6 +663,7 @@ impl ShyValue {
         match self {
             ShyValue::Scalar(ShyScalar::Integer(value)) => *value == 0i64,
             ShyValue::Scalar(ShyScalar::Rational(value)) => *value == 0.0f64,
+            ShyValue::Scalar(ShyScalar::Decimal(value)) => value.is_zero(),
             _ => false
         }
     }
@@ -407,6 +675,70 @@ impl ShyValue {
     // Methods to perform operations
     // Note: They will not load a Variable value from the context. Caller must take care of that first.
 
+    // Checked arithmetic, which reports an Integer result that does not fit in an i64 as an IntegerOverflowError 
+    // and leaves operands of any other type to the unchecked operator.
+
+    /// Add two ShyValues, reporting integer overflow apart from other errors.
+    pub fn checked_add(left_operand: &Self, right_operand: &Self) -> Result<Self, IntegerOverflowError> {
+        match (left_operand, right_operand) {
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => Self::integer_result(left.checked_add(*right), &[left_operand, right_operand], "add"),
+            _ => Ok(Self::add(left_operand, right_operand))
+        }
+    }
+
+    /// Subtract the right_operand from the left_operand, reporting integer overflow apart from other errors.
+    pub fn checked_subtract(left_operand: &Self, right_operand: &Self) -> Result<Self, IntegerOverflowError> {
+        match (left_operand, right_operand) {
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => Self::integer_result(left.checked_sub(*right), &[left_operand, right_operand], "subtract"),
+            _ => Ok(Self::subtract(left_operand, right_operand))
+        }
+    }
+
+    /// Multiply two ShyValues, reporting integer overflow apart from other errors.
+    pub fn checked_multiply(left_operand: &Self, right_operand: &Self) -> Result<Self, IntegerOverflowError> {
+        match (left_operand, right_operand) {
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => Self::integer_result(left.checked_mul(*right), &[left_operand, right_operand], "multiply"),
+            _ => Ok(Self::multiply(left_operand, right_operand))
+        }
+    }
+
+    /// Raise the left_operand to the power of the right_operand, reporting integer overflow apart from other errors.
+    pub fn checked_power(left_operand: &Self, right_operand: &Self) -> Result<Self, IntegerOverflowError> {
+        match (left_operand, right_operand) {
+            // Integer raised to non-negative integer power. Return an Integer. 
+            // Only 0, 1 and -1 may be raised to a power too large for a u32 without overflowing.
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) if *right >= 0 => {
+                let result = match (u32::try_from(*right), *left) {
+                    (Ok(upower), _) => left.checked_pow(upower),
+                    (Err(_), 0) | (Err(_), 1) => Some(*left),
+                    (Err(_), -1) => Some(if *right % 2 == 0 { 1 } else { -1 }),
+                    (Err(_), _) => None
+                };
+                Self::integer_result(result, &[left_operand, right_operand], "power")
+            },
+            _ => Ok(Self::power(left_operand, right_operand))
+        }
+    }
+
+    /// Factorial of a ShyValue, reporting integer overflow apart from other errors.
+    pub fn checked_factorial(left_operand: &Self) -> Result<Self, IntegerOverflowError> {
+        match left_operand {
+            ShyValue::Scalar(ShyScalar::Integer(value)) if *value > 20 => Self::integer_result(None, &[left_operand], "factorial"),
+            _ => Ok(Self::factorial(left_operand))
+        }
+    }
+
+    /// Prefix minus of a ShyValue, reporting integer overflow apart from other errors.
+    pub fn checked_prefix_minus(left_operand: &Self) -> Result<Self, IntegerOverflowError> {
+        match left_operand {
+            ShyValue::Scalar(ShyScalar::Integer(value)) => Self::integer_result(value.checked_neg(), &[left_operand], "negate"),
+            _ => Ok(Self::prefix_minus(left_operand))
+        }
+    }
+
     /// Add two ShyValues.
     pub fn add(left_operand: &Self, right_operand: &Self) -> Self {
         match (left_operand, right_operand) {
@@ -416,11 +748,32 @@ impl ShyValue {
             (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left + *right as f64).into(),
 
             // Integer addition
-            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left + right).into(),
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Integer(_))) 
+                => Self::checked_add(left_operand, right_operand).unwrap_or_else(IntegerOverflowError::into_error),
+
+            // Exact decimal addition (with cast of integer to decimal)
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) 
+                => Self::decimal_arithmetic(left_operand, right_operand, "add", Decimal::checked_add, Self::add),
 
             // String concatenation
             (ShyValue::Scalar(ShyScalar::String(left)), ShyValue::Scalar(ShyScalar::String(right))) => format!("{}{}", left , right).into(),
 
+            // Shift a date/time by a duration, or lengthen a duration
+            (ShyValue::Scalar(ShyScalar::DateTime(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::temporal_result(left.checked_add_signed(*right), left_operand, "add"),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::DateTime(right))) 
+                => Self::temporal_result(right.checked_add_signed(*left), left_operand, "add"),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::temporal_result(left.checked_add(right), left_operand, "add"),
+
+            // Quantities must measure the same thing; the result is in the unit of the left operand
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::combine_quantities(*left, left_unit, *right, right_unit, "add", |a, b| a + b),
+
+            // A quantity may not be combined with a value that has no unit
+            (ShyValue::Scalar(ShyScalar::Quantity(_, _)), _) | (_, ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => ShyValue::missing_unit(left_operand, right_operand, "add"),
+
             _ => ShyValue::incompatible(left_operand, right_operand, "add")
         }
     }
@@ -434,7 +787,28 @@ impl ShyValue {
             (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left - *right as f64).into(),
 
             // Integer subtraction
-            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left - right).into(),
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Integer(_))) 
+                => Self::checked_subtract(left_operand, right_operand).unwrap_or_else(IntegerOverflowError::into_error),
+
+            // Exact decimal subtraction (with cast of integer to decimal)
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) 
+                => Self::decimal_arithmetic(left_operand, right_operand, "subtract", Decimal::checked_sub, Self::subtract),
+
+            // Shift a date/time back by a duration, find the duration between two date/times, or shorten a duration
+            (ShyValue::Scalar(ShyScalar::DateTime(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::temporal_result(left.checked_sub_signed(*right), left_operand, "subtract"),
+            (ShyValue::Scalar(ShyScalar::DateTime(left)), ShyValue::Scalar(ShyScalar::DateTime(right))) 
+                => left.signed_duration_since(*right).into(),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::temporal_result(left.checked_sub(right), left_operand, "subtract"),
+
+            // Quantities must measure the same thing; the result is in the unit of the left operand
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::combine_quantities(*left, left_unit, *right, right_unit, "subtract", |a, b| a - b),
+
+            // A quantity may not be combined with a value that has no unit
+            (ShyValue::Scalar(ShyScalar::Quantity(_, _)), _) | (_, ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => ShyValue::missing_unit(left_operand, right_operand, "subtract"),
 
             _ => ShyValue::incompatible(left_operand, right_operand, "subtract")
         }
@@ -449,7 +823,12 @@ impl ShyValue {
             (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left * *right as f64).into(),
 
             // Integer multiplication
-            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left * right).into(),
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Integer(_))) 
+                => Self::checked_multiply(left_operand, right_operand).unwrap_or_else(IntegerOverflowError::into_error),
+
+            // Exact decimal multiplication (with cast of integer to decimal)
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) 
+                => Self::decimal_arithmetic(left_operand, right_operand, "multiply", Decimal::checked_mul, Self::multiply),
 
             // String replication
             (ShyValue::Scalar(ShyScalar::String(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => { 
@@ -460,6 +839,30 @@ impl ShyValue {
                 s.into()
             },
 
+            // Duration scaling
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => Self::temporal_result(temporal::scale_duration(left, *right as f64), left_operand, "multiply"),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Rational(right))) 
+                => Self::temporal_result(temporal::scale_duration(left, *right), left_operand, "multiply"),
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Duration(_))) 
+            | (ShyValue::Scalar(ShyScalar::Rational(_)), ShyValue::Scalar(ShyScalar::Duration(_))) 
+                => Self::multiply(right_operand, left_operand),
+
+            // Quantity scaling, or multiplication of units
+            (ShyValue::Scalar(ShyScalar::Quantity(left, unit)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => ShyValue::Scalar(ShyScalar::Quantity(left * *right as f64, unit.clone())),
+            (ShyValue::Scalar(ShyScalar::Quantity(left, unit)), ShyValue::Scalar(ShyScalar::Rational(right))) 
+                => ShyValue::Scalar(ShyScalar::Quantity(left * right, unit.clone())),
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+            | (ShyValue::Scalar(ShyScalar::Rational(_)), ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => Self::multiply(right_operand, left_operand),
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::scale_quantities(*left, left_unit, *right, right_unit, 1),
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::scale_quantities(*left, left_unit, temporal::duration_seconds(right), &Unit::seconds(), 1),
+            (ShyValue::Scalar(ShyScalar::Duration(_)), ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => Self::multiply(right_operand, left_operand),
+
             _ => ShyValue::incompatible(left_operand, right_operand, "multiply")
         }
     }
@@ -475,6 +878,43 @@ impl ShyValue {
             // Integers are divided using floating point division
             (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (*left as f64 / *right as f64).into(),
 
+            // Exact decimal division (with cast of integer to decimal), where division by zero is an error rather than infinity
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) if right_operand.is_zero()
+                => ShyValue::error("Division by zero in divide operator".to_string()),
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) 
+                => Self::decimal_arithmetic(left_operand, right_operand, "divide", Decimal::checked_div, Self::divide),
+
+            // Divide a duration into parts, or find the ratio of two durations
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => Self::temporal_result(temporal::scale_duration(left, 1.0 / *right as f64), left_operand, "divide"),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Rational(right))) 
+                => Self::temporal_result(temporal::scale_duration(left, 1.0 / right), left_operand, "divide"),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => match temporal::duration_ratio(left, right) {
+                    Some(ratio) => ratio.into(),
+                    None => ShyValue::out_of_range(left_operand, "divide")
+                },
+
+            // Quantity scaling, or division of units
+            (ShyValue::Scalar(ShyScalar::Quantity(left, unit)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => ShyValue::Scalar(ShyScalar::Quantity(left / *right as f64, unit.clone())),
+            (ShyValue::Scalar(ShyScalar::Quantity(left, unit)), ShyValue::Scalar(ShyScalar::Rational(right))) 
+                => ShyValue::Scalar(ShyScalar::Quantity(left / right, unit.clone())),
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::scale_quantities(*left as f64, &Unit::dimensionless(), *right, right_unit, -1),
+            (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::scale_quantities(*left, &Unit::dimensionless(), *right, right_unit, -1),
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::scale_quantities(*left, left_unit, *right, right_unit, -1),
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::scale_quantities(*left, left_unit, temporal::duration_seconds(right), &Unit::seconds(), -1),
+            (ShyValue::Scalar(ShyScalar::Duration(left)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::scale_quantities(temporal::duration_seconds(left), &Unit::seconds(), *right, right_unit, -1),
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::scale_quantities(*left as f64, &Unit::dimensionless(), temporal::duration_seconds(right), &Unit::seconds(), -1),
+            (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Duration(right))) 
+                => Self::scale_quantities(*left, &Unit::dimensionless(), temporal::duration_seconds(right), &Unit::seconds(), -1),
+
             _ => ShyValue::incompatible(left_operand, right_operand, "divide")
         }
     }
@@ -487,8 +927,25 @@ impl ShyValue {
             (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Rational(right))) => (*left as f64 % right).into(),
             (ShyValue::Scalar(ShyScalar::Rational(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (left % *right as f64).into(),
 
-            // Integers use integer modular division
-            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) => (*left % *right).into(),
+            // Integers use integer modular division. The remainder of i64::MIN % -1 is zero, although computing it overflows.
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Integer(0))) 
+                => ShyValue::error("Division by zero in modulo operator".to_string()),
+            (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => left.checked_rem(*right).unwrap_or(0).into(),
+
+            // Exact decimal modular division (with cast of integer to decimal)
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) if right_operand.is_zero()
+                => ShyValue::error("Division by zero in modulo operator".to_string()),
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) 
+                => Self::decimal_arithmetic(left_operand, right_operand, "modulo", Decimal::checked_rem, Self::modulo),
+
+            // Quantities must measure the same thing; the result is in the unit of the left operand
+            (ShyValue::Scalar(ShyScalar::Quantity(left, left_unit)), ShyValue::Scalar(ShyScalar::Quantity(right, right_unit))) 
+                => Self::combine_quantities(*left, left_unit, *right, right_unit, "modulo", |a, b| a % b),
+
+            // A quantity may not be combined with a value that has no unit
+            (ShyValue::Scalar(ShyScalar::Quantity(_, _)), _) | (_, ShyValue::Scalar(ShyScalar::Quantity(_, _))) 
+                => ShyValue::missing_unit(left_operand, right_operand, "modulo"),
 
             _ => ShyValue::incompatible(left_operand, right_operand, "modulo")
         }
@@ -509,12 +966,10 @@ impl ShyValue {
                 },
 
             // Integers use pow or powi when possible
+            (ShyValue::Scalar(ShyScalar::Integer(_)), ShyValue::Scalar(ShyScalar::Integer(right))) if *right >= 0
+                => Self::checked_power(left_operand, right_operand).unwrap_or_else(IntegerOverflowError::into_error),
             (ShyValue::Scalar(ShyScalar::Integer(left)), ShyValue::Scalar(ShyScalar::Integer(right)))
                 => {
-                    if let Ok(upower) = u32::try_from(*right) {
-                        // Integer raised to non-negative integer power. Return an Integer.
-                        return left.pow(upower).into();
-                    }
                     if let Ok(ipower) = i32::try_from(*right) {
                         // Integer possibly raised to negative integer power. Return a Rational.
                         return (*left as f64).powi(ipower).into();
@@ -522,6 +977,25 @@ impl ShyValue {
                     (*left as f64).powf(*right as f64).into()
                 },
 
+            // A decimal raised to a non-negative integer power is exact; any other power of a decimal is floating point
+            (ShyValue::Scalar(ShyScalar::Decimal(left)), ShyValue::Scalar(ShyScalar::Integer(right))) if *right >= 0
+                => Self::decimal_result(u32::try_from(*right).ok().and_then(|upower| left.checked_pow(upower)), &[left_operand, right_operand], "power"),
+            (ShyValue::Scalar(ShyScalar::Decimal(_)), _) | (_, ShyValue::Scalar(ShyScalar::Decimal(_))) 
+                => match Self::power(&left_operand.promote_decimal(), &right_operand.promote_decimal()) {
+                    result if result.is_error() => Self::incompatible(left_operand, right_operand, "power"),
+                    result => result
+                },
+
+            // A quantity raised to an integer power also raises its unit to that power
+            (ShyValue::Scalar(ShyScalar::Quantity(left, unit)), ShyValue::Scalar(ShyScalar::Integer(right))) 
+                => match i32::try_from(*right) {
+                    Ok(ipower) => match unit.power(ipower) {
+                        Ok(raised_unit) => ShyValue::Scalar(ShyScalar::Quantity(left.powi(ipower), raised_unit)),
+                        Err(message) => ShyValue::error(message)
+                    },
+                    Err(_) => ShyValue::out_of_range(left_operand, "power")
+                },
+
             _ => ShyValue::incompatible(left_operand, right_operand, "power")
         }
     }
@@ -531,23 +1005,26 @@ impl ShyValue {
         Self::power(left_operand, &0.5.into())
     }
  
-    /// Factorial operator.
+    /// Factorial operator. 
+    /// The factorial of an Integer above 20 overflows, but that of a whole Rational is approximated up to 170!.
     pub fn factorial(left_operand: &Self) -> Self {
         match left_operand {
-            ShyValue::Scalar(ShyScalar::Integer(value)) if *value <= 20 => {
+            ShyValue::Scalar(ShyScalar::Integer(value)) if *value > 20 
+                => Self::checked_factorial(left_operand).unwrap_or_else(IntegerOverflowError::into_error),
+            ShyValue::Scalar(ShyScalar::Integer(value)) => {
                 match factorial(*value) {
                     Some(fact) => fact.into(),
                     _ => ShyValue::out_of_range(left_operand, "factorial")
                 }
             },
-            ShyValue::Scalar(ShyScalar::Integer(value)) if *value > 20 => {
-                match factorial_approx(*value) {
+            ShyValue::Scalar(ShyScalar::Rational(value)) if value.fract() == 0.0 && *value <= 20.0 => {
+                match factorial(*value as i64) {
                     Some(fact) => fact.into(),
                     _ => ShyValue::out_of_range(left_operand, "factorial")
                 }
             },
             ShyValue::Scalar(ShyScalar::Rational(value)) if value.fract() == 0.0 => {
-                match factorial(*value as i64) {
+                match factorial_approx(*value as i64) {
                     Some(fact) => fact.into(),
                     _ => ShyValue::out_of_range(left_operand, "factorial")
                 }
@@ -558,7 +1035,7 @@ impl ShyValue {
 
     //..................................................................
 
-    // Logical and Relational Operators: and, or, not, less_than, less_than_or_equal_to, greater_than, greater_than_or_equal_to, equals, not_equals
+    // Logical and Relational Operators: and, or, coalesce, not, less_than, less_than_or_equal_to, greater_than, greater_than_or_equal_to, equals, not_equals
 
     /// Logical AND of two ShyValues.
     pub fn and(left_operand: &Self, right_operand: &Self) -> Self {
@@ -586,6 +1063,12 @@ impl ShyValue {
         right_operand.is_truthy().into()
     }
 
+    /// Null-coalescing of two ShyValues: the left operand, unless it is null, in which case the right operand.
+    pub fn coalesce(left_operand: &Self, right_operand: &Self) -> Self {
+        if left_operand.is_null() { right_operand.clone() }
+        else { left_operand.clone() }
+    }
+
     /// Logical NOT of one ShyValue.
     pub fn not(left_operand: &Self) -> Self {
         if left_operand.is_error() { 
@@ -600,7 +1083,7 @@ impl ShyValue {
             Some(Ordering::Less) => true.into(),
             Some(Ordering::Equal) => false.into(),
             Some(Ordering::Greater) => false.into(),
-            None => ShyValue::incomparable_types()
+            None => ShyValue::incomparable(left_operand, right_operand)
         }
     }
 
@@ -610,7 +1093,7 @@ impl ShyValue {
             Some(Ordering::Less) => true.into(),
             Some(Ordering::Equal) => true.into(),
             Some(Ordering::Greater) => false.into(),
-            None => ShyValue::incomparable_types()
+            None => ShyValue::incomparable(left_operand, right_operand)
         }
     }
 
@@ -620,7 +1103,7 @@ impl ShyValue {
             Some(Ordering::Less) => false.into(),
             Some(Ordering::Equal) => false.into(),
             Some(Ordering::Greater) => true.into(),
-            None => ShyValue::incomparable_types()
+            None => ShyValue::incomparable(left_operand, right_operand)
         }
     }
 
@@ -630,19 +1113,23 @@ impl ShyValue {
             Some(Ordering::Less) => false.into(),
             Some(Ordering::Equal) => true.into(),
             Some(Ordering::Greater) => true.into(),
-            None => ShyValue::incomparable_types()
+            None => ShyValue::incomparable(left_operand, right_operand)
         }
     }
 
     /// Equals operator for ShyValues.
     pub fn equals(left_operand: &Self, right_operand: &Self) -> Self {
-        if left_operand == right_operand { true.into() }
+        if let Some(result) = Self::quantity_equality(left_operand, right_operand) { result }
+        else if let Some(result) = Self::decimal_equality(left_operand, right_operand) { result }
+        else if left_operand == right_operand { true.into() }
         else { false.into() }
     }
 
     /// Not equals operator for ShyValues.
     pub fn not_equals(left_operand: &Self, right_operand: &Self) -> Self {
-        if left_operand == right_operand { false.into() }
+        if let Some(result) = Self::quantity_equality(left_operand, right_operand) { Self::not(&result) }
+        else if let Some(result) = Self::decimal_equality(left_operand, right_operand) { Self::not(&result) }
+        else if left_operand == right_operand { false.into() }
         else { true.into() }
     }
 
@@ -692,11 +1179,11 @@ impl ShyValue {
         ShyValue::error(format!("Left operand must be a variable, not {}", left_operand.type_name()))
     }
 
-    pub fn bad_property_chain(property_chain: &Vec<String>) -> Self {
-        ShyValue::error(format!("Property chain includes an invalid property: {}", property_chain.join(".")))
+    pub fn bad_property_chain(property_chain: &[ChainLink]) -> Self {
+        ShyValue::error(format!("Property chain includes an invalid property: {}", ChainLink::join(property_chain)))
     }
 
-    fn no_such_variable(var_name: &String) -> Self {
+    pub fn no_such_variable(var_name: &str) -> Self {
         ShyValue::error(format!("No variable named {}", var_name))
     }
     
@@ -721,21 +1208,25 @@ impl ShyValue {
     /// new value for that variable.
     /// If the variable is not defined, initialize it to the value of the right_operand.
     pub fn plus_assign(left_operand: &Self, right_operand: &Self, ctx: &mut ExecutionContext) -> Self {
-        Self::common_assign(left_operand, right_operand, ctx, &Self::add)
+        let overflow = ctx.integer_overflow;
+        Self::common_assign(left_operand, right_operand, ctx, &|left, right| Self::with_overflow(left, right, overflow, Self::checked_add))
     }
 
     pub fn minus_assign(left_operand: &Self, right_operand: &Self, ctx: &mut ExecutionContext) -> Self {
+        let overflow = ctx.integer_overflow;
+        let subtract = |left: &Self, right: &Self| Self::with_overflow(left, right, overflow, Self::checked_subtract);
+        let negate = |operand: &Self| Self::with_overflow(operand, operand, overflow, |operand, _| Self::checked_prefix_minus(operand));
         match left_operand {
             ShyValue::Variable(name) => {
                 let current_value = ctx.load(name);
                 match current_value {
                     Some(current) => {
-                        let difference = ShyValue::subtract(&current, right_operand);
+                        let difference = subtract(&current, right_operand);
                         ctx.store(name, difference.clone());
                         difference
                     },
                     None => {
-                        let negation = ShyValue::prefix_minus(right_operand);
+                        let negation = negate(right_operand);
                         ctx.store(name, negation.clone());
                         negation
                     }
@@ -745,8 +1236,8 @@ impl ShyValue {
                 ctx.property_chain_update(
                     path, 
                     &0.into(),
-                    &|previous_value| ShyValue::subtract(previous_value, right_operand),
-                    &|| ShyValue::prefix_minus(right_operand),
+                    &|previous_value| subtract(previous_value, right_operand),
+                    &|| negate(right_operand),
                     &|_previous_value, new_value| new_value.clone())
             },
             _ => Self::not_a_variable(left_operand)
@@ -757,7 +1248,8 @@ impl ShyValue {
     /// If no value has yet been stored for that variable, set the value to the right_operand,
     /// as if the value was originally one.
     pub fn multiply_assign(left_operand: &Self, right_operand: &Self, ctx: &mut ExecutionContext) -> Self {
-        Self::common_assign(left_operand, right_operand, ctx, &Self::multiply)
+        let overflow = ctx.integer_overflow;
+        Self::common_assign(left_operand, right_operand, ctx, &|left, right| Self::with_overflow(left, right, overflow, Self::checked_multiply))
     }
 
     /// Divide a value loaded from the context by the right_operand.
@@ -842,12 +1334,14 @@ impl ShyValue {
     /// If the variable was previously uninitialized, pretend that it started with the value zero,
     /// return zero, and store a one.
     pub fn post_increment(left_operand: &Self, ctx: &mut ExecutionContext) -> Self {
+        let overflow = ctx.integer_overflow;
+        let increment = |value: &Self| Self::with_overflow(value, &1.into(), overflow, Self::checked_add);
         match left_operand {
             ShyValue::Variable(name) => {
                 let current_value = ctx.load(name);
                 match current_value {
                     Some(current) => {
-                        let result = ShyValue::add(&current, &1.into());
+                        let result = increment(&current);
                         ctx.store(name, result.clone());
                         current
                     },
@@ -861,7 +1355,7 @@ impl ShyValue {
                 ctx.property_chain_update(
                     path, 
                     &0.into(),
-                    &|previous_value| ShyValue::add(previous_value, &1.into()),
+                    &|previous_value| increment(previous_value),
                     &|| ShyValue::invalid_path(path),
                     &|previous_value, _new_value| previous_value.clone())
             },
@@ -874,12 +1368,14 @@ impl ShyValue {
     /// and return the value it held prior to the decrement operation.
     /// If the variable was previously uninitialized, return an error wrapped in a ShyValue.
     pub fn post_decrement(left_operand: &Self, ctx: &mut ExecutionContext) -> Self {
+        let overflow = ctx.integer_overflow;
+        let decrement = |value: &Self| Self::with_overflow(value, &1.into(), overflow, Self::checked_subtract);
         match left_operand {
             ShyValue::Variable(name) => {
                 let current_value = ctx.load(name);
                 match current_value {
                     Some(current) => {
-                        let result = ShyValue::subtract(&current, &1.into());
+                        let result = decrement(&current);
                         ctx.store(name, result.clone());
                         current
                     },
@@ -894,7 +1390,7 @@ impl ShyValue {
                 ctx.property_chain_update(
                     path, 
                     &0.into(),
-                    &|previous_value| ShyValue::subtract(previous_value, &1.into()),
+                    &|previous_value| decrement(previous_value),
                     &|| ShyValue::invalid_path(path),
                     &|previous_value, _new_value| previous_value.clone())
             },
@@ -938,38 +1434,37 @@ impl ShyValue {
 
     //..................................................................
 
-    // Miscellaneous Operators: call, comma, member, prefix_plus, prefix_minus, matches, not_matches, ternary
-
-    /*
-        OpenBracket,
-        CloseBracket,
-    */
+    // Miscellaneous Operators: call, comma, append, member, index, prefix_plus, prefix_minus, matches, not_matches, ternary
 
-    /// call operator - performs a function call.
+    /// call operator - performs a function call. The right_operand is the vector of arguments built by the compiler.
     pub fn call(left_operand: &Self, right_operand: &Self, ctx: &mut ExecutionContext) -> Self {
         match left_operand {
-            ShyValue::FunctionName(name) => ctx.call(name.clone(), right_operand.clone()),
+            ShyValue::FunctionName(name) => match right_operand {
+                ShyValue::Vector(args) => ctx.call(name.clone(), args),
+                _ => ShyValue::error(format!("Cannot call function {} because its arguments are type {}", name, right_operand.type_name()))
+            },
             _ => ShyValue::error(format!("Cannot call a function because the name is type {}", left_operand.type_name()))
         }
     }
 
-    /// Comma operator for ShyValues (combines arguments into a list).
-    /// The right_operand must be a ShyValue::Scalar.
-    /// If the left_operand is not a ShyValue::Vector, return a ShyValue::Vector containing both operands.
-    /// If the left_operand is a ShyValue::Vector, append a clone of the right_operand to a clone of that Vector.
-    /// Return a new Vector.
+    /// Comma operator for ShyValues (starts a list of arguments).
+    /// Return a new ShyValue::Vector containing both operands. 
+    /// The operands may themselves be Vectors, which are not flattened.
     pub fn comma(left_operand: &Self, right_operand: &Self) -> Self {
-        match (left_operand, right_operand) {
-            (ShyValue::Vector(v), ShyValue::Scalar(right_scalar)) => {
-                let mut v_clone = v.clone();
-                v_clone.push(right_scalar.clone());
+        ShyValue::Vector(vec![left_operand.shallow_clone(), right_operand.shallow_clone()])
+    }
+
+    /// Append operator for ShyValues (continues a list of arguments or builds a vector literal).
+    /// The left_operand must be a ShyValue::Vector; return a clone of that Vector with a clone of the right_operand appended.
+    pub fn append(left_operand: &Self, right_operand: &Self) -> Self {
+        match left_operand {
+            ShyValue::Vector(v) => {
+                let mut v_clone: Vec<ShyValue> = v.iter().map(|element| element.shallow_clone()).collect();
+                v_clone.push(right_operand.shallow_clone());
                 ShyValue::Vector(v_clone)
-            } ,
-            (ShyValue::Scalar(left_scalar), ShyValue::Scalar(right_scalar)) => {
-                ShyValue::Vector(vec![left_scalar.clone(), right_scalar.clone()])
             },
             _ => ShyValue::error(
-                format!("wrong type of arguments for comma operator: {} and {}", 
+                format!("wrong type of arguments for append operator: {} and {}", 
                     left_operand.type_name(), 
                     right_operand.type_name()))
         }
@@ -983,35 +1478,173 @@ impl ShyValue {
     /// Prefix minus of one ShyValue.
     pub fn prefix_minus(left_operand: &Self) -> Self {
         match *left_operand {
-            ShyValue::Scalar(ShyScalar::Integer(i)) => (-i).into(),
+            ShyValue::Scalar(ShyScalar::Integer(_)) => Self::checked_prefix_minus(left_operand).unwrap_or_else(IntegerOverflowError::into_error),
             ShyValue::Scalar(ShyScalar::Rational(r)) => (-r).into(),
+            ShyValue::Scalar(ShyScalar::Decimal(d)) => Self::decimal_result(d.checked_neg(), &[left_operand], "negate"),
             ShyValue::Scalar(ShyScalar::Boolean(b)) => (!b).into(),
+            ShyValue::Scalar(ShyScalar::Duration(d)) => (-d).into(),
+            ShyValue::Scalar(ShyScalar::Quantity(value, ref unit)) => ShyValue::Scalar(ShyScalar::Quantity(-value, unit.clone())),
             _ => ShyValue::error("cannot negate a non-number".to_string())
         }
     }
 
-    /// Regex matching operator.
-    pub fn matches(left_operand: &Self, right_operand: &Self) -> Self {
+    /// Apply a regex to a string for one of the regex operators.
+    /// The regex is normally compiled along with the expression, but a string holding a pattern is compiled on the spot.
+    fn apply_regex<F>(left_operand: &Self, right_operand: &Self, operator_name: &str, f: F) -> Self
+    where F: Fn(&ShyRegex, &str) -> Self {
         match (left_operand, right_operand) {
+            (ShyValue::Scalar(ShyScalar::String(s)), ShyValue::Scalar(ShyScalar::Regex(regex))) => f(regex, s),
             (ShyValue::Scalar(ShyScalar::String(s)), ShyValue::Scalar(ShyScalar::String(regex_string))) => {
-                match Regex::new(regex_string) {
-                    Ok(regex) => {
-                        regex.is_match(s).into()
-                    },
-                    Err(_) => ShyValue::error(format!("malformed regular expression {}", regex_string))
+                match ShyRegex::new(regex_string) {
+                    Ok(regex) => f(&regex, s),
+                    Err(message) => ShyValue::error(message)
                 }
             } ,
             _ => ShyValue::error(
-                format!("wrong type of arguments for matches operator: {} and {}", 
+                format!("wrong type of arguments for {} operator: {} and {}", 
+                    operator_name,
                     left_operand.type_name(), 
                     right_operand.type_name()))
         }
     }
 
+    /// Regex matching operator.
+    pub fn matches(left_operand: &Self, right_operand: &Self) -> Self {
+        ShyValue::apply_regex(left_operand, right_operand, "matches", |regex, s| regex.is_match(s).into())
+    }
+
     /// Regex matching operator.
     pub fn not_matches(left_operand: &Self, right_operand: &Self) -> Self {
         ShyValue::not(&ShyValue::matches(left_operand, right_operand))
     }
+
+    /// Regex capture operator, as in `m = serial ~> "^(\w+)-(\d+)$"`. 
+    /// Yields the groups of the first match as a Vector (or as an Object if the regex has named groups), or Null if there is no match.
+    pub fn captures(left_operand: &Self, right_operand: &Self) -> Self {
+        ShyValue::apply_regex(left_operand, right_operand, "capture", |regex, s| regex.captures(s))
+    }
+
+    /// Membership operator, as in `code in [3, 7, 9]`, `"ERR" in message` or `key in obj`. 
+    /// True if the left operand equals an item of a Vector, is a substring of a String, or names a property of an Object.
+    pub fn is_in(left_operand: &Self, right_operand: &Self) -> Self {
+        match (left_operand, right_operand) {
+            (ShyValue::Scalar(ShyScalar::Error(_)), _) => left_operand.clone(),
+            (_, ShyValue::Scalar(ShyScalar::Error(_))) => right_operand.clone(),
+            (_, ShyValue::Vector(items)) => items.iter().any(|item| ShyValue::equals(left_operand, item).is_true()).into(),
+            (ShyValue::Scalar(ShyScalar::String(part)), ShyValue::Scalar(ShyScalar::String(whole))) => whole.contains(part.as_str()).into(),
+            (ShyValue::Scalar(ShyScalar::String(key)), ShyValue::Object(obj)) => obj.as_deref().can_get_property(key).into(),
+            _ => ShyValue::incompatible(left_operand, right_operand, "in")
+        }
+    }
+
+    /// Negated membership operator, as in `code not in [3, 7, 9]`.
+    pub fn not_in(left_operand: &Self, right_operand: &Self) -> Self {
+        ShyValue::not(&ShyValue::is_in(left_operand, right_operand))
+    }
+
+    /// Inclusive range operator, as in `x between 10 and 20`. 
+    /// The right operand is a Vector holding the lower and upper bounds.
+    pub fn between(left_operand: &Self, right_operand: &Self) -> Self {
+        match right_operand {
+            ShyValue::Vector(bounds) if bounds.len() == 2 => ShyValue::and(
+                &ShyValue::less_than_or_equal_to(&bounds[0], left_operand), 
+                &ShyValue::less_than_or_equal_to(left_operand, &bounds[1])
+            ),
+            _ => ShyValue::error("between operator requires a lower and an upper bound".to_string())
+        }
+    }
+
+    /// Half-open range operator of a match pattern - true if the left operand is at least the first bound of the right operand, 
+    /// but less than the second, as in the pattern `100..500`, which 500 does not match.
+    pub fn in_range(left_operand: &Self, right_operand: &Self) -> Self {
+        match right_operand {
+            ShyValue::Vector(bounds) if bounds.len() == 2 => ShyValue::and(
+                &ShyValue::less_than_or_equal_to(&bounds[0], left_operand), 
+                &ShyValue::less_than(left_operand, &bounds[1])
+            ),
+            _ => ShyValue::error("range requires a lower and an upper bound".to_string())
+        }
+    }
+
+    /// Convert the value inside brackets (a vector index or property name) into the string form used in property chains.
+    fn index_key(key: &Self) -> Result<String, Self> {
+        match key {
+            ShyValue::Scalar(ShyScalar::String(s)) => Ok(s.clone()),
+            ShyValue::Scalar(ShyScalar::Integer(i)) => Ok(i.to_string()),
+            _ => Err(ShyValue::error(format!("Cannot use a {} as an index", key.type_name())))
+        }
+    }
+
+    /// Member operator - extends a reference to a variable or property chain with a property name or vector index, 
+    /// yielding a PropertyChain that may be loaded or assigned.
+    /// An integer key becomes a ChainLink::Index, so that assigning through it may create a missing vector.
+    pub fn member(container: &Self, key: &Self) -> Self {
+        let link = Self::index_key(key).map(|k| match key {
+            ShyValue::Scalar(ShyScalar::Integer(_)) => ChainLink::Index(k),
+            _ => ChainLink::Field(k)
+        });
+        match (container, link) {
+            (_, Err(error)) => error,
+            (ShyValue::Variable(name), Ok(link)) => ShyValue::PropertyChain(vec![ChainLink::Field(name.clone()), link]),
+            (ShyValue::PropertyChain(path), Ok(link)) => {
+                let mut extended_path = path.clone();
+                extended_path.push(link);
+                ShyValue::PropertyChain(extended_path)
+            },
+            _ => ShyValue::error(format!("Cannot index into a {} by reference", container.type_name()))
+        }
+    }
+
+    /// Index operator - retrieves an element of a Vector by position or a property of an Object by name.
+    pub fn index(container: &Self, key: &Self) -> Self {
+        match Self::index_key(key) {
+            Ok(k) => container.get_safe(&k),
+            Err(error) => error
+        }
+    }
+
+    /// Ternary conditional operator: if the test is truthy, return the second operand, otherwise the third. 
+    /// If the test is an error, return the error.
+    /// Compiled expressions branch around the operand not chosen, so this is only used when all three operands have been evaluated.
+    pub fn ternary(test: &Self, if_true: &Self, if_false: &Self) -> Self {
+        if test.is_error() { test.clone() }
+        else if test.is_truthy() { if_true.clone() }
+        else { if_false.clone() }
+    }
+
+    /// Unit conversion operator - expresses a quantity in another unit that measures the same thing, as in `p to kPa`.
+    /// The unit is given by its name.
+    pub fn convert_to(quantity: &Self, unit_name: &Self) -> Self {
+        match (quantity, unit_name) {
+            (ShyValue::Scalar(ShyScalar::Error(_)), _) => quantity.clone(),
+            (ShyValue::Scalar(ShyScalar::Quantity(value, unit)), ShyValue::Scalar(ShyScalar::String(name))) => {
+                match Unit::lookup(name).ok_or_else(|| format!("Unknown unit '{}'", name)).and_then(|target| unit.convert(*value, &target).map(|converted| (converted, target))) {
+                    Ok((converted, target)) => ShyValue::Scalar(ShyScalar::Quantity(converted, target)),
+                    Err(message) => ShyValue::error(message)
+                }
+            },
+            _ => ShyValue::error(format!("Only a quantity with a unit of measure may be converted, not a {}", quantity.type_name()))
+        }
+    }
+
+    /// Property operator - sets a property of an object under construction by an object literal, then returns the object.
+    /// If the property value is an error, return the error instead.
+    pub fn property(object: &Self, key: &Self, property_value: &Self) -> Self {
+        if property_value.is_error() {
+            return property_value.clone();
+        }
+        match (object, key) {
+            (ShyValue::Object(obj), ShyValue::Scalar(ShyScalar::String(name))) => {
+                obj.as_deref_mut().set(name, property_value.shallow_clone());
+                object.shallow_clone()
+            },
+            (ShyValue::Scalar(ShyScalar::Error(_)), _) => object.clone(),
+            _ => ShyValue::error(
+                format!("wrong type of arguments for property operator: {} and {}", 
+                    object.type_name(), 
+                    key.type_name()))
+        }
+    }
 }
 
 // Conversions from basic types to ShyValue
@@ -1026,6 +1659,9 @@ impl From<bool> for ShyValue { fn from(x: bool) -> Self { ShyValue::Scalar(ShySc
 impl From<&bool> for ShyValue { fn from(x: &bool) -> Self { ShyValue::Scalar(ShyScalar::Boolean(*x)) } }
 impl From<String> for ShyValue { fn from(s: String) -> Self { ShyValue::Scalar(ShyScalar::String(s.clone())) } }
 impl From<&str> for ShyValue { fn from(s: &str) -> Self { ShyValue::Scalar(ShyScalar::String(s.to_string())) } }
+impl From<Decimal> for ShyValue { fn from(d: Decimal) -> Self { ShyValue::Scalar(ShyScalar::Decimal(d)) } }
+impl From<DateTime<Utc>> for ShyValue { fn from(d: DateTime<Utc>) -> Self { ShyValue::Scalar(ShyScalar::DateTime(d)) } }
+impl From<Duration> for ShyValue { fn from(d: Duration) -> Self { ShyValue::Scalar(ShyScalar::Duration(d)) } }
 
 
 //..................................................................
@@ -1035,11 +1671,25 @@ impl From<&str> for ShyValue { fn from(s: &str) -> Self { ShyValue::Scalar(ShySc
 ///   - The None value is for error processing.
 ///   - The OperatorWithValue (used for Functions and Power) will be split into 
 ///     a Value token (the Function name) and an Operator token (the function invocation).
+///   - The Branch variant is added by the ShuntingYard. It holds an operator that may jump 
+///     to another position in the postfix order, and the index of that position.
+///   - The FunctionDefinition variant is also added by the ShuntingYard, in place of a `fn` declaration. 
+///     When executed, it registers the function in the context.
+///   - The LoadSlot, LoadChain and StoreSlot variants appear only once an Expression is bound to a context.
+///     They access a variable by its slot in the context's Frame instead of by name.
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum ShyToken{
     Value(ShyValue),
     Operator(ShyOperator),
     OperatorWithValue(ShyOperator, ShyValue),
+    Branch(ShyOperator, usize),
+    FunctionDefinition(UserFunction),
+    /// Load the variable in the slot, replacing a Variable followed by Load.
+    LoadSlot(usize),
+    /// Load the property chain whose first variable is in the slot, replacing a PropertyChain followed by Load.
+    LoadChain(usize, Vec<ChainLink>),
+    /// Store the value on top of the stack in the slot, leaving it there, replacing an `=` assignment to a Variable.
+    StoreSlot(usize),
     Error,
     None
 }
@@ -1085,6 +1735,33 @@ impl From<ShyOperator> for ShyToken {
 
 //..................................................................
 
+/// A ShyToken paired with the span of the source text from which it was compiled.
+/// A token that the ShuntingYard adds gets the span of the token that caused it to be added.
+/// 
+/// Only the token is serialized. A deserialized token has an empty span, so errors at it cannot be located.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(transparent)]
+pub struct SpannedToken {
+    pub token: ShyToken,
+    #[serde(skip)]
+    pub span: Span
+}
+
+impl SpannedToken {
+    pub fn new(token: ShyToken, span: Span) -> Self {
+        SpannedToken { token, span }
+    }
+}
+
+/// A token with an empty span, for one not compiled from source text.
+impl From<ShyToken> for SpannedToken {
+    fn from(token: ShyToken) -> Self {
+        SpannedToken { token, span: Span::default() }
+    }
+}
+
+//..................................................................
+
 #[cfg(test)]
 /// Tests of the ShyOperator, ShyToken, and ShyValue.
 mod tests {
@@ -1256,7 +1933,44 @@ mod tests {
         unary_operator_test(&1.into(), &1.into(), &ShyValue::factorial);
         unary_operator_test(&4.into(), &24.into(), &ShyValue::factorial);
         unary_operator_test(&5.0.into(), &120.into(), &ShyValue::factorial);
-        assert!(&ShyValue::factorial(&21.0.into()).is_error());
+        unary_operator_test(&20.into(), &2432902008176640000_i64.into(), &ShyValue::factorial);
+        assert!(ShyValue::checked_factorial(&21.into()).is_err());
+        unary_operator_test(&21.0.into(), &51090942171709440000.0.into(), &ShyValue::factorial);
+        assert!(&ShyValue::factorial(&171.0.into()).is_error());
+        assert!(&ShyValue::factorial(&(-1).into()).is_error());
+    }
+
+    #[test]
+    /// Integer arithmetic at the limits of an i64 either succeeds exactly or reports an overflow, 
+    /// which with_overflow may promote to a Rational result.
+    fn integer_overflow_at_boundaries() {
+        let max: ShyValue = i64::MAX.into();
+        let min: ShyValue = i64::MIN.into();
+        // For each operator: operands whose result just fits, that result, operands whose result just overflows, and the promoted result.
+        let cases: Vec<(&str, &dyn Fn(&ShyValue, &ShyValue) -> Result<ShyValue, IntegerOverflowError>, (i64, i64), i64, (i64, i64), f64)> = vec![
+            ("add", &ShyValue::checked_add, (i64::MAX - 1, 1), i64::MAX, (i64::MAX, 1), i64::MAX as f64 + 1.0),
+            ("subtract", &ShyValue::checked_subtract, (i64::MIN + 1, 1), i64::MIN, (i64::MIN, 1), i64::MIN as f64 - 1.0),
+            ("multiply", &ShyValue::checked_multiply, (i64::MIN / 2, 2), i64::MIN, (i64::MAX / 2 + 1, 2), (i64::MAX / 2 + 1) as f64 * 2.0),
+            ("power", &ShyValue::checked_power, (-2, 63), i64::MIN, (2, 63), 2_f64.powi(63))
+        ];
+        for (name, op, (left, right), result, (over_left, over_right), promoted) in cases {
+            let (over_left, over_right): (ShyValue, ShyValue) = (over_left.into(), over_right.into());
+            asserting(&format!("{} within range", name)).that(&op(&left.into(), &right.into())).is_equal_to(Ok(ShyValue::from(result)));
+            let overflow = op(&over_left, &over_right);
+            asserting(&format!("{} overflows", name)).that(&overflow.is_err()).is_true();
+            asserting(&format!("{} reports overflow", name))
+                .that(&ShyValue::with_overflow(&over_left, &over_right, IntegerOverflow::Error, op)).is_equal_to(overflow.unwrap_err().into_error());
+            asserting(&format!("{} promotes", name))
+                .that(&ShyValue::with_overflow(&over_left, &over_right, IntegerOverflow::Promote, op)).is_equal_to(ShyValue::from(promoted));
+        }
+        asserting("negate within range").that(&ShyValue::prefix_minus(&max)).is_equal_to(ShyValue::from(i64::MIN + 1));
+        assert!(ShyValue::checked_prefix_minus(&min).is_err());
+        asserting("negation promotes")
+            .that(&ShyValue::with_overflow(&min, &min, IntegerOverflow::Promote, |value, _| ShyValue::checked_prefix_minus(value))).is_equal_to(ShyValue::from(-(i64::MIN as f64)));
+        asserting("huge power of one").that(&ShyValue::power(&(-1).into(), &5_000_000_001_i64.into())).is_equal_to(ShyValue::from(-1));
+        assert!(ShyValue::checked_power(&2.into(), &5_000_000_001_i64.into()).is_err());
+        asserting("modulo at the limit").that(&ShyValue::modulo(&min, &(-1).into())).is_equal_to(ShyValue::from(0));
+        assert!(&ShyValue::modulo(&5.into(), &0.into()).is_error());
     }
 
     #[test]
@@ -1345,6 +2059,13 @@ mod tests {
         binary_operator_test(&1.into(), &2.into(), &true.into(), &ShyValue::less_than);
         binary_operator_test(&4.5.into(), &4.into(), &false.into(), &ShyValue::less_than);
         binary_operator_test(&7.14.into(), &7.15.into(), &true.into(), &ShyValue::less_than);
+
+        // Decimals compare with rationals as floating point, and not at all with NaN
+        let decimal = ShyValue::Scalar(ShyScalar::Decimal(Decimal::parse("1.5").unwrap()));
+        binary_operator_test(&decimal, &1.75.into(), &true.into(), &ShyValue::less_than);
+        binary_operator_test(&1.75.into(), &decimal, &false.into(), &ShyValue::less_than);
+        asserting("NaN and a decimal are incomparable").that(&decimal.partial_cmp(&f64::NAN.into())).is_equal_to(None);
+        asserting("NaN is not less than a decimal").that(&ShyValue::less_than(&f64::NAN.into(), &decimal).is_error()).is_equal_to(true);
     }
 
     #[test]
@@ -1492,7 +2213,7 @@ mod tests {
         let ab = ShyValue::comma(&a, &b);
         match ab {
             ShyValue::Vector(v) => {
-                if let &[ShyScalar::Integer(aa), ShyScalar::Rational(bb)] = &*v {
+                if let &[ShyValue::Scalar(ShyScalar::Integer(aa)), ShyValue::Scalar(ShyScalar::Rational(bb))] = &*v {
                     asserting("First operand").that(&aa).is_equal_to(5_i64);
                     asserting("Second operand").that(&bb).is_equal_to(2.75_f64);
                 } else {
@@ -1503,6 +2224,16 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Test append operator, which must not flatten a vector being appended.
+    fn shyvalue_append() {
+        let ab = ShyValue::comma(&5.into(), &ShyValue::Vector(vec![]));
+        let abc = ShyValue::append(&ab, &ShyValue::Vector(vec![1.into()]));
+        let expected = ShyValue::Vector(vec![5.into(), ShyValue::Vector(vec![]), ShyValue::Vector(vec![1.into()])]);
+        asserting("Appended vector").that(&abc).is_equal_to(&expected);
+        assert!(ShyValue::append(&5.into(), &6.into()).is_error());
+    }
+
     #[test]
     /// Test prefix minus operator.
     fn shyvalue_prefix_minus() {
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+
+//..................................................................
+
+// Parsing and formatting of date/time and duration values, used by ShyScalar::DateTime and ShyScalar::Duration.
+//
+//   - Date/time literals are written after an at-sign in ISO-8601 form: @2024-01-05T10:00Z, @2024-01-05T10:00:30.5+02:00, @2024-01-05.
+//     If no time zone is given, UTC is assumed. All date/times are converted to UTC.
+//   - Duration literals are a number followed by a unit, with or without a space: 250ms, 30s, 5 min, 2h, 1.5 day.
+//     These suffixes belong to durations alone; the units module has no units of time, but counts a duration as seconds 
+//     when it multiplies or divides a quantity.
+
+/// Units that may follow a number to make a duration literal, with the number of nanoseconds in each.
+const DURATION_UNITS: [(&str, i64); 5] = [
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("min", 60_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("day", 86_400_000_000_000)
+];
+
+/// True if the given name is a unit that may follow a number to make a duration literal.
+pub fn is_duration_unit(name: &str) -> bool {
+    DURATION_UNITS.iter().any(|(unit, _)| *unit == name)
+}
+
+/// Parse an ISO-8601 date/time (with or without seconds, fractional seconds or time zone) or a date, converting it to UTC.
+pub fn parse_date_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(text) {
+        return Some(date_time.with_timezone(&Utc));
+    }
+    let with_offset = match text.strip_suffix('Z').or_else(|| text.strip_suffix('z')) {
+        Some(local_part) => format!("{}+00:00", local_part),
+        None => text.to_string()
+    };
+    for format in ["%Y-%m-%dT%H:%M:%S%.f%:z", "%Y-%m-%dT%H:%M%:z"].iter() {
+        if let Ok(date_time) = DateTime::parse_from_str(&with_offset, format) {
+            return Some(date_time.with_timezone(&Utc));
+        }
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"].iter() {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(DateTime::from_utc(naive, Utc));
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(|date| DateTime::from_utc(date.and_hms(0, 0, 0), Utc))
+}
+
+/// Parse a duration literal, which is an optionally signed integer or decimal number followed by a unit (ms, s, min, h or day).
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let unit_start = text.find(|c: char| c.is_alphabetic())?;
+    let (amount, unit) = text.split_at(unit_start);
+    let (_, nanoseconds_per_unit) = DURATION_UNITS.iter().find(|(name, _)| *name == unit)?;
+    match amount.parse::<i64>() {
+        Ok(whole_amount) => whole_amount.checked_mul(*nanoseconds_per_unit).map(Duration::nanoseconds),
+        Err(_) => amount.parse::<f64>().ok().and_then(|fractional_amount| scale_duration(&Duration::nanoseconds(*nanoseconds_per_unit), fractional_amount))
+    }
+}
+
+/// Multiply a duration by a factor, to the nearest nanosecond. Return None if the result is too large.
+pub fn scale_duration(duration: &Duration, factor: f64) -> Option<Duration> {
+    let nanoseconds = duration.num_nanoseconds()? as f64 * factor;
+    if nanoseconds.is_finite() && nanoseconds.abs() < i64::MAX as f64 { Some(Duration::nanoseconds(nanoseconds.round() as i64)) }
+    else { None }
+}
+
+/// Divide one duration by another, giving their ratio. Return None if either is too large or the divisor is zero.
+pub fn duration_ratio(numerator: &Duration, denominator: &Duration) -> Option<f64> {
+    let denominator_nanoseconds = denominator.num_nanoseconds()?;
+    if denominator_nanoseconds == 0 { return None; }
+    Some(numerator.num_nanoseconds()? as f64 / denominator_nanoseconds as f64)
+}
+
+/// Express a duration in seconds, with a fractional part, as when it divides or multiplies a quantity.
+pub fn duration_seconds(duration: &Duration) -> f64 {
+    match duration.num_nanoseconds() {
+        Some(nanoseconds) => nanoseconds as f64 / 1e9,
+        None => duration.num_milliseconds() as f64 / 1e3
+    }
+}
+
+/// Format a date/time as an RFC3339 string in UTC, such as "2024-01-05T10:00:00+00:00".
+pub fn format_date_time(date_time: &DateTime<Utc>) -> String {
+    date_time.to_rfc3339()
+}
+
+/// Format a duration using the same syntax as a duration literal, in the largest unit that holds it exactly, such as "5min".
+/// Durations that are not a whole number of milliseconds are given as fractional milliseconds.
+pub fn format_duration(duration: &Duration) -> String {
+    let nanoseconds = match duration.num_nanoseconds() {
+        Some(nanoseconds) => nanoseconds,
+        None => return format!("{}s", duration.num_seconds())
+    };
+    if nanoseconds == 0 {
+        return "0s".to_string();
+    }
+    match DURATION_UNITS.iter().rev().find(|(_, unit_nanoseconds)| nanoseconds % unit_nanoseconds == 0) {
+        Some((name, unit_nanoseconds)) => format!("{}{}", nanoseconds / unit_nanoseconds, name),
+        None => format!("{}ms", nanoseconds as f64 / 1_000_000.0)
+    }
+}
+
+/// Parse a date/time that must be in strict RFC3339 form, such as "2024-01-05T10:00:00+00:00", converting it to UTC.
+pub fn parse_rfc3339(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text).ok().map(|date_time| date_time.with_timezone(&Utc))
+}
+
+/// Serialize and deserialize a ShyScalar::DateTime as an RFC3339 string.
+pub mod date_time_serde {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(date_time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_date_time(date_time))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse_date_time(&text).ok_or_else(|| D::Error::custom(format!("Invalid date/time '{}'", text)))
+    }
+}
+
+/// Serialize and deserialize a ShyScalar::Duration as a whole number of nanoseconds.
+pub mod duration_serde {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::ser::Error;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration.num_nanoseconds() {
+            Some(nanoseconds) => serializer.serialize_i64(nanoseconds),
+            None => Err(S::Error::custom("Duration is too large to serialize"))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::nanoseconds(i64::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    /// Verify that the supported forms of date/time literal are parsed and converted to UTC.
+    fn parse_date_times() {
+        let ten_am = Utc.ymd(2024, 1, 5).and_hms(10, 0, 0);
+        for text in vec!["2024-01-05T10:00Z", "2024-01-05T10:00:00Z", "2024-01-05T12:00+02:00", "2024-01-05T10:00", "2024-01-05T10:00:00.000"].iter() {
+            asserting(text).that(&parse_date_time(text)).is_equal_to(&Some(ten_am));
+        }
+        asserting("date only").that(&parse_date_time("2024-01-05")).is_equal_to(&Some(Utc.ymd(2024, 1, 5).and_hms(0, 0, 0)));
+        asserting("fractional seconds").that(&parse_date_time("2024-01-05T10:00:00.5Z")).is_equal_to(&Some(Utc.ymd(2024, 1, 5).and_hms_milli(10, 0, 0, 500)));
+        asserting("invalid month").that(&parse_date_time("2024-13-05")).is_equal_to(&None);
+        asserting("trailing junk").that(&parse_date_time("2024-01-05T10:00Z-5min")).is_equal_to(&None);
+    }
+
+    #[test]
+    /// Verify that each duration unit is recognized, with whole, fractional and negative amounts.
+    fn parse_durations() {
+        asserting("ms").that(&parse_duration("250ms")).is_equal_to(&Some(Duration::milliseconds(250)));
+        asserting("s").that(&parse_duration("30s")).is_equal_to(&Some(Duration::seconds(30)));
+        asserting("min").that(&parse_duration("5min")).is_equal_to(&Some(Duration::minutes(5)));
+        asserting("h").that(&parse_duration("1.5h")).is_equal_to(&Some(Duration::minutes(90)));
+        asserting("day").that(&parse_duration("-2day")).is_equal_to(&Some(Duration::days(-2)));
+        asserting("unknown unit").that(&parse_duration("5m")).is_equal_to(&None);
+        asserting("no amount").that(&parse_duration("min")).is_equal_to(&None);
+    }
+
+    #[test]
+    /// Verify that durations are formatted in their largest exact unit and can be parsed back.
+    fn format_durations() {
+        for (duration, expected) in vec![(Duration::minutes(90), "90min"), (Duration::hours(48), "2day"), (Duration::zero(), "0s"), 
+                                         (Duration::seconds(-30), "-30s"), (Duration::microseconds(1500), "1.5ms")].iter() {
+            asserting(expected).that(&format_duration(duration)).is_equal_to(&expected.to_string());
+            asserting("round trip").that(&parse_duration(&format_duration(duration))).is_equal_to(&Some(*duration));
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result};
+use serde::{Serialize, Deserialize};
+
+//..................................................................
+
+// Physical units of measure, used by ShyScalar::Quantity.
+//
+//   - A quantity literal is a number followed by the name of a unit, with or without a space: 15 psi, 3.2 m, 70°F.
+//   - Every unit has a dimension, expressed as powers of the base dimensions (length, mass, time and temperature).
+//     Quantities may only be added, subtracted or compared if they have the same dimension.
+//   - Every unit converts to the SI unit of its dimension with a scale factor and an offset (the offset is only used by temperatures).
+//   - Durations (like 5min or 5 min) are not quantities; they are handled by the temporal module, which owns the time suffixes.
+//     When a duration multiplies or divides a quantity, it counts as a quantity in seconds, as in 10 m / 2 s, 
+//     and a product or quotient of quantities whose unit is a pure time becomes a duration again.
+//   - The symbol of a compound unit is always written the same way: the units of the numerator with their powers as superscripts, 
+//     separated by a middle dot, then a slash and the units of the denominator, as in kg·m/s². 
+//     So m * m and m² both give m².
+
+/// Number of base dimensions: length, mass, time and temperature.
+const BASE_DIMENSIONS: usize = 4;
+
+/// Superscript digits, for writing the powers of units in a compound symbol.
+const SUPERSCRIPTS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// A unit of measure, such as psi or °C, or a compound unit made by multiplying or dividing quantities, such as psi/m.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Unit {
+    /// Name of the unit, as written after the number in a literal.
+    pub symbol: String,
+
+    /// Powers of length, mass, time and temperature.
+    pub dimension: [i8; BASE_DIMENSIONS],
+
+    /// Multiply by this to convert a value in this unit to SI (after adding the offset, if any).
+    pub scale: f64,
+
+    /// Offset of this unit's zero from the SI unit's zero, in SI units. Only nonzero for °C and °F.
+    pub offset: f64
+}
+
+lazy_static! {
+    static ref UNITS: HashMap<&'static str, Unit> = {
+        let length = [1, 0, 0, 0];
+        let mass = [0, 1, 0, 0];
+        let temperature = [0, 0, 0, 1];
+        let volume = [3, 0, 0, 0];
+        let pressure = [-1, 1, -2, 0];
+        let mut units = HashMap::new();
+        for (symbol, dimension, scale, offset) in vec![
+            ("m", length, 1.0, 0.0),
+            ("km", length, 1000.0, 0.0),
+            ("cm", length, 0.01, 0.0),
+            ("mm", length, 0.001, 0.0),
+            ("ft", length, 0.3048, 0.0),
+            ("inch", length, 0.0254, 0.0),
+            ("mi", length, 1609.344, 0.0),
+            ("kg", mass, 1.0, 0.0),
+            ("g", mass, 0.001, 0.0),
+            ("lb", mass, 0.45359237, 0.0),
+            ("L", volume, 0.001, 0.0),
+            ("bbl", volume, 0.158987294928, 0.0),
+            ("gal", volume, 0.003785411784, 0.0),
+            ("Pa", pressure, 1.0, 0.0),
+            ("kPa", pressure, 1000.0, 0.0),
+            ("MPa", pressure, 1_000_000.0, 0.0),
+            ("bar", pressure, 100_000.0, 0.0),
+            ("atm", pressure, 101_325.0, 0.0),
+            ("psi", pressure, 6894.757293168, 0.0),
+            ("K", temperature, 1.0, 0.0),
+            ("°C", temperature, 1.0, 273.15),
+            ("degC", temperature, 1.0, 273.15),
+            ("°F", temperature, 5.0 / 9.0, 459.67 * 5.0 / 9.0),
+            ("degF", temperature, 5.0 / 9.0, 459.67 * 5.0 / 9.0)
+        ] {
+            units.insert(symbol, Unit { symbol: symbol.to_string(), dimension, scale, offset });
+        }
+        units
+    };
+}
+
+impl Unit {
+    /// Find a unit by its symbol.
+    pub fn lookup(symbol: &str) -> Option<Unit> {
+        UNITS.get(symbol).cloned()
+    }
+
+    /// The unit of a pure number, used as the numerator when a number is divided by a quantity.
+    pub fn dimensionless() -> Unit {
+        Unit { symbol: "1".to_string(), dimension: [0; BASE_DIMENSIONS], scale: 1.0, offset: 0.0 }
+    }
+
+    /// The unit of a duration that multiplies or divides a quantity.
+    pub fn seconds() -> Unit {
+        Unit { symbol: "s".to_string(), dimension: [0, 0, 1, 0], scale: 1.0, offset: 0.0 }
+    }
+
+    /// True if the given name is the symbol of a known unit.
+    pub fn is_unit(symbol: &str) -> bool {
+        UNITS.contains_key(symbol)
+    }
+
+    /// True if the two units measure the same kind of thing, so that they may be added, subtracted, compared or converted.
+    pub fn is_compatible(&self, other: &Unit) -> bool {
+        self.dimension == other.dimension
+    }
+
+    /// True if all the dimensions cancelled out, as in m/ft.
+    pub fn is_dimensionless(&self) -> bool {
+        self.dimension.iter().all(|power| *power == 0)
+    }
+
+    /// True if the unit measures a span of time, as in m·s/m, so that a quantity in it is better expressed as a duration.
+    pub fn is_time(&self) -> bool {
+        self.dimension == [0, 0, 1, 0]
+    }
+
+    /// Convert a value in this unit to the SI unit of the same dimension.
+    pub fn to_si(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+
+    /// Convert a value in the SI unit of this unit's dimension to this unit.
+    pub fn from_si(&self, value: f64) -> f64 {
+        (value - self.offset) / self.scale
+    }
+
+    /// Convert a value from this unit to another unit of the same dimension.
+    pub fn convert(&self, value: f64, target: &Unit) -> std::result::Result<f64, String> {
+        if !self.is_compatible(target) {
+            return Err(format!("Cannot convert {} to {}, which measures something different", self.symbol, target.symbol));
+        }
+        Ok(target.from_si(self.to_si(value)))
+    }
+
+    /// Combine two units by multiplication (when exponent is 1) or division (when exponent is -1).
+    /// Units with an offset (like °C) cannot be combined, because their zero is not a true zero.
+    pub fn combine(&self, other: &Unit, exponent: i8) -> std::result::Result<Unit, String> {
+        if self.offset != 0.0 || other.offset != 0.0 {
+            return Err(format!("Cannot multiply or divide quantities in {} and {}", self.symbol, other.symbol));
+        }
+        let mut dimension = self.dimension;
+        for (power, other_power) in dimension.iter_mut().zip(other.dimension.iter()) {
+            *power += other_power * exponent;
+        }
+        let mut factors = self.factors();
+        factors.extend(other.factors().into_iter().map(|(symbol, power)| (symbol, power * exponent as i32)));
+        Ok(Unit {
+            symbol: Self::compound_symbol(&factors),
+            dimension,
+            scale: self.scale * other.scale.powi(exponent as i32),
+            offset: 0.0
+        })
+    }
+
+    /// Raise a unit to an integer power, as in m² for an area.
+    pub fn power(&self, exponent: i32) -> std::result::Result<Unit, String> {
+        if self.offset != 0.0 {
+            return Err(format!("Cannot raise a quantity in {} to a power", self.symbol));
+        }
+        let mut dimension = self.dimension;
+        for power in dimension.iter_mut() {
+            *power *= exponent as i8;
+        }
+        let factors: Vec<(String, i32)> = self.factors().into_iter().map(|(symbol, power)| (symbol, power * exponent)).collect();
+        Ok(Unit { symbol: Self::compound_symbol(&factors), dimension, scale: self.scale.powi(exponent), offset: 0.0 })
+    }
+
+    /// Split the symbol of a unit into the symbols of the simple units it is made of, each with its power,
+    /// as kg·m/s² becomes kg¹, m¹ and s⁻².
+    fn factors(&self) -> Vec<(String, i32)> {
+        if self.symbol == "1" {
+            return vec![];
+        }
+        let (numerator, denominator) = match self.symbol.find('/') {
+            Some(slash) => (&self.symbol[..slash], &self.symbol[slash + 1..]),
+            None => (&self.symbol[..], "")
+        };
+        let mut factors = Vec::new();
+        for (part, sign) in [(numerator, 1), (denominator, -1)].iter() {
+            for factor in part.split('·').filter(|factor| !factor.is_empty() && *factor != "1") {
+                let symbol = factor.trim_end_matches(|c| SUPERSCRIPTS.contains(&c));
+                let power = factor[symbol.len()..].chars()
+                    .filter_map(|c| SUPERSCRIPTS.iter().position(|digit| *digit == c))
+                    .fold(0, |power, digit| power * 10 + digit as i32);
+                factors.push((symbol.to_string(), *sign * power.max(1)));
+            }
+        }
+        factors
+    }
+
+    /// Write the symbol of a compound unit in the one canonical form, combining the powers of the same unit 
+    /// and leaving out those that cancel, as in m·s/m becoming s. If nothing remains, the symbol is 1.
+    fn compound_symbol(factors: &[(String, i32)]) -> String {
+        let mut combined: Vec<(String, i32)> = Vec::new();
+        for (symbol, power) in factors {
+            match combined.iter_mut().find(|(existing, _)| existing == symbol) {
+                Some((_, existing_power)) => *existing_power += power,
+                None => combined.push((symbol.clone(), *power))
+            }
+        }
+        let write = |sign: i32| -> Vec<String> {
+            combined.iter().filter(|(_, power)| power * sign > 0).map(|(symbol, power)| {
+                let power = power.abs();
+                if power == 1 { symbol.clone() }
+                else { format!("{}{}", symbol, power.to_string().chars().map(|digit| SUPERSCRIPTS[digit.to_digit(10).unwrap() as usize]).collect::<String>()) }
+            }).collect()
+        };
+        let (numerator, denominator) = (write(1), write(-1));
+        match (numerator.is_empty(), denominator.is_empty()) {
+            (true, true) => "1".to_string(),
+            (_, true) => numerator.join("·"),
+            (true, _) => format!("1/{}", denominator.join("·")),
+            _ => format!("{}/{}", numerator.join("·"), denominator.join("·"))
+        }
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}", self.symbol)
+    }
+}
+
+/// Parse a quantity literal such as "15 psi" or "3.2m" into its value and unit.
+pub fn parse_quantity(text: &str) -> Option<(f64, Unit)> {
+    let unit_start = text.find(|c: char| c.is_alphabetic() || c == '°')?;
+    let (amount, symbol) = text.split_at(unit_start);
+    let unit = Unit::lookup(symbol)?;
+    let value = amount.trim().parse::<f64>().ok()?;
+    Some((value, unit))
+}
+
+/// Format a quantity with a space between its value and unit, as in "15 psi".
+pub fn format_quantity(value: f64, unit: &Unit) -> String {
+    format!("{} {}", value, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    #[test]
+    /// Verify conversions between units, including temperatures, which have an offset.
+    fn convert_units() {
+        let psi = Unit::lookup("psi").unwrap();
+        let kpa = Unit::lookup("kPa").unwrap();
+        let meters = Unit::lookup("m").unwrap();
+        asserting("psi to kPa").that(&(psi.convert(100.0, &kpa).unwrap() - 689.4757293168).abs()).is_less_than(1e-9);
+        asserting("°F to °C").that(&(Unit::lookup("°F").unwrap().convert(212.0, &Unit::lookup("°C").unwrap()).unwrap() - 100.0).abs()).is_less_than(1e-9);
+        assert!(psi.convert(1.0, &meters).is_err());
+    }
+
+    #[test]
+    /// Verify that combining units combines their dimensions, and that units with offsets cannot be combined.
+    fn combine_units() {
+        let meters = Unit::lookup("m").unwrap();
+        let feet = Unit::lookup("ft").unwrap();
+        let area = meters.combine(&feet, 1).unwrap();
+        asserting("area dimension").that(&area.dimension).is_equal_to(&[2, 0, 0, 0]);
+        asserting("area symbol").that(&area.symbol).is_equal_to(&"m·ft".to_string());
+        assert!(meters.combine(&feet, -1).unwrap().is_dimensionless());
+        assert!(meters.combine(&Unit::lookup("°C").unwrap(), 1).is_err());
+        asserting("cube").that(&meters.power(3).unwrap().dimension).is_equal_to(&Unit::lookup("L").unwrap().dimension);
+    }
+
+    #[test]
+    /// Verify that a compound unit has one symbol however it was made.
+    fn canonical_symbols() {
+        let meters = Unit::lookup("m").unwrap();
+        let seconds = Unit::seconds();
+        let kilograms = Unit::lookup("kg").unwrap();
+        let squared = meters.combine(&meters, 1).unwrap();
+        asserting("m * m").that(&squared.symbol).is_equal_to("m²".to_string());
+        asserting("m * m equals m²").that(&squared).is_equal_to(meters.power(2).unwrap());
+        let acceleration = meters.combine(&seconds, -1).unwrap().combine(&seconds, -1).unwrap();
+        asserting("m/s/s").that(&acceleration.symbol).is_equal_to("m/s²".to_string());
+        asserting("kg·m/s²").that(&kilograms.combine(&acceleration, 1).unwrap().symbol).is_equal_to("kg·m/s²".to_string());
+        asserting("per second").that(&seconds.power(-1).unwrap().symbol).is_equal_to("1/s".to_string());
+        asserting("cancelled").that(&acceleration.combine(&seconds, 1).unwrap().combine(&seconds, 1).unwrap().symbol).is_equal_to("m".to_string());
+        asserting("twelfth power").that(&meters.power(12).unwrap().symbol).is_equal_to("m¹²".to_string());
+    }
+
+    #[test]
+    fn parse_quantities() {
+        asserting("with space").that(&parse_quantity("15 psi")).is_equal_to(&Some((15.0, Unit::lookup("psi").unwrap())));
+        asserting("attached").that(&parse_quantity("-3.2m")).is_equal_to(&Some((-3.2, Unit::lookup("m").unwrap())));
+        asserting("degrees").that(&parse_quantity("70°F")).is_equal_to(&Some((70.0, Unit::lookup("°F").unwrap())));
+        asserting("unknown unit").that(&parse_quantity("5 furlongs")).is_equal_to(&None);
+    }
+}
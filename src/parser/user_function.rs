@@ -0,0 +1,74 @@
+use serde::{Serialize, Deserialize};
+
+use super::shy_token::ShyValue;
+use super::expression::Expression;
+use super::execution_context::ExecutionContext;
+
+//..................................................................
+
+// Functions declared by rule authors inside an expression, as in `fn c_to_f(c) = c * 9/5 + 32`.
+//
+//   - The declaration must begin a statement. The body extends to the next semicolon (or the end of the expression).
+//   - The body is compiled once, when the declaring expression is compiled.
+//   - Executing the declaration registers the function in the ExecutionContext, after which any expression
+//     executed against that context may call it. A RuleSet registers the functions declared by all its Rules before executing any Rule.
+//   - When called, the parameters are bound in a local scope that hides any variables of the same name,
+//     which are restored when the call returns.
+//   - A call must pass exactly one argument per parameter. A vector is a single argument, so add([1, 2]) does not call add(1, 2).
+
+/// How deeply user functions may call one another (or themselves) before the call is abandoned as a runaway recursion.
+pub const MAX_CALL_DEPTH: usize = 100;
+
+/// A function declared in an expression, with its body compiled into an Expression.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserFunction {
+    pub name: String,
+
+    /// Names of the parameters, which become variables in the local scope of the body.
+    pub parameters: Vec<String>,
+
+    pub body: Expression<'static>
+}
+
+/// Functions are equal if their bodies compile to the same tokens, wherever in the source those tokens were read.
+impl PartialEq for UserFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.parameters == other.parameters 
+            && self.body.postfix_order.iter().map(|spanned| &spanned.token).eq(other.body.postfix_order.iter().map(|spanned| &spanned.token))
+    }
+}
+
+impl UserFunction {
+    pub fn new(name: String, parameters: Vec<String>, body: Expression<'static>) -> Self {
+        UserFunction { name, parameters, body }
+    }
+
+    /// Pair each parameter with its argument, or return an error if the number of arguments does not match.
+    fn bind(&self, args: &[ShyValue]) -> Result<Vec<(String, ShyValue)>, ShyValue> {
+        if args.len() != self.parameters.len() {
+            return Err(ShyValue::error(format!("Function {} expects {} arguments but was given {}", self.name, self.parameters.len(), args.len())));
+        }
+        Ok(self.parameters.iter().cloned().zip(args.iter().map(|arg| arg.shallow_clone())).collect())
+    }
+
+    /// Execute the body with the parameters bound to the arguments in a new local scope.
+    /// The applicability of the calling expression is not affected by the call.
+    pub fn call<'a>(&self, args: &[ShyValue], context: &mut ExecutionContext<'a>) -> ShyValue {
+        if context.call_depth() >= MAX_CALL_DEPTH {
+            return ShyValue::error(format!("Function {} exceeded the maximum call depth of {}", self.name, MAX_CALL_DEPTH));
+        }
+        let bindings = match self.bind(args) {
+            Ok(bindings) => bindings,
+            Err(error) => return error
+        };
+        let was_applicable = context.is_applicable;
+        context.push_scope(bindings);
+        let result = self.body.exec(context);
+        context.pop_scope();
+        context.is_applicable = was_applicable;
+        match result {
+            Ok(value) => value,
+            Err(message) => ShyValue::error(format!("Function {} failed: {}", self.name, message))
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Serialize, Deserialize};
 use crate::parser::expression::Expression;
 use crate::parser::expression::Expressive;
+use crate::parser::shy_error::ShyError;
 
 use crate::parser::execution_context::ExecutionContext;
 pub mod ruleset;
@@ -66,6 +67,11 @@ pub struct Rule<'a> {
     /// Optional rule category. 
     pub category : Option<String>,
 
+    /// Number of lines of the `RuleSet` text that precede the expression, if the `Rule` was read from a larger text. 
+    /// Errors in the expression are reported relative to that larger text.
+    #[serde(default)]
+    pub line_offset : usize,
+
     /// Required expression to be evaluated for this rule.
     ///
     /// The expression should return a boolean for Predicate type rules, but may return any value for Property type rules. 
@@ -108,10 +114,23 @@ impl<'a, 'b : 'a> Rule<'a> {
             description : description_to_use,
             rule_type : rule_type_to_use,
             category : category_to_use,
+            line_offset : 0,
             expression : expression_to_use
         }
     }
 
+    /// The errors that prevented the expression from compiling, if any, 
+    /// with their lines counted from the start of the text from which the `Rule` was read.
+    pub fn compile_errors(&self) -> Vec<ShyError> {
+        self.expression.compile_errors().iter().map(|error| error.clone().shift_lines(self.line_offset)).collect()
+    }
+
+    /// Warnings about the expression, such as a comparison of the result of another comparison,
+    /// with their lines counted from the start of the text from which the `Rule` was read.
+    pub fn compile_warnings(&self) -> Vec<ShyError> {
+        self.expression.compile_warnings.iter().map(|warning| warning.clone().shift_lines(self.line_offset)).collect()
+    }
+
     fn string_or_none(s : &str) -> Option<String> {
         if s.len() == 0 { None }
         else { Some(s.into()) }
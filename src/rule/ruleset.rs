@@ -2,9 +2,12 @@ use std::result::Result;
 use serde::{Serialize, Deserialize};
 use serde_json::{Value};
 use crate::parser::execution_context::ExecutionContext;
+use crate::parser::frame::Frame;
 use crate::parser::expression::{Expressive, Expression};
 use crate::parser::shy_token::ShyValue;
 use crate::parser::shy_scalar::ShyScalar;
+use crate::parser::shy_error::ShyError;
+use crate::lexer::Lexer;
 use super::{Rule, RuleType};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
@@ -222,7 +225,11 @@ pub struct RuleSet<'a> {
 
     /// The Rules to be executed, assumed to be properly sorted so that no Rule with a dependency on another Rule
     /// is listed before that dependency. 
-    pub rules: Vec<Rule<'a>>
+    pub rules: Vec<Rule<'a>>,
+
+    #[serde(skip)]
+    /// The variables of the context that the Rules were last bound to, from which `bound_context` makes new contexts.
+    bound_variables: Option<Box<Frame>>
 }
 
 impl<'a> RuleSet<'a> {
@@ -239,13 +246,22 @@ impl<'a> RuleSet<'a> {
     pub fn new<T>(name : T, context_name : T, criteria : SuccessCriteria, category : Option<String>, uncompiled_rules : &Vec<String>) -> Result<Self,Self> 
     where T : Into<String>
     {
-        let mut ruleset = RuleSet { name : name.into(), context_name : context_name.into(), criteria, category, rules : Vec::new() };
+        RuleSet::new_with_line_offsets(name, context_name, criteria, category, uncompiled_rules, &vec![0; uncompiled_rules.len()])
+    }
+
+    /// Construct a RuleSet as `new` does, where each of the `uncompiled_rules` was read from a larger text
+    /// after the given number of lines, so that errors can be reported relative to that text.
+    fn new_with_line_offsets<T>(name : T, context_name : T, criteria : SuccessCriteria, category : Option<String>, uncompiled_rules : &Vec<String>, line_offsets : &Vec<usize>) -> Result<Self,Self> 
+    where T : Into<String>
+    {
+        let mut ruleset = RuleSet { name : name.into(), context_name : context_name.into(), criteria, category, rules : Vec::new(), bound_variables : None };
         let mut has_errors = false;
         let mut unsorted_rules = Vec::new();
-        for (i, rule_source) in uncompiled_rules.iter().enumerate() {
-            let rule = Rule::new(rule_source, i+1, None);
+        for (i, (rule_source, line_offset)) in uncompiled_rules.iter().zip(line_offsets.iter()).enumerate() {
+            let mut rule = Rule::new(rule_source, i+1, None);
+            rule.line_offset = *line_offset;
             if rule.expression.had_compile_error() {
-                has_errors = false;
+                has_errors = true;
             }
             unsorted_rules.push(rule);
         }
@@ -286,41 +302,89 @@ impl<'a> RuleSet<'a> {
     /// Otherwise, assume that `Rules` may span multiple lines and are separated by one or more consecutive 
     /// blank lines. A blank line consists of zero or more spaces or tabs followed by a newline.
     /// 
+    /// Comments (see `Lexer`) may appear anywhere. A line holding nothing but a comment is not blank, 
+    /// and a blank line inside a block comment does not separate rules. 
+    /// Text holding nothing but comments does not become a `Rule`.
+    /// 
     ///   - If any of the rules fail to compile, do not sort the rules, then return an `Err`, otherwise an `Ok`. 
     ///   - If sorting fails because of circular dependencies, return an `Err`.
     ///   - If an `Err` is returned, all compiled rules will still be returned, and some may be marked as having an error. 
     pub fn new_from_text<T>(ruleset_text : T, single_newline_separates_rules : bool) -> Result<Self,Self> 
     where T : Into<String> {
+        let ruleset_text: String = ruleset_text.into();
+
+        // Find the tokens and comments of the whole text, to tell which lines hold nothing but comments
+        // and which blank lines fall inside a block comment.
+        let mut lexer = Lexer::new(&ruleset_text);
+        lexer.resynchronize = true;
+        let mut token_spans = Vec::new();
+        while let Some(_) = lexer.next() {
+            token_spans.push(lexer.span());
+        }
+        let comments = lexer.comments();
+
         let mut rule_source = Vec::new();
+        // Number of lines of the text before each rule.
+        let mut line_offsets = Vec::new();
         let mut hold = String::new();
-        for line in ruleset_text.into().lines() {
-            // Check if the string is all white space
-            if line.trim().is_empty() {
-                if hold.len() > 0 {
-                    rule_source.push(hold);
-                    hold = String::new();
-                }
+        let mut hold_line_offset = 0;
+        let mut hold_has_tokens = false;
+        let mut line_start = 0;
+        let mut next_token = 0;
+        for (line_number, raw_line) in ruleset_text.split('\n').enumerate() {
+            let line = raw_line.trim_end_matches('\r');
+            let line_end = line_start + line.chars().count();
+            let is_in_comment = comments.iter().any(|comment| comment.start < line_start && comment.end > line_end);
+            let ends_in_comment = comments.iter().any(|comment| comment.start < line_end && comment.end > line_end);
+            while next_token < token_spans.len() && token_spans[next_token].end <= line_start {
+                next_token += 1;
             }
-            else {
-                if single_newline_separates_rules {
-                    rule_source.push(line.to_string());
+            let has_tokens = next_token < token_spans.len() && token_spans[next_token].start < line_end;
+            line_start += raw_line.chars().count() + 1;
+
+            // Check if the string is all white space
+            let is_blank = line.trim().is_empty() && !is_in_comment;
+            if !is_blank {
+                if hold.is_empty() {
+                    hold_line_offset = line_number;
                 }
-                else {
-                    hold.push_str(line);
+                hold.push_str(line);
+                hold_has_tokens |= has_tokens;
+                // A rule continues onto the next line if it is multi-line, or if a block comment does.
+                if !single_newline_separates_rules || ends_in_comment {
                     hold.push('\n');
+                    continue;
                 }
             }
+            if hold_has_tokens {
+                rule_source.push(hold);
+                line_offsets.push(hold_line_offset);
+            }
+            hold = String::new();
+            hold_has_tokens = false;
         }
-        if hold.len() > 0 {
+        if hold_has_tokens {
             rule_source.push(hold);
+            line_offsets.push(hold_line_offset);
         }
-        let ruleset_opt = RuleSet::new("Untitled", "$", SuccessCriteria::LastPasses, None, &rule_source);
+        let ruleset_opt = RuleSet::new_with_line_offsets("Untitled", "$", SuccessCriteria::LastPasses, None, &rule_source, &line_offsets);
         if ruleset_opt.is_err() { return ruleset_opt }
         let mut ruleset = ruleset_opt.unwrap();
         ruleset.apply_ruleset_variables();
         Ok(ruleset)
     }
 
+    /// Every error that prevented `Rules` from compiling, in rule order, with lines counted from the start of the text 
+    /// if the `RuleSet` was constructed by `new_from_text`, so that each can be rendered against that text.
+    pub fn compile_errors(&self) -> Vec<ShyError> {
+        self.rules.iter().flat_map(|rule| rule.compile_errors()).collect()
+    }
+
+    /// Every warning about the expressions of the `Rules`, in rule order, with lines counted as for `compile_errors`.
+    pub fn compile_warnings(&self) -> Vec<ShyError> {
+        self.rules.iter().flat_map(|rule| rule.compile_warnings()).collect()
+    }
+
     /// Execute the `RuleSet` and extract some variables from the context to set the `RuleSet` `name`, `criteria` and `category`. 
     fn apply_ruleset_variables(&mut self) {
         // The Context does not need any of the variables expected by the formulas in the RuleSet.
@@ -346,6 +410,28 @@ impl<'a> RuleSet<'a> {
         self.category = ruleset_category;
     }
 
+    /// Bind the `Expressions` of all the `Rules` to the context, so that when the `RuleSet` is executed against it,
+    /// variables are accessed by slot instead of by name.
+    /// Bind once, after the context has been given the variables the `Rules` need, then execute as often as desired.
+    pub fn bind(&mut self, context : &mut ExecutionContext<'a>) {
+        for rule in self.rules.iter_mut() {
+            rule.express_mut().bind(context);
+        }
+        self.bound_variables = Some(Box::new(context.variables.clone()));
+    }
+
+    /// Make a default context that holds the variables of the context the `Rules` were last bound to, 
+    /// so that executing against it accesses variables by slot. Storing a variable that the `Rules` use does not change that, 
+    /// but storing any other variable makes the `Rules` fall back to accessing variables by name.
+    /// If the `RuleSet` was never bound, this is simply a default context.
+    pub fn bound_context(&self) -> ExecutionContext<'a> {
+        let mut context = ExecutionContext::default();
+        if let Some(ref variables) = self.bound_variables {
+            context.variables = (**variables).clone();
+        }
+        context
+    }
+
     /// Execute all the `Expressions` in the `RuleSet`, decide if it passes or fails, and return a structure
     /// that explains the results, which could be an error.  
     pub fn exec(&mut self, context : &ExecutionContext<'a>, trace_on : bool) -> RuleSetResult 
@@ -357,6 +443,13 @@ impl<'a> RuleSet<'a> {
         let mut result = RuleSetResult::new(self.name.clone(), self.criteria, context.clone());
         let mut exec_result;
 
+        // Functions declared by any Rule may be called by every Rule, regardless of the order of execution.
+        for rule in self.rules.iter() {
+            for function in rule.express().function_definitions() {
+                result.context.define_function(function.clone());
+            }
+        }
+
         // Loop through all the Rules in the RuleSet and evaluate them against the context. 
         for rule in self.rules.iter_mut() {
             {
@@ -377,7 +470,8 @@ impl<'a> RuleSet<'a> {
                     Ok(val) => {
                         (val, false)
                     },
-                    Err(error_val) => {
+                    Err(error) => {
+                        let error_val = error.to_string();
                         result.rules_with_errors_count += 1;
                         result.errors.push(format!("Rule `{}` had error: {:?}", rule.name, error_val));
                         (ShyValue::error(error_val), true)
@@ -403,7 +497,7 @@ impl<'a> RuleSet<'a> {
                         else {
                             result.did_last_applicable_rule_pass = false;
                         }
-                        result.last_applicable_rule_value = Some(rule_value.into());
+                        result.last_applicable_rule_value = Some(rule_value.to_json(result.context.decimal_json));
                     }
                     else {
                         result.inapplicable_rule_count += 1;
@@ -424,6 +518,8 @@ mod tests {
     #[allow(unused_imports)]
     use spectral::prelude::*;
 
+    use crate::parser::bench;
+
     const CAR_RULESET : &str = r#"
           rule.name = "RuleSet header"
           rule.type = "Property";
@@ -483,4 +579,185 @@ mod tests {
         
     }
 
+    /// Make a context holding the car used by the tests of CAR_RULESET, starting from the given context.
+    fn car_context<'a>(mut context : ExecutionContext<'a>) -> ExecutionContext<'a> {
+        for (property, value) in [("car.make", "Honda".into()), ("car.age", 10.into()), ("car.miles_driven", 120000.into()), ("car.price", 4750.into()), ("car.accidents", 2.into())] {
+            let _ = context.store_chain_string(property, value);
+        }
+        context
+    }
+
+    /// Test that a context made by bound_context keeps the Rules bound after the caller's data is stored in it, 
+    /// and that they give the same result as when unbound.
+    #[test]
+    fn exec_with_bound_context() {
+        let mut unbound_ruleset = RuleSet::new_from_text(CAR_RULESET, false).unwrap();
+        let unbound_result = unbound_ruleset.exec(&car_context(ExecutionContext::default()), false);
+
+        let mut ruleset = RuleSet::new_from_text(CAR_RULESET, false).unwrap();
+        let mut context = ExecutionContext::default();
+        context.variables.slot("car");
+        ruleset.bind(&mut context);
+        let bound_context = car_context(ruleset.bound_context());
+        asserting("Rules are bound").that(&ruleset.rules.iter().all(|rule| rule.express().is_bound_to(&bound_context))).is_equal_to(true);
+        let bound_result = ruleset.exec(&bound_context, false);
+
+        asserting("Same passing count").that(&bound_result.passing_applicable_rule_count).is_equal_to(unbound_result.passing_applicable_rule_count);
+        asserting("Same variables").that(&bound_result.context.variables).is_equal_to(&unbound_result.context.variables);
+    }
+
+    /// Time many executions of a RuleSet, unbound and then bound, to show that binding speeds them up.
+    /// Run with `cargo test --release -- --ignored exec_bound_is_faster`.
+    #[test]
+    #[ignore]
+    fn exec_bound_is_faster() {
+        let executions = 20000;
+        let mut unbound_ruleset = RuleSet::new_from_text(CAR_RULESET, false).unwrap();
+        let unbound_context = car_context(ExecutionContext::default());
+
+        let mut bound_ruleset = RuleSet::new_from_text(CAR_RULESET, false).unwrap();
+        let mut context = ExecutionContext::default();
+        context.variables.slot("car");
+        bound_ruleset.bind(&mut context);
+        let bound_context = car_context(bound_ruleset.bound_context());
+
+        bench::assert_faster("Bound execution is faster", executions,
+            || { unbound_ruleset.exec(&unbound_context, false); },
+            || { bound_ruleset.exec(&bound_context, false); });
+    }
+
+    /// Test that a RuleSet with a Rule that fails to compile is returned as an Err, holding its Rules unsorted.
+    #[test]
+    fn new_with_compile_error_is_err() {
+        let rules = vec!["y = x + 1".to_string(), "x = (2".to_string()];
+        let result = RuleSet::new("broken", "$", SuccessCriteria::LastPasses, None, &rules);
+        asserting("is err").that(&result.is_err()).is_equal_to(true);
+        let ruleset = result.err().unwrap();
+        let had_errors: Vec<bool> = ruleset.rules.iter().map(|rule| rule.expression.had_compile_error()).collect();
+        asserting("rules unsorted").that(&had_errors).is_equal_to(vec![false, true]);
+    }
+
+    /// Test that compile errors in a RuleSet read from text are located on the lines of that text.
+    #[test]
+    fn compile_errors_located_in_ruleset_text() {
+        let ruleset_text = "rule.name = \"first\";\nx = 1\n\nrule.name = \"second\";\ny = (x + 2))\n\nz = 3";
+        let ruleset = RuleSet::new_from_text(ruleset_text, false).err().unwrap();
+        let errors = ruleset.compile_errors();
+        asserting("one error").that(&errors.len()).is_equal_to(1);
+        asserting("rendered").that(&errors[0].render(ruleset_text)).is_equal_to(
+            "syntax error: Unbalanced closing parenthesis\n --> line 5, column 12\n  |\n5 | y = (x + 2))\n  |            ^".to_string()
+        );
+    }
+
+    /// Test that every compile error in every Rule of a RuleSet is reported, in the order they appear in the text.
+    #[test]
+    fn compile_errors_collected_from_all_rules() {
+        let ruleset_text = "rule.name = \"first\";\nx = (1 + 2;\ny = `;\n\nrule.name = \"second\";\nz = [x, y\n\nrule.name = \"third\";\nw = 4";
+        let ruleset = RuleSet::new_from_text(ruleset_text, false).err().unwrap();
+        let locations: Vec<(usize, usize)> = ruleset.compile_errors().iter().map(|error| (error.line, error.column)).collect();
+        asserting("errors").that(&locations).is_equal_to(vec![(2, 5), (3, 5), (6, 5)]);
+    }
+
+    /// Test that comments do not become Rules, and that blank lines still separate Rules when comments are present,
+    /// except inside a block comment.
+    #[test]
+    fn new_from_text_with_comments() {
+        let ruleset_text = r#"
+          # Shopping rules
+          // (checked daily)
+
+          rule.name = "big"; # too big for the car?
+          size > 150
+
+          /* Rules below this point
+             
+             were added later. */
+          rule.name = "cheap";
+          // A bargain
+          price < 100 /* dollars */
+
+          // The end
+        "#;
+        let ruleset = RuleSet::new_from_text(ruleset_text, false).unwrap();
+        let names: Vec<String> = ruleset.rules.iter().map(|rule| rule.name.clone()).collect();
+        asserting("rule names").that(&names).is_equal_to(vec!["big".to_string(), "cheap".to_string()]);
+
+        let single_line_text = "# Header\nrule.name = \"a\"; x = 1 // first\n\n/* second\n */ rule.name = \"b\"; y = 2";
+        let ruleset = RuleSet::new_from_text(single_line_text, true).unwrap();
+        let names: Vec<String> = ruleset.rules.iter().map(|rule| rule.name.clone()).collect();
+        asserting("one rule per line, joined across a block comment").that(&names).is_equal_to(vec!["a".to_string(), "b".to_string()]);
+    }
+
+    /// Test that a function declared by one Rule may be called by every Rule, including those executed before it.
+    #[test]
+    fn exec_user_function_shared_by_rules() {
+        let ruleset_text = r#"
+          rule.name = "cold";
+          below_freezing = c_to_f(temperature) < 32;
+
+          rule.name = "conversion";
+          rule.type = "Property";
+          fn c_to_f(c) = c * 9/5 + 32;
+
+          rule.name = "hot";
+          above_boiling = c_to_f(temperature) > 212;
+        "#;
+        let mut ruleset = RuleSet::new_from_text(ruleset_text, false).unwrap();
+        let mut context = ExecutionContext::default();
+        context.store(&"temperature".to_string(), -5);
+
+        let exec_result = ruleset.exec(&context, false);
+
+        asserting("No rule had an error").that(&exec_result.rules_with_errors_count).is_equal_to(0);
+        asserting("Cold rule passed").that(&exec_result.context.load(&"below_freezing".to_string())).is_equal_to(Some(true.into()));
+        asserting("Hot rule failed").that(&exec_result.context.load(&"above_boiling".to_string())).is_equal_to(Some(false.into()));
+    }
+
+    /// Test that rules may reuse the name of a local variable without depending upon each other through it,
+    /// and that local variables are not written to the context.
+    #[test]
+    fn exec_rules_with_local_variables() {
+        let ruleset_text = r#"
+          rule.name = "doubled";
+          let t = base * 2;
+          doubled = t
+
+          rule.name = "base";
+          let t = price + 1;
+          base = t
+        "#;
+        let mut ruleset = RuleSet::new_from_text(ruleset_text, false).unwrap();
+        let mut context = ExecutionContext::default();
+        context.store(&"price".to_string(), 4);
+
+        let exec_result = ruleset.exec(&context, false);
+
+        asserting("No rule had an error").that(&exec_result.rules_with_errors_count).is_equal_to(0);
+        asserting("Base rule ran first").that(&exec_result.context.load(&"doubled".to_string())).is_equal_to(Some(10.into()));
+        asserting("Local variable not stored").that(&exec_result.context.load(&"t".to_string())).is_equal_to(None);
+    }
+
+    /// Test that a Rule that assigns a standard constant, like e, changes the value read by the Rules that follow it,
+    /// even when they were bound to the context while it held the standard value.
+    #[test]
+    fn exec_rules_overriding_constant() {
+        let ruleset_text = r#"
+          rule.name = "growth";
+          rule.type = "Property";
+          e = 5;
+
+          rule.name = "doubled";
+          rule.type = "Property";
+          doubled = e * 2
+        "#;
+        let mut ruleset = RuleSet::new_from_text(ruleset_text, false).unwrap();
+        let mut context = ExecutionContext::default();
+        ruleset.bind(&mut context);
+
+        let exec_result = ruleset.exec(&context, false);
+
+        asserting("No rule had an error").that(&exec_result.rules_with_errors_count).is_equal_to(0);
+        asserting("Overridden constant read").that(&exec_result.context.load(&"doubled".to_string())).is_equal_to(Some(10.into()));
+    }
+
 }
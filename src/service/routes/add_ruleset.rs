@@ -4,6 +4,8 @@ use serde_json::{Value};
 use actix_web::{put, web, HttpResponse};
 use log::{warn, info};
 use crate::rule::ruleset::{RuleSet, SuccessCriteria};
+use crate::parser::execution_context::ExecutionContext;
+use crate::parser::shy_error::ShyError;
 use super::super::service_state::ServiceState;
 use crate::cache::Cache;
 
@@ -47,16 +49,22 @@ fn default_ruleset_source() -> Option<String> { None }
 pub struct AddRulesetResponse<'a> {
     pub ruleset: Option<RuleSet<'a>>,
     pub success : bool,
-    pub error : Option<Value>
+    pub error : Option<Value>,
+
+    /// Every error that kept a Rule from compiling, in rule order, 
+    /// with its line and column counted from the start of the `ruleset_source` (or from the start of its rule in `rule_source`).
+    #[serde(default)]
+    pub compile_errors : Vec<ShyError>
 }
 
 impl<'a> AddRulesetResponse<'a> {
     pub fn new_with_error(error : String, compiled_ruleset : Option<RuleSet<'a>>) -> Self {
         warn!(target: "service::routes", "Add RuleSet. {}", error);
-        AddRulesetResponse { ruleset : compiled_ruleset, success : false, error : Some(error.into()) }
+        let compile_errors = compiled_ruleset.as_ref().map_or(Vec::new(), |ruleset| ruleset.compile_errors());
+        AddRulesetResponse { ruleset : compiled_ruleset, success : false, error : Some(error.into()), compile_errors }
     }
     pub fn new_with_success(compiled_ruleset : RuleSet<'a>) -> Self {
-        AddRulesetResponse { ruleset : Some(compiled_ruleset), success : true, error : None }
+        AddRulesetResponse { ruleset : Some(compiled_ruleset), success : true, error : None, compile_errors : Vec::new() }
     }
 }
 
@@ -82,7 +90,12 @@ fn route((path, req, data): (web::Path<String>, web::Json<AddRulesetRequest>, we
 
     let response = 
         match ruleset_result {
-            Ok(ruleset) => {
+            Ok(mut ruleset) => {
+                // Bind once, here, so that every execution accesses variables by slot. 
+                // A slot is added for the context name, so that storing the caller's data does not change the layout.
+                let mut context = ExecutionContext::default();
+                context.variables.slot(&ruleset.context_name);
+                ruleset.bind(&mut context);
                 state.ruleset_cache.add_or_replace(&ruleset.name, &ruleset, true);
                 AddRulesetResponse::new_with_success(ruleset)
             },
@@ -95,3 +108,36 @@ fn route((path, req, data): (web::Path<String>, web::Json<AddRulesetRequest>, we
         HttpResponse::BadRequest().json(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    use actix_web::{test, App};
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    /// Verify that the response to adding a RuleSet that fails to compile lists every compile error, 
+    /// located by line and column in the ruleset_source.
+    fn add_ruleset_reports_compile_errors() {
+        let mut app = test::init_service(App::new().register_data(web::Data::new(ServiceState::new(10))).service(route));
+        let ruleset_source = "rule.name = \"first\";\nx = (1 + 2;\ny = `;\n\nrule.name = \"second\";\nz = [x, y\n\nrule.name = \"third\";\nw = 4";
+        let request = test::TestRequest::put().uri("/rulesets/broken").set_json(&json!({ "ruleset_source": ruleset_source })).to_request();
+        let response = test::call_service(&mut app, request);
+        asserting("status").that(&response.status()).is_equal_to(StatusCode::BAD_REQUEST);
+
+        let request = test::TestRequest::put().uri("/rulesets/broken").set_json(&json!({ "ruleset_source": ruleset_source })).to_request();
+        let body: Value = test::read_response_json(&mut app, request);
+        let locations: Vec<(u64, u64)> = body["compile_errors"].as_array().unwrap().iter()
+            .map(|error| (error["line"].as_u64().unwrap(), error["column"].as_u64().unwrap()))
+            .collect();
+        asserting("locations").that(&locations).is_equal_to(vec![(2, 5), (3, 5), (6, 5)]);
+        asserting("message").that(&body["compile_errors"][0]["message"]).is_equal_to(json!("Unbalanced opening parenthesis"));
+        asserting("kind").that(&body["compile_errors"][1]["kind"]).is_equal_to(json!("Lexical"));
+    }
+}
@@ -6,7 +6,7 @@ use log::{info};
 
 use super::super::service_state::ServiceState;
 use crate::cache::Cache;
-use crate::parser::execution_context::ExecutionContext;
+use crate::parser::execution_context::DecimalJson;
 
 #[derive(Serialize, Deserialize, Debug)]
 /// Request for when you load a RuleSet from Cache and execute it against a context supplied as the posted data.
@@ -31,7 +31,11 @@ pub struct ExecuteRulesetRequest {
 
     /// If true, a detailed log of the execution of the expression will be logged to the console.
     #[serde(default = "default_trace_on")]
-    pub trace_on : bool
+    pub trace_on : bool,
+
+    /// How Decimals are written in the result and the returned context: as "String" (the default) or "Number".
+    #[serde(default)]
+    pub decimal_json : DecimalJson
 }
 
 // Supply these default values for missing fields when Deserializing. 
@@ -101,7 +105,9 @@ fn route((path, req, data): (web::Path<String>, web::Json<ExecuteRulesetRequest>
                 // but the calls to `trace` deeper in the code modify the Expression temporarily, 
                 // and that is not threadsafe. Need to refactor trace to have state passed in, maybe a logger. 
                 ruleset = ruleset_from_cache.clone();
-                let mut context = ExecutionContext::default();
+                // The RuleSet was bound when it was added, so a context made from the bound one lets it access variables by slot.
+                let mut context = ruleset.bound_context();
+                context.decimal_json = req.decimal_json;
 
                 // Add data sent by caller to the context.
                 if let Some(value) = &req.context {
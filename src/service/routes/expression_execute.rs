@@ -2,8 +2,9 @@ use std::sync::RwLock;
 use serde::{Serialize, Deserialize};
 use serde_json::{Value};
 use actix_web::{post, web, HttpResponse};
-use crate::parser::execution_context::ExecutionContext;
+use crate::parser::execution_context::{ExecutionContext, DecimalJson};
 use crate::parser::ShuntingYard;
+use crate::parser::shy_error::ShyError;
 use super::super::service_state::ServiceState;
 
 
@@ -31,7 +32,11 @@ pub struct ExpressionExecuteRequest {
 
     /// If true, a detailed log of the execution of the expression will be logged to the console.
     #[serde(default = "default_trace_on")]
-    pub trace_on : bool
+    pub trace_on : bool,
+
+    /// How Decimals are written in the result and the returned context: as "String" (the default) or "Number".
+    #[serde(default)]
+    pub decimal_json : DecimalJson
 }
 
 // Supply these default values for missing fields when Deserializing. 
@@ -48,7 +53,11 @@ fn default_trace_on() -> bool { false }
 pub struct ExpressionExecuteResponse<'a> {
     pub result : Option<Value>,
     pub context : Option<ExecutionContext<'a>>,
-    pub error : Option<Value>
+    pub error : Option<Value>,
+
+    /// Every error that kept the expression from compiling, located by line and column in the expression.
+    #[serde(default)]
+    pub compile_errors : Vec<ShyError>
 }
 
 impl<'a> ExpressionExecuteResponse<'a> {
@@ -56,7 +65,7 @@ impl<'a> ExpressionExecuteResponse<'a> {
         self.error.is_some()
     }
     pub fn new() -> ExpressionExecuteResponse<'a> {
-        ExpressionExecuteResponse { result: None, context: None, error: None }
+        ExpressionExecuteResponse { result: None, context: None, error: None, compile_errors: Vec::new() }
     }
 }
 
@@ -75,11 +84,12 @@ fn route((req, data): (web::Json<ExpressionExecuteRequest>, web::Data<RwLock<Ser
     // Because we need to create the ExecutionContext in such a way that it is owned by the response,
     // so that we can return it without having to clone it!
     response.context = Some(ExecutionContext::default());
-    match shy.compile() {
+    match shy.compile_with_recovery() {
         Ok(mut expr) => {
             let exec_result;
             {
                 let ctx = response.context.as_mut().unwrap();
+                ctx.decimal_json = req.decimal_json;
                 match &req.context {
                     Some(value) => { ctx.store(&req.context_name, value); },
                     None => ()
@@ -90,13 +100,14 @@ fn route((req, data): (web::Json<ExpressionExecuteRequest>, web::Data<RwLock<Ser
             }
             if !req.return_context { response.context = None; }
             match exec_result {
-                Ok(answer) => { response.result = Some(answer.into()); },
+                Ok(answer) => { response.result = Some(answer.to_json(req.decimal_json)); },
                 Err(msg) => { response.error = Some(Value::String(format!("Error executing {}: {}", req.expression, msg))); }
             };
         },
-        Err(msg) => { 
+        Err(errors) => { 
             response.context = None;
-            response.error = Some(Value::String(format!("Error compiling {}: {}", req.expression, msg)));
+            response.error = Some(Value::String(format!("Error compiling {}: {}", req.expression, errors[0])));
+            response.compile_errors = errors;
         }
     };
     
@@ -107,3 +118,33 @@ fn route((req, data): (web::Json<ExpressionExecuteRequest>, web::Data<RwLock<Ser
         HttpResponse::BadRequest().json(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(unused_imports)]
+    use spectral::prelude::*;
+
+    use actix_web::{test, App};
+    use serde_json::json;
+
+    #[test]
+    /// Verify that the response to executing an expression that fails to compile lists every compile error, 
+    /// located by line and column in the expression.
+    fn execute_reports_compile_errors() {
+        let mut app = test::init_service(App::new().register_data(web::Data::new(ServiceState::new(10))).service(route));
+        let request = test::TestRequest::post().uri("/expression/execute")
+            .set_json(&json!({ "expression": "a = (1 + 2;\nb = 3;\nc = `" })).to_request();
+        let body: Value = test::read_response_json(&mut app, request);
+        let errors: Vec<(String, u64, u64)> = body["compile_errors"].as_array().unwrap().iter()
+            .map(|error| (error["message"].as_str().unwrap().to_string(), error["line"].as_u64().unwrap(), error["column"].as_u64().unwrap()))
+            .collect();
+        asserting("errors").that(&errors).is_equal_to(vec![
+            ("Unbalanced opening parenthesis".to_string(), 1, 5), 
+            ("Unexpected character '`'".to_string(), 3, 5)
+        ]);
+        asserting("error names the first").that(&body["error"]).is_equal_to(json!("Error compiling a = (1 + 2;\nb = 3;\nc = `: Unbalanced opening parenthesis at line 1, column 5"));
+    }
+}